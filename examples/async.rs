@@ -1,29 +1,29 @@
 // MIT/Apache2 License
 
-// This example requires the "async" feature to be activated.
+// This example requires the "async-io-support" feature to be activated.
 
 // Note: I use smol here, as it is a). my personal favorite async runtime in the Rust ecosystem at the
 //       moment, and b). breadx is implemented in terms of its objects. You should be able to
 //       use async-std or an executor-less system here as well without much headache. See the tokio example
 //       for an example on that.
-#[cfg(feature = "async")]
+#[cfg(feature = "async-io-support")]
 use breadx::{
     prelude::*, auto::xproto::ExposeEvent, rgb, AsyncDisplayConnection, Event, EventMask, GcParameters, Rectangle,
 };
-#[cfg(feature = "async")]
+#[cfg(feature = "async-io-support")]
 use easy_parallel::Parallel;
-#[cfg(feature = "async")]
+#[cfg(feature = "async-io-support")]
 use futures_lite::{future, FutureExt, StreamExt};
-#[cfg(feature = "async")]
+#[cfg(feature = "async-io-support")]
 use smol::{
     channel::{unbounded, Receiver, Sender},
     Executor, Timer,
 };
-#[cfg(feature = "async")]
+#[cfg(feature = "async-io-support")]
 use std::{env, mem, time::Duration};
 
 // coroutine base: wait 3 seconds, generate three random numbers, and send them down the channel
-#[cfg(feature = "async")]
+#[cfg(feature = "async-io-support")]
 #[inline]
 async fn rng(sender: Sender<[u8; 3]>) {
     let mut timer = Timer::interval(Duration::from_secs(3));
@@ -49,7 +49,7 @@ async fn rng(sender: Sender<[u8; 3]>) {
 }
 
 // coroutine base: set up an X connection and, given the receiver channel, change color based on its result
-#[cfg(feature = "async")]
+#[cfg(feature = "async-io-support")]
 #[inline]
 async fn x_process(receiver: Receiver<[u8; 3]>) -> breadx::Result<()> {
     /// Are we processing events, or are we processing what we get from the channel?
@@ -176,7 +176,7 @@ async fn x_process(receiver: Receiver<[u8; 3]>) -> breadx::Result<()> {
 }
 
 // async entry point
-#[cfg(feature = "async")]
+#[cfg(feature = "async-io-support")]
 async fn entry(ex: &Executor<'_>) -> breadx::Result<()> {
     let (sender, receiver) = unbounded::<[u8; 3]>();
 
@@ -189,7 +189,7 @@ async fn entry(ex: &Executor<'_>) -> breadx::Result<()> {
     x11_res
 }
 
-#[cfg(all(feature = "async", not(feature = "tokio-support")))]
+#[cfg(all(feature = "async-io-support", not(feature = "tokio-support")))]
 fn main() -> breadx::Result<()> {
     env::set_var("RUST_LOG", "breadx=trace");
     env_logger::init();
@@ -208,7 +208,7 @@ fn main() -> breadx::Result<()> {
         .1
 }
 
-#[cfg(any(not(feature = "async"), feature = "tokio-support"))]
+#[cfg(any(not(feature = "async-io-support"), feature = "tokio-support"))]
 fn main() {
-    println!("async example requires the 'async' feature to be enabled without 'tokio-support'");
+    println!("async example requires the 'async-io-support' feature to be enabled without 'tokio-support'");
 }
@@ -1,6 +1,7 @@
 // MIT/Apache2 License
 
 use std::{
+    collections::HashSet,
     env,
     fs::File,
     io::{prelude::*, BufReader, BufWriter},
@@ -10,6 +11,8 @@ fn main() {
     // read in data to a JSON structure
     let mut args = env::args_os().skip(1);
     let mut infile = BufReader::new(File::open(args.next().unwrap()).unwrap());
+    let mut charfile = BufReader::new(File::open(args.next().unwrap()).unwrap());
+    let mut namefile = BufReader::new(File::open(args.next().unwrap()).unwrap());
     let mut outfile = BufWriter::new(File::create(args.next().unwrap()).unwrap());
 
     let mut buffer = String::new();
@@ -17,12 +20,26 @@ fn main() {
 
     let data: Vec<Vec<serde_json::Value>> = serde_json::from_str(&buffer).unwrap();
 
+    buffer.clear();
+    charfile.read_to_string(&mut buffer).unwrap();
+
+    let chars: Vec<Vec<serde_json::Value>> = serde_json::from_str(&buffer).unwrap();
+
+    buffer.clear();
+    namefile.read_to_string(&mut buffer).unwrap();
+
+    let names: Vec<Vec<serde_json::Value>> = serde_json::from_str(&buffer).unwrap();
+
     // begin writing to the outfile.
     outfile
         .write_all(
             "// Generated by breadx-keysym-generator, licensed under MIT/Apache2 License
 
-#![allow(clippy::match_same_arms, clippy::too_many_lines)]
+#![allow(
+    clippy::match_same_arms,
+    clippy::too_many_lines,
+    clippy::unreadable_literal
+)]
 
 use crate::auto::xproto::Keysym;
 use gluten_keyboard::Key;
@@ -52,6 +69,119 @@ pub fn keysym_to_key(keysym: Keysym) -> Option<Key> {
             "
         _ => None,
     }
+}
+
+/// Get the character that a keysym represents, if it represents one.
+///
+/// Latin-1 keysyms and keysyms in the Unicode range (`0x01000100` to `0x0110ffff`) map directly onto their
+/// code points. Other legacy keysyms are mapped using the Unicode annotations in `keysymdef.h`. Function keys
+/// that produce text, such as `Return`, `Tab` or the keypad digits, are mapped onto their ASCII equivalents.
+#[inline]
+#[must_use]
+pub fn keysym_to_char(keysym: Keysym) -> Option<char> {
+    let code = match keysym {
+        0x20..=0x7e | 0xa0..=0xff => keysym,
+        0x0100_0100..=0x0110_ffff => keysym - 0x0100_0000,
+"
+            .as_bytes(),
+        )
+        .unwrap();
+
+    chars.into_iter().for_each(|d| {
+        if let (Some(num), Some(code)) = (d[0].as_str(), d[1].as_str()) {
+            let num = u32::from_str_radix(&num[2..], 16).unwrap();
+            let code = u32::from_str_radix(&code[2..], 16).unwrap();
+
+            outfile
+                .write_all(format!("{:#x} => {:#x},", num, code).as_bytes())
+                .unwrap();
+        }
+    });
+
+    outfile
+        .write_all(
+            "
+        _ => return None,
+    };
+
+    core::char::from_u32(code)
+}
+
+/// Get the keysym with the given name, as it appears in `keysymdef.h` without the `XK_` prefix.
+///
+/// Like Xlib's `XStringToKeysym`, names of the form `U20AC` are mapped onto Unicode keysyms and names of the
+/// form `0x1008ff13` are parsed as raw keysym values.
+#[inline]
+#[must_use]
+pub fn keysym_from_name(name: &str) -> Option<Keysym> {
+    let keysym = match name {
+"
+            .as_bytes(),
+        )
+        .unwrap();
+
+    names.iter().for_each(|d| {
+        if let (Some(name), Some(num)) = (d[0].as_str(), d[1].as_str()) {
+            let num = u32::from_str_radix(&num[2..], 16).unwrap();
+
+            outfile
+                .write_all(format!("{:?} => {:#x},", name, num).as_bytes())
+                .unwrap();
+        }
+    });
+
+    outfile
+        .write_all(
+            "
+        _ => {
+            if let Some(hex) = name.strip_prefix(\"0x\") {
+                return Keysym::from_str_radix(hex, 16).ok();
+            }
+
+            let code = u32::from_str_radix(name.strip_prefix('U')?, 16).ok()?;
+            return match code {
+                0x20..=0x7e | 0xa0..=0xff => Some(code),
+                0x100..=0x0010_ffff => Some(code | 0x0100_0000),
+                _ => None,
+            };
+        }
+    };
+
+    Some(keysym)
+}
+
+/// Get the name of the given keysym, as it appears in `keysymdef.h` without the `XK_` prefix.
+///
+/// Keysyms with several names are given the first name listed for them. Returns `None` for keysyms without a
+/// name; like Xlib's `XKeysymToString`, callers may fall back on the `U20AC` or `0x1008ff13` forms.
+#[inline]
+#[must_use]
+pub fn keysym_name(keysym: Keysym) -> Option<&'static str> {
+    Some(match keysym {
+"
+            .as_bytes(),
+        )
+        .unwrap();
+
+    // only the first name of each keysym is used
+    let mut seen = HashSet::new();
+    names.iter().for_each(|d| {
+        if let (Some(name), Some(num)) = (d[0].as_str(), d[1].as_str()) {
+            let num = u32::from_str_radix(&num[2..], 16).unwrap();
+
+            if seen.insert(num) {
+                outfile
+                    .write_all(format!("{:#x} => {:?},", num, name).as_bytes())
+                    .unwrap();
+            }
+        }
+    });
+
+    outfile
+        .write_all(
+            "
+        _ => return None,
+    })
 }"
             .as_bytes(),
         )
@@ -7,10 +7,10 @@ use alloc::vec;
 #[cfg(feature = "std")]
 use std::{env, fs::File, io::Read};
 
-#[cfg(all(feature = "async", not(feature = "tokio-support")))]
+#[cfg(all(feature = "async-io-support", not(feature = "tokio-support")))]
 use blocking::{unblock, Unblock};
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use futures_lite::{AsyncRead, AsyncReadExt};
 
 #[cfg(feature = "tokio-support")]
@@ -114,7 +114,7 @@ impl AuthInfo {
     }
 
     /// Reads in the auth info from the given reading stream, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     #[must_use]
     pub async fn from_stream_async<R: AsyncRead + Unpin>(reader: &mut R) -> Option<Vec<Self>> {
@@ -134,7 +134,10 @@ impl AuthInfo {
     }
 
     /// Reads in the auth info from the file specified by the XAuthority variable, async redox.
-    #[cfg(feature = "async")]
+    ///
+    /// With neither the `tokio-support` nor the `async-io-support` feature enabled, there is no runtime to
+    /// offload the file read to, so the file is read synchronously. It is usually very small.
+    #[cfg(feature = "async-core")]
     #[inline]
     #[must_use]
     pub async fn from_xauthority_async() -> Option<Vec<Self>> {
@@ -144,10 +147,13 @@ impl AuthInfo {
             if #[cfg(feature = "tokio-support")] {
                 let mut file = tokio::fs::File::open(&fname).await.ok()?.compat();
                 Self::from_stream_async(&mut file).await
-            } else {
+            } else if #[cfg(feature = "async-io-support")] {
                 let file = unblock(move || File::open(&fname)).await.ok()?;
                 let mut file = Unblock::new(file);
                 Self::from_stream_async(&mut file).await
+            } else {
+                let mut file = File::open(&fname).ok()?;
+                Self::from_stream(&mut file)
             }
         }
     }
@@ -173,7 +179,7 @@ impl AuthInfo {
         }
     }
 
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub(crate) async fn get_async() -> Self {
         if cfg!(test) {
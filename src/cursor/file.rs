@@ -0,0 +1,174 @@
+// MIT/Apache2 License
+
+//! Parser for the Xcursor file format, which stores ARGB cursor images in several sizes, and optionally several
+//! frames of an animation per size.
+
+use crate::BreadError;
+use alloc::{boxed::Box, vec::Vec};
+use core::convert::TryInto;
+
+const INVALID_XCURSOR: BreadError = BreadError::StaticMsg("Invalid Xcursor file");
+
+/// "Xcur", in little endian.
+const MAGIC: u32 = 0x7275_6358;
+/// The type of a table of contents entry that points to an image.
+const IMAGE_TYPE: u32 = 0xFFFD_0002;
+/// Images larger than this in either dimension are rejected.
+const MAX_IMAGE_SIZE: u32 = 0x7FFF;
+
+/// An image of a cursor, taken from an Xcursor file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XcursorImage {
+    /// The nominal size of the image, which is used to pick the images that fit the desired cursor size.
+    pub size: u32,
+    /// The width of the image.
+    pub width: u32,
+    /// The height of the image.
+    pub height: u32,
+    /// The X coordinate of the hotspot.
+    pub xhot: u32,
+    /// The Y coordinate of the hotspot.
+    pub yhot: u32,
+    /// The time in milliseconds that this frame is shown for, if the cursor is animated.
+    pub delay: u32,
+    /// The pixels of the image, row by row, as ARGB values with premultiplied alpha.
+    pub pixels: Box<[u32]>,
+}
+
+/// The images contained within an Xcursor file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct XcursorFile {
+    /// The images, in the order that they appear in the file's table of contents.
+    pub images: Vec<XcursorImage>,
+}
+
+/// Read a little endian `u32` at the given offset.
+#[inline]
+fn read_u32(data: &[u8], offset: usize) -> crate::Result<u32> {
+    let bytes = data
+        .get(offset..offset.checked_add(4).ok_or(INVALID_XCURSOR)?)
+        .ok_or(INVALID_XCURSOR)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+impl XcursorFile {
+    /// Parse the contents of an Xcursor file. Chunks other than images, such as comments, are skipped.
+    #[inline]
+    pub fn parse(data: &[u8]) -> crate::Result<Self> {
+        if read_u32(data, 0)? != MAGIC {
+            return Err(INVALID_XCURSOR);
+        }
+        let header_len = read_u32(data, 4)? as usize;
+        let ntoc = read_u32(data, 12)? as usize;
+
+        let mut images = Vec::new();
+        for i in 0..ntoc {
+            let entry = header_len + i * 12;
+            if read_u32(data, entry)? != IMAGE_TYPE {
+                continue;
+            }
+            let position = read_u32(data, entry + 8)? as usize;
+
+            // the chunk header repeats the type and size from the table of contents
+            let chunk_header_len = read_u32(data, position)? as usize;
+            let field = |n: usize| read_u32(data, position + 4 * n);
+            if field(1)? != IMAGE_TYPE {
+                return Err(INVALID_XCURSOR);
+            }
+            let (size, width, height) = (field(2)?, field(4)?, field(5)?);
+            let (xhot, yhot, delay) = (field(6)?, field(7)?, field(8)?);
+            if width > MAX_IMAGE_SIZE || height > MAX_IMAGE_SIZE || xhot > width || yhot > height {
+                return Err(INVALID_XCURSOR);
+            }
+
+            let start = position + chunk_header_len;
+            let len = width as usize * height as usize * 4;
+            let pixels = data.get(start..start + len).ok_or(INVALID_XCURSOR)?;
+            images.push(XcursorImage {
+                size,
+                width,
+                height,
+                xhot,
+                yhot,
+                delay,
+                pixels: pixels
+                    .chunks_exact(4)
+                    .map(|pixel| u32::from_le_bytes(pixel.try_into().unwrap()))
+                    .collect(),
+            });
+        }
+
+        Ok(Self { images })
+    }
+
+    /// Get the nominal size out of the sizes of this file's images that is closest to `size`.
+    #[inline]
+    #[must_use]
+    pub fn best_size(&self, size: u32) -> Option<u32> {
+        self.images
+            .iter()
+            .map(|image| image.size)
+            .min_by_key(|nominal| (i64::from(*nominal) - i64::from(size)).abs())
+    }
+
+    /// Get the frames of the cursor with the nominal size that is closest to `size`. If the cursor is not
+    /// animated, there is only one frame.
+    #[inline]
+    pub fn frames(&self, size: u32) -> impl Iterator<Item = &XcursorImage> {
+        let best = self.best_size(size);
+        self.images
+            .iter()
+            .filter(move |image| Some(image.size) == best)
+    }
+}
+
+#[test]
+fn xcursor_picks_the_closest_size() {
+    // a file with a comment, a 24px image and two frames of a 32px animation
+    let image = |size: u32, delay: u32, pixel: u32| {
+        let mut chunk = alloc::vec![];
+        for value in &[36, IMAGE_TYPE, size, 1, 1, 1, 0, 1, delay, pixel] {
+            chunk.extend_from_slice(&value.to_le_bytes());
+        }
+        chunk
+    };
+    let chunks = [
+        image(24, 0, 0xFF00_0000),
+        image(32, 50, 0xFFFF_FFFF),
+        image(32, 70, 0x8080_8080),
+    ];
+
+    let mut file = alloc::vec![];
+    for value in &[MAGIC, 16, 0x1_0000, 4] {
+        file.extend_from_slice(&value.to_le_bytes());
+    }
+    let mut position = 16 + 4 * 12;
+    for value in &[0xFFFE_0001, 1, 0] {
+        file.extend_from_slice(&u32::to_le_bytes(*value));
+    }
+    for (chunk, size) in chunks.iter().zip(&[24u32, 32, 32]) {
+        for value in &[IMAGE_TYPE, *size, position] {
+            file.extend_from_slice(&value.to_le_bytes());
+        }
+        position += chunk.len() as u32;
+    }
+    for chunk in &chunks {
+        file.extend_from_slice(chunk);
+    }
+
+    let xcursor = XcursorFile::parse(&file).unwrap();
+    assert_eq!(xcursor.images.len(), 3);
+    assert_eq!(xcursor.images[0].yhot, 1);
+
+    let frames = |size| {
+        xcursor
+            .frames(size)
+            .map(|frame| (frame.size, frame.delay, frame.pixels[0]))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(frames(16), [(24, 0, 0xFF00_0000)]);
+    assert_eq!(frames(30), [(32, 50, 0xFFFF_FFFF), (32, 70, 0x8080_8080)]);
+
+    assert!(XcursorFile::parse(&file[..file.len() - 1]).is_err());
+    assert!(XcursorFile::parse(b"not a cursor").is_err());
+}
@@ -0,0 +1,357 @@
+// MIT/Apache2 License
+
+//! Loading cursors by name, from Xcursor themes or from the core cursor font.
+//!
+//! Xcursor themes contain full-color cursors, which may be animated, and are created with the `XRender`
+//! extension. Servers or builds without `XRender` get the glyph of the same name from the core cursor font
+//! instead.
+
+mod file;
+#[cfg(feature = "std")]
+mod theme;
+
+pub use file::*;
+#[cfg(feature = "std")]
+pub use theme::*;
+
+use crate::{
+    auto::xproto::{CloseFontRequest, CreateGlyphCursorRequest, Cursor, Font, OpenFontRequest},
+    display::{generate_xid, prelude::*, Display},
+    BreadError,
+};
+
+#[cfg(all(feature = "std", feature = "render"))]
+use crate::{
+    auto::{
+        render::{
+            Animcursorelt, CreateAnimCursorRequest, CreateCursorRequest, CreatePictureRequest,
+            FreePictureRequest, Pictformat, Picture, QueryPictFormatsRequest, QueryVersionRequest,
+        },
+        xproto::{FreeCursorRequest, ImageFormat, ImageOrder, QueryExtensionRequest, Window},
+    },
+    image::Image,
+    render::StandardFormat,
+};
+#[cfg(all(feature = "std", feature = "render"))]
+use alloc::{boxed::Box, vec::Vec};
+
+/// The names of the glyphs in the core cursor font, in order. Every cursor takes up two glyphs: the cursor
+/// itself and its mask.
+const CURSOR_FONT_NAMES: &[&str] = &[
+    "X_cursor",
+    "arrow",
+    "based_arrow_down",
+    "based_arrow_up",
+    "boat",
+    "bogosity",
+    "bottom_left_corner",
+    "bottom_right_corner",
+    "bottom_side",
+    "bottom_tee",
+    "box_spiral",
+    "center_ptr",
+    "circle",
+    "clock",
+    "coffee_mug",
+    "cross",
+    "cross_reverse",
+    "crosshair",
+    "diamond_cross",
+    "dot",
+    "dotbox",
+    "double_arrow",
+    "draft_large",
+    "draft_small",
+    "draped_box",
+    "exchange",
+    "fleur",
+    "gobbler",
+    "gumby",
+    "hand1",
+    "hand2",
+    "heart",
+    "icon",
+    "iron_cross",
+    "left_ptr",
+    "left_side",
+    "left_tee",
+    "leftbutton",
+    "ll_angle",
+    "lr_angle",
+    "man",
+    "middlebutton",
+    "mouse",
+    "pencil",
+    "pirate",
+    "plus",
+    "question_arrow",
+    "right_ptr",
+    "right_side",
+    "right_tee",
+    "rightbutton",
+    "rtl_logo",
+    "sailboat",
+    "sb_down_arrow",
+    "sb_h_double_arrow",
+    "sb_left_arrow",
+    "sb_right_arrow",
+    "sb_up_arrow",
+    "sb_v_double_arrow",
+    "shuttle",
+    "sizing",
+    "spider",
+    "spraycan",
+    "star",
+    "target",
+    "tcross",
+    "top_left_arrow",
+    "top_left_corner",
+    "top_right_corner",
+    "top_side",
+    "top_tee",
+    "trek",
+    "ul_angle",
+    "umbrella",
+    "ur_angle",
+    "watch",
+    "xterm",
+];
+
+/// Get the index of the glyph for a cursor in the core cursor font, e.g. 68 for `left_ptr`.
+#[inline]
+#[must_use]
+pub fn cursor_font_glyph(name: &str) -> Option<u16> {
+    CURSOR_FONT_NAMES
+        .iter()
+        .position(|glyph| *glyph == name)
+        .map(|index| index as u16 * 2)
+}
+
+/// Create a cursor from the core cursor font, given the name of its glyph. The cursor is black, with a white
+/// outline.
+#[inline]
+pub fn create_font_cursor<Dpy: Display + ?Sized>(
+    dpy: &mut Dpy,
+    name: &str,
+) -> crate::Result<Cursor> {
+    let glyph = cursor_font_glyph(name).ok_or(BreadError::StaticMsg(
+        "No glyph with that name in the cursor font",
+    ))?;
+
+    let font = Font::const_from_xid(generate_xid(dpy)?);
+    dpy.exchange_request(OpenFontRequest {
+        fid: font,
+        name: "cursor".into(),
+        ..Default::default()
+    })?;
+
+    let cursor = Cursor::const_from_xid(generate_xid(dpy)?);
+    let res = dpy.exchange_request(CreateGlyphCursorRequest {
+        cid: cursor,
+        source_font: font,
+        mask_font: font,
+        source_char: glyph,
+        mask_char: glyph + 1,
+        back_red: 0xFFFF,
+        back_green: 0xFFFF,
+        back_blue: 0xFFFF,
+        ..Default::default()
+    });
+
+    // the cursor keeps its own reference to the font
+    dpy.exchange_request(CloseFontRequest {
+        font,
+        ..Default::default()
+    })?;
+    res.map(|()| cursor)
+}
+
+/// Load the cursor with the given name, e.g. `left_ptr` or `xterm`. The cursor is looked up in the user's
+/// Xcursor theme (see `CursorTheme::from_display`) and created with `XRender`, which requires the `render`
+/// feature. If the theme does not contain the cursor, or `XRender` is not available, the glyph of the same name
+/// from the core cursor font is used.
+#[cfg(feature = "std")]
+#[inline]
+pub fn load_cursor<Dpy: Display + ?Sized>(dpy: &mut Dpy, name: &str) -> crate::Result<Cursor> {
+    #[cfg(feature = "render")]
+    match load_theme_cursor(dpy, name) {
+        Ok(Some(cursor)) => return Ok(cursor),
+        Ok(None) => {}
+        Err(e) => log::debug!("Unable to load cursor {} from the theme: {:?}", name, e),
+    }
+
+    create_font_cursor(dpy, name)
+}
+
+/// Load a cursor from the user's Xcursor theme. Returns `None` if the cursor isn't in the theme, or if the
+/// server doesn't support ARGB cursors.
+#[cfg(all(feature = "std", feature = "render"))]
+#[inline]
+fn load_theme_cursor<Dpy: Display + ?Sized>(
+    dpy: &mut Dpy,
+    name: &str,
+) -> crate::Result<Option<Cursor>> {
+    let theme = CursorTheme::from_display(dpy)?;
+    if theme.name == "core" {
+        return Ok(None);
+    }
+    let path = match theme.find(name) {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let file = XcursorFile::parse(&std::fs::read(path)?)?;
+
+    // ARGB cursors were added in XRender 0.5, and animated cursors in XRender 0.8
+    let extension = dpy.exchange_request(QueryExtensionRequest {
+        name: "RENDER".into(),
+        ..Default::default()
+    })?;
+    if !extension.present {
+        return Ok(None);
+    }
+    let version = dpy.exchange_request(QueryVersionRequest {
+        client_major_version: 0,
+        client_minor_version: 11,
+        ..Default::default()
+    })?;
+    let version = (version.major_version, version.minor_version);
+    if version < (0, 5) {
+        return Ok(None);
+    }
+
+    let formats = dpy.exchange_request(QueryPictFormatsRequest::default())?;
+    let format = match formats
+        .formats
+        .iter()
+        .find(|format| StandardFormat::Argb32.matches(format))
+    {
+        Some(format) => format.id,
+        None => return Ok(None),
+    };
+
+    let root = dpy.default_root();
+    let mut frames = file.frames(theme.size).collect::<Vec<_>>();
+    if version < (0, 8) {
+        frames.truncate(1);
+    }
+
+    match frames[..] {
+        [] => Ok(None),
+        [frame] => create_argb_cursor(dpy, root, format, frame).map(Some),
+        _ => {
+            let mut elements = Vec::with_capacity(frames.len());
+            for frame in frames {
+                match create_argb_cursor(dpy, root, format, frame) {
+                    Ok(cursor) => elements.push(Animcursorelt {
+                        cursor,
+                        delay: frame.delay,
+                    }),
+                    Err(e) => {
+                        free_cursors(dpy, &elements)?;
+                        return Err(e);
+                    }
+                }
+            }
+
+            let cursor = Cursor::const_from_xid(generate_xid(dpy)?);
+            let res = dpy.exchange_request(CreateAnimCursorRequest {
+                cid: cursor,
+                cursors: elements.as_slice().into(),
+                ..Default::default()
+            });
+
+            // the animated cursor keeps references to its frames
+            free_cursors(dpy, &elements)?;
+            res.map(|()| Some(cursor))
+        }
+    }
+}
+
+/// Create a cursor out of a single image from an Xcursor file.
+#[cfg(all(feature = "std", feature = "render"))]
+#[inline]
+fn create_argb_cursor<Dpy: Display + ?Sized>(
+    dpy: &mut Dpy,
+    root: Window,
+    format: Pictformat,
+    frame: &XcursorImage,
+) -> crate::Result<Cursor> {
+    // the pixels have to be sent in the server's byte order
+    let msb_first = dpy.setup().image_byte_order == ImageOrder::MsbFirst;
+    let data: Box<[u8]> = frame
+        .pixels
+        .iter()
+        .flat_map(|pixel| {
+            if msb_first {
+                pixel.to_be_bytes()
+            } else {
+                pixel.to_le_bytes()
+            }
+        })
+        .collect();
+    let image = Image::new(
+        &*dpy,
+        None,
+        32,
+        ImageFormat::ZPixmap,
+        0,
+        data,
+        frame.width as usize,
+        frame.height as usize,
+        32,
+        None,
+    )
+    .ok_or(BreadError::StaticMsg("Failed to create cursor image"))?;
+    let pixmap = dpy.create_pixmap_from_image(root, &image)?;
+
+    let picture = Picture::const_from_xid(generate_xid(dpy)?);
+    let res = dpy
+        .exchange_request(CreatePictureRequest {
+            pid: picture,
+            drawable: pixmap.into(),
+            format,
+            ..Default::default()
+        })
+        .and_then(|()| {
+            let res = generate_xid(dpy).and_then(|xid| {
+                let cursor = Cursor::const_from_xid(xid);
+                dpy.exchange_request(CreateCursorRequest {
+                    cid: cursor,
+                    source: picture,
+                    x: frame.xhot as u16,
+                    y: frame.yhot as u16,
+                    ..Default::default()
+                })
+                .map(|()| cursor)
+            });
+
+            // the picture has to be freed even if the cursor couldn't be created
+            let freed = dpy.exchange_request(FreePictureRequest {
+                picture,
+                ..Default::default()
+            });
+            res.and_then(|cursor| freed.map(|()| cursor))
+        });
+
+    pixmap.free(dpy)?;
+    res
+}
+
+#[cfg(all(feature = "std", feature = "render"))]
+#[inline]
+fn free_cursors<Dpy: Display + ?Sized>(dpy: &mut Dpy, elements: &[Animcursorelt]) -> crate::Result {
+    elements.iter().try_for_each(|element| {
+        dpy.exchange_request(FreeCursorRequest {
+            cursor: element.cursor,
+            ..Default::default()
+        })
+    })
+}
+
+#[test]
+fn cursor_font_glyphs_match_cursorfont_h() {
+    assert_eq!(cursor_font_glyph("X_cursor"), Some(0));
+    assert_eq!(cursor_font_glyph("left_ptr"), Some(68));
+    assert_eq!(cursor_font_glyph("xterm"), Some(152));
+    assert_eq!(cursor_font_glyph("nonexistent"), None);
+}
@@ -0,0 +1,236 @@
+// MIT/Apache2 License
+
+//! Looking up cursors in Xcursor themes, following the same rules as `libXcursor`.
+
+use crate::{
+    auto::xproto::{GetPropertyRequest, ATOM_RESOURCE_MANAGER, ATOM_STRING},
+    display::{prelude::*, Display},
+};
+use alloc::{
+    borrow::ToOwned,
+    string::{String, ToString},
+    vec::Vec,
+};
+use std::{env, fs, path::PathBuf};
+
+/// The directories that are searched for themes if `XCURSOR_PATH` is not set.
+const DEFAULT_PATH: &str =
+    "~/.local/share/icons:~/.icons:/usr/share/icons:/usr/share/pixmaps:/usr/X11R6/lib/X11/icons";
+
+/// Inherited themes are not followed further than this, in case themes inherit from each other in a cycle.
+const MAX_INHERIT_DEPTH: usize = 8;
+
+/// An Xcursor theme, along with the size of the cursors that should be loaded from it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CursorTheme {
+    /// The name of the theme. The special name `core` means that the core cursor font should be used.
+    pub name: String,
+    /// The nominal size of cursors, in pixels.
+    pub size: u32,
+    /// The directories that themes are searched for in.
+    pub search_path: Vec<PathBuf>,
+}
+
+impl CursorTheme {
+    /// Create a theme with the given name and cursor size. Themes are searched for in the directories listed in
+    /// `XCURSOR_PATH`, or in the default directories if it is not set.
+    #[inline]
+    #[must_use]
+    pub fn new(name: &str, size: u32) -> Self {
+        let path = env::var("XCURSOR_PATH").unwrap_or_else(|_| DEFAULT_PATH.to_string());
+        let home = env::var_os("HOME");
+
+        let search_path = path
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .filter_map(|dir| match dir.strip_prefix('~') {
+                Some(rest) => home
+                    .as_ref()
+                    .map(|home| PathBuf::from(home).join(rest.trim_start_matches('/'))),
+                None => Some(PathBuf::from(dir)),
+            })
+            .collect();
+
+        Self {
+            name: name.to_string(),
+            size,
+            search_path,
+        }
+    }
+
+    /// Determine the theme and cursor size that the user has configured. The theme is taken from the
+    /// `XCURSOR_THEME` variable, or the `Xcursor.theme` resource, and falls back to `default`. The size is
+    /// taken from the `XCURSOR_SIZE` variable, or the `Xcursor.size` resource; otherwise, it is derived from
+    /// the `Xft.dpi` resource or the size of the screen.
+    #[inline]
+    pub fn from_display<Dpy: Display + ?Sized>(dpy: &mut Dpy) -> crate::Result<Self> {
+        let resources = resource_manager_string(dpy)?;
+        let resource = |name| resource_value(&resources, name);
+
+        let name = env::var("XCURSOR_THEME")
+            .ok()
+            .filter(|theme| !theme.is_empty())
+            .or_else(|| resource("Xcursor.theme").map(ToOwned::to_owned))
+            .unwrap_or_else(|| "default".to_string());
+
+        let size = env::var("XCURSOR_SIZE")
+            .ok()
+            .as_deref()
+            .or_else(|| resource("Xcursor.size"))
+            .and_then(|size| size.trim().parse::<u32>().ok())
+            .filter(|size| *size > 0)
+            .or_else(|| {
+                resource("Xft.dpi")
+                    .and_then(|dpi| dpi.trim().parse::<u32>().ok())
+                    .filter(|dpi| *dpi > 0)
+                    .and_then(|dpi| dpi.checked_mul(16))
+                    .map(|size| size / 72)
+            })
+            .unwrap_or_else(|| {
+                let screen = dpy.default_screen();
+                u32::from(screen.width_in_pixels.min(screen.height_in_pixels)) / 48
+            });
+
+        Ok(Self::new(&name, size))
+    }
+
+    /// Find the file for the cursor with the given name, in this theme or in one of the themes it inherits
+    /// from. If it cannot be found, the `default` theme is searched as well.
+    #[inline]
+    #[must_use]
+    pub fn find(&self, cursor: &str) -> Option<PathBuf> {
+        self.find_in_theme(&self.name, cursor, 0).or_else(|| {
+            if self.name == "default" {
+                None
+            } else {
+                self.find_in_theme("default", cursor, 0)
+            }
+        })
+    }
+
+    #[inline]
+    fn find_in_theme(&self, theme: &str, cursor: &str, depth: usize) -> Option<PathBuf> {
+        if depth > MAX_INHERIT_DEPTH {
+            return None;
+        }
+
+        let mut inherits = None;
+        for dir in &self.search_path {
+            let theme_dir = dir.join(theme);
+            let path = theme_dir.join("cursors").join(cursor);
+            if path.is_file() {
+                return Some(path);
+            }
+
+            // the themes this one inherits from are listed by the first index.theme that is found
+            if inherits.is_none() {
+                inherits = fs::read_to_string(theme_dir.join("index.theme"))
+                    .ok()
+                    .and_then(|index| inherited_themes(&index));
+            }
+        }
+
+        inherits?
+            .iter()
+            .filter(|parent| parent.as_str() != theme)
+            .find_map(|parent| self.find_in_theme(parent, cursor, depth + 1))
+    }
+}
+
+/// Get the names of the themes listed in the `Inherits` key of an `index.theme` file.
+#[inline]
+fn inherited_themes(index: &str) -> Option<Vec<String>> {
+    index.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        if key.trim() != "Inherits" {
+            return None;
+        }
+
+        Some(
+            value
+                .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+                .filter(|theme| !theme.is_empty())
+                .map(ToString::to_string)
+                .collect(),
+        )
+    })
+}
+
+/// Get the `RESOURCE_MANAGER` property of the first screen's root window, which contains the resources that
+/// were loaded by `xrdb`.
+#[inline]
+fn resource_manager_string<Dpy: Display + ?Sized>(dpy: &mut Dpy) -> crate::Result<String> {
+    let root = match dpy.setup().roots.first() {
+        Some(screen) => screen.root,
+        None => return Ok(String::new()),
+    };
+
+    let reply = dpy.exchange_request(GetPropertyRequest {
+        window: root,
+        property: ATOM_RESOURCE_MANAGER,
+        ty: ATOM_STRING,
+        long_offset: 0,
+        long_length: 100_000_000,
+        delete: false,
+        ..Default::default()
+    })?;
+    Ok(String::from_utf8_lossy(&reply.value).into_owned())
+}
+
+/// Look up a resource by its full name in a resource database string. Only lines that name the resource
+/// exactly, or with a leading wildcard, are matched.
+#[inline]
+fn resource_value<'a>(resources: &'a str, name: &str) -> Option<&'a str> {
+    resources.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        if key.trim().trim_start_matches('*') == name {
+            Some(value.trim())
+        } else {
+            None
+        }
+    })
+}
+
+#[test]
+fn cursor_themes_are_inherited() {
+    use std::{fs::File, io::Write};
+
+    let root = env::temp_dir().join(alloc::format!("breadx-cursor-theme-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let first = root.join("first");
+    let second = root.join("second");
+    fs::create_dir_all(first.join("Child/cursors")).unwrap();
+    fs::create_dir_all(second.join("Parent/cursors")).unwrap();
+    fs::create_dir_all(second.join("default/cursors")).unwrap();
+
+    File::create(first.join("Child/index.theme"))
+        .unwrap()
+        .write_all(b"[Icon Theme]\nName=Child\nInherits=Child, Parent\n")
+        .unwrap();
+    File::create(first.join("Child/cursors/xterm")).unwrap();
+    File::create(second.join("Parent/cursors/left_ptr")).unwrap();
+    File::create(second.join("default/cursors/watch")).unwrap();
+
+    let theme = CursorTheme {
+        name: "Child".to_string(),
+        size: 24,
+        search_path: alloc::vec![first.clone(), second.clone()],
+    };
+    assert_eq!(theme.find("xterm"), Some(first.join("Child/cursors/xterm")));
+    assert_eq!(
+        theme.find("left_ptr"),
+        Some(second.join("Parent/cursors/left_ptr"))
+    );
+    assert_eq!(
+        theme.find("watch"),
+        Some(second.join("default/cursors/watch"))
+    );
+    assert_eq!(theme.find("fleur"), None);
+
+    let resources = "Xft.dpi:\t96\n*Xcursor.theme:\tAdwaita\nXcursor.size: 32\n";
+    assert_eq!(resource_value(resources, "Xcursor.theme"), Some("Adwaita"));
+    assert_eq!(resource_value(resources, "Xcursor.size"), Some("32"));
+    assert_eq!(resource_value(resources, "Xft.rgba"), None);
+
+    fs::remove_dir_all(&root).unwrap();
+}
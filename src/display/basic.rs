@@ -12,15 +12,18 @@ use hashbrown::HashMap;
 #[cfg(feature = "std")]
 use super::name::NameConnection;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use super::{
     common::{SendBuffer, WaitBuffer, WaitBufferReturn},
-    name::AsyncNameConnection,
     AsyncConnection, AsyncDisplay, PollOr, RequestWorkaround,
 };
-#[cfg(feature = "async")]
+#[cfg(any(feature = "async-io-support", feature = "tokio-support"))]
+use super::name::AsyncNameConnection;
+#[cfg(all(feature = "tokio-support", unix))]
+use super::TokioConnection;
+#[cfg(feature = "async-core")]
 use alloc::{vec, vec::Vec};
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use core::{
     mem,
     task::{Context, Poll},
@@ -113,16 +116,21 @@ pub struct BasicDisplay<Conn> {
     pub(crate) extensions: HashMap<[u8; EXT_KEY_SIZE], u8>,
 
     /// Internal buffer for polling for waiting
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     wait_buffer: Option<WaitBuffer>,
 
     /// Internal buffer for sending a request
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     send_buffer: SendBuffer,
 
+    /// Whether or not the request in the send buffer was abandoned by its future, and needs to be finished
+    /// before anything else is done with the connection.
+    #[cfg(feature = "async-core")]
+    send_abandoned: bool,
+
     /// List of requests we need to consider the GLX workaround for. This simplifies
     /// async operations.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     workarounders: Vec<u16>,
 }
 
@@ -147,11 +155,13 @@ impl<Conn> BasicDisplay<Conn> {
             checked: cfg!(debug_assertions),
             //            context: HashMap::new(),
             extensions: HashMap::with_capacity(8),
-            #[cfg(feature = "async")]
+            #[cfg(feature = "async-core")]
             wait_buffer: None,
-            #[cfg(feature = "async")]
+            #[cfg(feature = "async-core")]
             send_buffer: Default::default(),
-            #[cfg(feature = "async")]
+            #[cfg(feature = "async-core")]
+            send_abandoned: false,
+            #[cfg(feature = "async-core")]
             workarounders: vec![],
         }
     }
@@ -260,7 +270,7 @@ impl<Conn: Connection> BasicDisplay<Conn> {
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 impl<Conn: AsyncConnection + Unpin> BasicDisplay<Conn> {
     /// Establishes this display using an inner connection type. This receives setup information from the server
     /// on the other side of the connection in order to populate the display. This method uses async types in
@@ -270,20 +280,19 @@ impl<Conn: AsyncConnection + Unpin> BasicDisplay<Conn> {
     ///
     /// # Example
     ///
+    /// This function does not depend on any particular async runtime. Any `AsyncRead + AsyncWrite` stream can be
+    /// used by wrapping it in a [`StreamConnection`](crate::display::StreamConnection), optionally alongside an
+    /// [`FdHook`](crate::display::FdHook) for passing file descriptors. If `auth_info` is `None`, the
+    /// authorization info is read from the file specified by the `XAUTHORITY` environment variable.
+    ///
     /// ```rust,no_run
-    /// use async_io::Async;
-    /// use blocking::unblock;
-    /// use breadx::display::BasicDisplay;
-    /// use std::net::TcpStream;
+    /// use breadx::display::{BasicDisplay, StreamConnection};
+    /// use futures_lite::{AsyncRead, AsyncWrite};
     ///
-    /// # fn main() -> breadx::Result {
-    /// # futures_lite::future::block_on(async {
-    /// let server = blocking::unblock(|| TcpStream::connect("127.0.0.1:60000")).await?;
-    /// let server = Async::new(server)?;
-    /// let conn = BasicDisplay::from_connection_async(server, 0, None).await?;
-    /// # Ok(())
-    /// # })
-    /// # }
+    /// async fn connect<S: AsyncRead + AsyncWrite + Unpin>(server: S) -> breadx::Result {
+    ///     let conn = BasicDisplay::from_connection_async(StreamConnection::new(server), 0, None).await?;
+    ///     Ok(())
+    /// }
     /// ```
     #[inline]
     pub async fn from_connection_async(
@@ -343,6 +352,15 @@ impl<Conn> DisplayBase for BasicDisplay<Conn> {
         self.event_queue.pop_front()
     }
 
+    #[inline]
+    fn pop_event_if(&mut self, predicate: &mut dyn FnMut(&Event) -> bool) -> Option<Event> {
+        if predicate(self.event_queue.front()?) {
+            self.event_queue.pop_front()
+        } else {
+            None
+        }
+    }
+
     #[inline]
     fn generate_xid(&mut self) -> Option<XID> {
         self.xid.next_xid()
@@ -351,7 +369,7 @@ impl<Conn> DisplayBase for BasicDisplay<Conn> {
     #[inline]
     fn add_pending_item(&mut self, req_id: u16, item: PendingItem) {
         log_trace!("Adding pending item for {}: {:?}", req_id, &item);
-        #[cfg(feature = "async")]
+        #[cfg(feature = "async-core")]
         {
             if let PendingItem::Request(ref pereq) = item {
                 if matches!(pereq.flags.workaround, RequestWorkaround::GlxFbconfigBug) {
@@ -370,7 +388,7 @@ impl<Conn> DisplayBase for BasicDisplay<Conn> {
     #[inline]
     fn take_pending_item(&mut self, req_id: u16) -> Option<PendingItem> {
         log_trace!("Removing pending item for {}", req_id);
-        #[cfg(feature = "async")]
+        #[cfg(feature = "async-core")]
         self.workarounders.retain(|&r| r != req_id);
         self.pending_items.remove(&req_id)
     }
@@ -453,6 +471,14 @@ impl<Connect: Connection> Display for BasicDisplay<Connect> {
         res
     }
 
+    #[inline]
+    fn try_wait(&mut self) -> crate::Result<bool> {
+        let mut conn = self.connection.take().expect("Poisoned!");
+        let res = input::try_wait(self, &mut conn);
+        self.connection = Some(conn);
+        res
+    }
+
     #[inline]
     fn send_request_raw(&mut self, request_info: RequestInfo) -> crate::Result<u16> {
         let mut conn = self.connection.take().expect("Poisoned!");
@@ -462,10 +488,36 @@ impl<Connect: Connection> Display for BasicDisplay<Connect> {
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
+impl<Connect: AsyncConnection + Unpin> BasicDisplay<Connect> {
+    /// If a request was abandoned partway through being sent, poll to finish sending it.
+    #[inline]
+    fn poll_finish_abandoned(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if !self.send_abandoned {
+            return Poll::Ready(());
+        }
+
+        let mut send_buffer = mem::replace(&mut self.send_buffer, SendBuffer::OccupiedHole);
+        let mut conn = self.connection.take().expect("Poisoned!");
+        let res = send_buffer.poll_finish_abandoned(self, &mut conn, cx);
+        self.send_buffer = send_buffer;
+        self.connection = Some(conn);
+
+        if res.is_ready() {
+            self.send_abandoned = false;
+        }
+        res
+    }
+}
+
+#[cfg(feature = "async-core")]
 impl<Connect: AsyncConnection + Unpin> AsyncDisplay for BasicDisplay<Connect> {
     #[inline]
     fn poll_wait(&mut self, cx: &mut Context<'_>) -> Poll<crate::Result> {
+        if self.poll_finish_abandoned(cx).is_pending() {
+            return Poll::Pending;
+        }
+
         let mut conn = self.connection.take().expect("Poisoned!");
         let workarounders = &self.workarounders;
         let res = self
@@ -491,8 +543,12 @@ impl<Connect: AsyncConnection + Unpin> AsyncDisplay for BasicDisplay<Connect> {
     fn begin_send_request_raw(
         &mut self,
         req: RequestInfo,
-        _cx: &mut Context<'_>,
+        cx: &mut Context<'_>,
     ) -> PollOr<(), RequestInfo> {
+        if self.poll_finish_abandoned(cx).is_pending() {
+            return PollOr::Pending(req);
+        }
+
         self.send_buffer.fill_hole(req);
         PollOr::Ready(())
     }
@@ -509,11 +565,16 @@ impl<Connect: AsyncConnection + Unpin> AsyncDisplay for BasicDisplay<Connect> {
             self.send_buffer.dig_hole();
         }
         match res {
-            Poll::Ready(Ok(pr)) => Poll::Ready(Ok(output::finish_request(self, pr))),
+            Poll::Ready(Ok(pr)) => Poll::Ready(Ok(output::finish_request(pr))),
             Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
             Poll::Pending => Poll::Pending,
         }
     }
+
+    #[inline]
+    fn abandon_send_request_raw(&mut self) {
+        self.send_abandoned = self.send_buffer.abandon();
+    }
 }
 
 /// A variant of `BasicDisplay` that uses X11's default connection mechanisms to connect to the server. In
@@ -524,9 +585,17 @@ impl<Connect: AsyncConnection + Unpin> AsyncDisplay for BasicDisplay<Connect> {
 pub type DisplayConnection = BasicDisplay<NameConnection>;
 
 /// [`DisplayConnection`], but using an async connection type instead of the standard blocking one.
-#[cfg(all(feature = "std", feature = "async"))]
+#[cfg(any(feature = "async-io-support", feature = "tokio-support"))]
 pub type AsyncDisplayConnection = BasicDisplay<AsyncNameConnection>;
 
+/// [`DisplayConnection`], but using a connection registered directly with `tokio`'s reactor.
+///
+/// Unlike [`AsyncDisplayConnection`] with the `tokio-support` feature enabled, the underlying connection does not
+/// need to be locked, and supports sending and receiving file descriptors. This type is `Send`, so it can be
+/// moved into `tokio::spawn`. See [`TokioConnection`] for more information.
+#[cfg(all(feature = "tokio-support", unix))]
+pub type TokioDisplayConnection = BasicDisplay<TokioConnection>;
+
 #[cfg(feature = "std")]
 impl DisplayConnection {
     /// Create a new connection to the X server, given an optional name and authorization information.
@@ -582,7 +651,7 @@ impl DisplayConnection {
     }
 }
 
-#[cfg(all(feature = "std", feature = "async"))]
+#[cfg(any(feature = "async-io-support", feature = "tokio-support"))]
 impl AsyncDisplayConnection {
     /// Create a new connection to the X server, given an optional name and authorization information, async
     /// redox. See `DisplayConnection::create()` for more information regarding this function.
@@ -595,3 +664,25 @@ impl AsyncDisplayConnection {
         Self::from_connection_async(connection, screen, auth_info).await
     }
 }
+
+#[cfg(all(feature = "tokio-support", unix))]
+impl TokioDisplayConnection {
+    /// Create a new connection to the X server, given an optional name and authorization information. The
+    /// connection is registered with the current `tokio` runtime, so this must be called from within one. See
+    /// `DisplayConnection::create()` for more information regarding this function.
+    #[inline]
+    pub async fn create_tokio(
+        name: Option<Cow<'_, str>>,
+        auth_info: Option<AuthInfo>,
+    ) -> crate::Result<Self> {
+        let (connection, screen) = TokioConnection::connect_internal_tokio(name).await?;
+        Self::from_connection_async(connection, screen, auth_info).await
+    }
+}
+
+#[cfg(all(feature = "tokio-support", unix))]
+#[test]
+fn tokio_display_is_send() {
+    fn assert_send<T: Send>() {}
+    assert_send::<TokioDisplayConnection>();
+}
@@ -5,12 +5,12 @@
 use super::{prelude::*, Display};
 use crate::auto::bigreq::EnableRequest;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use super::{
     futures::{ExchangeRequestFuture, MapFuture},
     AsyncDisplay,
 };
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::auto::bigreq::EnableReply;
 
 /// Try to enable `bigreq` for this display.
@@ -24,7 +24,7 @@ pub(crate) fn try_bigreq<D: Display + ?Sized>(display: &mut D) -> crate::Result<
 }
 
 /// Try to enable `bigreq` for this display, async redox.
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 #[inline]
 pub(crate) fn try_bigreq_async<D: AsyncDisplay + ?Sized>(
     display: &mut D,
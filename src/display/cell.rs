@@ -12,14 +12,14 @@ use core::{
 };
 use hashbrown::HashMap;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use super::{
     common::{SendBuffer, WaitBuffer, WaitBufferReturn},
     AsyncConnection, AsyncDisplay, PollOr, RequestWorkaround,
 };
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use alloc::{vec, vec::Vec};
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use core::{
     mem,
     task::{Context, Poll},
@@ -92,10 +92,14 @@ pub struct CellDisplay<Conn> {
     checked: Cell<bool>,
 
     // used for polling
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     wait_buffer: RefCell<Option<WaitBuffer>>,
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     send_buffer: RefCell<SendBuffer>,
+    // whether or not the request in the send buffer was abandoned by its future; if it was, it still holds the
+    // io lock, and needs to be finished before anything else is done with the connection
+    #[cfg(feature = "async-core")]
+    send_abandoned: Cell<bool>,
 }
 
 /// Collection types for `CellDisplay` that need to be put behind an interior mutability lock.
@@ -105,7 +109,7 @@ struct Data {
     pending_items: HashMap<u16, PendingItem>,
     special_event_queues: HashMap<XID, VecDeque<Event>>,
     extensions: HashMap<[u8; EXT_KEY_SIZE], u8>,
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     workarounders: Vec<u16>,
 }
 
@@ -143,16 +147,18 @@ impl<Conn> From<BasicDisplay<Conn>> for CellDisplay<Conn> {
                 pending_items,
                 special_event_queues,
                 extensions,
-                #[cfg(feature = "async")]
+                #[cfg(feature = "async-core")]
                 workarounders: vec![],
             }),
             request_number: Cell::new(request_number),
             wm_protocols_atom: Cell::new(wm_protocols_atom),
             checked: Cell::new(checked),
-            #[cfg(feature = "async")]
+            #[cfg(feature = "async-core")]
             wait_buffer: RefCell::new(None),
-            #[cfg(feature = "async")]
+            #[cfg(feature = "async-core")]
             send_buffer: Default::default(),
+            #[cfg(feature = "async-core")]
+            send_abandoned: Cell::new(false),
         }
     }
 }
@@ -222,12 +228,21 @@ impl<Conn> DisplayBase for CellDisplay<Conn> {
         self.inner.get_mut().event_queue.pop_front()
     }
     #[inline]
+    fn pop_event_if(&mut self, predicate: &mut dyn FnMut(&Event) -> bool) -> Option<Event> {
+        let event_queue = &mut self.inner.get_mut().event_queue;
+        if predicate(event_queue.front()?) {
+            event_queue.pop_front()
+        } else {
+            None
+        }
+    }
+    #[inline]
     fn generate_xid(&mut self) -> Option<XID> {
         self.xid.next_xid()
     }
     #[inline]
     fn add_pending_item(&mut self, req_id: u16, item: PendingItem) {
-        #[cfg(feature = "async")]
+        #[cfg(feature = "async-core")]
         {
             if let PendingItem::Request(ref pereq) = item {
                 if matches!(pereq.flags.workaround, RequestWorkaround::GlxFbconfigBug) {
@@ -243,7 +258,7 @@ impl<Conn> DisplayBase for CellDisplay<Conn> {
     }
     #[inline]
     fn take_pending_item(&mut self, req_id: u16) -> Option<PendingItem> {
-        #[cfg(feature = "async")]
+        #[cfg(feature = "async-core")]
         self.inner.get_mut().workarounders.retain(|&r| r != req_id);
         self.inner.get_mut().pending_items.remove(&req_id)
     }
@@ -323,6 +338,18 @@ impl<Connect: Connection> Display for CellDisplay<Connect> {
         res
     }
 
+    #[inline]
+    fn try_wait(&mut self) -> crate::Result<bool> {
+        self.lock_internal();
+        let mut connection = self.connection.take().expect("Poisoned!");
+
+        let res = input::try_wait(self, &mut connection);
+
+        self.connection = Some(connection);
+        *self.io_lock.get_mut() = false;
+        res
+    }
+
     #[inline]
     fn send_request_raw(&mut self, req: RequestInfo) -> crate::Result<u16> {
         self.lock_internal();
@@ -336,10 +363,37 @@ impl<Connect: Connection> Display for CellDisplay<Connect> {
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
+impl<Connect: AsyncConnection + Unpin> CellDisplay<Connect> {
+    /// If a request was abandoned partway through being sent, poll to finish sending it and release the io lock.
+    #[inline]
+    fn poll_finish_abandoned(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if !self.send_abandoned.get() {
+            return Poll::Ready(());
+        }
+
+        let mut send_buffer = mem::replace(self.send_buffer.get_mut(), SendBuffer::OccupiedHole);
+        let mut conn = self.connection.take().expect("Poisoned!");
+        let res = send_buffer.poll_finish_abandoned(self, &mut conn, cx);
+        *self.send_buffer.get_mut() = send_buffer;
+        self.connection = Some(conn);
+
+        if res.is_ready() {
+            self.send_abandoned.set(false);
+            *self.io_lock.get_mut() = false;
+        }
+        res
+    }
+}
+
+#[cfg(feature = "async-core")]
 impl<Connect: AsyncConnection + Unpin> AsyncDisplay for CellDisplay<Connect> {
     #[inline]
     fn poll_wait(&mut self, ctx: &mut Context<'_>) -> Poll<crate::Result> {
+        if self.poll_finish_abandoned(ctx).is_pending() {
+            return Poll::Pending;
+        }
+
         let mut conn = self.connection.take().expect("Poisoned!");
         let wait_buffer = match self.wait_buffer.get_mut() {
             Some(wait_buffer) => wait_buffer,
@@ -378,8 +432,12 @@ impl<Connect: AsyncConnection + Unpin> AsyncDisplay for CellDisplay<Connect> {
     fn begin_send_request_raw(
         &mut self,
         req: RequestInfo,
-        _cx: &mut Context<'_>,
+        cx: &mut Context<'_>,
     ) -> PollOr<(), RequestInfo> {
+        if self.poll_finish_abandoned(cx).is_pending() {
+            return PollOr::Pending(req);
+        }
+
         if self.try_lock_internal() {
             self.send_buffer.get_mut().fill_hole(req);
             PollOr::Ready(())
@@ -401,11 +459,51 @@ impl<Connect: AsyncConnection + Unpin> AsyncDisplay for CellDisplay<Connect> {
             *self.io_lock.get_mut() = false;
         }
         match res {
-            Poll::Ready(Ok(pr)) => Poll::Ready(Ok(output::finish_request(self, pr))),
+            Poll::Ready(Ok(pr)) => Poll::Ready(Ok(output::finish_request(pr))),
             Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
             Poll::Pending => Poll::Pending,
         }
     }
+
+    #[inline]
+    fn abandon_send_request_raw(&mut self) {
+        if self.send_buffer.get_mut().abandon() {
+            self.send_abandoned.set(true);
+        } else {
+            *self.io_lock.get_mut() = false;
+        }
+    }
+}
+
+/// If a request was abandoned partway through being sent, poll to finish sending it and release the io lock.
+#[cfg(feature = "async-core")]
+#[inline]
+fn poll_finish_abandoned_immutable<'a, Connect>(
+    display: &mut &'a CellDisplay<Connect>,
+    cx: &mut Context<'_>,
+) -> Poll<()>
+where
+    &'a Connect: AsyncConnection + Unpin,
+{
+    if !display.send_abandoned.get() {
+        return Poll::Ready(());
+    }
+
+    let this = *display;
+    let mut sbslot = this.send_buffer.borrow_mut();
+    let mut send_buffer = mem::replace(&mut *sbslot, SendBuffer::OccupiedHole);
+    let res = send_buffer.poll_finish_abandoned(
+        display,
+        &mut this.connection.as_ref().expect("Poisoned!"),
+        cx,
+    );
+    *sbslot = send_buffer;
+
+    if res.is_ready() {
+        this.send_abandoned.set(false);
+        this.io_lock.set(false);
+    }
+    res
 }
 
 impl<'a, Conn> DisplayBase for &'a CellDisplay<Conn> {
@@ -435,13 +533,22 @@ impl<'a, Conn> DisplayBase for &'a CellDisplay<Conn> {
         self.inner.borrow_mut().event_queue.pop_front()
     }
     #[inline]
+    fn pop_event_if(&mut self, predicate: &mut dyn FnMut(&Event) -> bool) -> Option<Event> {
+        let mut inner = self.inner.borrow_mut();
+        if predicate(inner.event_queue.front()?) {
+            inner.event_queue.pop_front()
+        } else {
+            None
+        }
+    }
+    #[inline]
     fn generate_xid(&mut self) -> Option<XID> {
         self.xid.next_xid()
     }
     #[inline]
     fn add_pending_item(&mut self, req_id: u16, item: PendingItem) {
         let mut inner = self.inner.borrow_mut();
-        #[cfg(feature = "async")]
+        #[cfg(feature = "async-core")]
         {
             if let PendingItem::Request(ref pereq) = item {
                 if matches!(pereq.flags.workaround, RequestWorkaround::GlxFbconfigBug) {
@@ -458,7 +565,7 @@ impl<'a, Conn> DisplayBase for &'a CellDisplay<Conn> {
     #[inline]
     fn take_pending_item(&mut self, req_id: u16) -> Option<PendingItem> {
         let mut inner = self.inner.borrow_mut();
-        #[cfg(feature = "async")]
+        #[cfg(feature = "async-core")]
         inner.workarounders.retain(|&r| r != req_id);
         inner.pending_items.remove(&req_id)
     }
@@ -539,6 +646,16 @@ where
         res
     }
 
+    #[inline]
+    fn try_wait(&mut self) -> crate::Result<bool> {
+        self.lock_internal_immutable();
+
+        let res = input::try_wait(self, &mut self.connection.as_ref().expect("Poisoned!"));
+
+        self.io_lock.set(false);
+        res
+    }
+
     #[inline]
     fn send_request_raw(&mut self, req: RequestInfo) -> crate::Result<u16> {
         self.lock_internal_immutable();
@@ -551,13 +668,17 @@ where
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 impl<'a, Connect> AsyncDisplay for &'a CellDisplay<Connect>
 where
     &'a Connect: AsyncConnection + Unpin,
 {
     #[inline]
     fn poll_wait(&mut self, ctx: &mut Context<'_>) -> Poll<crate::Result> {
+        if poll_finish_abandoned_immutable(self, ctx).is_pending() {
+            return Poll::Pending;
+        }
+
         let data = self.inner.borrow_mut();
         let mut wait_buffer = self.wait_buffer.borrow_mut();
         let workarounders = &data.workarounders;
@@ -591,8 +712,12 @@ where
     fn begin_send_request_raw(
         &mut self,
         req: RequestInfo,
-        _cx: &mut Context<'_>,
+        cx: &mut Context<'_>,
     ) -> PollOr<(), RequestInfo> {
+        if poll_finish_abandoned_immutable(self, cx).is_pending() {
+            return PollOr::Pending(req);
+        }
+
         if self.try_lock_internal_immutable() {
             self.send_buffer.borrow_mut().fill_hole(req);
             PollOr::Ready(())
@@ -616,9 +741,18 @@ where
             self.io_lock.set(false);
         }
         match res {
-            Poll::Ready(Ok(pr)) => Poll::Ready(Ok(output::finish_request(self, pr))),
+            Poll::Ready(Ok(pr)) => Poll::Ready(Ok(output::finish_request(pr))),
             Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
             Poll::Pending => Poll::Pending,
         }
     }
+
+    #[inline]
+    fn abandon_send_request_raw(&mut self) {
+        if self.send_buffer.borrow_mut().abandon() {
+            self.send_abandoned.set(true);
+        } else {
+            self.io_lock.set(false);
+        }
+    }
 }
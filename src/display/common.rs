@@ -171,6 +171,49 @@ impl SendBuffer {
         *self = SendBuffer::Hole;
     }
 
+    /// Called when the future driving this `SendBuffer` is dropped before it completes. A request that hasn't
+    /// been assigned a sequence number yet is simply discarded. Otherwise, part of it may already be on the wire,
+    /// so it has to be finished using `poll_finish_abandoned`. Returns whether or not that is the case.
+    #[inline]
+    pub(crate) fn abandon(&mut self) -> bool {
+        match self {
+            SendBuffer::Hole => false,
+            SendBuffer::Uninit(..) => {
+                self.dig_hole();
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Poll to finish sending a request whose future was dropped. Since there is no one left to report an error
+    /// to, errors are logged instead.
+    #[inline]
+    pub(crate) fn poll_finish_abandoned<
+        D: AsyncDisplay + ?Sized,
+        C: AsyncConnection + Unpin + ?Sized,
+    >(
+        &mut self,
+        display: &mut D,
+        conn: &mut C,
+        cx: &mut Context<'_>,
+    ) -> Poll<()> {
+        let res = match self.poll_send_request(display, conn, cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(res) => res,
+        };
+
+        self.dig_hole();
+        match res {
+            Ok(pr) => {
+                let seq = output::finish_request(pr);
+                log::debug!("Finished sending abandoned request with sequence {}", seq);
+            }
+            Err(e) => log::error!("Failed to finish sending abandoned request: {:?}", e),
+        }
+        Poll::Ready(())
+    }
+
     /// Poll for the creation of a new `SendBuffer`, given the `Display` one wants to create
     /// it with.
     #[inline]
@@ -199,10 +242,11 @@ impl SendBuffer {
                 // we are currently polling for requesting the extension opcode
                 SendBuffer::PollingForExt(req, mut sb) => match sb.poll_send_request(conn, cx) {
                     Poll::Ready(Ok(pereq)) => {
-                        let req_id = output::finish_request(display, pereq);
+                        let req_id = output::finish_request(pereq);
                         *self = SendBuffer::WaitingForExt(req, req_id, None);
                     }
                     Poll::Ready(Err(e)) => {
+                        output::abort_request(display, &sb.request);
                         self.dig_hole();
                         return Poll::Ready(Err(e));
                     }
@@ -339,6 +383,9 @@ impl SendBuffer {
             match mem::replace(self, Self::Hole) {
                 SendBuffer::Init(mut isb) => {
                     let res = isb.poll_send_request(conn, context);
+                    if let Poll::Ready(Err(_)) = res {
+                        output::abort_request(display, &isb.request);
+                    }
                     *self = SendBuffer::Init(isb);
                     return res;
                 }
@@ -449,8 +496,10 @@ impl InnerSendBuffer {
 impl Drop for InnerSendBuffer {
     #[inline]
     fn drop(&mut self) {
-        if self.request.data.len() != 0 {
-            panic!("Interrupted send future while it was mid-transition!");
+        // this can only happen if the display itself is dropped mid-send, since abandoned requests are finished
+        // by the display
+        if !self.request.data.is_empty() {
+            log::warn!("Dropped a request that was only partially sent");
         }
     }
 }
@@ -7,7 +7,7 @@ use crate::{
 };
 use core::task::{Context, Poll};
 
-#[cfg(all(feature = "std", unix))]
+#[cfg(all(any(feature = "async-io-support", feature = "tokio-support"), unix))]
 use super::unix;
 use crate::Fd;
 use alloc::vec::Vec;
@@ -20,11 +20,11 @@ use futures_lite::{AsyncRead, AsyncWrite};
 #[cfg(all(feature = "std", not(unix)))]
 use std::io;
 
-#[cfg(feature = "std")]
+#[cfg(feature = "async-io-support")]
 use async_io::Async;
-#[cfg(feature = "std")]
+#[cfg(feature = "async-io-support")]
 use std::net::TcpStream;
-#[cfg(all(feature = "std", unix))]
+#[cfg(all(feature = "async-io-support", unix))]
 use std::os::unix::net::UnixStream;
 
 #[cfg(not(unix))]
@@ -123,6 +123,7 @@ impl<C: AsyncConnection + ?Sized> AsyncConnectionExt for C {
     }
 }
 
+#[cfg(any(feature = "async-io-support", all(feature = "tokio-support", unix)))]
 macro_rules! unix_aware_async_connection_impl {
     ($name: ty) => {
         impl AsyncConnection for $name {
@@ -201,13 +202,13 @@ macro_rules! unix_aware_async_connection_impl {
 //       However, neither implement AsyncRead or AsyncWrite for immutable access. The underlying
 //       "Async" primitive, however, does.
 
-#[cfg(feature = "std")]
+#[cfg(feature = "async-io-support")]
 unix_aware_async_connection_impl! { Async<TcpStream> }
-#[cfg(all(feature = "std", unix))]
+#[cfg(all(feature = "async-io-support", unix))]
 unix_aware_async_connection_impl! { Async<UnixStream> }
-#[cfg(feature = "std")]
+#[cfg(feature = "async-io-support")]
 unix_aware_async_connection_impl! { &Async<TcpStream> }
-#[cfg(all(feature = "std", unix))]
+#[cfg(all(feature = "async-io-support", unix))]
 unix_aware_async_connection_impl! { &Async<UnixStream> }
 #[cfg(all(feature = "tokio-support", unix))]
 unix_aware_async_connection_impl! { tokio::net::UnixStream }
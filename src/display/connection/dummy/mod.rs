@@ -8,7 +8,7 @@ mod setup;
 use super::Connection;
 use crate::{
     auto::{
-        xproto::{Setup, SetupRequest},
+        xproto::{QueryExtensionReply, Setup, SetupRequest},
         AsByteSequence,
     },
     display::{BasicDisplay, StaticSetup},
@@ -18,6 +18,11 @@ use alloc::{borrow::Cow, collections::VecDeque, vec::Vec};
 use core::iter;
 use tinyvec::TinyVec;
 
+#[cfg(feature = "async-core")]
+use super::AsyncConnection;
+#[cfg(feature = "async-core")]
+use core::task::{Context, Poll};
+
 /// An imitation connection to a fake X11 server.
 ///
 /// In order to run doctests, we need to instantiate a connection to the X11 server. Unfortunately, this tends to
@@ -32,12 +37,17 @@ use tinyvec::TinyVec;
 ///
 /// By using this, the only thing left to chance are the actual implementations of the connections themselves,
 /// but those can't actually be tested without an X server.
+///
+/// When used as an `AsyncConnection`, the number of bytes written per poll can be limited in order to simulate
+/// a connection that isn't always writable.
 #[derive(Debug)]
 pub struct DummyConnection {
     /// Bytes that we expect to see from the server.
     expected: VecDeque<u8>,
     /// Bytes that we are sending from the server.
     reply: VecDeque<u8>,
+    /// The maximum number of bytes we accept per poll, if any.
+    write_limit: Option<usize>,
 }
 
 impl DummyConnection {
@@ -47,9 +57,23 @@ impl DummyConnection {
         Self {
             expected: VecDeque::new(),
             reply: VecDeque::new(),
+            write_limit: None,
         }
     }
 
+    /// Only accept up to `limit` bytes every time a packet is sent asynchronously, and return `Poll::Pending`
+    /// if there are more.
+    #[inline]
+    pub fn limit_writes(&mut self, limit: usize) {
+        self.write_limit = Some(limit);
+    }
+
+    /// Whether or not every byte we expected to see has been sent.
+    #[inline]
+    pub fn received_all_expected(&self) -> bool {
+        self.expected.is_empty()
+    }
+
     /// Expect to see a certain sequence of bytes.
     #[inline]
     pub fn expects_bytes<I: IntoIterator<Item = u8>>(&mut self, bytes: I) {
@@ -62,6 +86,12 @@ impl DummyConnection {
         self.reply.extend(bytes);
     }
 
+    /// Take the bytes that we would reply with, e.g. in order to write them into a real socket instead.
+    #[inline]
+    pub fn take_reply_bytes(&mut self) -> Vec<u8> {
+        self.reply.drain(..).collect()
+    }
+
     /// Expect to see a certain object.
     #[inline]
     pub fn expects<O: AsByteSequence>(&mut self, object: O) {
@@ -95,8 +125,24 @@ impl DummyConnection {
             authorization_protocol_data: Cow::Borrowed(&[]),
         });
         this.reply_bytes(default_setup());
+        // the display will try to enable BIG-REQUESTS, tell it that the extension isn't there
+        this.reply_padded(QueryExtensionReply {
+            reply_type: 1,
+            sequence: 1,
+            present: false,
+            ..Default::default()
+        });
         this
     }
+
+    /// Reply with a certain object, padding it to the 32 bytes that every reply, event and error takes up at
+    /// minimum.
+    #[inline]
+    pub fn reply_padded<O: AsByteSequence>(&mut self, object: O) {
+        let mut data: TinyVec<[u8; 32]> = iter::repeat(0).take(object.size().max(32)).collect();
+        object.as_bytes(&mut data);
+        self.reply_bytes(data)
+    }
 }
 
 impl Connection for DummyConnection {
@@ -123,6 +169,50 @@ impl Connection for DummyConnection {
 
         Ok(())
     }
+
+    #[inline]
+    fn readable(&mut self) -> crate::Result<bool> {
+        Ok(!self.reply.is_empty())
+    }
+}
+
+#[cfg(feature = "async-core")]
+impl AsyncConnection for DummyConnection {
+    #[inline]
+    fn poll_send_packet(
+        &mut self,
+        bytes: &[u8],
+        fds: &mut Vec<Fd>,
+        cx: &mut Context<'_>,
+        bytes_written: &mut usize,
+    ) -> Poll<crate::Result> {
+        let len = match self.write_limit {
+            Some(limit) => limit.min(bytes.len()),
+            None => bytes.len(),
+        };
+        self.send_packet(&bytes[..len], fds)?;
+        *bytes_written += len;
+
+        if len < bytes.len() {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[inline]
+    fn poll_read_packet(
+        &mut self,
+        bytes: &mut [u8],
+        fds: &mut Vec<Fd>,
+        _cx: &mut Context<'_>,
+        bytes_read: &mut usize,
+    ) -> Poll<crate::Result> {
+        self.read_packet(bytes, fds)?;
+        *bytes_read += bytes.len();
+        Poll::Ready(Ok(()))
+    }
 }
 
 impl BasicDisplay<DummyConnection> {
@@ -139,3 +229,9 @@ fn default_setup() -> Vec<u8> {
     // close to the default setup on my system
     setup::default_setup()
 }
+
+#[test]
+fn dummy_display() {
+    let display = BasicDisplay::dummy();
+    assert!(!display.bigreq_enabled);
+}
@@ -3,22 +3,30 @@
 #[cfg(all(feature = "std", unix))]
 pub(crate) mod unix;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 mod async_connection;
 mod sync;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 mod async_establish;
+#[cfg(feature = "async-core")]
+mod stream;
+#[cfg(all(feature = "tokio-support", unix))]
+mod tokio_connection;
 mod establish;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub use async_establish::EstablishConnectionFuture;
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub(crate) use establish::create_setup;
 pub(crate) use establish::establish_connection;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub use async_connection::*;
+#[cfg(feature = "async-core")]
+pub use stream::{FdHook, NoFdHook, StreamConnection};
+#[cfg(all(feature = "tokio-support", unix))]
+pub use tokio_connection::TokioConnection;
 pub use sync::*;
 
 #[cfg(not(unix))]
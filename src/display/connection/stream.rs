@@ -0,0 +1,317 @@
+// MIT/Apache2 License
+
+use super::AsyncConnection;
+use crate::Fd;
+use alloc::vec::Vec;
+use core::{
+    mem,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures_lite::{AsyncRead, AsyncWrite};
+use std::io;
+
+/// A hook used by [`StreamConnection`] to send and receive file descriptors alongside the bytes of the stream.
+///
+/// Generic async streams have no notion of file descriptor passing, so an implementor of this trait can be used
+/// to provide it, e.g. by sharing the underlying socket with the stream and using `SCM_RIGHTS` on Unix.
+pub trait FdHook {
+    /// Called before the bytes of a packet are written to the stream, with the file descriptors that should be
+    /// sent alongside them.
+    fn send_fds(&mut self, fds: Vec<Fd>) -> crate::Result;
+
+    /// Called after bytes have been read from the stream, in order to collect any file descriptors that were
+    /// received alongside them.
+    fn receive_fds(&mut self, fds: &mut Vec<Fd>) -> crate::Result;
+}
+
+/// The default `FdHook`, which does not support passing file descriptors.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct NoFdHook;
+
+impl FdHook for NoFdHook {
+    #[inline]
+    fn send_fds(&mut self, fds: Vec<Fd>) -> crate::Result {
+        if !fds.is_empty() {
+            log::warn!("Cannot pass file descriptors over a stream without an FdHook.");
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn receive_fds(&mut self, _fds: &mut Vec<Fd>) -> crate::Result {
+        Ok(())
+    }
+}
+
+/// A connection to the X server over any `AsyncRead + AsyncWrite` stream.
+///
+/// This does not depend on any particular async runtime, so it can be used to connect to the X server on
+/// executors that `breadx` does not natively support. File descriptor passing is delegated to an optional
+/// [`FdHook`].
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use breadx::display::{BasicDisplay, StreamConnection};
+/// use futures_lite::{AsyncRead, AsyncWrite};
+///
+/// async fn connect<S: AsyncRead + AsyncWrite + Unpin>(stream: S) -> breadx::Result {
+///     let conn = BasicDisplay::from_connection_async(StreamConnection::new(stream), 0, None).await?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct StreamConnection<S, H = NoFdHook> {
+    stream: S,
+    fd_hook: H,
+}
+
+impl<S> StreamConnection<S> {
+    /// Create a new `StreamConnection` that cannot pass file descriptors.
+    #[inline]
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            fd_hook: NoFdHook,
+        }
+    }
+}
+
+impl<S, H> StreamConnection<S, H> {
+    /// Create a new `StreamConnection` that passes file descriptors using the given hook.
+    #[inline]
+    pub fn with_fd_hook(stream: S, fd_hook: H) -> Self {
+        Self { stream, fd_hook }
+    }
+
+    /// Get a reference to the inner stream.
+    #[inline]
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Get a mutable reference to the inner stream.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consume this connection and return the inner stream and file descriptor hook.
+    #[inline]
+    pub fn into_inner(self) -> (S, H) {
+        (self.stream, self.fd_hook)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin, H: FdHook> AsyncConnection for StreamConnection<S, H> {
+    #[inline]
+    fn poll_send_packet(
+        &mut self,
+        bytes: &[u8],
+        fds: &mut Vec<Fd>,
+        cx: &mut Context<'_>,
+        bytes_written: &mut usize,
+    ) -> Poll<crate::Result> {
+        if !fds.is_empty() {
+            self.fd_hook.send_fds(mem::take(fds))?;
+        }
+
+        let mut bytes = bytes;
+        while !bytes.is_empty() {
+            match Pin::new(&mut self.stream).poll_write(cx, bytes) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e.into())),
+                Poll::Ready(Ok(0)) => {
+                    let err: io::Error = io::ErrorKind::WriteZero.into();
+                    return Poll::Ready(Err(err.into()));
+                }
+                Poll::Ready(Ok(n)) => {
+                    bytes = &bytes[n..];
+                    *bytes_written += n;
+                }
+            }
+        }
+
+        Pin::new(&mut self.stream)
+            .poll_flush(cx)
+            .map_err(Into::into)
+    }
+
+    #[inline]
+    fn poll_read_packet(
+        &mut self,
+        bytes: &mut [u8],
+        fds: &mut Vec<Fd>,
+        cx: &mut Context<'_>,
+        bytes_read: &mut usize,
+    ) -> Poll<crate::Result> {
+        let mut bytes = bytes;
+        while !bytes.is_empty() {
+            match Pin::new(&mut self.stream).poll_read(cx, bytes) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e.into())),
+                Poll::Ready(Ok(0)) => {
+                    let err: io::Error = io::ErrorKind::UnexpectedEof.into();
+                    return Poll::Ready(Err(err.into()));
+                }
+                Poll::Ready(Ok(n)) => {
+                    bytes = &mut bytes[n..];
+                    *bytes_read += n;
+                    self.fd_hook.receive_fds(fds)?;
+                }
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// An in-memory duplex stream, which reads from a fixed buffer and writes into another one.
+#[cfg(test)]
+#[derive(Debug, Default)]
+struct MemoryStream {
+    input: futures_lite::io::Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+#[cfg(test)]
+impl AsyncRead for MemoryStream {
+    #[inline]
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.input).poll_read(cx, buf)
+    }
+}
+
+#[cfg(test)]
+impl AsyncWrite for MemoryStream {
+    #[inline]
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        // only take part of the buffer, in order to exercise partial writes
+        let len = buf.len().min(16);
+        self.output.extend_from_slice(&buf[..len]);
+        Poll::Ready(Ok(len))
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    #[inline]
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// An `FdHook` that records the file descriptors it sends, and hands out a set of file descriptors to receive.
+#[cfg(test)]
+#[derive(Debug, Default)]
+struct RecordingFdHook {
+    sent: Vec<Fd>,
+    received: Vec<Fd>,
+}
+
+#[cfg(test)]
+impl FdHook for RecordingFdHook {
+    #[inline]
+    fn send_fds(&mut self, fds: Vec<Fd>) -> crate::Result {
+        self.sent.extend(fds);
+        Ok(())
+    }
+
+    #[inline]
+    fn receive_fds(&mut self, fds: &mut Vec<Fd>) -> crate::Result {
+        fds.append(&mut self.received);
+        Ok(())
+    }
+}
+
+#[test]
+fn stream_connection_establishes_display() {
+    use crate::{
+        auto::xproto::{GetInputFocusReply, GetInputFocusRequest, InputFocus, Window},
+        display::{connection::dummy::DummyConnection, AsyncDisplayExt, BasicDisplay, DisplayBase},
+    };
+    use futures_lite::future;
+
+    let mut script = DummyConnection::new();
+    script.reply_padded(GetInputFocusReply {
+        reply_type: 1,
+        revert_to: InputFocus::Parent,
+        sequence: 2,
+        length: 0,
+        focus: Window::const_from_xid(0x1234),
+    });
+    let stream = MemoryStream {
+        input: futures_lite::io::Cursor::new(script.take_reply_bytes()),
+        output: Vec::new(),
+    };
+
+    let mut display = future::block_on(BasicDisplay::from_connection_async(
+        StreamConnection::new(stream),
+        0,
+        None,
+    ))
+    .unwrap();
+    display.set_checked(false);
+    let focus =
+        future::block_on(display.exchange_request_async(GetInputFocusRequest::default())).unwrap();
+    assert_eq!(focus.focus, Window::const_from_xid(0x1234));
+
+    // the setup request, the BIG-REQUESTS query, and the GetInputFocus request
+    let output = &display.connection().get_ref().output;
+    assert_eq!(output.len(), 12 + 20 + 4);
+    assert_eq!(output[32], 43);
+
+    // the server hanging up is an error rather than a hang
+    assert!(
+        future::block_on(display.exchange_request_async(GetInputFocusRequest::default())).is_err()
+    );
+}
+
+#[test]
+fn stream_connection_passes_fds_to_hook() {
+    use futures_lite::future;
+
+    let stream = MemoryStream {
+        input: futures_lite::io::Cursor::new(alloc::vec![1, 2, 3, 4]),
+        output: Vec::new(),
+    };
+    let hook = RecordingFdHook {
+        sent: Vec::new(),
+        received: alloc::vec![5],
+    };
+    let mut conn = StreamConnection::with_fd_hook(stream, hook);
+
+    let bytes = [0xAB; 40];
+    let mut fds = alloc::vec![3, 4];
+    let mut written = 0;
+    future::block_on(future::poll_fn(|cx| {
+        conn.poll_send_packet(&bytes, &mut fds, cx, &mut written)
+    }))
+    .unwrap();
+    assert_eq!(written, 40);
+    assert!(fds.is_empty());
+
+    let mut buf = [0; 4];
+    let mut read = 0;
+    future::block_on(future::poll_fn(|cx| {
+        conn.poll_read_packet(&mut buf, &mut fds, cx, &mut read)
+    }))
+    .unwrap();
+    assert_eq!(buf, [1, 2, 3, 4]);
+    assert_eq!(fds, [5]);
+
+    let (stream, hook) = conn.into_inner();
+    assert_eq!(stream.output, bytes);
+    assert_eq!(hook.sent, [3, 4]);
+}
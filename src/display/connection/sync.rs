@@ -14,7 +14,7 @@ use super::standard_fd_warning;
 use std::io::{Read, Write};
 
 #[cfg(feature = "std")]
-use std::net::TcpStream;
+use std::net::{Shutdown, TcpStream};
 #[cfg(all(feature = "std", unix))]
 use std::os::unix::{io::AsRawFd, net::UnixStream};
 
@@ -24,6 +24,19 @@ pub trait Connection {
     fn send_packet(&mut self, bytes: &[u8], fds: &mut Vec<Fd>) -> crate::Result;
     /// Read a packet from the connection in a blocking manner.
     fn read_packet(&mut self, bytes: &mut [u8], fds: &mut Vec<Fd>) -> crate::Result;
+    /// Tell whether there is data waiting to be read, so that `read_packet` can be called without blocking. By
+    /// default, this assumes there is nothing to read.
+    #[inline]
+    fn readable(&mut self) -> crate::Result<bool> {
+        Ok(false)
+    }
+    /// Shut down the reading half of the connection, so that any blocked or future calls to `read_packet` return
+    /// immediately. This is used to stop the reader thread of a split `SyncDisplay`. By default, this does
+    /// nothing.
+    #[inline]
+    fn shutdown_read(&mut self) -> crate::Result {
+        Ok(())
+    }
     /// Establish a setup using this connection.
     #[inline]
     fn establish(
@@ -44,6 +57,16 @@ impl<C: Connection + ?Sized> Connection for &mut C {
     fn read_packet(&mut self, bytes: &mut [u8], fds: &mut Vec<Fd>) -> crate::Result {
         (**self).read_packet(bytes, fds)
     }
+
+    #[inline]
+    fn readable(&mut self) -> crate::Result<bool> {
+        (**self).readable()
+    }
+
+    #[inline]
+    fn shutdown_read(&mut self) -> crate::Result {
+        (**self).shutdown_read()
+    }
 }
 
 // Implement Connection on TcpStream and UnixStream
@@ -80,6 +103,31 @@ macro_rules! unix_aware_connection_impl {
                     }
                 }
             }
+
+            #[inline]
+            fn readable(&mut self) -> crate::Result<bool> {
+                cfg_if::cfg_if! {
+                    if #[cfg(unix)] {
+                        unix::readable_unix(self.as_raw_fd())
+                    } else {
+                        // peek at the stream without blocking
+                        self.set_nonblocking(true)?;
+                        let res = self.peek(&mut [0]);
+                        self.set_nonblocking(false)?;
+                        match res {
+                            Ok(n) => Ok(n > 0),
+                            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(false),
+                            Err(e) => Err(e.into()),
+                        }
+                    }
+                }
+            }
+
+            #[inline]
+            fn shutdown_read(&mut self) -> crate::Result {
+                self.shutdown(Shutdown::Read)?;
+                Ok(())
+            }
         }
     };
 }
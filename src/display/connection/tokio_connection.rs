@@ -0,0 +1,345 @@
+// MIT/Apache2 License
+
+//! A connection that is driven directly by `tokio`'s reactor.
+
+#![cfg(all(feature = "tokio-support", unix))]
+
+use super::{unix, AsyncConnection};
+use crate::Fd;
+use alloc::vec::Vec;
+use core::task::{Context, Poll};
+use futures_lite::ready;
+use std::{
+    io,
+    net::TcpStream,
+    os::unix::{
+        io::{AsRawFd, RawFd},
+        net::UnixStream,
+    },
+};
+use tokio::io::unix::AsyncFd;
+
+/// A connection to the X11 server that is registered directly with `tokio`'s reactor.
+///
+/// Unlike the `AsyncConnection` implementations for `tokio::net::TcpStream` and `tokio::net::UnixStream`, this
+/// connection wraps the socket in an [`AsyncFd`] and performs its I/O using `sendmsg` and `recvmsg`. This means
+/// that file descriptors can be sent and received along with requests and replies (which extensions like `DRI3`
+/// and `MIT-SHM` rely on), and that readiness is properly cleared when the socket would block.
+///
+/// Both `TokioConnection` and `&TokioConnection` implement `AsyncConnection`, so it can be shared between tasks
+/// without being wrapped in a lock. It is `Send` and `Sync`, so a display built around it can be moved into
+/// `tokio::spawn`. Since the socket is registered with the current runtime when it is created, it must be
+/// created from within a `tokio` runtime.
+///
+/// ## Construction
+///
+/// Most users will want to use the [`TokioDisplayConnection`](crate::display::TokioDisplayConnection) type
+/// alias, which is a `BasicDisplay` around this connection.
+///
+/// ```rust,no_run
+/// use breadx::display::TokioDisplayConnection;
+///
+/// # async fn f() -> breadx::Result {
+/// let conn = TokioDisplayConnection::create_tokio(None, None).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TokioConnection {
+    inner: AsyncFd<TokioSocket>,
+}
+
+/// The sockets that a `TokioConnection` can be built on.
+#[derive(Debug)]
+enum TokioSocket {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl AsRawFd for TokioSocket {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            TokioSocket::Tcp(t) => t.as_raw_fd(),
+            TokioSocket::Unix(u) => u.as_raw_fd(),
+        }
+    }
+}
+
+impl TokioConnection {
+    #[inline]
+    fn new(socket: TokioSocket) -> io::Result<Self> {
+        // AsyncFd::new is deprecated in newer versions of tokio, since it can't guarantee that the file descriptor
+        // stays valid for as long as it's registered. Since TokioSocket owns its stream and never replaces it,
+        // the file descriptor is valid until the AsyncFd is dropped.
+        #[allow(deprecated)]
+        let inner = AsyncFd::new(socket)?;
+        Ok(Self { inner })
+    }
+
+    /// Create a new `TokioConnection` from a TCP stream. The stream is put into non-blocking mode.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stream cannot be put into non-blocking mode, or if it cannot be registered with
+    /// the current `tokio` runtime.
+    #[inline]
+    pub fn from_tcp(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Self::new(TokioSocket::Tcp(stream))
+    }
+
+    /// Create a new `TokioConnection` from a Unix socket stream. The stream is put into non-blocking mode.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stream cannot be put into non-blocking mode, or if it cannot be registered with
+    /// the current `tokio` runtime.
+    #[inline]
+    pub fn from_unix(stream: UnixStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Self::new(TokioSocket::Unix(stream))
+    }
+
+    /// Send a packet across the connection, waiting for the socket to become writable as needed.
+    #[inline]
+    fn poll_send(
+        &self,
+        mut bytes: &[u8],
+        fds: &mut Vec<Fd>,
+        cx: &mut Context<'_>,
+        bytes_written: &mut usize,
+    ) -> Poll<crate::Result> {
+        loop {
+            let mut guard = ready!(self.inner.poll_write_ready(cx))?;
+            let mut sent = 0;
+            let res = guard.try_io(|inner| {
+                let (offset, res) = unix::send_msg_packet(inner.as_raw_fd(), bytes, fds);
+                sent = offset;
+                res
+            });
+
+            *bytes_written += sent;
+            bytes = &bytes[sent..];
+
+            match res {
+                Ok(res) => return Poll::Ready(res.map_err(Into::into)),
+                // readiness has been cleared, poll again
+                Err(_would_block) => {}
+            }
+        }
+    }
+
+    /// Read a packet from the connection, waiting for the socket to become readable as needed.
+    #[inline]
+    fn poll_read(
+        &self,
+        mut bytes: &mut [u8],
+        fds: &mut Vec<Fd>,
+        cx: &mut Context<'_>,
+        bytes_read: &mut usize,
+    ) -> Poll<crate::Result> {
+        loop {
+            let mut guard = ready!(self.inner.poll_read_ready(cx))?;
+            let mut read = 0;
+            let res = guard.try_io(|inner| {
+                unix::read_msg_packet(inner.as_raw_fd(), &mut *bytes, fds, &mut read)
+            });
+
+            *bytes_read += read;
+            bytes = &mut bytes[read..];
+
+            match res {
+                Ok(res) => return Poll::Ready(res.map_err(Into::into)),
+                // readiness has been cleared, poll again
+                Err(_would_block) => {}
+            }
+        }
+    }
+}
+
+impl AsyncConnection for TokioConnection {
+    #[inline]
+    fn poll_send_packet(
+        &mut self,
+        bytes: &[u8],
+        fds: &mut Vec<Fd>,
+        cx: &mut Context<'_>,
+        bytes_written: &mut usize,
+    ) -> Poll<crate::Result> {
+        self.poll_send(bytes, fds, cx, bytes_written)
+    }
+
+    #[inline]
+    fn poll_read_packet(
+        &mut self,
+        bytes: &mut [u8],
+        fds: &mut Vec<Fd>,
+        cx: &mut Context<'_>,
+        bytes_read: &mut usize,
+    ) -> Poll<crate::Result> {
+        self.poll_read(bytes, fds, cx, bytes_read)
+    }
+}
+
+impl AsyncConnection for &TokioConnection {
+    #[inline]
+    fn poll_send_packet(
+        &mut self,
+        bytes: &[u8],
+        fds: &mut Vec<Fd>,
+        cx: &mut Context<'_>,
+        bytes_written: &mut usize,
+    ) -> Poll<crate::Result> {
+        self.poll_send(bytes, fds, cx, bytes_written)
+    }
+
+    #[inline]
+    fn poll_read_packet(
+        &mut self,
+        bytes: &mut [u8],
+        fds: &mut Vec<Fd>,
+        cx: &mut Context<'_>,
+        bytes_read: &mut usize,
+    ) -> Poll<crate::Result> {
+        self.poll_read(bytes, fds, cx, bytes_read)
+    }
+}
+
+#[cfg(test)]
+fn tokio_pair() -> (TokioConnection, TokioConnection) {
+    let (a, b) = tokio::net::UnixStream::pair().unwrap();
+    (
+        TokioConnection::from_unix(a.into_std().unwrap()).unwrap(),
+        TokioConnection::from_unix(b.into_std().unwrap()).unwrap(),
+    )
+}
+
+#[cfg(test)]
+fn block_on_tokio<F: core::future::Future>(f: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()
+        .unwrap()
+        .block_on(f)
+}
+
+#[cfg(test)]
+async fn read_exact(conn: &TokioConnection, bytes: &mut [u8], fds: &mut Vec<Fd>) -> crate::Result {
+    let mut offset = 0;
+    futures_lite::future::poll_fn(|cx| {
+        while offset < bytes.len() {
+            let mut read = 0;
+            let res = conn.poll_read(&mut bytes[offset..], fds, cx, &mut read);
+            offset += read;
+            ready!(res)?;
+        }
+        Poll::Ready(Ok(()))
+    })
+    .await
+}
+
+#[test]
+fn tokio_connection_round_trip() {
+    block_on_tokio(async {
+        let (mut client, mut server) = tokio_pair();
+
+        let request: Vec<u8> = (0..32).collect();
+        let mut written = 0;
+        futures_lite::future::poll_fn(|cx| {
+            client.poll_send_packet(&request, &mut Vec::new(), cx, &mut written)
+        })
+        .await
+        .unwrap();
+        assert_eq!(written, 32);
+
+        let mut buf = [0; 32];
+        let mut read = 0;
+        futures_lite::future::poll_fn(|cx| {
+            server.poll_read_packet(&mut buf, &mut Vec::new(), cx, &mut read)
+        })
+        .await
+        .unwrap();
+        assert_eq!(read, 32);
+        assert_eq!(&buf[..], &request[..]);
+
+        // and back the other way, through the shared references
+        let reply = [0xAB; 8];
+        futures_lite::future::poll_fn(|cx| {
+            (&server).poll_send_packet(&reply, &mut Vec::new(), cx, &mut 0)
+        })
+        .await
+        .unwrap();
+        let mut buf = [0; 8];
+        read_exact(&client, &mut buf, &mut Vec::new())
+            .await
+            .unwrap();
+        assert_eq!(buf, reply);
+    });
+}
+
+#[test]
+fn tokio_connection_resumes_partial_writes() {
+    use futures_lite::future;
+
+    block_on_tokio(async {
+        let (client, server) = tokio_pair();
+
+        // far more than the socket buffer can hold, so sending has to stop partway through
+        let bytes: Vec<u8> = (0..251).cycle().take(4 * 1024 * 1024).collect();
+        let mut sent = 0;
+        let mut sending = future::poll_fn(|cx| {
+            let mut written = 0;
+            let res = client.poll_send(&bytes[sent..], &mut Vec::new(), cx, &mut written);
+            sent += written;
+            res
+        });
+
+        // nobody is reading yet, so the socket blocks after a partial write
+        assert!(future::poll_once(&mut sending).await.is_none());
+
+        let mut received = alloc::vec![0; bytes.len()];
+        let mut fds = Vec::new();
+        let (sent_res, read_res) =
+            future::zip(sending, read_exact(&server, &mut received, &mut fds)).await;
+        sent_res.unwrap();
+        read_res.unwrap();
+        assert_eq!(sent, bytes.len());
+        assert!(received == bytes);
+    });
+}
+
+#[test]
+fn tokio_connection_passes_fds() {
+    use nix::unistd::{close, pipe, read, write};
+
+    block_on_tokio(async {
+        let (mut client, mut server) = tokio_pair();
+        let (pipe_read, pipe_write) = pipe().unwrap();
+
+        let mut fds = alloc::vec![pipe_write];
+        futures_lite::future::poll_fn(|cx| {
+            client.poll_send_packet(&[1, 2, 3, 4], &mut fds, cx, &mut 0)
+        })
+        .await
+        .unwrap();
+        assert!(fds.is_empty());
+
+        let mut buf = [0; 4];
+        futures_lite::future::poll_fn(|cx| server.poll_read_packet(&mut buf, &mut fds, cx, &mut 0))
+            .await
+            .unwrap();
+        assert_eq!(buf, [1, 2, 3, 4]);
+        assert_eq!(fds.len(), 1);
+
+        // the received descriptor refers to the same pipe
+        assert_eq!(write(fds[0], b"fd").unwrap(), 2);
+        let mut out = [0; 2];
+        assert_eq!(read(pipe_read, &mut out).unwrap(), 2);
+        assert_eq!(&out, b"fd");
+
+        for fd in &[pipe_read, pipe_write, fds[0]] {
+            close(*fd).unwrap();
+        }
+    });
+}
@@ -5,21 +5,24 @@
 
 use crate::{log_debug, log_trace, util::convert_nix_error, Fd};
 use alloc::{vec, vec::Vec};
-use nix::sys::{
-    socket::{recvmsg, sendmsg, ControlMessage, ControlMessageOwned, MsgFlags},
-    uio::IoVec,
+use nix::{
+    poll::{poll, PollFd, PollFlags},
+    sys::{
+        socket::{recvmsg, sendmsg, ControlMessage, ControlMessageOwned, MsgFlags},
+        uio::IoVec,
+    },
 };
 use std::{io, os::unix::io::RawFd};
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-io-support")]
 use async_io::Async;
-#[cfg(feature = "async")]
+#[cfg(any(feature = "async-io-support", feature = "tokio-support"))]
 use core::task::{Context, Poll};
-#[cfg(feature = "async")]
+#[cfg(any(feature = "async-io-support", feature = "tokio-support"))]
 use std::os::unix::io::AsRawFd;
 
 #[inline]
-fn send_msg_packet(conn: RawFd, data: &[u8], fds: &mut Vec<Fd>) -> (usize, io::Result<()>) {
+pub(crate) fn send_msg_packet(conn: RawFd, data: &[u8], fds: &mut Vec<Fd>) -> (usize, io::Result<()>) {
     #[inline]
     fn sendmsg_loop(
         conn: RawFd,
@@ -85,7 +88,7 @@ pub(crate) fn send_packet_unix(conn: RawFd, data: &[u8], fds: &mut Vec<Fd>) -> c
 }
 
 /// The same as the above function, but in polling form.
-#[cfg(feature = "async")]
+#[cfg(any(feature = "async-io-support", feature = "tokio-support"))]
 #[inline]
 pub(crate) fn poll_send_packet_unix<Conn: ConnSource>(
     conn: &mut Conn,
@@ -119,7 +122,7 @@ pub(crate) fn poll_send_packet_unix<Conn: ConnSource>(
 /// Read a packet, unix style. Includes fds.
 #[allow(clippy::similar_names)]
 #[inline]
-fn read_msg_packet(
+pub(crate) fn read_msg_packet(
     conn: RawFd,
     mut data: &mut [u8],
     fds: &mut Vec<Fd>,
@@ -188,8 +191,21 @@ pub(crate) fn read_packet_unix(conn: RawFd, data: &mut [u8], fds: &mut Vec<Fd>)
     Ok(())
 }
 
+/// Tell whether data can be read from the socket without blocking.
+#[inline]
+pub(crate) fn readable_unix(conn: RawFd) -> crate::Result<bool> {
+    let mut pollfds = [PollFd::new(conn, PollFlags::POLLIN)];
+    loop {
+        match poll(&mut pollfds, 0) {
+            Ok(n) => return Ok(n > 0),
+            Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => (),
+            Err(e) => return Err(convert_nix_error(e).into()),
+        }
+    }
+}
+
 /// Read a packet, async redox.
-#[cfg(feature = "async")]
+#[cfg(any(feature = "async-io-support", feature = "tokio-support"))]
 #[inline]
 pub(crate) fn poll_read_packet_unix<Conn: ConnSource>(
     conn: &mut Conn,
@@ -215,14 +231,14 @@ pub(crate) fn poll_read_packet_unix<Conn: ConnSource>(
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(any(feature = "async-io-support", feature = "tokio-support"))]
 pub(crate) trait ConnSource {
     fn poll_readable(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
     fn poll_writable(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
     fn raw_fd(&mut self) -> RawFd;
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-io-support")]
 impl<Conn: AsRawFd + Unpin> ConnSource for Async<Conn> {
     #[inline]
     fn poll_readable(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
@@ -238,7 +254,7 @@ impl<Conn: AsRawFd + Unpin> ConnSource for Async<Conn> {
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-io-support")]
 impl<Conn: AsRawFd + Unpin> ConnSource for &Async<Conn> {
     #[inline]
     fn poll_readable(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
@@ -0,0 +1,106 @@
+// MIT/Apache2 License
+
+use super::ExchangeRequestFuture;
+use crate::{display::AsyncDisplay, util::take_mut, Request};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures_lite::prelude::*;
+
+/// An `ExchangeRequestFuture` whose reply is used to build a second request, which is then exchanged as well.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you poll or .await them"]
+pub enum ExchangeThenFuture<'a, D: ?Sized, R: Request, S: Request, F> {
+    /// We're exchanging the first request.
+    #[doc(hidden)]
+    First {
+        exchange: ExchangeRequestFuture<'a, D, R>,
+        then: F,
+    },
+    /// We're exchanging the second request.
+    #[doc(hidden)]
+    Second(ExchangeRequestFuture<'a, D, S>),
+    /// The future has been completed.
+    #[doc(hidden)]
+    Complete,
+    #[doc(hidden)]
+    Hole,
+}
+
+impl<'a, D: ?Sized, R: Request, S: Request, F> Default for ExchangeThenFuture<'a, D, R, S, F> {
+    #[inline]
+    fn default() -> Self {
+        Self::Hole
+    }
+}
+
+impl<'a, D: AsyncDisplay + ?Sized, R: Request, S: Request, F> ExchangeThenFuture<'a, D, R, S, F> {
+    #[inline]
+    pub(crate) fn run(display: &'a mut D, request: R, then: F) -> Self {
+        ExchangeThenFuture::First {
+            exchange: ExchangeRequestFuture::run(display, request),
+            then,
+        }
+    }
+}
+
+impl<
+        'a,
+        D: AsyncDisplay + ?Sized,
+        R: Request + Unpin + 'a,
+        S: Request + Unpin + 'a,
+        F: FnOnce(R::Reply) -> crate::Result<S> + Unpin,
+    > Future for ExchangeThenFuture<'a, D, R, S, F>
+where
+    R::Reply: Default + Unpin,
+    S::Reply: Default + Unpin,
+{
+    type Output = crate::Result<S::Reply>;
+
+    #[inline]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut result = None;
+        loop {
+            take_mut(&mut *self, |this| match this {
+                ExchangeThenFuture::Complete => panic!("Attempted to poll future past completion"),
+                ExchangeThenFuture::Hole => panic!("Cannot pole an empty hole"),
+                ExchangeThenFuture::First { mut exchange, then } => match exchange.poll(cx) {
+                    Poll::Pending => {
+                        result = Some(Poll::Pending);
+                        ExchangeThenFuture::First { exchange, then }
+                    }
+                    Poll::Ready(Err(e)) => {
+                        result = Some(Poll::Ready(Err(e)));
+                        ExchangeThenFuture::Complete
+                    }
+                    Poll::Ready(Ok(reply)) => match then(reply) {
+                        Ok(request) => ExchangeThenFuture::Second(ExchangeRequestFuture::run(
+                            exchange.cannibalize(),
+                            request,
+                        )),
+                        Err(e) => {
+                            result = Some(Poll::Ready(Err(e)));
+                            ExchangeThenFuture::Complete
+                        }
+                    },
+                },
+                ExchangeThenFuture::Second(mut exchange) => match exchange.poll(cx) {
+                    Poll::Pending => {
+                        result = Some(Poll::Pending);
+                        ExchangeThenFuture::Second(exchange)
+                    }
+                    Poll::Ready(res) => {
+                        result = Some(Poll::Ready(res));
+                        ExchangeThenFuture::Complete
+                    }
+                },
+            });
+
+            if let Some(result) = result.take() {
+                return result;
+            }
+        }
+    }
+}
@@ -17,6 +17,7 @@
 mod and_then;
 mod either;
 mod exchange_request;
+mod exchange_then;
 mod get_image;
 mod looping;
 mod map;
@@ -24,10 +25,14 @@ mod put_image;
 mod read_packet;
 mod resolve_request;
 mod resolve_request_raw;
+mod resolve_requests;
 mod send_packet;
 mod send_request;
 mod send_request_raw;
+mod send_requests;
 mod synchronize;
+#[cfg(feature = "xkb")]
+mod toggle_indicator;
 mod wait;
 mod wait_for_event;
 mod wait_for_special_event;
@@ -35,6 +40,7 @@ mod wait_for_special_event;
 pub use and_then::ExchangeXidFuture;
 pub use either::EitherFuture;
 pub use exchange_request::ExchangeRequestFuture;
+pub use exchange_then::ExchangeThenFuture;
 pub use get_image::GetImageFuture;
 pub use looping::{WaitLoopFuture, WaitLoopHandler};
 pub use map::MapFuture;
@@ -42,10 +48,14 @@ pub use put_image::PutImageFuture;
 pub use read_packet::ReadPacketFuture;
 pub use resolve_request::ResolveRequestFuture;
 pub use resolve_request_raw::{ResolveRequestRawFuture, ResolveRequestRawHandler};
+pub use resolve_requests::ResolveRequestsFuture;
 pub use send_packet::SendPacketFuture;
 pub use send_request::SendRequestFuture;
 pub use send_request_raw::SendRequestRawFuture;
+pub use send_requests::SendRequestsFuture;
 pub use synchronize::SynchronizeFuture;
+#[cfg(feature = "xkb")]
+pub use toggle_indicator::ToggleIndicatorFuture;
 pub use wait::WaitFuture;
 pub use wait_for_event::{WaitForEventFuture, WaitForEventHandler};
 pub use wait_for_special_event::{WaitForSpecialEventFuture, WaitForSpecialEventHandler};
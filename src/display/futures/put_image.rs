@@ -15,6 +15,10 @@ use core::{
 use futures_lite::prelude::*;
 
 /// The future returned by the `put_image_async` function.
+///
+/// Large images are split into several requests. After each request but the last one is sent, this future
+/// yields to the executor, so that other tasks (e.g. one that processes events using a shared display) get a
+/// chance to run in the middle of a long upload.
 #[derive(Debug)]
 #[must_use = "futures do nothing unless polled or .awaited"]
 pub enum PutImageFuture<'a, D: ?Sized, I: IntoIterator> {
@@ -102,6 +106,10 @@ where
                                 inner: SendRequestFuture::run(display, request),
                                 tokens,
                             };
+
+                            // give other tasks a chance to run before sending the next band
+                            cx.waker().wake_by_ref();
+                            return Poll::Pending;
                         }
                         None => {
                             let tok = tokens.pop().expect("shouldn't happen");
@@ -109,7 +117,6 @@ where
                                 tokens: tokens.into_iter(),
                                 inner: ResolveRequestFuture::run(display, tok),
                             };
-                            // TODO: might be worth it to collaboratively give up a time slice here
                         }
                     }
                 }
@@ -142,3 +149,46 @@ where
         }
     }
 }
+
+#[test]
+fn put_image_yields_between_bands() {
+    use crate::{
+        auto::xproto::{Gcontext, ImageFormat},
+        display::{BasicDisplay, DisplayBase},
+        image::Image,
+        prelude::AsyncDisplayDrawableExt,
+    };
+    use futures_lite::future;
+
+    let mut display = BasicDisplay::dummy();
+    let image = Image::new(
+        &display,
+        Some(display.default_visual()),
+        24,
+        ImageFormat::ZPixmap,
+        0,
+        alloc::vec![0; 8 * 4 * 5].into_boxed_slice(),
+        8,
+        5,
+        32,
+        None,
+    )
+    .unwrap();
+    let root = display.default_root();
+    display.max_request_len = 24 + 64;
+    display.set_checked(false);
+
+    // three bands, with a chance for other tasks to run after the first two
+    let mut fut =
+        display.put_image_async(root, Gcontext::const_from_xid(1), &image, 0, 0, 0, 0, 8, 5);
+    let mut yields = 0;
+    future::block_on(future::poll_fn(|cx| match Pin::new(&mut fut).poll(cx) {
+        Poll::Pending => {
+            yields += 1;
+            Poll::Pending
+        }
+        Poll::Ready(res) => Poll::Ready(res),
+    }))
+    .unwrap();
+    assert_eq!(yields, 2);
+}
@@ -0,0 +1,187 @@
+// MIT/Apache2 License
+
+use super::{SynchronizeFuture, WaitFuture};
+use crate::{
+    display::{check_for_pending_errors, harvest_replies, AsyncDisplay, RequestCookie},
+    util::take_mut,
+    Request,
+};
+use alloc::vec::Vec;
+use core::{
+    any::type_name,
+    future::Future,
+    iter,
+    mem::size_of,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures_lite::prelude::*;
+
+/// The future returned by `AsyncDisplayExt::resolve_requests_async`. Rather than resolving each request in turn,
+/// this waits on the display and picks up every reply as it arrives.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you poll or .await them"]
+pub enum ResolveRequestsFuture<'a, D: ?Sized, R: Request> {
+    /// We can fast-path to the answer, since the replies are zero sized and we aren't checked.
+    #[doc(hidden)]
+    FastPath { display: &'a mut D, len: usize },
+    /// The replies are zero sized, and the display is currently synchronizing.
+    #[doc(hidden)]
+    Synchronizing {
+        sf: SynchronizeFuture<'a, D>,
+        tokens: Vec<RequestCookie<R>>,
+    },
+    /// We are checking the display for any replies that have arrived.
+    #[doc(hidden)]
+    Harvesting {
+        display: &'a mut D,
+        tokens: Vec<RequestCookie<R>>,
+        replies: Vec<Option<crate::Result<R::Reply>>>,
+    },
+    /// We are waiting for more replies to arrive.
+    #[doc(hidden)]
+    Waiting {
+        wf: WaitFuture<'a, D>,
+        tokens: Vec<RequestCookie<R>>,
+        replies: Vec<Option<crate::Result<R::Reply>>>,
+    },
+    /// We've completed.
+    #[doc(hidden)]
+    Complete { display: &'a mut D },
+    #[doc(hidden)]
+    Hole,
+}
+
+impl<'a, D: ?Sized, R: Request> Default for ResolveRequestsFuture<'a, D, R> {
+    #[inline]
+    fn default() -> Self {
+        Self::Hole
+    }
+}
+impl<'a, D: ?Sized, R: Request> Unpin for ResolveRequestsFuture<'a, D, R> {}
+
+impl<'a, D: AsyncDisplay + ?Sized, R: Request> ResolveRequestsFuture<'a, D, R> {
+    #[inline]
+    pub(crate) fn run(display: &'a mut D, tokens: Vec<RequestCookie<R>>) -> Self {
+        log::info!(
+            "Resolving for {} replies of type {} from the server",
+            tokens.len(),
+            type_name::<R>()
+        );
+
+        match (size_of::<R::Reply>(), display.checked()) {
+            (0, false) => ResolveRequestsFuture::FastPath {
+                display,
+                len: tokens.len(),
+            },
+            (0, true) => ResolveRequestsFuture::Synchronizing {
+                sf: SynchronizeFuture::run(display),
+                tokens,
+            },
+            _ => ResolveRequestsFuture::Harvesting {
+                display,
+                replies: iter::repeat_with(|| None).take(tokens.len()).collect(),
+                tokens,
+            },
+        }
+    }
+}
+
+impl<'a, D: AsyncDisplay + ?Sized, R: Request> Future for ResolveRequestsFuture<'a, D, R>
+where
+    R::Reply: Default,
+{
+    type Output = crate::Result<Vec<R::Reply>>;
+
+    #[inline]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut result = None;
+        loop {
+            take_mut(&mut *self, |this| match this {
+                ResolveRequestsFuture::FastPath { display, len } => {
+                    result = Some(Poll::Ready(Ok(iter::repeat_with(R::Reply::default)
+                        .take(len)
+                        .collect())));
+                    ResolveRequestsFuture::Complete { display }
+                }
+                ResolveRequestsFuture::Synchronizing { mut sf, tokens } => match sf.poll(cx) {
+                    Poll::Pending => {
+                        result = Some(Poll::Pending);
+                        ResolveRequestsFuture::Synchronizing { sf, tokens }
+                    }
+                    Poll::Ready(Err(e)) => {
+                        result = Some(Poll::Ready(Err(e)));
+                        ResolveRequestsFuture::Complete {
+                            display: sf.cannibalize(),
+                        }
+                    }
+                    Poll::Ready(Ok(())) => {
+                        let display = sf.cannibalize();
+                        let res = check_for_pending_errors(display, &tokens);
+                        result = Some(Poll::Ready(res.map(|()| {
+                            iter::repeat_with(R::Reply::default)
+                                .take(tokens.len())
+                                .collect()
+                        })));
+                        ResolveRequestsFuture::Complete { display }
+                    }
+                },
+                ResolveRequestsFuture::Harvesting {
+                    display,
+                    tokens,
+                    mut replies,
+                } => {
+                    if harvest_replies(display, &tokens, &mut replies) {
+                        result = Some(Poll::Ready(
+                            replies
+                                .into_iter()
+                                .map(|reply| reply.expect("Reply wasn't harvested"))
+                                .collect(),
+                        ));
+                        ResolveRequestsFuture::Complete { display }
+                    } else {
+                        // some replies are still on their way; wait for them
+                        ResolveRequestsFuture::Waiting {
+                            wf: WaitFuture::run(display),
+                            tokens,
+                            replies,
+                        }
+                    }
+                }
+                ResolveRequestsFuture::Waiting {
+                    mut wf,
+                    tokens,
+                    replies,
+                } => match wf.poll(cx) {
+                    Poll::Pending => {
+                        result = Some(Poll::Pending);
+                        ResolveRequestsFuture::Waiting {
+                            wf,
+                            tokens,
+                            replies,
+                        }
+                    }
+                    Poll::Ready(Err(e)) => {
+                        result = Some(Poll::Ready(Err(e)));
+                        ResolveRequestsFuture::Complete {
+                            display: wf.cannibalize(),
+                        }
+                    }
+                    Poll::Ready(Ok(())) => ResolveRequestsFuture::Harvesting {
+                        display: wf.cannibalize(),
+                        tokens,
+                        replies,
+                    },
+                },
+                ResolveRequestsFuture::Complete { .. } => {
+                    panic!("Attempted to poll future past completion")
+                }
+                ResolveRequestsFuture::Hole => panic!("Cannot pole an empty hole"),
+            });
+
+            if let Some(result) = result.take() {
+                return result;
+            }
+        }
+    }
+}
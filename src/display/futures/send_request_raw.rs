@@ -2,6 +2,7 @@
 
 use crate::display::{AsyncDisplay, PollOr, RequestInfo};
 use core::{
+    fmt,
     future::Future,
     pin::Pin,
     task::{Context, Poll},
@@ -9,12 +10,29 @@ use core::{
 
 /// The future returned by `send_request_raw_async`. This polls the `AsyncDisplay` instance until it
 /// returns properly.
-#[derive(Debug)]
+///
+/// This future is cancellation-safe. If it is dropped after the request has been handed to the display, the
+/// display is told to finish sending the request the next time it is polled, so that the connection is not left
+/// with a partially written request.
 #[must_use = "futures do nothing unless you poll or .await them"]
 pub struct SendRequestRawFuture<'a, D: ?Sized> {
-    display: &'a mut D,
+    display: Option<&'a mut D>,
     request_info: Option<RequestInfo>,
     is_finished: bool,
+    // tells the display that we've been dropped mid-send; this is a function pointer so that the struct, and
+    // therefore the `Drop` impl, doesn't need an `AsyncDisplay` bound
+    abandon: fn(&mut D),
+}
+
+impl<'a, D: fmt::Debug + ?Sized> fmt::Debug for SendRequestRawFuture<'a, D> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendRequestRawFuture")
+            .field("display", &self.display)
+            .field("request_info", &self.request_info)
+            .field("is_finished", &self.is_finished)
+            .finish()
+    }
 }
 
 impl<'a, D: ?Sized> Unpin for SendRequestRawFuture<'a, D> {}
@@ -24,19 +42,48 @@ impl<'a, D: AsyncDisplay + ?Sized> SendRequestRawFuture<'a, D> {
     pub(crate) fn run(display: &'a mut D, request: RequestInfo) -> Self {
         // begin the send request process
         Self {
-            display,
+            display: Some(display),
             request_info: Some(request),
             is_finished: false,
+            abandon: abandon::<D>,
         }
     }
 
     /// Consumes this future and returns the display we are currently sending a request to.
     #[inline]
-    pub(crate) fn cannibalize(self) -> &'a mut D {
+    pub(crate) fn cannibalize(mut self) -> &'a mut D {
+        self.abandon_if_sending();
         self.display
+            .take()
+            .expect("Future was already cannibalized")
     }
 }
 
+impl<'a, D: ?Sized> SendRequestRawFuture<'a, D> {
+    /// If the display is in the middle of sending our request, tell it that we're no longer around to finish.
+    #[inline]
+    fn abandon_if_sending(&mut self) {
+        if !self.is_finished && self.request_info.is_none() {
+            if let Some(display) = self.display.as_mut() {
+                (self.abandon)(display);
+            }
+            self.is_finished = true;
+        }
+    }
+}
+
+impl<'a, D: ?Sized> Drop for SendRequestRawFuture<'a, D> {
+    #[inline]
+    fn drop(&mut self) {
+        self.abandon_if_sending();
+    }
+}
+
+#[inline]
+fn abandon<D: AsyncDisplay + ?Sized>(display: &mut D) {
+    display.abandon_send_request_raw();
+}
+
 impl<'a, D: AsyncDisplay + ?Sized> Future for SendRequestRawFuture<'a, D> {
     type Output = crate::Result<u16>;
 
@@ -46,19 +93,23 @@ impl<'a, D: AsyncDisplay + ?Sized> Future for SendRequestRawFuture<'a, D> {
             panic!("Attempted to poll future after completion");
         }
 
+        let this = &mut *self;
+        let display = this
+            .display
+            .as_mut()
+            .expect("Attempted to poll future after completion");
+
         // begin the send request process; once we've done that,
         let res = loop {
-            match self.request_info.take() {
-                Some(request_info) => {
-                    match self.display.begin_send_request_raw(request_info, cx) {
-                        PollOr::Pending(req) => {
-                            self.request_info = Some(req);
-                            break Poll::Pending;
-                        }
-                        PollOr::Ready(()) => { /* request_info is already set to None */ }
+            match this.request_info.take() {
+                Some(request_info) => match display.begin_send_request_raw(request_info, cx) {
+                    PollOr::Pending(req) => {
+                        this.request_info = Some(req);
+                        break Poll::Pending;
                     }
-                }
-                None => break self.display.poll_send_request_raw(cx),
+                    PollOr::Ready(()) => { /* request_info is already set to None */ }
+                },
+                None => break display.poll_send_request_raw(cx),
             }
         };
 
@@ -68,3 +119,111 @@ impl<'a, D: AsyncDisplay + ?Sized> Future for SendRequestRawFuture<'a, D> {
         res
     }
 }
+
+#[cfg(test)]
+fn expect_request<R: crate::Request>(
+    conn: &mut crate::display::connection::dummy::DummyConnection,
+    request: R,
+) {
+    let mut info = RequestInfo::from_request(request, false, usize::from(u16::MAX) * 4);
+    crate::display::output::modify_for_opcode(&mut info.data, info.opcode, None);
+    conn.expects_bytes(info.data);
+}
+
+#[test]
+fn dropped_send_is_finished_by_next_send() {
+    use crate::{
+        auto::xproto::{GetInputFocusRequest, InternAtomRequest},
+        display::{AsyncDisplayExt, BasicDisplay},
+    };
+    use futures_lite::future;
+
+    let first = InternAtomRequest {
+        name: "_NET_WM_NAME".into(),
+        ..Default::default()
+    };
+    let second = GetInputFocusRequest::default();
+
+    let mut display = BasicDisplay::dummy();
+    let conn = display.connection_mut();
+    expect_request(conn, first.clone());
+    expect_request(conn, second.clone());
+    conn.limit_writes(4);
+
+    // get the first few bytes of the request on the wire, then cancel it
+    let mut fut = display.send_request_async(first);
+    assert!(future::block_on(future::poll_once(&mut fut)).is_none());
+    drop(fut);
+
+    // the rest of the first request goes out before the second request does
+    future::block_on(display.send_request_async(second)).unwrap();
+    assert!(display.connection_mut().received_all_expected());
+}
+
+#[test]
+fn dropped_send_releases_cell_display_lock() {
+    use crate::{
+        auto::xproto::{GetInputFocusRequest, InternAtomRequest},
+        display::{AsyncDisplayExt, BasicDisplay, CellDisplay},
+    };
+    use futures_lite::future;
+
+    let first = InternAtomRequest {
+        name: "_NET_WM_NAME".into(),
+        ..Default::default()
+    };
+    let second = GetInputFocusRequest::default();
+
+    let mut display = BasicDisplay::dummy();
+    let conn = display.connection_mut();
+    expect_request(conn, first.clone());
+    expect_request(conn, second.clone());
+    expect_request(conn, second.clone());
+    conn.limit_writes(4);
+    let mut display: CellDisplay<_> = display.into();
+
+    let mut fut = display.send_request_async(first);
+    assert!(future::block_on(future::poll_once(&mut fut)).is_none());
+    drop(fut);
+
+    // if the abandoned request kept the io lock, these would never complete
+    future::block_on(display.send_request_async(second.clone())).unwrap();
+    future::block_on(display.send_request_async(second)).unwrap();
+}
+
+#[cfg(feature = "sync-display")]
+#[test]
+fn dropped_send_is_finished_before_wait() {
+    use crate::{
+        auto::xproto::{InternAtomReply, InternAtomRequest},
+        display::{AsyncDisplayExt, BasicDisplay, DisplayBase, SyncDisplay},
+        Atom,
+    };
+    use futures_lite::future;
+
+    let request = InternAtomRequest {
+        name: "_NET_WM_NAME".into(),
+        ..Default::default()
+    };
+
+    let mut display = BasicDisplay::dummy();
+    let conn = display.connection_mut();
+    expect_request(conn, request.clone());
+    conn.limit_writes(4);
+    // the BIG-REQUESTS query took the first sequence number
+    conn.reply_padded(InternAtomReply {
+        reply_type: 1,
+        sequence: 2,
+        atom: Atom::const_from_xid(7),
+        ..Default::default()
+    });
+    let mut display: SyncDisplay<_> = display.into();
+
+    let mut fut = display.send_request_async(request);
+    assert!(future::block_on(future::poll_once(&mut fut)).is_none());
+    drop(fut);
+
+    // waiting finishes the request before reading its reply
+    future::block_on(display.wait_async()).unwrap();
+    assert!(display.take_pending_reply(2).is_some());
+}
@@ -0,0 +1,119 @@
+// MIT/Apache2 License
+
+use super::SendRequestFuture;
+use crate::{
+    display::{AsyncDisplay, RequestCookie},
+    Request,
+};
+use alloc::vec::Vec;
+use core::{
+    future::Future,
+    mem,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures_lite::prelude::*;
+
+/// The future returned by the `AsyncDisplayExt::send_requests_async` method. It sends every request in an
+/// iterator, one after another, and collects their cookies.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled or .awaited"]
+pub enum SendRequestsFuture<'a, D: ?Sized, I: IntoIterator>
+where
+    I::Item: Request,
+{
+    /// We haven't been polled yet.
+    #[doc(hidden)]
+    AwaitingPoll { display: &'a mut D, requests: I },
+    /// We are sending the requests, one at a time.
+    #[doc(hidden)]
+    SendingRequests {
+        remaining: I::IntoIter,
+        inner: SendRequestFuture<'a, D, I::Item>,
+        tokens: Vec<RequestCookie<I::Item>>,
+    },
+    /// We've completed or error'd out.
+    #[doc(hidden)]
+    Complete,
+}
+
+impl<'a, D: ?Sized, I: IntoIterator + Unpin> Unpin for SendRequestsFuture<'a, D, I>
+where
+    I::IntoIter: Unpin,
+    I::Item: Request,
+{
+}
+
+impl<'a, D: ?Sized, I: IntoIterator> SendRequestsFuture<'a, D, I>
+where
+    I::Item: Request,
+{
+    #[inline]
+    pub(crate) fn run(display: &'a mut D, requests: I) -> Self {
+        Self::AwaitingPoll { display, requests }
+    }
+}
+
+impl<'a, D: AsyncDisplay + ?Sized, I: IntoIterator + Unpin> Future for SendRequestsFuture<'a, D, I>
+where
+    I::IntoIter: Unpin,
+    I::Item: Request + Unpin,
+{
+    type Output = crate::Result<Vec<RequestCookie<I::Item>>>;
+
+    #[inline]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            match mem::replace(&mut *self, SendRequestsFuture::Complete) {
+                SendRequestsFuture::AwaitingPoll { display, requests } => {
+                    let mut requests = requests.into_iter();
+                    let first_request = match requests.next() {
+                        Some(first_request) => first_request,
+                        None => return Poll::Ready(Ok(Vec::new())),
+                    };
+
+                    let remaining_len = requests.size_hint().0;
+                    *self = SendRequestsFuture::SendingRequests {
+                        inner: SendRequestFuture::run(display, first_request),
+                        remaining: requests,
+                        tokens: Vec::with_capacity(remaining_len + 1),
+                    };
+                }
+                SendRequestsFuture::SendingRequests {
+                    mut remaining,
+                    mut inner,
+                    mut tokens,
+                } => {
+                    match inner.poll(cx) {
+                        Poll::Pending => {
+                            *self = SendRequestsFuture::SendingRequests {
+                                remaining,
+                                inner,
+                                tokens,
+                            };
+                            return Poll::Pending;
+                        }
+                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                        Poll::Ready(Ok(tok)) => tokens.push(tok),
+                    }
+
+                    // pull the next request out of the iterator and start sending it
+                    let display = inner.cannibalize();
+                    match remaining.next() {
+                        Some(request) => {
+                            *self = SendRequestsFuture::SendingRequests {
+                                remaining,
+                                inner: SendRequestFuture::run(display, request),
+                                tokens,
+                            };
+                        }
+                        None => return Poll::Ready(Ok(tokens)),
+                    }
+                }
+                SendRequestsFuture::Complete => {
+                    panic!("Attempted to poll future after completion")
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+// MIT/Apache2 License
+
+use super::ExchangeRequestFuture;
+use crate::{
+    auto::xproto::InternAtomRequest,
+    display::{
+        traits::{
+            named_indicator_request, set_named_indicator_request, NamedIndicatorRequest,
+            SetNamedIndicatorStateRequest,
+        },
+        AsyncDisplay,
+    },
+    util::take_mut,
+};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures_lite::prelude::*;
+
+/// The future returned by `AsyncDisplayXkbExt::toggle_xkb_indicator_by_name_async`. It interns the name of the
+/// indicator, gets its state and then sets the opposite state.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you poll or .await them"]
+pub enum ToggleIndicatorFuture<'a, 'b, D: ?Sized> {
+    /// We're interning the name of the indicator.
+    #[doc(hidden)]
+    Interning(ExchangeRequestFuture<'a, D, InternAtomRequest<'b>>),
+    /// We're getting the state of the indicator.
+    #[doc(hidden)]
+    Getting(ExchangeRequestFuture<'a, D, NamedIndicatorRequest>),
+    /// We're setting the new state of the indicator.
+    #[doc(hidden)]
+    Setting {
+        exchange: ExchangeRequestFuture<'a, D, SetNamedIndicatorStateRequest>,
+        on: bool,
+    },
+    /// The future has been completed.
+    #[doc(hidden)]
+    Complete,
+    #[doc(hidden)]
+    Hole,
+}
+
+impl<'a, 'b, D: ?Sized> Default for ToggleIndicatorFuture<'a, 'b, D> {
+    #[inline]
+    fn default() -> Self {
+        Self::Hole
+    }
+}
+
+impl<'a, 'b, D: AsyncDisplay + ?Sized> ToggleIndicatorFuture<'a, 'b, D> {
+    #[inline]
+    pub(crate) fn run(display: &'a mut D, request: InternAtomRequest<'b>) -> Self {
+        ToggleIndicatorFuture::Interning(ExchangeRequestFuture::run(display, request))
+    }
+}
+
+impl<'a, 'b: 'a, D: AsyncDisplay + ?Sized> Future for ToggleIndicatorFuture<'a, 'b, D> {
+    type Output = crate::Result<Option<bool>>;
+
+    #[inline]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut result = None;
+        loop {
+            take_mut(&mut *self, |this| match this {
+                ToggleIndicatorFuture::Complete => {
+                    panic!("Attempted to poll future past completion")
+                }
+                ToggleIndicatorFuture::Hole => panic!("Cannot pole an empty hole"),
+                ToggleIndicatorFuture::Interning(mut exchange) => match exchange.poll(cx) {
+                    Poll::Pending => {
+                        result = Some(Poll::Pending);
+                        ToggleIndicatorFuture::Interning(exchange)
+                    }
+                    Poll::Ready(Err(e)) => {
+                        result = Some(Poll::Ready(Err(e)));
+                        ToggleIndicatorFuture::Complete
+                    }
+                    Poll::Ready(Ok(repl)) => {
+                        ToggleIndicatorFuture::Getting(ExchangeRequestFuture::run(
+                            exchange.cannibalize(),
+                            named_indicator_request(repl.atom),
+                        ))
+                    }
+                },
+                ToggleIndicatorFuture::Getting(mut exchange) => match exchange.poll(cx) {
+                    Poll::Pending => {
+                        result = Some(Poll::Pending);
+                        ToggleIndicatorFuture::Getting(exchange)
+                    }
+                    Poll::Ready(Err(e)) => {
+                        result = Some(Poll::Ready(Err(e)));
+                        ToggleIndicatorFuture::Complete
+                    }
+                    Poll::Ready(Ok(repl)) => {
+                        if let Some(state) = repl.into_indicator() {
+                            ToggleIndicatorFuture::Setting {
+                                exchange: ExchangeRequestFuture::run(
+                                    exchange.cannibalize(),
+                                    set_named_indicator_request(state.name, !state.on),
+                                ),
+                                on: !state.on,
+                            }
+                        } else {
+                            // the keyboard doesn't have this indicator
+                            result = Some(Poll::Ready(Ok(None)));
+                            ToggleIndicatorFuture::Complete
+                        }
+                    }
+                },
+                ToggleIndicatorFuture::Setting { mut exchange, on } => match exchange.poll(cx) {
+                    Poll::Pending => {
+                        result = Some(Poll::Pending);
+                        ToggleIndicatorFuture::Setting { exchange, on }
+                    }
+                    Poll::Ready(res) => {
+                        result = Some(Poll::Ready(res.map(|()| Some(on))));
+                        ToggleIndicatorFuture::Complete
+                    }
+                },
+            });
+
+            if let Some(result) = result.take() {
+                return result;
+            }
+        }
+    }
+}
@@ -195,3 +195,17 @@ pub(crate) fn wait<C: Connection + ?Sized, D: Display + ?Sized>(
     log::debug!("Found {} bytes; now processing them...", bytes.len());
     process_bytes(display, bytes, fds)
 }
+
+/// Run `wait` if the connection has data waiting to be read. Returns whether or not anything was read.
+#[inline]
+pub(crate) fn try_wait<C: Connection + ?Sized, D: Display + ?Sized>(
+    display: &mut D,
+    connection: &mut C,
+) -> crate::Result<bool> {
+    if connection.readable()? {
+        wait(display, connection)?;
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
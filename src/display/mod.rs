@@ -44,12 +44,18 @@ use crate::{
     Fd, Request, XID,
 };
 use alloc::{boxed::Box, vec::Vec};
-use core::{fmt, iter, marker::PhantomData, mem, num::NonZeroU32};
+use core::{
+    any::type_name,
+    fmt, iter,
+    marker::PhantomData,
+    mem::{self, size_of},
+    num::NonZeroU32,
+};
 use tinyvec::TinyVec;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::xid::XidType;
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use core::task::{Context, Poll};
 
 mod basic;
@@ -68,9 +74,9 @@ pub use basic::*;
 pub use cell::*;
 pub use connection::*;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub(crate) mod futures;
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub use futures::*;
 
 #[cfg(feature = "sync-display")]
@@ -81,7 +87,7 @@ pub use sync::*;
 pub(crate) mod input;
 pub(crate) mod output;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub(crate) mod common;
 
 #[cfg(feature = "std")]
@@ -90,7 +96,7 @@ pub mod name;
 /// A set of traits to import to enable functionality.
 pub mod prelude {
     pub use super::traits::*;
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     pub use super::{AsyncDisplay, AsyncDisplayExt};
     pub use super::{Display, DisplayBase, DisplayExt};
 }
@@ -180,6 +186,17 @@ pub trait DisplayBase {
     /// Pop an event from this display's event queue. See `push_event` for more information.
     fn pop_event(&mut self) -> Option<Event>;
 
+    /// Pop the event at the front of this display's event queue, but only if it satisfies `predicate`. This
+    /// lets callers peek at the next event without taking it out of the queue.
+    ///
+    /// The default implementation always returns `None`; displays that cannot look into their event queue
+    /// without removing from it (such as those backed by a concurrent queue) keep this behavior.
+    #[inline]
+    fn pop_event_if(&mut self, predicate: &mut dyn FnMut(&Event) -> bool) -> Option<Event> {
+        let _ = predicate;
+        None
+    }
+
     /// Create a new special event queue.
     ///
     /// Some extensions, like `present`, may want to keep a different queue of events than the general event
@@ -426,6 +443,11 @@ impl<D: DisplayBase + ?Sized> DisplayBase for &mut D {
         (**self).pop_event()
     }
 
+    #[inline]
+    fn pop_event_if(&mut self, predicate: &mut dyn FnMut(&Event) -> bool) -> Option<Event> {
+        (**self).pop_event_if(predicate)
+    }
+
     #[inline]
     fn generate_xid(&mut self) -> Option<XID> {
         (**self).generate_xid()
@@ -534,6 +556,17 @@ pub trait Display: DisplayBase {
     /// In addition, system IO errors should be wrapped into a `BreadError` and returned.
     fn wait(&mut self) -> crate::Result;
 
+    /// Like `wait`, but only reads a packet if the connection already has data waiting, so that it never
+    /// blocks. Returns whether or not a packet was read. The default implementation never reads anything.
+    ///
+    /// # Errors
+    ///
+    /// This function can return any error that `wait` can.
+    #[inline]
+    fn try_wait(&mut self) -> crate::Result<bool> {
+        Ok(false)
+    }
+
     /// Send a request across the connection, given the monomorphized request info.
     ///
     /// This function sends the bytes and occasionally file descriptors contained in the given [`RequestInfo`]
@@ -640,6 +673,11 @@ impl<D: Display + ?Sized> Display for &mut D {
         (**self).wait()
     }
 
+    #[inline]
+    fn try_wait(&mut self) -> crate::Result<bool> {
+        (**self).try_wait()
+    }
+
     #[inline]
     fn send_request_raw(&mut self, request_info: RequestInfo) -> crate::Result<u16> {
         (**self).send_request_raw(request_info)
@@ -652,7 +690,7 @@ impl<D: Display + ?Sized> Display for &mut D {
 /// functions in this trait and its extension traits closely mirror those of the [`Display`] trait.
 ///
 /// See the [`DisplayBase`] trait for more information on what the display is expected to do.
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub trait AsyncDisplay: DisplayBase {
     /// Poll the current status of waiting for more input. There is no default implementation; the buffering
     /// strategy depends on the implementor. If this is called after a `Poll::Ready` is returned, it is assumed
@@ -679,9 +717,21 @@ pub trait AsyncDisplay: DisplayBase {
     /// This function should rarely be called by the user directly; instead, use
     /// [`AsyncDisplayExt::send_request_raw_async`], or one of the functions that use it.
     fn poll_send_request_raw(&mut self, cx: &mut Context<'_>) -> Poll<crate::Result<u16>>;
+
+    /// Notify the display that the request begun by `begin_send_request_raw` will not be polled anymore, since
+    /// the future polling it was dropped. If any part of that request may have been written to the connection,
+    /// the display should finish sending it the next time it is polled, in order to keep the connection intact.
+    ///
+    /// This function is called by [`SendRequestRawFuture`]'s destructor, and should never need to be called by
+    /// the user directly. The default implementation does nothing but log a warning, so displays that can write
+    /// a request across several polls should override it.
+    #[inline]
+    fn abandon_send_request_raw(&mut self) {
+        log::warn!("Abandoned a request that may have been partially sent");
+    }
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 impl<D: AsyncDisplay + ?Sized> AsyncDisplay for &mut D {
     #[inline]
     fn poll_wait(&mut self, cx: &mut Context<'_>) -> Poll<crate::Result> {
@@ -701,6 +751,11 @@ impl<D: AsyncDisplay + ?Sized> AsyncDisplay for &mut D {
     fn poll_send_request_raw(&mut self, cx: &mut Context<'_>) -> Poll<crate::Result<u16>> {
         (**self).poll_send_request_raw(cx)
     }
+
+    #[inline]
+    fn abandon_send_request_raw(&mut self) {
+        (**self).abandon_send_request_raw();
+    }
 }
 
 /// Monomorphized methods we can't put into the `Display` trait proper.
@@ -737,6 +792,45 @@ pub trait DisplayExt {
     where
         R::Reply: Default;
 
+    /// Send several requests of the same type to the server.
+    ///
+    /// Every request in the iterator is sent before any of their replies are waited on, so the server can work
+    /// through all of them without waiting on a round trip for each one. The returned cookies can be redeemed
+    /// all at once using `resolve_requests`.
+    ///
+    /// # Errors
+    ///
+    /// This function returns every error that `send_request_raw` can return. If an error occurs, the requests
+    /// sent before it are not resolved.
+    #[inline]
+    fn send_requests<R: Request, I: IntoIterator<Item = R>>(
+        &mut self,
+        requests: I,
+    ) -> crate::Result<Vec<RequestCookie<R>>> {
+        requests
+            .into_iter()
+            .map(|request| self.send_request(request))
+            .collect()
+    }
+
+    /// Resolve several requests that we sent to the server.
+    ///
+    /// Rather than waiting for each reply in turn, this function waits on the display and picks up every reply
+    /// as it arrives. The replies are returned in the same order as the cookies they correspond to. Zero sized
+    /// replies are handled the same way that `resolve_request` handles them, except that the display is only
+    /// synchronized once.
+    ///
+    /// # Errors
+    ///
+    /// This function returns every error that `resolve_request` can return. If the server replies to any of the
+    /// requests with an error, that error is returned.
+    fn resolve_requests<R: Request>(
+        &mut self,
+        tokens: Vec<RequestCookie<R>>,
+    ) -> crate::Result<Vec<R::Reply>>
+    where
+        R::Reply: Default;
+
     /// Send a request to the server and immediately resolve for its reply. This is equivalent to calling
     /// `send_request` followd by `resolve_request`.
     #[inline]
@@ -744,7 +838,7 @@ pub trait DisplayExt {
     where
         R::Reply: Default,
     {
-        log::info!("Sending {} to server", core::any::type_name::<R>());
+        log::info!("Sending {} to server", type_name::<R>());
         let tok = self.send_request(request)?;
         log::info!("Resolving request...");
         self.resolve_request(tok)
@@ -778,10 +872,41 @@ impl<D: Display + ?Sized> DisplayExt for D {
         let PendingReply { data, fds } = self.resolve_request_raw(token.sequence())?;
         decode_reply::<R>(&data, fds)
     }
+
+    #[inline]
+    fn resolve_requests<R: Request>(
+        &mut self,
+        tokens: Vec<RequestCookie<R>>,
+    ) -> crate::Result<Vec<R::Reply>>
+    where
+        R::Reply: Default,
+    {
+        if size_of::<R::Reply>() == 0 {
+            if self.checked() {
+                self.synchronize()?;
+                check_for_pending_errors(self, &tokens)?;
+            }
+
+            return Ok(iter::repeat_with(R::Reply::default)
+                .take(tokens.len())
+                .collect());
+        }
+
+        let mut replies: Vec<Option<crate::Result<R::Reply>>> =
+            iter::repeat_with(|| None).take(tokens.len()).collect();
+        while !harvest_replies(self, &tokens, &mut replies) {
+            self.wait()?;
+        }
+
+        replies
+            .into_iter()
+            .map(|reply| reply.expect("Reply wasn't harvested"))
+            .collect()
+    }
 }
 
 /// Monomorphized methods we can't put into the `AsyncDisplay` trait proper.
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub trait AsyncDisplayExt: AsyncDisplay {
     /// Wait until we recieve data.
     ///
@@ -811,6 +936,26 @@ pub trait AsyncDisplayExt: AsyncDisplay {
     where
         R::Reply: Default;
 
+    /// Send several requests of the same type to the server. This is the async equivalent of the
+    /// [`DisplayExt::send_requests`] function. See that function for more information on what this is expected
+    /// to do.
+    fn send_requests_async<I: IntoIterator>(
+        &mut self,
+        requests: I,
+    ) -> SendRequestsFuture<'_, Self, I>
+    where
+        I::Item: Request;
+
+    /// Resolve several requests that we sent to the server, concurrently. This is the async equivalent of the
+    /// [`DisplayExt::resolve_requests`] function. See that function for more information on what this is
+    /// expected to do.
+    fn resolve_requests_async<R: Request>(
+        &mut self,
+        tokens: Vec<RequestCookie<R>>,
+    ) -> ResolveRequestsFuture<'_, Self, R>
+    where
+        R::Reply: Default;
+
     /// Synchronize this display so that every request that has been sent is resolved. This is the async
     /// equivalent of the [`Display::synchronize`] function. See that function for more information on what this
     /// is expected to do.
@@ -846,7 +991,7 @@ pub trait AsyncDisplayExt: AsyncDisplay {
     ) -> ExchangeXidFuture<'_, Self, R, U, F>;
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 impl<D: AsyncDisplay + ?Sized> AsyncDisplayExt for D {
     #[inline]
     fn wait_async(&mut self) -> WaitFuture<Self> {
@@ -874,6 +1019,28 @@ impl<D: AsyncDisplay + ?Sized> AsyncDisplayExt for D {
         ResolveRequestFuture::run(self, token)
     }
 
+    #[inline]
+    fn send_requests_async<I: IntoIterator>(
+        &mut self,
+        requests: I,
+    ) -> SendRequestsFuture<'_, Self, I>
+    where
+        I::Item: Request,
+    {
+        SendRequestsFuture::run(self, requests)
+    }
+
+    #[inline]
+    fn resolve_requests_async<R: Request>(
+        &mut self,
+        tokens: Vec<RequestCookie<R>>,
+    ) -> ResolveRequestsFuture<'_, Self, R>
+    where
+        R::Reply: Default,
+    {
+        ResolveRequestsFuture::run(self, tokens)
+    }
+
     #[inline]
     fn synchronize_async(&mut self) -> SynchronizeFuture<'_, Self> {
         SynchronizeFuture::run(self)
@@ -1009,13 +1176,22 @@ pub struct PendingReply {
 ///
 /// Requests usually take time to resolve into replies. Therefore, the `Display::send_request` method returns
 /// the `RequestCookie`, which is later used to block (or await) for the request's eventual result.
-#[derive(Copy, Clone, PartialOrd, Ord, PartialEq, Default, Eq, Hash)]
+#[derive(PartialOrd, Ord, PartialEq, Default, Eq, Hash)]
 #[repr(transparent)]
 pub struct RequestCookie<R: Request> {
     sequence: u16,
     _phantom: PhantomData<Option<R::Reply>>,
 }
 
+// derive(Clone, Copy) would require R to be Clone and Copy, which we don't need
+impl<R: Request> Clone for RequestCookie<R> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<R: Request> Copy for RequestCookie<R> {}
+
 impl<R: Request> fmt::Debug for RequestCookie<R> {
     #[inline]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -1148,6 +1324,57 @@ pub(crate) fn decode_reply<R: Request>(reply: &[u8], fds: Box<[Fd]>) -> crate::R
     Ok(r)
 }
 
+/// For every request in `tokens` that hasn't been resolved yet, check the display for its reply (or its error)
+/// and store it in the corresponding slot of `replies`. Returns `true` once every slot has been filled.
+///
+/// Errors are stored rather than returned, so that the replies to the other requests are still taken out of
+/// the display once they arrive.
+#[inline]
+pub(crate) fn harvest_replies<D: DisplayBase + ?Sized, R: Request>(
+    display: &mut D,
+    tokens: &[RequestCookie<R>],
+    replies: &mut [Option<crate::Result<R::Reply>>],
+) -> bool {
+    let mut complete = true;
+    for (tok, slot) in tokens.iter().zip(replies.iter_mut()) {
+        if slot.is_some() {
+            continue;
+        }
+
+        let seq = tok.sequence();
+        if let Err(err) = display.check_for_pending_error(seq) {
+            *slot = Some(Err(err));
+            continue;
+        }
+        match display.take_pending_reply(seq) {
+            Some(PendingReply { data, fds }) => *slot = Some(decode_reply::<R>(&data, fds)),
+            None => complete = false,
+        }
+    }
+
+    complete
+}
+
+/// Once the display has been synchronized, take the pending request and any error for every request in
+/// `tokens` out of the display, returning the first error.
+#[inline]
+pub(crate) fn check_for_pending_errors<D: DisplayBase + ?Sized, R: Request>(
+    display: &mut D,
+    tokens: &[RequestCookie<R>],
+) -> crate::Result {
+    let mut result = Ok(());
+    for tok in tokens {
+        let seq = tok.sequence();
+        display.take_pending_request(seq);
+        let error = display.check_for_pending_error(seq);
+        if result.is_ok() {
+            result = error;
+        }
+    }
+
+    result
+}
+
 /// Convenience function that wraps `generate_xid` and returns an error instead of `None` if it is out of XIDs.
 #[inline]
 pub(crate) fn generate_xid<D: DisplayBase + ?Sized>(display: &mut D) -> crate::Result<XID> {
@@ -1155,3 +1382,100 @@ pub(crate) fn generate_xid<D: DisplayBase + ?Sized>(display: &mut D) -> crate::R
         .generate_xid()
         .ok_or(crate::BreadError::StaticMsg("Ran out of XIDs"))
 }
+
+/// The number of bytes that are left in a single request for data, after a fixed-size part of `header` bytes.
+/// This is always a multiple of four.
+#[inline]
+pub(crate) fn max_request_payload<D: DisplayBase + ?Sized>(display: &D, header: usize) -> usize {
+    let max_request_len = match display.max_request_len() {
+        0 => usize::from(display.setup().maximum_request_length) << 2,
+        max_request_len => max_request_len,
+    };
+
+    // requests that use BIG-REQUESTS have an extra four bytes for their length
+    let header = header + if display.bigreq_enabled() { 4 } else { 0 };
+    (max_request_len & !3).saturating_sub(header).max(4)
+}
+
+#[test]
+fn resolve_requests_out_of_order() {
+    use crate::auto::xproto::{Atom, InternAtomReply, InternAtomRequest};
+
+    let mut display = BasicDisplay::dummy();
+    let tokens = display
+        .send_requests(["FOO", "BAR", "BAZ"].iter().map(|name| InternAtomRequest {
+            name: (*name).into(),
+            ..Default::default()
+        }))
+        .unwrap();
+    let sequences: Vec<u16> = tokens.iter().map(|tok| tok.sequence()).collect();
+
+    // the server replies in an arbitrary order
+    for (i, seq) in sequences.iter().copied().enumerate().rev() {
+        display.connection_mut().reply_padded(InternAtomReply {
+            reply_type: 1,
+            sequence: seq,
+            atom: Atom::const_from_xid(i as u32 + 1),
+            ..Default::default()
+        });
+    }
+
+    let replies = display.resolve_requests(tokens).unwrap();
+    let atoms: Vec<u32> = replies.iter().map(|r| r.atom.xid).collect();
+    assert_eq!(atoms, [1, 2, 3]);
+}
+
+#[test]
+fn resolve_requests_drains_every_request_on_error() {
+    use crate::auto::xproto::{
+        Atom, GetInputFocusReply, InternAtomReply, InternAtomRequest, MapWindowRequest,
+    };
+
+    let error = |seq: u16, major_code| {
+        let mut error = [0; 32];
+        error[1] = 3;
+        error[2..4].copy_from_slice(&seq.to_ne_bytes());
+        error[10] = major_code;
+        error
+    };
+
+    // the first request fails, and the reply to the second one arrives afterwards
+    let mut display = BasicDisplay::dummy();
+    let tokens = display
+        .send_requests(["FOO", "BAR"].iter().map(|name| InternAtomRequest {
+            name: (*name).into(),
+            ..Default::default()
+        }))
+        .unwrap();
+    let conn = display.connection_mut();
+    conn.reply_bytes(error(2, 16).iter().copied());
+    conn.reply_padded(InternAtomReply {
+        reply_type: 1,
+        sequence: 3,
+        atom: Atom::const_from_xid(1),
+        ..Default::default()
+    });
+    match display.resolve_requests(tokens) {
+        Err(BreadError::XProtocol { sequence: 2, .. }) => {}
+        res => panic!("Unexpected result: {:?}", res),
+    }
+    assert!(display.get_pending_item(3).is_none());
+
+    // zero-sized replies: both requests fail, and both errors are taken out of the display
+    let tokens = display
+        .send_requests(iter::repeat_with(MapWindowRequest::default).take(2))
+        .unwrap();
+    let conn = display.connection_mut();
+    conn.reply_bytes(error(4, 8).iter().copied());
+    conn.reply_bytes(error(5, 8).iter().copied());
+    conn.reply_padded(GetInputFocusReply {
+        reply_type: 1,
+        sequence: 6,
+        ..Default::default()
+    });
+    match display.resolve_requests(tokens) {
+        Err(BreadError::XProtocol { sequence: 4, .. }) => {}
+        res => panic!("Unexpected result: {:?}", res),
+    }
+    assert!(display.get_pending_item(5).is_none());
+}
@@ -12,24 +12,27 @@ use core::mem;
 use memchr::memrchr;
 use std::{env, net, path::Path};
 
-#[cfg(feature = "async")]
+#[cfg(any(feature = "async-io-support", feature = "tokio-support"))]
 use super::AsyncConnection;
-#[cfg(feature = "async")]
+#[cfg(any(feature = "async-io-support", feature = "tokio-support"))]
 use core::task::{Context, Poll};
 
-#[cfg(all(feature = "async", not(feature = "tokio-support")))]
+#[cfg(all(feature = "async-io-support", not(feature = "tokio-support")))]
 use async_io::Async;
-#[cfg(all(feature = "async", not(feature = "tokio-support")))]
+#[cfg(all(feature = "async-io-support", not(feature = "tokio-support")))]
 use std::net::ToSocketAddrs;
 
-#[cfg(all(feature = "async", feature = "tokio-support"))]
+#[cfg(feature = "tokio-support")]
 use spinning_top::Spinlock;
-#[cfg(all(feature = "async", feature = "tokio-support"))]
+#[cfg(feature = "tokio-support")]
 use tokio::net::TcpStream as TokioTcpStream;
 
-#[cfg(all(feature = "async", feature = "tokio-support", unix))]
+#[cfg(all(feature = "tokio-support", unix))]
 use tokio::net::UnixStream as TokioUnixStream;
 
+#[cfg(all(feature = "tokio-support", unix))]
+use super::TokioConnection;
+
 #[cfg(test)]
 use std::borrow::ToOwned;
 
@@ -68,6 +71,24 @@ impl Connection for NameConnection {
             NameConnection::Socket(s) => s.read_packet(bytes, fds),
         }
     }
+
+    #[inline]
+    fn readable(&mut self) -> crate::Result<bool> {
+        match self {
+            NameConnection::Tcp(t) => t.readable(),
+            #[cfg(unix)]
+            NameConnection::Socket(s) => s.readable(),
+        }
+    }
+
+    #[inline]
+    fn shutdown_read(&mut self) -> crate::Result {
+        match self {
+            NameConnection::Tcp(t) => t.shutdown_read(),
+            #[cfg(unix)]
+            NameConnection::Socket(s) => s.shutdown_read(),
+        }
+    }
 }
 
 impl<'a> Connection for &'a NameConnection {
@@ -100,10 +121,44 @@ impl<'a> Connection for &'a NameConnection {
             }
         }
     }
+
+    #[inline]
+    fn readable(&mut self) -> crate::Result<bool> {
+        match self {
+            NameConnection::Tcp(ref t) => {
+                let mut t = t;
+                t.readable()
+            }
+            #[cfg(unix)]
+            NameConnection::Socket(ref s) => {
+                let mut s = s;
+                s.readable()
+            }
+        }
+    }
+
+    #[inline]
+    fn shutdown_read(&mut self) -> crate::Result {
+        match self {
+            NameConnection::Tcp(ref t) => {
+                let mut t = t;
+                t.shutdown_read()
+            }
+            #[cfg(unix)]
+            NameConnection::Socket(ref s) => {
+                let mut s = s;
+                s.shutdown_read()
+            }
+        }
+    }
 }
 
 /// An async version of the [`NameConnection`] object. See `NameConnection`'s documentation for more information.
-#[cfg(feature = "async")]
+///
+/// With the `tokio-support` feature enabled, this wraps `tokio`'s stream types in a lock so that it can be used
+/// immutably. On Unix systems, [`TokioConnection`](crate::display::TokioConnection) should be preferred instead,
+/// since it needs no lock and can send and receive file descriptors.
+#[cfg(any(feature = "async-io-support", feature = "tokio-support"))]
 pub enum AsyncNameConnection {
     #[cfg(not(feature = "tokio-support"))]
     #[doc(hidden)]
@@ -119,7 +174,7 @@ pub enum AsyncNameConnection {
     Socket(Spinlock<TokioUnixStream>),
 }
 
-#[cfg(feature = "async")]
+#[cfg(any(feature = "async-io-support", feature = "tokio-support"))]
 impl AsyncConnection for AsyncNameConnection {
     #[inline]
     fn poll_send_packet(
@@ -166,7 +221,7 @@ impl AsyncConnection for AsyncNameConnection {
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(any(feature = "async-io-support", feature = "tokio-support"))]
 impl<'a> AsyncConnection for &'a AsyncNameConnection {
     #[inline]
     fn poll_send_packet(
@@ -223,12 +278,12 @@ impl<'a> AsyncConnection for &'a AsyncNameConnection {
             AsyncNameConnection::Tcp(t) => t
                 .try_lock()
                 .expect("Tried to access tokio connection concurrently")
-                .poll_send_packet(bytes, fds, cx, bytes_read),
+                .poll_read_packet(bytes, fds, cx, bytes_read),
             #[cfg(all(feature = "tokio-support", unix))]
             AsyncNameConnection::Socket(s) => s
                 .try_lock()
                 .expect("Tried to access tokio connection concurrently")
-                .poll_send_packet(bytes, fds, cx, bytes_read),
+                .poll_read_packet(bytes, fds, cx, bytes_read),
         }
     }
 }
@@ -484,7 +539,7 @@ impl<'a> XConnection<'a> {
     }
 
     /// Open the connection via TCP, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(any(feature = "async-io-support", feature = "tokio-support"))]
     #[inline]
     async fn open_tcp_async(self) -> crate::Result<AsyncNameConnection> {
         let (host, port) = self.host_and_port();
@@ -522,7 +577,7 @@ impl<'a> XConnection<'a> {
     }
 
     /// Open a socket file on Unix, async redox.
-    #[cfg(all(feature = "async", unix))]
+    #[cfg(all(any(feature = "async-io-support", feature = "tokio-support"), unix))]
     async fn open_unix_async(self) -> crate::Result<AsyncNameConnection> {
         let fname = self.socket_filename()?;
         cfg_if::cfg_if! {
@@ -538,7 +593,7 @@ impl<'a> XConnection<'a> {
 
     /// Open an asynchronous connection.
     #[allow(unused_mut)]
-    #[cfg(feature = "async")]
+    #[cfg(any(feature = "async-io-support", feature = "tokio-support"))]
     pub async fn open_async(mut self) -> crate::Result<AsyncNameConnection> {
         // if the protocol or hostname isn't "unix", just run the tcp code
         if self.protocol != Some(Protocol::Unix)
@@ -579,11 +634,11 @@ impl NameConnection {
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(any(feature = "async-io-support", feature = "tokio-support"))]
 impl AsyncNameConnection {
     /// Open a new asynchronous connection.
     #[inline]
-    #[cfg(feature = "async")]
+    #[cfg(any(feature = "async-io-support", feature = "tokio-support"))]
     pub(crate) async fn connect_internal_async(
         name: Option<Cow<'_, str>>,
     ) -> crate::Result<(AsyncNameConnection, usize)> {
@@ -593,6 +648,25 @@ impl AsyncNameConnection {
     }
 }
 
+#[cfg(all(feature = "tokio-support", unix))]
+impl TokioConnection {
+    /// Open a new connection that is registered with the current `tokio` runtime.
+    #[inline]
+    pub(crate) async fn connect_internal_tokio(
+        name: Option<Cow<'_, str>>,
+    ) -> crate::Result<(TokioConnection, usize)> {
+        let (connection, screen) = AsyncNameConnection::connect_internal_async(name).await?;
+        // take the sockets out of tokio's wrappers, and register them ourselves
+        let connection = match connection {
+            AsyncNameConnection::Tcp(t) => TokioConnection::from_tcp(t.into_inner().into_std()?)?,
+            AsyncNameConnection::Socket(s) => {
+                TokioConnection::from_unix(s.into_inner().into_std()?)?
+            }
+        };
+        Ok((connection, screen))
+    }
+}
+
 #[cfg(test)]
 macro_rules! borrowed_test {
     ($name: expr, $res: expr) => {{
@@ -8,6 +8,10 @@ use crate::{auto::xproto::QueryExtensionRequest, log_debug, log_trace};
 use alloc::borrow::Cow;
 use core::mem;
 
+/// Assign a sequence number to the request and register it in the display's pending item map.
+///
+/// The request is registered before any of its bytes are sent, so that a reply read by another thread or task
+/// (e.g. one using a `&SyncDisplay`) can always be matched up with its request.
 #[inline]
 pub(crate) fn preprocess_request<D: DisplayBase + ?Sized>(
     display: &mut D,
@@ -19,14 +23,13 @@ pub(crate) fn preprocess_request<D: DisplayBase + ?Sized>(
     let sequence = sequence as u16;
 
     pr.set_sequence(sequence);
+    register_request(display, &pr, sequence);
     pr
 }
 
+/// Add the request to the pending item map, if we expect the server to reply to it.
 #[inline]
-pub(crate) fn finish_request<D: DisplayBase + ?Sized>(display: &mut D, mut pr: RequestInfo) -> u16 {
-    log_trace!("Entering finish_request() with request info: {:?}", &pr);
-
-    // data has already been sent over the bandwaves, make sure we acknowledge it
+fn register_request<D: DisplayBase + ?Sized>(display: &mut D, pr: &RequestInfo, seq: u16) {
     let mut flags = PendingRequestFlags {
         expects_fds: pr.expects_fds,
         discard_reply: pr.discard_reply,
@@ -50,16 +53,34 @@ pub(crate) fn finish_request<D: DisplayBase + ?Sized>(display: &mut D, mut pr: R
         _ => (),
     }
 
-    let seq = pr.sequence.take().expect("Failed to set sequence number");
-    log_debug!("Got sequence number {}", seq);
-
     if !pr.zero_sized_reply || display.checked() {
         log::trace!(
             "Request is neither zero-sized nor is the display not checked, so we expect a reply"
         );
         input::expect_reply(display, seq, flags);
     }
+}
+
+/// Called if sending the request failed. Since the server will never reply to the request, its entry in the
+/// pending item map is removed.
+#[inline]
+pub(crate) fn abort_request<D: DisplayBase + ?Sized>(display: &mut D, pr: &RequestInfo) {
+    if let Some(seq) = pr.sequence {
+        log_debug!("Failed to send request {}, unregistering it", seq);
+        display.take_pending_item(seq);
+    }
+}
+
+/// Called once the request's bytes have been sent across the connection. Returns the request's sequence
+/// number.
+#[inline]
+pub(crate) fn finish_request(mut pr: RequestInfo) -> u16 {
+    log_trace!("Entering finish_request() with request info: {:?}", &pr);
 
+    // data has already been sent over the bandwaves, and the request was registered when we assigned it a
+    // sequence number
+    let seq = pr.sequence.take().expect("Failed to set sequence number");
+    log_debug!("Got sequence number {}", seq);
     seq
 }
 
@@ -113,10 +134,13 @@ pub(crate) fn send_request<D: Display + ?Sized, C: Connection + ?Sized>(
     // send the packet
     log_debug!("Request is ready to send, beginning send_packet()");
     let mut fds = mem::take(&mut req.fds);
-    connection.send_packet(&req.data, &mut fds)?;
+    if let Err(e) = connection.send_packet(&req.data, &mut fds) {
+        abort_request(display, &req);
+        return Err(e);
+    }
     log_debug!("Finished send_packet()");
 
-    Ok(finish_request(display, req))
+    Ok(finish_request(req))
 }
 
 #[inline]
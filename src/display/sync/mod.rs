@@ -3,6 +3,9 @@
 mod mutex;
 use mutex::Mutex;
 
+mod split;
+pub use split::*;
+
 use super::{
     input, output, BasicDisplay, Connection, Display, DisplayBase, PendingItem, RequestInfo,
     StaticSetup, EXT_KEY_SIZE,
@@ -19,19 +22,19 @@ use core::{
 };
 use dashmap::DashMap;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use super::{
     common::{SendBuffer, WaitBuffer, WaitBufferReturn},
     AsyncConnection, AsyncDisplay, PollOr, RequestWorkaround,
 };
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use core::{
     mem,
     task::{Context, Poll},
 };
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use futures_lite::ready;
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use spinning_top::Spinlock;
 
 /// A display that uses concurrent primitives in order to allow for thread-safe immutable access to the X
@@ -42,12 +45,15 @@ use spinning_top::Spinlock;
 /// It is intended to be put into an `Arc`, synchronous `OnceCell`, or other multithread-accessible location
 /// and used in that way.
 ///
-/// Note that `SyncDisplay` is generally designed with the idea in mind that one thread will use the connection
-/// at a time. Although non-IO usage (e.g. `DisplayBase` functions) is entirely concurrent, the display will lock
-/// up while one thread uses it and unlock when it is done. If you expect this object to be under high levels of
-/// contention (e.g. multiple threads are attempting to send requests or listen to it at once), it is preferred
-/// to use a `Mutex<BasicDisplay>` or `RwLock<BasicDisplay>` instead. If thread safety is not required, using
-/// `CellDisplay` is much faster than using atomics and locks.
+/// Reading from the connection and writing to it are protected by two separate locks. Non-IO usage (e.g.
+/// `DisplayBase` functions) is entirely concurrent, and one thread or task can send requests while another is
+/// waiting for replies or events. Replies are sorted into the shared pending item map no matter which thread
+/// reads them, so several requests can be in flight from several threads at once. However, only one thread can
+/// write to the connection at a time, and only one can read from it at a time. If thread safety is not required,
+/// using `CellDisplay` is much faster than using atomics and locks.
+///
+/// If a thread-per-direction model is preferred, `SyncDisplay::split` moves reading onto a dedicated thread and
+/// returns a cloneable [`RequestHandle`] and an [`EventReceiver`].
 ///
 /// ## Construction
 ///
@@ -70,8 +76,14 @@ use spinning_top::Spinlock;
 pub struct SyncDisplay<Conn> {
     // the connection to the server
     connection: Option<Conn>,
-    // connection lock
-    io_lock: Mutex,
+    // connection locks; one for reading from the connection and one for writing to it, so that a task waiting
+    // for a reply doesn't prevent other tasks from sending requests
+    read_lock: Mutex,
+    write_lock: Mutex,
+    // whether or not the current holder of the write lock also holds the read lock, in order to query the
+    // server for an extension opcode
+    #[cfg(feature = "async-core")]
+    sender_holds_read_lock: AtomicBool,
 
     // setup from the server
     setup: StaticSetup,
@@ -113,10 +125,14 @@ pub struct SyncDisplay<Conn> {
 
     // we don't actually spin on these spinlocks, they're just used for mutable access that we can panic if
     // we get mutual access to it
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     wait_buffer: Spinlock<Option<WaitBuffer>>,
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     send_buffer: Spinlock<SendBuffer>,
+    // whether or not the request in the send buffer was abandoned by its future; if it was, it still holds the
+    // send locks, and whoever gets to it first needs to finish sending it
+    #[cfg(feature = "async-core")]
+    send_abandoned: AtomicBool,
 }
 
 impl<Conn> From<BasicDisplay<Conn>> for SyncDisplay<Conn> {
@@ -141,7 +157,10 @@ impl<Conn> From<BasicDisplay<Conn>> for SyncDisplay<Conn> {
 
         SyncDisplay {
             connection,
-            io_lock: Mutex::new(),
+            read_lock: Mutex::new(),
+            write_lock: Mutex::new(),
+            #[cfg(feature = "async-core")]
+            sender_holds_read_lock: AtomicBool::new(false),
             setup,
             bigreq_enabled,
             max_request_len,
@@ -157,10 +176,76 @@ impl<Conn> From<BasicDisplay<Conn>> for SyncDisplay<Conn> {
                 Some(wpa) => wpa.get(),
             }),
             checked: AtomicBool::new(checked),
-            #[cfg(feature = "async")]
+            #[cfg(feature = "async-core")]
             wait_buffer: Spinlock::new(None),
-            #[cfg(feature = "async")]
+            #[cfg(feature = "async-core")]
             send_buffer: Spinlock::new(Default::default()),
+            #[cfg(feature = "async-core")]
+            send_abandoned: AtomicBool::new(false),
+        }
+    }
+}
+
+impl<Conn> SyncDisplay<Conn> {
+    /// Whether or not sending this request requires us to ask the server for its extension's opcode. If it does,
+    /// the sender also needs to read from the connection, and has to hold the read lock as well as the write lock.
+    #[inline]
+    fn needs_extension_lookup(&self, req: &RequestInfo) -> bool {
+        match req.extension {
+            Some(extension) => !self.extensions.contains_key(&output::str_to_key(extension)),
+            None => false,
+        }
+    }
+
+    /// Try to acquire the locks needed to send the given request. Returns `false` and registers the waker if we
+    /// couldn't. The read lock is always acquired before the write lock, and released if the write lock isn't
+    /// available, so that senders can't deadlock with each other.
+    #[cfg(feature = "async-core")]
+    #[inline]
+    fn poll_lock_for_send(&self, req: &RequestInfo, cx: &mut Context<'_>) -> bool {
+        let needs_read_lock = self.needs_extension_lookup(req);
+        if needs_read_lock && self.read_lock.poll_lock(cx).is_pending() {
+            return false;
+        }
+
+        if self.write_lock.poll_lock(cx).is_pending() {
+            if needs_read_lock {
+                self.read_lock.unlock();
+            }
+            return false;
+        }
+
+        // we hold the write lock, so we're the only ones touching this flag
+        self.sender_holds_read_lock
+            .store(needs_read_lock, Ordering::Release);
+        true
+    }
+
+    /// Release the locks acquired by `poll_lock_for_send`.
+    #[cfg(feature = "async-core")]
+    #[inline]
+    fn unlock_for_send(&self) {
+        let holds_read_lock = self.sender_holds_read_lock.swap(false, Ordering::AcqRel);
+        self.write_lock.unlock();
+        if holds_read_lock {
+            self.read_lock.unlock();
+        }
+    }
+
+    /// Called when the future sending a request is dropped. If the request needs to be finished, it keeps the
+    /// send locks until it is; otherwise, they are released.
+    #[cfg(feature = "async-core")]
+    #[inline]
+    fn abandon_send(&self) {
+        let needs_finishing = self
+            .send_buffer
+            .try_lock()
+            .expect("Locking mechanism failed: send buffer is currently locked")
+            .abandon();
+        if needs_finishing {
+            self.send_abandoned.store(true, Ordering::Release);
+        } else {
+            self.unlock_for_send();
         }
     }
 }
@@ -291,38 +376,73 @@ impl<Conn> DisplayBase for SyncDisplay<Conn> {
 impl<Conn: Connection> Display for SyncDisplay<Conn> {
     #[inline]
     fn wait(&mut self) -> crate::Result {
-        self.io_lock.lock();
+        self.read_lock.lock();
         let mut connection = self.connection.take().expect("Poisoned!");
 
         let result = input::wait(self, &mut connection);
 
         self.connection = Some(connection);
-        self.io_lock.unlock();
+        self.read_lock.unlock();
         result
     }
 
     #[inline]
     fn send_request_raw(&mut self, req: RequestInfo) -> crate::Result<u16> {
-        self.io_lock.lock();
+        let needs_read_lock = self.needs_extension_lookup(&req);
+        if needs_read_lock {
+            self.read_lock.lock();
+        }
+        self.write_lock.lock();
         let mut connection = self.connection.take().expect("Poisoned!");
 
         let result = output::send_request(self, &mut connection, req);
 
         self.connection = Some(connection);
-        self.io_lock.unlock();
+        self.write_lock.unlock();
+        if needs_read_lock {
+            self.read_lock.unlock();
+        }
         result
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
+impl<Conn: AsyncConnection + Unpin> SyncDisplay<Conn> {
+    /// If a request was abandoned partway through being sent, poll to finish sending it and release the send
+    /// locks.
+    #[inline]
+    fn poll_finish_abandoned(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if !*self.send_abandoned.get_mut() {
+            return Poll::Ready(());
+        }
+
+        let mut send_buffer = mem::replace(self.send_buffer.get_mut(), SendBuffer::OccupiedHole);
+        let mut conn = self.connection.take().expect("Poisoned!");
+        let res = send_buffer.poll_finish_abandoned(self, &mut conn, cx);
+        *self.send_buffer.get_mut() = send_buffer;
+        self.connection = Some(conn);
+
+        if res.is_ready() {
+            *self.send_abandoned.get_mut() = false;
+            self.unlock_for_send();
+        }
+        res
+    }
+}
+
+#[cfg(feature = "async-core")]
 impl<Conn: AsyncConnection + Unpin> AsyncDisplay for SyncDisplay<Conn> {
     #[inline]
     fn poll_wait(&mut self, ctx: &mut Context<'_>) -> Poll<crate::Result> {
+        if self.poll_finish_abandoned(ctx).is_pending() {
+            return Poll::Pending;
+        }
+
         let mut conn = self.connection.take().expect("Poisoned!");
         let wait_buffer = match self.wait_buffer.get_mut() {
             Some(wait_buffer) => wait_buffer,
             None => {
-                if let Poll::Pending = self.io_lock.poll_lock(ctx) {
+                if let Poll::Pending = self.read_lock.poll_lock(ctx) {
                     self.connection = Some(conn);
                     return Poll::Pending;
                 }
@@ -350,7 +470,7 @@ impl<Conn: AsyncConnection + Unpin> AsyncDisplay for SyncDisplay<Conn> {
         let (bytes, fds) = match res {
             Poll::Pending => return Poll::Pending,
             Poll::Ready(res) => {
-                self.io_lock.unlock();
+                self.read_lock.unlock();
                 self.wait_buffer.get_mut().take();
                 match res {
                     Ok(WaitBufferReturn { data, fds }) => (data, fds),
@@ -368,13 +488,12 @@ impl<Conn: AsyncConnection + Unpin> AsyncDisplay for SyncDisplay<Conn> {
         req: RequestInfo,
         cx: &mut Context<'_>,
     ) -> PollOr<(), RequestInfo> {
-        match self.io_lock.poll_lock(cx) {
-            Poll::Ready(()) => {
-                self.send_buffer.get_mut().fill_hole(req);
-                PollOr::Ready(())
-            }
-            Poll::Pending => PollOr::Pending(req),
+        if self.poll_finish_abandoned(cx).is_pending() || !self.poll_lock_for_send(&req, cx) {
+            return PollOr::Pending(req);
         }
+
+        self.send_buffer.get_mut().fill_hole(req);
+        PollOr::Ready(())
     }
 
     #[inline]
@@ -387,15 +506,20 @@ impl<Conn: AsyncConnection + Unpin> AsyncDisplay for SyncDisplay<Conn> {
 
         if res.is_ready() {
             self.send_buffer.get_mut().dig_hole();
-            self.io_lock.unlock();
+            self.unlock_for_send();
         }
 
         match res {
-            Poll::Ready(Ok(pr)) => Poll::Ready(Ok(output::finish_request(self, pr))),
+            Poll::Ready(Ok(pr)) => Poll::Ready(Ok(output::finish_request(pr))),
             Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
             Poll::Pending => Poll::Pending,
         }
     }
+
+    #[inline]
+    fn abandon_send_request_raw(&mut self) {
+        self.abandon_send();
+    }
 }
 
 impl<'a, Conn> DisplayBase for &'a SyncDisplay<Conn> {
@@ -524,30 +648,39 @@ where
 {
     #[inline]
     fn wait(&mut self) -> crate::Result {
-        self.io_lock.lock();
+        self.read_lock.lock();
         let mut conn = self.connection.as_ref().expect("Poisoned");
         let result = input::wait(self, &mut conn);
-        self.io_lock.unlock();
+        self.read_lock.unlock();
         result
     }
 
     #[inline]
     fn send_request_raw(&mut self, req: RequestInfo) -> crate::Result<u16> {
-        self.io_lock.lock();
+        let needs_read_lock = self.needs_extension_lookup(&req);
+        if needs_read_lock {
+            self.read_lock.lock();
+        }
+        self.write_lock.lock();
         let mut conn = self.connection.as_ref().expect("Poisoned");
         let result = output::send_request(self, &mut conn, req);
-        self.io_lock.unlock();
+        self.write_lock.unlock();
+        if needs_read_lock {
+            self.read_lock.unlock();
+        }
         result
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 impl<'a, Conn> AsyncDisplay for &'a SyncDisplay<Conn>
 where
     &'a Conn: AsyncConnection,
 {
     #[inline]
     fn poll_wait(&mut self, ctx: &mut Context<'_>) -> Poll<crate::Result> {
+        ready!(poll_finish_abandoned_immutable(self, ctx));
+
         let mut conn = self.connection.as_ref().expect("Poisoned");
         let mut wbslot = self
             .wait_buffer
@@ -557,7 +690,7 @@ where
         let wait_buffer = match &mut *wbslot {
             Some(wait_buffer) => wait_buffer,
             None => {
-                ready!(self.io_lock.poll_lock(ctx));
+                ready!(self.read_lock.poll_lock(ctx));
                 let wait_buffer = Default::default();
                 *wbslot = Some(wait_buffer);
                 wbslot.as_mut().unwrap()
@@ -581,7 +714,7 @@ where
             Poll::Pending => return Poll::Pending,
             Poll::Ready(res) => {
                 wbslot.take();
-                self.io_lock.unlock();
+                self.read_lock.unlock();
                 match res {
                     Ok(WaitBufferReturn { data, fds }) => (data, fds),
                     Err(e) => return Poll::Ready(Err(e)),
@@ -598,16 +731,17 @@ where
         req: RequestInfo,
         cx: &mut Context<'_>,
     ) -> PollOr<(), RequestInfo> {
-        match self.io_lock.poll_lock(cx) {
-            Poll::Ready(()) => {
-                self.send_buffer
-                    .try_lock()
-                    .expect("Failed locking mechanism")
-                    .fill_hole(req);
-                PollOr::Ready(())
-            }
-            Poll::Pending => PollOr::Pending(req),
+        if poll_finish_abandoned_immutable(self, cx).is_pending()
+            || !self.poll_lock_for_send(&req, cx)
+        {
+            return PollOr::Pending(req);
         }
+
+        self.send_buffer
+            .try_lock()
+            .expect("Failed locking mechanism")
+            .fill_hole(req);
+        PollOr::Ready(())
     }
 
     #[inline]
@@ -623,15 +757,54 @@ where
 
         if res.is_ready() {
             sbslot.dig_hole();
-            self.io_lock.unlock();
+            self.unlock_for_send();
         }
 
         match res {
-            Poll::Ready(Ok(pr)) => Poll::Ready(Ok(output::finish_request(self, pr))),
+            Poll::Ready(Ok(pr)) => Poll::Ready(Ok(output::finish_request(pr))),
             Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
             Poll::Pending => Poll::Pending,
         }
     }
+
+    #[inline]
+    fn abandon_send_request_raw(&mut self) {
+        self.abandon_send();
+    }
+}
+
+/// If a request was abandoned partway through being sent, poll to finish sending it and release the send locks.
+/// Only one task can claim the abandoned request at a time; the others wait on the send locks as usual.
+#[cfg(feature = "async-core")]
+#[inline]
+fn poll_finish_abandoned_immutable<'a, Conn>(
+    display: &mut &'a SyncDisplay<Conn>,
+    cx: &mut Context<'_>,
+) -> Poll<()>
+where
+    &'a Conn: AsyncConnection,
+{
+    let this = *display;
+    if !this.send_abandoned.swap(false, Ordering::AcqRel) {
+        return Poll::Ready(());
+    }
+
+    let mut sbslot = this
+        .send_buffer
+        .try_lock()
+        .expect("Locking mechanism failed: send buffer is currently locked");
+    let mut send_buffer = mem::replace(&mut *sbslot, SendBuffer::OccupiedHole);
+    let mut conn = this.connection.as_ref().expect("Poisoned!");
+    let res = send_buffer.poll_finish_abandoned(display, &mut conn, cx);
+    *sbslot = send_buffer;
+
+    if res.is_ready() {
+        this.unlock_for_send();
+    } else {
+        // let whoever is polled next pick it back up
+        this.send_abandoned.store(true, Ordering::Release);
+    }
+    res
 }
 
 /// Convenience function to turn an iteratable struct (most often a `VecDeque`) into an unbounded
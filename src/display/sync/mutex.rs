@@ -4,7 +4,7 @@ use concurrent_queue::ConcurrentQueue;
 use core::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use core::task::{Context, Poll, Waker};
 
 /// A simple implementation of non-RAII mutex. It supports both blocking and non-blocking usage, in order to
@@ -54,7 +54,7 @@ impl Mutex {
     }
 
     /// Try to lock the mutex. If it is locked, register the waker.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub fn poll_lock(&self, cx: &mut Context<'_>) -> Poll<()> {
         // try to lock the mutex
@@ -104,7 +104,7 @@ enum ThreadOrWaker {
     /// A thread handle, used to unpark a given thread.
     Thread(thread::Thread),
     /// A waker, used to wake a given async task.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     Waker(Waker),
 }
 
@@ -114,7 +114,7 @@ impl ThreadOrWaker {
     pub(crate) fn wake(self) {
         match self {
             ThreadOrWaker::Thread(t) => t.unpark(),
-            #[cfg(feature = "async")]
+            #[cfg(feature = "async-core")]
             ThreadOrWaker::Waker(w) => w.wake(),
         }
     }
@@ -0,0 +1,549 @@
+// MIT/Apache2 License
+
+use super::SyncDisplay;
+use crate::{
+    auto::xproto::QueryExtensionRequest,
+    display::{
+        output, Connection, Display, DisplayBase, DisplayExt, PendingItem, RequestInfo,
+        StaticSetup, EXT_KEY_SIZE,
+    },
+    event::Event,
+    xid::XID,
+    BreadError,
+};
+use alloc::{borrow::Cow, boxed::Box, sync::Arc};
+use concurrent_queue::ConcurrentQueue;
+use core::{
+    fmt,
+    num::NonZeroU32,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+use std::thread;
+
+impl<Conn> SyncDisplay<Conn>
+where
+    Conn: Send + Sync + 'static,
+    for<'a> &'a Conn: Connection,
+{
+    /// Split this display into a thread-per-direction model.
+    ///
+    /// This spawns a dedicated reader thread, which owns the read half of the connection. It sorts replies and
+    /// errors into the pending item map, where they are picked up by whichever [`RequestHandle`] is waiting on
+    /// them, and forwards events to the returned [`EventReceiver`]. The `RequestHandle` can be cloned and sent
+    /// to any thread in order to send requests and resolve their cookies; since it implements `Display`, all of
+    /// the usual extension traits can be used with it.
+    ///
+    /// The reader thread runs until the connection is closed or returns an error, or until every handle and the
+    /// receiver have been dropped. In the latter case, the read half of the connection is shut down using
+    /// `Connection::shutdown_read` in order to wake the reader thread up, after which the connection is dropped
+    /// along with the display.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the reader thread could not be spawned.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use breadx::{display::{DisplayConnection, SyncDisplay}, prelude::*};
+    /// use std::thread;
+    ///
+    /// let conn: SyncDisplay<_> = DisplayConnection::create(None, None).unwrap().into();
+    /// let (mut handle, events) = conn.split().unwrap();
+    ///
+    /// let mut other_handle = handle.clone();
+    /// thread::spawn(move || {
+    ///     other_handle.intern_atom_immediate("WM_DELETE_WINDOW".to_owned(), false).unwrap();
+    /// });
+    ///
+    /// loop {
+    ///     let event = events.recv().unwrap();
+    ///     println!("Received event: {:?}", event);
+    /// }
+    /// ```
+    #[inline]
+    pub fn split(self) -> crate::Result<(RequestHandle<Conn>, EventReceiver)> {
+        let display = Arc::new(self);
+        let shared = Arc::new(SplitShared {
+            packets: AtomicU64::new(0),
+            closed: AtomicBool::new(false),
+            waiters: ConcurrentQueue::unbounded(),
+            events: ConcurrentQueue::unbounded(),
+            errors: ConcurrentQueue::unbounded(),
+        });
+
+        // events received before the split are handed over to the receiver
+        while let Ok(event) = display.event_queue.pop() {
+            shared.events.push(event).ok();
+        }
+
+        let reader_display = display.clone();
+        let reader_shared = shared.clone();
+        thread::Builder::new()
+            .name("breadx-reader".into())
+            .spawn(move || reader_loop(&reader_display, &reader_shared))?;
+
+        let shutdown_display = display.clone();
+        let guard = Arc::new(SplitGuard {
+            shared: shared.clone(),
+            shutdown_read: Box::new(move || {
+                let mut conn = shutdown_display.connection.as_ref().expect("Poisoned!");
+                conn.shutdown_read()
+            }),
+        });
+
+        Ok((
+            RequestHandle {
+                display,
+                shared: shared.clone(),
+                guard: guard.clone(),
+                seen: 0,
+            },
+            EventReceiver {
+                shared,
+                _guard: guard,
+            },
+        ))
+    }
+}
+
+/// The loop run by the reader thread.
+#[inline]
+fn reader_loop<Conn>(display: &SyncDisplay<Conn>, shared: &SplitShared)
+where
+    for<'a> &'a Conn: Connection,
+{
+    let mut display = display;
+    loop {
+        let res = display.wait();
+
+        while let Some(event) = display.pop_event() {
+            shared.events.push(event).ok();
+        }
+
+        match res {
+            Ok(()) => {}
+            // these errors don't belong to any request, but the connection is still usable
+            Err(err @ (BreadError::XProtocol { .. } | BreadError::NoMatchingRequest(_))) => {
+                shared.errors.push(err).ok();
+            }
+            // the handles and the receiver were dropped, and the connection was shut down as a result
+            Err(_) if shared.closed.load(Ordering::SeqCst) => {
+                log::debug!("Reader thread stopping since the display was dropped");
+                return;
+            }
+            Err(err) => {
+                log::error!("Reader thread stopping due to error: {:?}", &err);
+                shared.errors.push(err).ok();
+                shared.closed.store(true, Ordering::SeqCst);
+                shared.notify();
+                return;
+            }
+        }
+
+        shared.notify();
+    }
+}
+
+/// State shared between the reader thread, the request handles, and the event receiver.
+#[derive(Debug)]
+struct SplitShared {
+    // the number of packets the reader thread has processed
+    packets: AtomicU64,
+    // whether or not the reader thread has stopped, or is being stopped because the display was dropped
+    closed: AtomicBool,
+    // threads waiting on the reader thread to process a packet
+    waiters: ConcurrentQueue<thread::Thread>,
+    // events read from the connection
+    events: ConcurrentQueue<Event>,
+    // errors that didn't correspond to any request, as well as the error that stopped the reader thread
+    errors: ConcurrentQueue<BreadError>,
+}
+
+impl SplitShared {
+    /// Tell every waiting thread that a new packet has been processed.
+    #[inline]
+    fn notify(&self) {
+        self.packets.fetch_add(1, Ordering::SeqCst);
+        while let Ok(waiter) = self.waiters.pop() {
+            waiter.unpark();
+        }
+    }
+
+    /// Park the current thread until the reader thread processes another packet or stops, unless `ready`
+    /// returns `true`. `ready` is checked after registering, so that we don't miss a wakeup.
+    #[inline]
+    fn park_unless<F: FnOnce() -> bool>(&self, ready: F) {
+        self.waiters
+            .push(thread::current())
+            .unwrap_or_else(|_| panic!("Concurrent queue could not be pushed onto"));
+        if !ready() {
+            // note: spurious wakeups are fine, callers check their condition in a loop
+            thread::park();
+        }
+    }
+}
+
+/// Stops the reader thread once every [`RequestHandle`] and the [`EventReceiver`] have been dropped.
+struct SplitGuard {
+    shared: Arc<SplitShared>,
+    // shuts down the read half of the connection
+    shutdown_read: Box<dyn Fn() -> crate::Result + Send + Sync>,
+}
+
+impl fmt::Debug for SplitGuard {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SplitGuard")
+            .field("shared", &self.shared)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for SplitGuard {
+    #[inline]
+    fn drop(&mut self) {
+        if self.shared.closed.swap(true, Ordering::SeqCst) {
+            // the reader thread already stopped
+            return;
+        }
+
+        if let Err(e) = (self.shutdown_read)() {
+            log::error!("Failed to shut down the reader thread: {:?}", e);
+        }
+    }
+}
+
+/// A cloneable handle used to send requests to the X server after a [`SyncDisplay`] has been split.
+///
+/// See [`SyncDisplay::split`] for more information. The handle implements `Display`; its `wait` function does
+/// not read from the connection, but instead blocks until the reader thread has processed another reply, error
+/// or event. Events are taken from the same queue as the [`EventReceiver`], so they will only be delivered to
+/// one of the two.
+#[derive(Debug)]
+pub struct RequestHandle<Conn> {
+    display: Arc<SyncDisplay<Conn>>,
+    shared: Arc<SplitShared>,
+    guard: Arc<SplitGuard>,
+    // the number of processed packets as of the last time we waited
+    seen: u64,
+}
+
+impl<Conn> Clone for RequestHandle<Conn> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            display: self.display.clone(),
+            shared: self.shared.clone(),
+            guard: self.guard.clone(),
+            seen: self.seen,
+        }
+    }
+}
+
+impl<Conn> DisplayBase for RequestHandle<Conn> {
+    #[inline]
+    fn setup(&self) -> &StaticSetup {
+        &self.display.setup
+    }
+
+    #[inline]
+    fn default_screen_index(&self) -> usize {
+        self.display.default_screen
+    }
+
+    #[inline]
+    fn next_request_number(&mut self) -> u64 {
+        (&*self.display).next_request_number()
+    }
+
+    #[inline]
+    fn has_pending_event(&self) -> bool {
+        !self.shared.events.is_empty()
+    }
+
+    #[inline]
+    fn push_event(&mut self, event: Event) {
+        self.shared.events.push(event).ok();
+    }
+
+    #[inline]
+    fn pop_event(&mut self) -> Option<Event> {
+        self.shared.events.pop().ok()
+    }
+
+    #[inline]
+    fn generate_xid(&mut self) -> Option<XID> {
+        (&*self.display).generate_xid()
+    }
+
+    #[inline]
+    fn add_pending_item(&mut self, req_id: u16, item: PendingItem) {
+        (&*self.display).add_pending_item(req_id, item);
+    }
+
+    #[inline]
+    fn get_pending_item(&mut self, req_id: u16) -> Option<PendingItem> {
+        (&*self.display).get_pending_item(req_id)
+    }
+
+    #[inline]
+    fn take_pending_item(&mut self, req_id: u16) -> Option<PendingItem> {
+        (&*self.display).take_pending_item(req_id)
+    }
+
+    #[inline]
+    fn create_special_event_queue(&mut self, xid: XID) {
+        (&*self.display).create_special_event_queue(xid);
+    }
+
+    #[inline]
+    fn push_special_event(&mut self, xid: XID, event: Event) -> Result<(), Event> {
+        (&*self.display).push_special_event(xid, event)
+    }
+
+    #[inline]
+    fn pop_special_event(&mut self, xid: XID) -> Option<Event> {
+        (&*self.display).pop_special_event(xid)
+    }
+
+    #[inline]
+    fn delete_special_event_queue(&mut self, xid: XID) {
+        (&*self.display).delete_special_event_queue(xid);
+    }
+
+    #[inline]
+    fn checked(&self) -> bool {
+        self.display.checked()
+    }
+
+    #[inline]
+    fn set_checked(&mut self, checked: bool) {
+        (&*self.display).set_checked(checked);
+    }
+
+    #[inline]
+    fn bigreq_enabled(&self) -> bool {
+        self.display.bigreq_enabled
+    }
+
+    #[inline]
+    fn max_request_len(&self) -> usize {
+        self.display.max_request_len
+    }
+
+    #[inline]
+    fn get_extension_opcode(&mut self, key: &[u8; EXT_KEY_SIZE]) -> Option<u8> {
+        (&*self.display).get_extension_opcode(key)
+    }
+
+    #[inline]
+    fn set_extension_opcode(&mut self, key: [u8; EXT_KEY_SIZE], opcode: u8) {
+        (&*self.display).set_extension_opcode(key, opcode);
+    }
+
+    #[inline]
+    fn wm_protocols_atom(&self) -> Option<NonZeroU32> {
+        self.display.wm_protocols_atom()
+    }
+
+    #[inline]
+    fn set_wm_protocols_atom(&mut self, a: NonZeroU32) {
+        (&*self.display).set_wm_protocols_atom(a);
+    }
+}
+
+impl<Conn> Display for RequestHandle<Conn>
+where
+    for<'a> &'a Conn: Connection,
+{
+    #[inline]
+    fn wait(&mut self) -> crate::Result {
+        loop {
+            let packets = self.shared.packets.load(Ordering::SeqCst);
+            if packets != self.seen {
+                self.seen = packets;
+                return Ok(());
+            }
+
+            if self.shared.closed.load(Ordering::SeqCst) {
+                return Err(BreadError::ClosedConnection);
+            }
+
+            let shared = &self.shared;
+            shared.park_unless(|| {
+                shared.packets.load(Ordering::SeqCst) != packets
+                    || shared.closed.load(Ordering::SeqCst)
+            });
+        }
+    }
+
+    #[inline]
+    fn send_request_raw(&mut self, req: RequestInfo) -> crate::Result<u16> {
+        // the reader thread owns the read half of the connection, so we can't read the reply to a
+        // QueryExtensionRequest in the middle of sending; resolve it like any other request beforehand
+        if let Some(extension) = req.extension {
+            let key = output::str_to_key(extension);
+            if self.get_extension_opcode(&key).is_none() {
+                let qer = self.exchange_request(QueryExtensionRequest {
+                    name: Cow::Borrowed(extension),
+                    ..Default::default()
+                })?;
+                if !qer.present {
+                    return Err(BreadError::ExtensionNotPresent(extension.into()));
+                }
+                self.set_extension_opcode(key, qer.major_opcode);
+            }
+        }
+
+        let display = self.display.clone();
+        display.write_lock.lock();
+        let mut conn = display.connection.as_ref().expect("Poisoned!");
+        let result = output::send_request(self, &mut conn, req);
+        display.write_lock.unlock();
+        result
+    }
+}
+
+/// Receives events from the reader thread after a [`SyncDisplay`] has been split.
+///
+/// See [`SyncDisplay::split`] for more information. Errors sent by the server that don't correspond to any
+/// pending request (e.g. errors for unchecked requests) are also delivered here.
+#[derive(Debug)]
+pub struct EventReceiver {
+    shared: Arc<SplitShared>,
+    // only held so that the reader thread keeps running
+    _guard: Arc<SplitGuard>,
+}
+
+impl EventReceiver {
+    /// Receive an event, or an error without a corresponding request, if one is available. Returns `None` if
+    /// there is nothing to receive.
+    #[inline]
+    #[must_use]
+    pub fn try_recv(&self) -> Option<crate::Result<Event>> {
+        if let Ok(err) = self.shared.errors.pop() {
+            return Some(Err(err));
+        }
+        self.shared.events.pop().ok().map(Ok)
+    }
+
+    /// Block until an event, or an error without a corresponding request, is received.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error sent by the server if there was one. If the reader thread stopped due to an error, that
+    /// error is returned first, and `BreadError::ClosedConnection` is returned afterwards.
+    #[inline]
+    pub fn recv(&self) -> crate::Result<Event> {
+        loop {
+            if let Some(res) = self.try_recv() {
+                return res;
+            }
+
+            if self.shared.closed.load(Ordering::SeqCst) {
+                return Err(BreadError::ClosedConnection);
+            }
+
+            let shared = &self.shared;
+            shared.park_unless(|| {
+                !shared.events.is_empty()
+                    || !shared.errors.is_empty()
+                    || shared.closed.load(Ordering::SeqCst)
+            });
+        }
+    }
+}
+
+/// Split a display connected to one end of a socket pair, and return the other end, which the test uses as the
+/// server. The server's replies are written using a `DummyConnection`.
+#[cfg(all(test, unix))]
+fn split_socket_pair() -> (
+    RequestHandle<std::os::unix::net::UnixStream>,
+    EventReceiver,
+    std::os::unix::net::UnixStream,
+) {
+    use crate::display::{connection::dummy::DummyConnection, BasicDisplay};
+    use std::{io::Write, os::unix::net::UnixStream};
+
+    let (client, server) = UnixStream::pair().unwrap();
+    // the setup, and the reply to the BIG-REQUESTS query
+    (&server)
+        .write_all(&DummyConnection::new().take_reply_bytes())
+        .unwrap();
+
+    let display: SyncDisplay<_> = BasicDisplay::from_connection(client, 0, None)
+        .unwrap()
+        .into();
+    let (handle, events) = display.split().unwrap();
+    (handle, events, server)
+}
+
+#[cfg(unix)]
+#[test]
+fn split_routes_replies_and_events() {
+    use crate::{
+        auto::xproto::{
+            GetInputFocusReply, GetInputFocusRequest, InputFocus, KeyPressEvent, Window,
+        },
+        display::connection::dummy::DummyConnection,
+    };
+    use std::io::{Read, Write};
+
+    let (mut handle, events, server) = split_socket_pair();
+
+    let mut server_handle = server.try_clone().unwrap();
+    let server_thread = thread::spawn(move || {
+        // the setup request, the BIG-REQUESTS query, and the GetInputFocus request
+        let mut request = [0; 12 + 20 + 4];
+        server_handle.read_exact(&mut request).unwrap();
+        assert_eq!(request[32], 43);
+
+        let mut script = DummyConnection::with_no_defaults();
+        script.reply_padded(KeyPressEvent {
+            event_type: 2,
+            detail: 38,
+            sequence: 1,
+            ..Default::default()
+        });
+        script.reply_padded(GetInputFocusReply {
+            reply_type: 1,
+            revert_to: InputFocus::Parent,
+            sequence: 2,
+            length: 0,
+            focus: Window::const_from_xid(0x1234),
+        });
+        server_handle.write_all(&script.take_reply_bytes()).unwrap();
+    });
+
+    let focus = handle
+        .exchange_request(GetInputFocusRequest::default())
+        .unwrap();
+    assert_eq!(focus.focus, Window::const_from_xid(0x1234));
+    server_thread.join().unwrap();
+
+    match events.recv().unwrap() {
+        Event::KeyPress(press) => assert_eq!(press.detail, 38),
+        event => panic!("Unexpected event: {:?}", event),
+    }
+    assert!(events.try_recv().is_none());
+}
+
+#[cfg(unix)]
+#[test]
+fn split_reader_stops_once_dropped() {
+    use std::{io::Read, time::Duration};
+
+    let (handle, events, mut server) = split_socket_pair();
+    let other_handle = handle.clone();
+    drop(handle);
+    drop(events);
+    drop(other_handle);
+
+    // once the reader thread stops, the display and its connection are dropped, closing the socket
+    server
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    let mut sent = alloc::vec![];
+    server.read_to_end(&mut sent).unwrap();
+}
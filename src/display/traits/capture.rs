@@ -0,0 +1,375 @@
+// MIT/Apache2 License
+
+//! This module contains functions for capturing the contents of windows, pixmaps and screens.
+
+use crate::{
+    auto::xproto::{Drawable, GetImageRequest, ImageFormat, Rectangle},
+    display::{prelude::*, Display},
+    image::{bits_per_pixel, Image},
+    util::roundup,
+    BreadError, RequestCookie,
+};
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::convert::TryFrom;
+
+#[cfg(all(feature = "shm", feature = "std", unix))]
+use crate::image::ShmImage;
+
+#[cfg(feature = "composite")]
+use crate::{
+    auto::{
+        composite::{NameWindowPixmapRequest, QueryVersionRequest as CompositeQueryVersionRequest},
+        xproto::{Pixmap, Window},
+    },
+    display::generate_xid,
+};
+
+/// The size of the header of a `GetImage` reply.
+const GET_IMAGE_REPLY_HEADER: usize = 32;
+
+/// Figure out how many rows of an image fit into a single `GetImage` reply.
+#[inline]
+fn rows_per_strip(max_request_len: usize, bytes_per_line: usize, height: usize) -> usize {
+    let rows = max_request_len.saturating_sub(GET_IMAGE_REPLY_HEADER) / bytes_per_line.max(1);
+    rows.max(1).min(height.max(1))
+}
+
+/// Capture an area of a drawable using a series of `GetImage` requests, each of which gets a strip of rows
+/// that fits into `max_request_len` bytes.
+#[inline]
+fn capture_strips<D: Display + ?Sized>(
+    display: &mut D,
+    target: Drawable,
+    rect: Rectangle,
+    depth: u8,
+    max_request_len: usize,
+) -> crate::Result<Image<Box<[u8]>>> {
+    let (width, height) = (usize::from(rect.width), usize::from(rect.height));
+    let scanline_pad = display.get_scanline_pad(depth);
+    let bytes_per_line = roundup(
+        usize::from(bits_per_pixel(display, depth)) * width,
+        scanline_pad,
+    ) >> 3;
+    let rows = rows_per_strip(max_request_len, bytes_per_line, height);
+
+    // make sure that the offset of every strip fits into the request
+    i16::try_from(height.saturating_sub(1))
+        .ok()
+        .and_then(|offset| rect.y.checked_add(offset))
+        .ok_or(BreadError::StaticMsg("Capture area is out of range"))?;
+
+    // send out every request before waiting for any of the replies
+    let mut strips: Vec<(usize, RequestCookie<GetImageRequest>)> =
+        Vec::with_capacity(height / rows + 1);
+    for y in (0..height).step_by(rows) {
+        let strip_height = rows.min(height - y);
+        let cookie = display.send_request(GetImageRequest {
+            drawable: target,
+            x: rect.x,
+            y: rect.y + y as i16,
+            width: rect.width,
+            height: strip_height as u16,
+            plane_mask: u32::MAX,
+            format: ImageFormat::ZPixmap,
+            ..Default::default()
+        })?;
+        strips.push((y, cookie));
+    }
+
+    let mut data = vec![0; bytes_per_line * height].into_boxed_slice();
+    let mut visual = 0;
+    for (y, cookie) in strips {
+        let reply = display.resolve_request(cookie)?;
+        visual = reply.visual;
+        let dest = &mut data[y * bytes_per_line..];
+        let len = dest.len().min(reply.data.len());
+        dest[..len].copy_from_slice(&reply.data[..len]);
+    }
+
+    Image::new(
+        display,
+        display.visual_id_to_visual(visual),
+        depth,
+        ImageFormat::ZPixmap,
+        0,
+        data,
+        width,
+        height,
+        scanline_pad as u32,
+        None,
+    )
+    .ok_or(BreadError::StaticMsg("Failed to create captured image"))
+}
+
+/// Capture an area of a drawable by having the X server write it into a shared memory segment.
+#[cfg(all(feature = "shm", feature = "std", unix))]
+#[inline]
+fn capture_shm<D: Display + ?Sized>(
+    display: &mut D,
+    target: Drawable,
+    rect: Rectangle,
+    depth: u8,
+) -> crate::Result<Image<Box<[u8]>>> {
+    let mut image = ShmImage::new(display, None, depth, rect.width, rect.height)?;
+    if !image.is_shared() {
+        image.destroy(display)?;
+        return Err(BreadError::StaticMsg(
+            "Unable to share memory with the X server",
+        ));
+    }
+
+    let res = image.get(display, target, rect.x, rect.y);
+    let image = image.destroy(display)?;
+    res.map(|()| image)
+}
+
+/// Capturing the contents of drawables.
+///
+/// Captured images are always in the `ZPixmap` format, contain every plane of the drawable, and carry the
+/// masks of the drawable's visual, so they can be converted using `Image::to_rgba_image` and friends.
+pub trait DisplayCaptureExt: Display {
+    /// Capture an area of a drawable.
+    ///
+    /// Areas that don't fit into a single `GetImage` reply are split into strips of rows, which are requested
+    /// all at once and then put back together. If the `shm` feature is enabled and the server supports the
+    /// MIT-SHM extension, the image is transferred through a shared memory segment instead; if that fails,
+    /// e.g. because the server is remote, this falls back to `GetImage`.
+    #[inline]
+    fn capture<Target: Into<Drawable>>(
+        &mut self,
+        target: Target,
+        rect: Rectangle,
+    ) -> crate::Result<Image<Box<[u8]>>> {
+        let target = target.into();
+        let depth = self.get_drawable_geometry_immediate(target)?.depth;
+
+        #[cfg(all(feature = "shm", feature = "std", unix))]
+        match capture_shm(self, target, rect.clone(), depth) {
+            Ok(image) => return Ok(image),
+            Err(e) => log::debug!("Unable to capture using MIT-SHM, using GetImage: {:?}", e),
+        }
+
+        let max_request_len = self.max_request_len();
+        capture_strips(self, target, rect, depth, max_request_len)
+    }
+
+    /// Capture the entire root window of a screen. Since the root window spans every monitor attached to the
+    /// screen, this captures all of them at once.
+    #[inline]
+    fn capture_screen(&mut self, screen: usize) -> crate::Result<Image<Box<[u8]>>> {
+        let (root, width, height) = match self.screens().get(screen) {
+            Some(screen) => (screen.root, screen.width_in_pixels, screen.height_in_pixels),
+            None => return Err(BreadError::StaticMsg("Screen index is out of range")),
+        };
+
+        self.capture(
+            root,
+            Rectangle {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            },
+        )
+    }
+
+    /// Capture an area of a window as it was drawn before compositing, using the Composite extension.
+    ///
+    /// Unlike `capture`, this includes the parts of the window that are covered by other windows. The window
+    /// has to be redirected, which is usually the case when a compositing manager is running; otherwise, the
+    /// server reports a `Match` error.
+    #[cfg(feature = "composite")]
+    #[inline]
+    fn capture_composited(
+        &mut self,
+        window: Window,
+        rect: Rectangle,
+    ) -> crate::Result<Image<Box<[u8]>>> {
+        self.exchange_request(CompositeQueryVersionRequest {
+            client_major_version: 0,
+            client_minor_version: 4,
+            ..Default::default()
+        })?;
+
+        // the pixmap has no visual of its own, so GetImage replies without one; the masks are taken from the
+        // window's visual instead
+        let visual = window.window_attributes_immediate(self)?.visual;
+
+        let pixmap = Pixmap::const_from_xid(generate_xid(self)?);
+        self.exchange_request(NameWindowPixmapRequest {
+            window,
+            pixmap,
+            ..Default::default()
+        })?;
+
+        let res = self.capture(pixmap, rect);
+        pixmap.free(self)?;
+
+        let mut image = res?;
+        if let Some(visual) = self.visual_id_to_visual(visual) {
+            image.red_mask = visual.red_mask;
+            image.green_mask = visual.green_mask;
+            image.blue_mask = visual.blue_mask;
+        }
+        Ok(image)
+    }
+}
+
+impl<D: Display + ?Sized> DisplayCaptureExt for D {}
+
+#[test]
+fn capture_splits_into_strips() {
+    use crate::{
+        auto::xproto::GetImageReply,
+        display::{BasicDisplay, DisplayBase},
+    };
+    use alloc::borrow::Cow;
+
+    assert_eq!(rows_per_strip(32 + 4 * 10, 10, 100), 4);
+    assert_eq!(rows_per_strip(16, 10, 100), 1);
+    assert_eq!(rows_per_strip(usize::MAX, 10, 100), 100);
+
+    // a 2x5 image at depth 24 takes up 8 bytes per line, so 2 lines fit into 48 bytes
+    let mut display = BasicDisplay::dummy();
+    let visual = display.default_visual().visual_id;
+    let strips: [&[u8]; 3] = [&[1; 16], &[2; 16], &[3; 8]];
+    for (i, strip) in strips.iter().enumerate() {
+        display.connection_mut().reply_padded(GetImageReply {
+            reply_type: 1,
+            depth: 24,
+            sequence: i as u16 + 2,
+            length: (strip.len() / 4) as u32,
+            visual,
+            data: Cow::Borrowed(strip),
+        });
+    }
+
+    let root = display.default_root();
+    let image = capture_strips(
+        &mut display,
+        root.into(),
+        Rectangle {
+            x: 10,
+            y: 20,
+            width: 2,
+            height: 5,
+        },
+        24,
+        48,
+    )
+    .unwrap();
+
+    assert_eq!((image.width, image.height, image.bytes_per_line), (2, 5, 8));
+    assert_eq!(image.red_mask, display.default_visual().red_mask);
+    assert_eq!(image.pixel(1, 1), 0x0001_0101);
+    assert_eq!(image.pixel(0, 2), 0x0002_0202);
+    assert_eq!(image.pixel(1, 4), 0x0003_0303);
+}
+
+#[cfg(feature = "composite")]
+#[test]
+fn capture_composited_uses_window_visual() {
+    use crate::{
+        auto::{
+            composite::QueryVersionReply,
+            xproto::{
+                GetGeometryReply, GetImageReply, GetWindowAttributesReply, QueryExtensionReply,
+            },
+            AsByteSequence,
+        },
+        display::{BasicDisplay, DisplayBase},
+    };
+    use alloc::borrow::Cow;
+
+    let mut display = BasicDisplay::dummy();
+    display.set_checked(false);
+    let window = Window::const_from_xid(0x40_0001);
+    let visual = display.default_visual().visual_id;
+
+    let conn = display.connection_mut();
+    conn.reply_padded(QueryExtensionReply {
+        reply_type: 1,
+        sequence: 2,
+        present: true,
+        major_opcode: 142,
+        ..Default::default()
+    });
+    conn.reply_padded(QueryVersionReply {
+        reply_type: 1,
+        sequence: 3,
+        major_version: 0,
+        minor_version: 4,
+        ..Default::default()
+    });
+    // the generated reply is two bytes longer than the one on the wire, since it reads the trailing padding
+    // into `do_not_propagate_mask`
+    let attributes = GetWindowAttributesReply {
+        reply_type: 1,
+        sequence: 4,
+        length: 3,
+        visual,
+        ..Default::default()
+    };
+    let mut bytes = vec![0; attributes.size()];
+    attributes.as_bytes(&mut bytes);
+    conn.reply_bytes(bytes.into_iter().take(44));
+    // NameWindowPixmap has no reply
+    conn.reply_padded(GetGeometryReply {
+        reply_type: 1,
+        depth: 24,
+        sequence: 6,
+        width: 1,
+        height: 1,
+        ..Default::default()
+    });
+    let mut sequence = 7;
+    if cfg!(all(feature = "shm", feature = "std", unix)) {
+        conn.reply_padded(QueryExtensionReply {
+            reply_type: 1,
+            sequence,
+            present: false,
+            ..Default::default()
+        });
+        sequence += 1;
+    }
+    conn.reply_padded(GetImageReply {
+        reply_type: 1,
+        depth: 24,
+        sequence,
+        length: 1,
+        visual: 0,
+        data: Cow::Borrowed(&[1, 2, 3, 0]),
+    });
+
+    let image = display
+        .capture_composited(
+            window,
+            Rectangle {
+                x: 0,
+                y: 0,
+                width: 1,
+                height: 1,
+            },
+        )
+        .unwrap();
+    let default_visual = display.default_visual();
+    assert_eq!(image.red_mask, default_visual.red_mask);
+    assert_eq!(image.blue_mask, default_visual.blue_mask);
+    assert_ne!(image.red_mask, 0);
+    assert_eq!(image.pixel(0, 0), 0x0003_0201);
+
+    // strips that start past i16::MAX can't be requested
+    assert!(capture_strips(
+        &mut display,
+        window.into(),
+        Rectangle {
+            x: 0,
+            y: i16::MAX - 1,
+            width: 1,
+            height: 4,
+        },
+        24,
+        usize::MAX,
+    )
+    .is_err());
+}
@@ -21,7 +21,7 @@ use crate::{
 use alloc::{vec, vec::Vec};
 use cty::c_int;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::{
     display::{
         futures::{ExchangeRequestFuture, ExchangeXidFuture, MapFuture, SendRequestFuture},
@@ -29,7 +29,7 @@ use crate::{
     },
     util::BoxedFnOnce,
 };
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use alloc::boxed::Box;
 
 #[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -256,7 +256,7 @@ pub trait DisplayDri3Ext: Display {
 
 impl<D: Display + ?Sized> DisplayDri3Ext for D {}
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub trait AsyncDisplayDri3Ext: AsyncDisplay {
     #[inline]
     fn open_dri3_async<Target: Into<Drawable>>(
@@ -518,5 +518,5 @@ pub trait AsyncDisplayDri3Ext: AsyncDisplay {
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 impl<D: AsyncDisplay + ?Sized> AsyncDisplayDri3Ext for D {}
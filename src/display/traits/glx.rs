@@ -17,7 +17,7 @@ use crate::{
 use alloc::{borrow::Cow, vec::Vec};
 use core::convert::TryInto;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::{
     auto::glx::{GetDrawableAttributesReply, QueryVersionReply},
     display::{
@@ -26,7 +26,7 @@ use crate::{
     },
     util::BoxedFnOnce,
 };
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use alloc::boxed::Box;
 
 impl From<xproto::Drawable> for Drawable {
@@ -209,7 +209,7 @@ pub trait DisplayGlxExt: Display {
 
 impl<D: Display + ?Sized> DisplayGlxExt for D {}
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub trait AsyncDisplayGlxExt: AsyncDisplay {
     /// Query GLX version, async redox.
     #[inline]
@@ -381,5 +381,5 @@ pub trait AsyncDisplayGlxExt: AsyncDisplay {
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 impl<D: AsyncDisplay + ?Sized> AsyncDisplayGlxExt for D {}
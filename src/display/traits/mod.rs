@@ -3,6 +3,7 @@
 //! Traits and objects used to give displays functionality.
 //#![allow(unused_qualifications)]
 
+mod capture;
 mod xproto;
 
 #[cfg(feature = "dri3")]
@@ -15,6 +16,8 @@ mod present;
 mod sync;
 #[cfg(feature = "fixes")]
 mod xfixes;
+#[cfg(feature = "xkb")]
+mod xkb;
 
 #[cfg(feature = "dri3")]
 pub use dri3::*;
@@ -26,4 +29,7 @@ pub use present::*;
 pub use sync::*;
 #[cfg(feature = "fixes")]
 pub use xfixes::*;
+#[cfg(feature = "xkb")]
+pub use xkb::*;
+pub use capture::*;
 pub use xproto::*;
@@ -19,7 +19,7 @@ use crate::{
 };
 use alloc::vec::Vec;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::{
     auto::present::{QueryCapabilitiesReply, QueryVersionReply},
     display::{
@@ -134,7 +134,7 @@ pub trait DisplayPresentExt: Display {
 
 impl<D: Display + ?Sized> DisplayPresentExt for D {}
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub trait AsyncDisplayPresentExt: AsyncDisplay {
     #[inline]
     fn query_present_version_async(
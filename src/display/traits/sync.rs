@@ -5,7 +5,7 @@ use crate::{
     display::{prelude::*, Display},
 };
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::display::{futures::ExchangeRequestFuture, AsyncDisplay};
 
 pub trait DisplaySyncExt: Display {
@@ -28,7 +28,7 @@ pub trait DisplaySyncExt: Display {
 
 impl<D: Display + ?Sized> DisplaySyncExt for D {}
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub trait AsyncDisplaySyncExt: AsyncDisplay {
     #[inline]
     fn trigger_fence_async(
@@ -53,5 +53,5 @@ pub trait AsyncDisplaySyncExt: AsyncDisplay {
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 impl<D: AsyncDisplay + ?Sized> AsyncDisplaySyncExt for D {}
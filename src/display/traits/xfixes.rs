@@ -9,7 +9,7 @@ use crate::{
 };
 use alloc::vec::Vec;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::{
     display::{
         futures::{ExchangeRequestFuture, ExchangeXidFuture},
@@ -17,7 +17,7 @@ use crate::{
     },
     util::BoxedFnOnce,
 };
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use alloc::boxed::Box;
 
 pub trait DisplayXfixesExt: Display {
@@ -35,7 +35,7 @@ pub trait DisplayXfixesExt: Display {
 
 impl<D: Display + ?Sized> DisplayXfixesExt for D {}
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub trait AsyncDisplayXfixesExt: AsyncDisplay {
     #[inline]
     fn create_region_async(
@@ -60,7 +60,7 @@ pub trait AsyncDisplayXfixesExt: AsyncDisplay {
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 impl<D: AsyncDisplay + ?Sized> AsyncDisplayXfixesExt for D {}
 
 impl Region {
@@ -72,7 +72,7 @@ impl Region {
         })
     }
 
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub fn destroy_async<Dpy: AsyncDisplay + ?Sized>(
         self,
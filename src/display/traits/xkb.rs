@@ -0,0 +1,700 @@
+// MIT/Apache2 License
+
+use crate::{
+    auto::{
+        xkb::{
+            BellRequest, GetIndicatorStateRequest, PerClientFlag, PerClientFlagsReply,
+            PerClientFlagsRequest, UseExtensionReply, UseExtensionRequest,
+        },
+        AsByteSequence,
+    },
+    display::{prelude::*, Display},
+    extension::ExtensionVersion,
+    Atom, BreadError, Request, Window,
+};
+use alloc::borrow::Cow;
+
+#[cfg(feature = "async-core")]
+use crate::{
+    auto::{
+        xkb::GetIndicatorStateReply,
+        xproto::{InternAtomReply, InternAtomRequest},
+    },
+    display::{
+        futures::{ExchangeRequestFuture, ExchangeThenFuture, MapFuture, ToggleIndicatorFuture},
+        AsyncDisplay,
+    },
+    util::BoxedFnOnce,
+};
+#[cfg(feature = "async-core")]
+use alloc::boxed::Box;
+
+#[cfg(feature = "async-core")]
+type UseExtensionMap = fn(crate::Result<UseExtensionReply>) -> crate::Result<ExtensionVersion>;
+#[cfg(feature = "async-core")]
+type IndicatorStateMap = fn(crate::Result<GetIndicatorStateReply>) -> crate::Result<u32>;
+#[cfg(feature = "async-core")]
+type InternNamedIndicator = fn(InternAtomReply) -> crate::Result<NamedIndicatorRequest>;
+#[cfg(feature = "async-core")]
+type NamedIndicatorMap =
+    fn(crate::Result<NamedIndicatorReply>) -> crate::Result<Option<NamedIndicator>>;
+#[cfg(feature = "async-core")]
+type PerClientFlagsMap = fn(crate::Result<PerClientFlagsReply>) -> crate::Result<bool>;
+
+const XKB_MAJOR_VERSION: u16 = 1;
+const XKB_MINOR_VERSION: u16 = 0;
+
+// XkbUseCoreKbd, XkbDfltXIClass and XkbDfltXIId
+const USE_CORE_KBD: u16 = 0x0100;
+const DFLT_XI_CLASS: u16 = 0x0300;
+const DFLT_XI_ID: u16 = 0x0400;
+
+/// The state of an XKB indicator, as looked up by name.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct NamedIndicator {
+    /// The name of the indicator, e.g. `Caps Lock`.
+    pub name: Atom,
+    /// Whether or not the indicator is lit.
+    pub on: bool,
+    /// The index of the physical LED that shows the indicator, if there is one.
+    pub led: Option<u8>,
+}
+
+// note: the generated GetNamedIndicator reply and SetNamedIndicator request use two bytes for modifier masks,
+//       while XKB only uses one, so they are encoded by hand here
+
+/// The `GetNamedIndicator` request, encoded as the XKB protocol specifies.
+#[doc(hidden)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NamedIndicatorRequest {
+    pub req_type: u8,
+    pub length: u16,
+    pub indicator: Atom,
+}
+
+impl AsByteSequence for NamedIndicatorRequest {
+    #[inline]
+    fn size(&self) -> usize {
+        16
+    }
+
+    #[inline]
+    fn as_bytes(&self, bytes: &mut [u8]) -> usize {
+        self.req_type.as_bytes(&mut bytes[0..]);
+        self.length.as_bytes(&mut bytes[2..]);
+        USE_CORE_KBD.as_bytes(&mut bytes[4..]);
+        DFLT_XI_CLASS.as_bytes(&mut bytes[6..]);
+        DFLT_XI_ID.as_bytes(&mut bytes[8..]);
+        self.indicator.as_bytes(&mut bytes[12..]);
+        16
+    }
+
+    #[inline]
+    fn from_bytes(bytes: &[u8]) -> Option<(Self, usize)> {
+        let (req_type, _) = u8::from_bytes(bytes)?;
+        let (length, _) = u16::from_bytes(bytes.get(2..)?)?;
+        let (indicator, _) = Atom::from_bytes(bytes.get(12..)?)?;
+        Some((
+            Self {
+                req_type,
+                length,
+                indicator,
+            },
+            16,
+        ))
+    }
+}
+
+impl Request for NamedIndicatorRequest {
+    const OPCODE: u8 = 15;
+    const EXTENSION: Option<&'static str> = Some("XKEYBOARD");
+    const REPLY_EXPECTS_FDS: bool = false;
+    type Reply = NamedIndicatorReply;
+}
+
+/// The reply to the `GetNamedIndicator` request, decoded as the XKB protocol specifies.
+#[doc(hidden)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NamedIndicatorReply {
+    pub indicator: Atom,
+    pub found: bool,
+    pub on: bool,
+    pub real_indicator: bool,
+    pub ndx: u8,
+}
+
+impl AsByteSequence for NamedIndicatorReply {
+    #[inline]
+    fn size(&self) -> usize {
+        32
+    }
+
+    #[inline]
+    fn as_bytes(&self, bytes: &mut [u8]) -> usize {
+        1u8.as_bytes(&mut bytes[0..]);
+        self.indicator.as_bytes(&mut bytes[8..]);
+        self.found.as_bytes(&mut bytes[12..]);
+        self.on.as_bytes(&mut bytes[13..]);
+        self.real_indicator.as_bytes(&mut bytes[14..]);
+        self.ndx.as_bytes(&mut bytes[15..]);
+        32
+    }
+
+    #[inline]
+    fn from_bytes(bytes: &[u8]) -> Option<(Self, usize)> {
+        if bytes.len() < 32 {
+            return None;
+        }
+
+        let (indicator, _) = Atom::from_bytes(&bytes[8..])?;
+        Some((
+            Self {
+                indicator,
+                found: bytes[12] != 0,
+                on: bytes[13] != 0,
+                real_indicator: bytes[14] != 0,
+                ndx: bytes[15],
+            },
+            32,
+        ))
+    }
+}
+
+impl NamedIndicatorReply {
+    #[inline]
+    pub(crate) fn into_indicator(self) -> Option<NamedIndicator> {
+        if self.found {
+            Some(NamedIndicator {
+                name: self.indicator,
+                on: self.on,
+                led: if self.real_indicator {
+                    Some(self.ndx)
+                } else {
+                    None
+                },
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// The `SetNamedIndicator` request, encoded as the XKB protocol specifies. Only the state of the indicator is
+/// changed, not its map.
+#[doc(hidden)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct SetNamedIndicatorStateRequest {
+    pub req_type: u8,
+    pub length: u16,
+    pub indicator: Atom,
+    pub on: bool,
+}
+
+impl AsByteSequence for SetNamedIndicatorStateRequest {
+    #[inline]
+    fn size(&self) -> usize {
+        32
+    }
+
+    #[inline]
+    fn as_bytes(&self, bytes: &mut [u8]) -> usize {
+        self.req_type.as_bytes(&mut bytes[0..]);
+        self.length.as_bytes(&mut bytes[2..]);
+        USE_CORE_KBD.as_bytes(&mut bytes[4..]);
+        DFLT_XI_CLASS.as_bytes(&mut bytes[6..]);
+        DFLT_XI_ID.as_bytes(&mut bytes[8..]);
+        self.indicator.as_bytes(&mut bytes[12..]);
+        // setState
+        true.as_bytes(&mut bytes[16..]);
+        self.on.as_bytes(&mut bytes[17..]);
+        // the rest of the request describes the indicator map, which we don't set
+        32
+    }
+
+    #[inline]
+    fn from_bytes(bytes: &[u8]) -> Option<(Self, usize)> {
+        if bytes.len() < 32 {
+            return None;
+        }
+
+        let (req_type, _) = u8::from_bytes(bytes)?;
+        let (length, _) = u16::from_bytes(&bytes[2..])?;
+        let (indicator, _) = Atom::from_bytes(&bytes[12..])?;
+        Some((
+            Self {
+                req_type,
+                length,
+                indicator,
+                on: bytes[17] != 0,
+            },
+            32,
+        ))
+    }
+}
+
+impl Request for SetNamedIndicatorStateRequest {
+    const OPCODE: u8 = 16;
+    const EXTENSION: Option<&'static str> = Some("XKEYBOARD");
+    const REPLY_EXPECTS_FDS: bool = false;
+    type Reply = ();
+}
+
+#[inline]
+fn use_extension_request() -> UseExtensionRequest {
+    UseExtensionRequest {
+        wanted_major: XKB_MAJOR_VERSION,
+        wanted_minor: XKB_MINOR_VERSION,
+        ..Default::default()
+    }
+}
+
+#[inline]
+fn use_extension_reply(repl: crate::Result<UseExtensionReply>) -> crate::Result<ExtensionVersion> {
+    let repl = repl?;
+    if repl.supported {
+        Ok(ExtensionVersion {
+            major: repl.server_major.into(),
+            minor: repl.server_minor.into(),
+        })
+    } else {
+        Err(BreadError::ExtensionNotPresent("XKEYBOARD".into()))
+    }
+}
+
+#[inline]
+fn indicator_state_request() -> GetIndicatorStateRequest {
+    GetIndicatorStateRequest {
+        device_spec: USE_CORE_KBD,
+        ..Default::default()
+    }
+}
+
+#[inline]
+pub(crate) fn named_indicator_request(indicator: Atom) -> NamedIndicatorRequest {
+    NamedIndicatorRequest {
+        indicator,
+        ..Default::default()
+    }
+}
+
+#[inline]
+pub(crate) fn set_named_indicator_request(
+    indicator: Atom,
+    on: bool,
+) -> SetNamedIndicatorStateRequest {
+    SetNamedIndicatorStateRequest {
+        indicator,
+        on,
+        ..Default::default()
+    }
+}
+
+#[inline]
+fn bell_request(window: Option<Window>, name: Atom, percent: i8) -> BellRequest {
+    BellRequest {
+        device_spec: USE_CORE_KBD,
+        bell_class: DFLT_XI_CLASS,
+        bell_id: DFLT_XI_ID,
+        percent,
+        name,
+        window: window.unwrap_or_else(|| Window::const_from_xid(0)),
+        ..Default::default()
+    }
+}
+
+#[inline]
+fn detectable_autorepeat_request(enable: bool) -> PerClientFlagsRequest {
+    let mut change = PerClientFlag::default();
+    change.set_detectable_auto_repeat(true);
+    let mut value = PerClientFlag::default();
+    value.set_detectable_auto_repeat(enable);
+
+    PerClientFlagsRequest {
+        device_spec: USE_CORE_KBD,
+        change,
+        value,
+        ..Default::default()
+    }
+}
+
+#[inline]
+fn detectable_autorepeat_reply(repl: crate::Result<PerClientFlagsReply>) -> crate::Result<bool> {
+    let repl = repl?;
+    Ok(repl.supported.detectable_auto_repeat() && repl.value.detectable_auto_repeat())
+}
+
+#[cfg(feature = "async-core")]
+#[inline]
+fn intern_atom_request(name: Cow<'_, str>) -> InternAtomRequest<'_> {
+    InternAtomRequest {
+        only_if_exists: false,
+        name,
+        ..Default::default()
+    }
+}
+
+/// Keyboard indicators, bells and per-client flags, using the XKB extension.
+///
+/// The XKB extension has to be initialized using [`use_xkb_immediate`] before any of the other functions
+/// are used. Indicators are identified by names such as `Caps Lock`, `Num Lock` or `Scroll Lock`, and bells
+/// by names such as `bell` or `terminal-bell`. Functions that take names intern them first.
+///
+/// [`use_xkb_immediate`]: DisplayXkbExt::use_xkb_immediate
+pub trait DisplayXkbExt: Display {
+    /// Initialize the XKB extension for this client, and get the version of XKB the server supports.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BreadError::ExtensionNotPresent` if the server does not support XKB.
+    #[inline]
+    fn use_xkb_immediate(&mut self) -> crate::Result<ExtensionVersion> {
+        use_extension_reply(self.exchange_request(use_extension_request()))
+    }
+
+    /// Get the state of the physical keyboard indicators, as a bitmask where bit `n` is set if LED `n` is lit.
+    #[inline]
+    fn xkb_indicator_state_immediate(&mut self) -> crate::Result<u32> {
+        Ok(self.exchange_request(indicator_state_request())?.state)
+    }
+
+    /// Get the state of the indicator with the given name, or `None` if the keyboard doesn't have it.
+    #[inline]
+    fn xkb_named_indicator_immediate(
+        &mut self,
+        indicator: Atom,
+    ) -> crate::Result<Option<NamedIndicator>> {
+        Ok(self
+            .exchange_request(named_indicator_request(indicator))?
+            .into_indicator())
+    }
+
+    /// Get the state of the indicator with the given name, e.g. `Caps Lock`, or `None` if the keyboard
+    /// doesn't have it.
+    #[inline]
+    fn xkb_indicator_by_name_immediate<'a, Name: Into<Cow<'a, str>>>(
+        &mut self,
+        name: Name,
+    ) -> crate::Result<Option<NamedIndicator>> {
+        let indicator = self.intern_atom_immediate(name, false)?;
+        self.xkb_named_indicator_immediate(indicator)
+    }
+
+    /// Turn the indicator with the given name on or off.
+    ///
+    /// Whether this affects the keyboard state (e.g. whether turning on `Caps Lock` actually locks the Lock
+    /// modifier) depends on the indicator's map, which is configured by the keymap.
+    #[inline]
+    fn set_xkb_named_indicator(&mut self, indicator: Atom, on: bool) -> crate::Result {
+        self.exchange_request(set_named_indicator_request(indicator, on))
+    }
+
+    /// Turn the indicator with the given name, e.g. `Num Lock`, on or off.
+    #[inline]
+    fn set_xkb_indicator_by_name<'a, Name: Into<Cow<'a, str>>>(
+        &mut self,
+        name: Name,
+        on: bool,
+    ) -> crate::Result {
+        let indicator = self.intern_atom_immediate(name, false)?;
+        self.set_xkb_named_indicator(indicator, on)
+    }
+
+    /// Toggle the indicator with the given name, and return its new state. Returns `None` if the keyboard
+    /// doesn't have the indicator.
+    #[inline]
+    fn toggle_xkb_indicator_by_name<'a, Name: Into<Cow<'a, str>>>(
+        &mut self,
+        name: Name,
+    ) -> crate::Result<Option<bool>> {
+        let indicator = self.intern_atom_immediate(name, false)?;
+        match self.xkb_named_indicator_immediate(indicator)? {
+            Some(state) => {
+                self.set_xkb_named_indicator(indicator, !state.on)?;
+                Ok(Some(!state.on))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Ring the named bell on the core keyboard. `percent` is relative to the base volume of the bell, as in
+    /// the core protocol's `Bell` request. If `window` is given, the bell is rung for that window.
+    #[inline]
+    fn xkb_bell(&mut self, window: Option<Window>, name: Atom, percent: i8) -> crate::Result {
+        self.exchange_request(bell_request(window, name, percent))
+    }
+
+    /// Ring the bell with the given name, e.g. `terminal-bell`.
+    #[inline]
+    fn xkb_bell_by_name<'a, Name: Into<Cow<'a, str>>>(
+        &mut self,
+        window: Option<Window>,
+        name: Name,
+        percent: i8,
+    ) -> crate::Result {
+        let name = self.intern_atom_immediate(name, false)?;
+        self.xkb_bell(window, name, percent)
+    }
+
+    /// Enable or disable detectable autorepeat for this client, and return whether it is enabled afterwards.
+    ///
+    /// Normally, a key that is held down repeats by sending a `KeyRelease` and a `KeyPress` event with the same
+    /// timestamp, which can't be told apart from the key actually being released. With detectable autorepeat,
+    /// the server only sends `KeyPress` events while the key repeats, and a single `KeyRelease` once it is
+    /// released. This returns `false` if the server doesn't support detectable autorepeat, in which case
+    /// `KeyRepeatFilter` can be used to merge the events instead.
+    #[inline]
+    fn set_xkb_detectable_autorepeat(&mut self, enable: bool) -> crate::Result<bool> {
+        detectable_autorepeat_reply(self.exchange_request(detectable_autorepeat_request(enable)))
+    }
+}
+
+impl<D: Display + ?Sized> DisplayXkbExt for D {}
+
+/// Keyboard indicators and bells using the XKB extension, async redox. See [`DisplayXkbExt`] for more
+/// information.
+#[cfg(feature = "async-core")]
+pub trait AsyncDisplayXkbExt: AsyncDisplay {
+    /// Initialize the XKB extension for this client, async redox.
+    #[inline]
+    fn use_xkb_immediate_async(
+        &mut self,
+    ) -> MapFuture<ExchangeRequestFuture<'_, Self, UseExtensionRequest>, UseExtensionMap> {
+        MapFuture::run(
+            self.exchange_request_async(use_extension_request()),
+            use_extension_reply,
+        )
+    }
+
+    /// Get the state of the physical keyboard indicators, async redox.
+    #[inline]
+    fn xkb_indicator_state_immediate_async(
+        &mut self,
+    ) -> MapFuture<ExchangeRequestFuture<'_, Self, GetIndicatorStateRequest>, IndicatorStateMap>
+    {
+        MapFuture::run(
+            self.exchange_request_async(indicator_state_request()),
+            |repl| repl.map(|repl| repl.state),
+        )
+    }
+
+    /// Get the state of the indicator with the given name, async redox.
+    #[inline]
+    fn xkb_named_indicator_immediate_async(
+        &mut self,
+        indicator: Atom,
+    ) -> MapFuture<ExchangeRequestFuture<'_, Self, NamedIndicatorRequest>, NamedIndicatorMap> {
+        MapFuture::run(
+            self.exchange_request_async(named_indicator_request(indicator)),
+            |repl| repl.map(NamedIndicatorReply::into_indicator),
+        )
+    }
+
+    /// Get the state of the indicator with the given name, e.g. `Caps Lock`, async redox.
+    #[inline]
+    fn xkb_indicator_by_name_immediate_async<'a, 'b, Name: Into<Cow<'b, str>>>(
+        &'a mut self,
+        name: Name,
+    ) -> MapFuture<
+        ExchangeThenFuture<
+            'a,
+            Self,
+            InternAtomRequest<'b>,
+            NamedIndicatorRequest,
+            InternNamedIndicator,
+        >,
+        NamedIndicatorMap,
+    > {
+        MapFuture::run(
+            ExchangeThenFuture::run(self, intern_atom_request(name.into()), |repl| {
+                Ok(named_indicator_request(repl.atom))
+            }),
+            |repl| repl.map(NamedIndicatorReply::into_indicator),
+        )
+    }
+
+    /// Turn the indicator with the given name on or off, async redox.
+    #[inline]
+    fn set_xkb_named_indicator_async(
+        &mut self,
+        indicator: Atom,
+        on: bool,
+    ) -> ExchangeRequestFuture<'_, Self, SetNamedIndicatorStateRequest> {
+        self.exchange_request_async(set_named_indicator_request(indicator, on))
+    }
+
+    /// Turn the indicator with the given name, e.g. `Num Lock`, on or off, async redox.
+    #[inline]
+    fn set_xkb_indicator_by_name_async<'a, 'b, Name: Into<Cow<'b, str>>>(
+        &'a mut self,
+        name: Name,
+        on: bool,
+    ) -> ExchangeThenFuture<
+        'a,
+        Self,
+        InternAtomRequest<'b>,
+        SetNamedIndicatorStateRequest,
+        BoxedFnOnce<InternAtomReply, crate::Result<SetNamedIndicatorStateRequest>>,
+    > {
+        ExchangeThenFuture::run(
+            self,
+            intern_atom_request(name.into()),
+            Box::new(move |repl| Ok(set_named_indicator_request(repl.atom, on))),
+        )
+    }
+
+    /// Toggle the indicator with the given name, and return its new state, async redox.
+    #[inline]
+    fn toggle_xkb_indicator_by_name_async<'a, 'b, Name: Into<Cow<'b, str>>>(
+        &'a mut self,
+        name: Name,
+    ) -> ToggleIndicatorFuture<'a, 'b, Self> {
+        ToggleIndicatorFuture::run(self, intern_atom_request(name.into()))
+    }
+
+    /// Ring the named bell on the core keyboard, async redox.
+    #[inline]
+    fn xkb_bell_async(
+        &mut self,
+        window: Option<Window>,
+        name: Atom,
+        percent: i8,
+    ) -> ExchangeRequestFuture<'_, Self, BellRequest> {
+        self.exchange_request_async(bell_request(window, name, percent))
+    }
+
+    /// Ring the bell with the given name, e.g. `terminal-bell`, async redox.
+    #[inline]
+    fn xkb_bell_by_name_async<'a, 'b, Name: Into<Cow<'b, str>>>(
+        &'a mut self,
+        window: Option<Window>,
+        name: Name,
+        percent: i8,
+    ) -> ExchangeThenFuture<
+        'a,
+        Self,
+        InternAtomRequest<'b>,
+        BellRequest,
+        BoxedFnOnce<InternAtomReply, crate::Result<BellRequest>>,
+    > {
+        ExchangeThenFuture::run(
+            self,
+            intern_atom_request(name.into()),
+            Box::new(move |repl| Ok(bell_request(window, repl.atom, percent))),
+        )
+    }
+
+    /// Enable or disable detectable autorepeat for this client, async redox.
+    #[inline]
+    fn set_xkb_detectable_autorepeat_async(
+        &mut self,
+        enable: bool,
+    ) -> MapFuture<ExchangeRequestFuture<'_, Self, PerClientFlagsRequest>, PerClientFlagsMap> {
+        MapFuture::run(
+            self.exchange_request_async(detectable_autorepeat_request(enable)),
+            detectable_autorepeat_reply,
+        )
+    }
+}
+
+#[cfg(feature = "async-core")]
+impl<D: AsyncDisplay + ?Sized> AsyncDisplayXkbExt for D {}
+
+#[test]
+fn named_indicator_requests_match_the_protocol() {
+    use crate::display::RequestInfo;
+
+    let req = RequestInfo::from_request(
+        set_named_indicator_request(Atom::const_from_xid(0x42), true),
+        false,
+        usize::from(u16::MAX) * 4,
+    );
+    // 32 bytes, or 8 units
+    assert_eq!(req.data.len(), 32);
+    assert_eq!(u16::from_ne_bytes([req.data[2], req.data[3]]), 8);
+    assert_eq!(u16::from_ne_bytes([req.data[4], req.data[5]]), USE_CORE_KBD);
+    assert_eq!(
+        u16::from_ne_bytes([req.data[6], req.data[7]]),
+        DFLT_XI_CLASS
+    );
+    assert_eq!(u16::from_ne_bytes([req.data[8], req.data[9]]), DFLT_XI_ID);
+    assert_eq!(
+        u32::from_ne_bytes([req.data[12], req.data[13], req.data[14], req.data[15]]),
+        0x42
+    );
+    assert_eq!(&req.data[16..18], &[1, 1]);
+
+    let req = RequestInfo::from_request(
+        named_indicator_request(Atom::const_from_xid(0x42)),
+        false,
+        usize::from(u16::MAX) * 4,
+    );
+    assert_eq!(req.data.len(), 16);
+
+    let mut reply = [0; 32];
+    reply[0] = 1;
+    reply[8..12].copy_from_slice(&0x42u32.to_ne_bytes());
+    reply[12..16].copy_from_slice(&[1, 1, 1, 2]);
+    let (reply, _) = NamedIndicatorReply::from_bytes(&reply).unwrap();
+    assert_eq!(
+        reply.into_indicator(),
+        Some(NamedIndicator {
+            name: Atom::const_from_xid(0x42),
+            on: true,
+            led: Some(2),
+        })
+    );
+
+    let req = RequestInfo::from_request(
+        detectable_autorepeat_request(true),
+        false,
+        usize::from(u16::MAX) * 4,
+    );
+    assert_eq!(req.data.len(), 28);
+    assert_eq!(u16::from_ne_bytes([req.data[2], req.data[3]]), 7);
+    // change and value both have XkbPCF_DetectableAutoRepeatMask set
+    assert_eq!(
+        u32::from_ne_bytes([req.data[8], req.data[9], req.data[10], req.data[11]]),
+        1
+    );
+    assert_eq!(
+        u32::from_ne_bytes([req.data[12], req.data[13], req.data[14], req.data[15]]),
+        1
+    );
+}
+
+#[cfg(feature = "async-core")]
+#[test]
+fn toggle_indicator_async_sets_the_opposite_state() {
+    use crate::{
+        auto::xproto::{InternAtomReply, QueryExtensionReply},
+        display::{BasicDisplay, DisplayBase},
+    };
+    use futures_lite::future;
+
+    let mut display = BasicDisplay::dummy();
+    display.set_checked(false);
+    let conn = display.connection_mut();
+    conn.reply_padded(InternAtomReply {
+        reply_type: 1,
+        sequence: 2,
+        atom: Atom::const_from_xid(0x42),
+        ..Default::default()
+    });
+    conn.reply_padded(QueryExtensionReply {
+        reply_type: 1,
+        sequence: 3,
+        present: true,
+        major_opcode: 135,
+        ..Default::default()
+    });
+    let mut reply = [0; 32];
+    reply[0] = 1;
+    reply[2..4].copy_from_slice(&4u16.to_ne_bytes());
+    reply[8..12].copy_from_slice(&0x42u32.to_ne_bytes());
+    reply[12..16].copy_from_slice(&[1, 1, 1, 2]);
+    conn.reply_bytes(reply.iter().copied());
+    // SetNamedIndicator has no reply
+
+    let on = future::block_on(display.toggle_xkb_indicator_by_name_async("Caps Lock")).unwrap();
+    assert_eq!(on, Some(false));
+}
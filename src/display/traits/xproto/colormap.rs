@@ -8,7 +8,7 @@ use crate::{
     Display, RequestCookie,
 };
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::{
     display::{
         futures::{ExchangeRequestFuture, MapFuture, SendRequestFuture},
@@ -16,7 +16,7 @@ use crate::{
     },
     util::BoxedFnOnce,
 };
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use alloc::boxed::Box;
 
 /// Convenience function for producing an RGB pixel value for supported monitors.
@@ -103,7 +103,7 @@ impl Colormap {
     }
 
     /// Allocate a new color in the colormap, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn alloc_color_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -134,7 +134,7 @@ impl Colormap {
     }
 
     /// Immediately allocate a new color in the colormap, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn alloc_color_immediate_async<Dpy: AsyncDisplay + ?Sized>(
         self,
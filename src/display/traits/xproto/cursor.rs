@@ -5,7 +5,7 @@ use crate::{
     display::{prelude::*, Display},
 };
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::display::{futures::ExchangeRequestFuture, AsyncDisplay};
 
 impl Cursor {
@@ -17,7 +17,7 @@ impl Cursor {
         })
     }
 
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn free_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -6,7 +6,7 @@
 use crate::{
     auto::xproto::{
         CopyAreaRequest, CopyPlaneRequest, CreatePixmapRequest, Drawable, GetGeometryReply,
-        GetGeometryRequest, GetImageRequest, ImageFormat, Pixmap, Window,
+        GetGeometryRequest, GetImageRequest, ImageFormat, Pixmap, Point, Rectangle, Window,
     },
     display::{generate_xid, prelude::*},
     image::{put::put_image_req, Image},
@@ -15,7 +15,7 @@ use crate::{
 use alloc::{boxed::Box, vec::Vec};
 use core::{convert::TryInto, ops::Deref};
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::{
     auto::xproto::PutImageRequest,
     display::{
@@ -161,6 +161,27 @@ pub(crate) fn get_image_req(
     }
 }
 
+/// Clip an area of an image to the bounds of the image, moving `dest` along with the area's upper left corner.
+/// Returns the source coordinates, destination coordinates, width and height to pass to `put_image`.
+#[inline]
+fn clip_sub_image<Data>(
+    image: &Image<Data>,
+    src_rect: Rectangle,
+    dest: Point,
+) -> (isize, isize, isize, isize, usize, usize) {
+    let clip = |src: i16, dest: i16, len: u16, bound: usize| {
+        let (src, dest) = (isize::from(src), isize::from(dest));
+        // a negative source offset cuts off the start of the area, so the destination has to move with it
+        let cut = (-src).max(0);
+        let len = (len as isize - cut).min(bound as isize - src - cut);
+        (src + cut, dest + cut, len.try_into().unwrap_or(0))
+    };
+
+    let (src_x, dest_x, width) = clip(src_rect.x, dest.x, src_rect.width, image.width);
+    let (src_y, dest_y, height) = clip(src_rect.y, dest.y, src_rect.height, image.height);
+    (src_x, src_y, dest_x, dest_y, width, height)
+}
+
 pub trait DisplayDrawableExt: Display {
     /// Get the geometry of a drawable object.
     #[inline]
@@ -294,6 +315,26 @@ pub trait DisplayDrawableExt: Display {
         Ok(())
     }
 
+    /// Write an area of an image to a drawable, with its upper left corner at `dest`. The area is clipped
+    /// to the image.
+    ///
+    /// This is useful for sending the parts of a large image that have changed, since only the scanlines in
+    /// `src_rect` are converted and sent. Areas that don't fit into a single request are split up.
+    #[inline]
+    fn put_sub_image<Target: Into<Drawable>, Data: Deref<Target = [u8]>>(
+        &mut self,
+        target: Target,
+        gc: Gcontext,
+        image: &Image<Data>,
+        src_rect: Rectangle,
+        dest: Point,
+    ) -> crate::Result<()> {
+        let (src_x, src_y, dest_x, dest_y, width, height) = clip_sub_image(image, src_rect, dest);
+        self.put_image(
+            target, gc, image, src_x, src_y, dest_x, dest_y, width, height,
+        )
+    }
+
     /// Create a pixmap from an image.
     #[inline]
     fn create_pixmap_from_image<Target: Clone + Into<Drawable>, Data: Deref<Target = [u8]>>(
@@ -302,12 +343,13 @@ pub trait DisplayDrawableExt: Display {
         image: &Image<Data>,
     ) -> crate::Result<Pixmap> {
         let pixmap = self.create_pixmap(
-            target.clone(),
+            target,
             image.width.try_into().unwrap(),
             image.height.try_into().unwrap(),
             image.depth,
         )?;
-        let gc = match self.create_gc(target.clone(), Default::default()) {
+        // the GC has to have the same depth as the pixmap, which may not be the depth of the target
+        let gc = match self.create_gc(pixmap, Default::default()) {
             Ok(gc) => gc,
             Err(e) => {
                 pixmap.free(self)?;
@@ -315,7 +357,7 @@ pub trait DisplayDrawableExt: Display {
             }
         };
 
-        self.put_image(target, gc, image, 0, 0, 0, 0, image.width, image.height)?;
+        self.put_image(pixmap, gc, image, 0, 0, 0, 0, image.width, image.height)?;
         gc.free(self)?;
         Ok(pixmap)
     }
@@ -372,7 +414,7 @@ pub trait DisplayDrawableExt: Display {
 
 impl<D: Display + ?Sized> DisplayDrawableExt for D {}
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub trait AsyncDisplayDrawableExt: AsyncDisplay {
     /// Get the geometry of a drawable object, async redox.
     #[inline]
@@ -512,6 +554,22 @@ pub trait AsyncDisplayDrawableExt: AsyncDisplay {
         PutImageFuture::run(self, reqs)
     }
 
+    /// Write an area of an image to a drawable, async redox.
+    #[inline]
+    fn put_sub_image_async<'a, Target: Into<Drawable>, Data: Deref<Target = [u8]>>(
+        &'a mut self,
+        target: Target,
+        gc: Gcontext,
+        image: &Image<Data>,
+        src_rect: Rectangle,
+        dest: Point,
+    ) -> PutImageFuture<'a, Self, Vec<PutImageRequest<'static>>> {
+        let (src_x, src_y, dest_x, dest_y, width, height) = clip_sub_image(image, src_rect, dest);
+        self.put_image_async(
+            target, gc, image, src_x, src_y, dest_x, dest_y, width, height,
+        )
+    }
+
     /// Get an image from a region in this drawable.
     #[inline]
     fn get_image_async<Target: Into<Drawable>>(
@@ -589,5 +647,50 @@ pub trait AsyncDisplayDrawableExt: AsyncDisplay {
     */
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 impl<D: AsyncDisplay + ?Sized> AsyncDisplayDrawableExt for D {}
+
+#[test]
+fn sub_images_are_clipped_with_their_destination() {
+    use crate::display::BasicDisplay;
+    use alloc::vec;
+
+    let display = BasicDisplay::dummy();
+    let image = Image::new(
+        &display,
+        None,
+        24,
+        ImageFormat::ZPixmap,
+        0,
+        vec![0; 4 * 10 * 8],
+        10,
+        8,
+        32,
+        None,
+    )
+    .unwrap();
+    let rect = |x, y, width, height| Rectangle {
+        x,
+        y,
+        width,
+        height,
+    };
+
+    // a negative offset moves the destination along with the part of the area that is left
+    assert_eq!(
+        clip_sub_image(&image, rect(-3, -2, 6, 4), Point { x: 100, y: 50 }),
+        (0, 0, 103, 52, 3, 2)
+    );
+    assert_eq!(
+        clip_sub_image(&image, rect(4, 5, 20, 20), Point { x: 0, y: 0 }),
+        (4, 5, 0, 0, 6, 3)
+    );
+    assert_eq!(
+        clip_sub_image(&image, rect(-20, 0, 5, 5), Point { x: 0, y: 0 }).4,
+        0
+    );
+    assert_eq!(
+        clip_sub_image(&image, rect(12, 0, 5, 5), Point { x: 0, y: 0 }).4,
+        0
+    );
+}
@@ -11,11 +11,11 @@ use crate::{
 };
 use alloc::borrow::Cow;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::display::{AsyncDisplay, EitherFuture, ExchangeRequestFuture};
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use alloc::vec;
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use futures_lite::future::{self, Ready};
 
 impl Gcontext {
@@ -42,7 +42,7 @@ impl Gcontext {
     }
 
     /// Change the properties of this GC, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub fn change_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -89,7 +89,7 @@ impl Gcontext {
     }
 
     /// Draw a set of lines, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub fn draw_lines_async<
         'a,
@@ -131,7 +131,7 @@ impl Gcontext {
     }
 
     /// Draw a singular line, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub fn draw_line_async<'a, Dpy: AsyncDisplay + ?Sized, Target: Into<Drawable>>(
         self,
@@ -182,7 +182,7 @@ impl Gcontext {
     }
 
     /// Draw one or more rectangles to the screen, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub fn draw_rectangles_async<
         'a,
@@ -222,7 +222,7 @@ impl Gcontext {
     }
 
     /// Draw a rectangle to the screen, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub fn draw_rectangle_async<Dpy: AsyncDisplay + ?Sized, Target: Into<Drawable>>(
         self,
@@ -269,7 +269,7 @@ impl Gcontext {
     }
 
     /// Draw one or more arcs to the screen, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub fn draw_arcs_async<
         'a,
@@ -310,7 +310,7 @@ impl Gcontext {
     }
 
     /// Draw an arc to the screen, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub fn draw_arc_async<'a, 'b, Dpy: AsyncDisplay + ?Sized, Target: Into<Drawable>>(
         self,
@@ -370,7 +370,7 @@ impl Gcontext {
     }
 
     /// Fill a polygon specified by the given points, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub fn fill_polygon_async<
         'a,
@@ -442,7 +442,7 @@ impl Gcontext {
     }
 
     /// Fill a set of one or more rectangles, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub fn fill_rectangles_async<
         'a,
@@ -485,7 +485,7 @@ impl Gcontext {
     }
 
     /// Fill a single rectangle, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub fn fill_rectangle_async<Dpy: AsyncDisplay + ?Sized, Target: Into<Drawable>>(
         self,
@@ -536,7 +536,7 @@ impl Gcontext {
     }
 
     /// Fill a set of one or more arcs, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub fn fill_arcs_async<
         'a,
@@ -575,7 +575,7 @@ impl Gcontext {
     }
 
     /// Fill an arc, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub fn fill_arc_async<Dpy: AsyncDisplay + ?Sized, Target: Into<Drawable>>(
         self,
@@ -600,7 +600,7 @@ impl Gcontext {
     }
 
     /// Free the memory this GC allocates, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub fn free_async<Dpy: AsyncDisplay + ?Sized>(
         self,
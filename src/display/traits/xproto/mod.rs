@@ -22,7 +22,7 @@ use crate::{
 use alloc::{borrow::Cow, boxed::Box, string::String};
 use cty::c_char;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::{
     auto::xproto::{GetAtomNameReply, InternAtomReply, QueryExtensionReply},
     display::{
@@ -652,7 +652,7 @@ pub trait DisplayXprotoExt: Display {
 
         self.send_request(GetKeyboardMappingRequest {
             first_keycode: min_keycode,
-            count: max_keycode - min_keycode,
+            count: max_keycode - min_keycode + 1,
             ..Default::default()
         })
     }
@@ -713,7 +713,7 @@ pub trait DisplayXprotoExt: Display {
 
 impl<D: Display + ?Sized> DisplayXprotoExt for D {}
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub trait AsyncDisplayXprotoExt: AsyncDisplay {
     /// Query for extension information redox.
     #[inline]
@@ -1031,7 +1031,7 @@ pub trait AsyncDisplayXprotoExt: AsyncDisplay {
 
         self.send_request_async(GetKeyboardMappingRequest {
             first_keycode: min_keycode,
-            count: max_keycode - min_keycode,
+            count: max_keycode - min_keycode + 1,
             ..Default::default()
         })
     }
@@ -1046,7 +1046,7 @@ pub trait AsyncDisplayXprotoExt: AsyncDisplay {
 
         self.exchange_request_async(GetKeyboardMappingRequest {
             first_keycode: min_keycode,
-            count: max_keycode - min_keycode,
+            count: max_keycode - min_keycode + 1,
             ..Default::default()
         })
     }
@@ -1113,5 +1113,5 @@ pub trait AsyncDisplayXprotoExt: AsyncDisplay {
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 impl<D: AsyncDisplay + ?Sized> AsyncDisplayXprotoExt for D {}
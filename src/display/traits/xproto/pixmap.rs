@@ -5,7 +5,7 @@ use crate::{
     display::{prelude::*, Display},
 };
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::display::{futures::ExchangeRequestFuture, AsyncDisplay};
 
 impl Pixmap {
@@ -19,7 +19,7 @@ impl Pixmap {
     }
 
     /// Free the memory used by a pixmap, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub fn free_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -21,10 +21,12 @@ pub use crate::{
     display::{prelude::*, Connection, Display, DisplayExt, RequestCookie, WindowParameters},
     xid::XidType,
 };
+#[cfg(feature = "image-support")]
+use crate::display::max_request_payload;
 use alloc::{borrow::Cow, boxed::Box, string::ToString, vec::Vec};
 use core::{iter, mem};
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::display::{futures::SendRequestFuture, traits::AsyncDisplayDrawableExt, AsyncDisplay};
 
 // macro for retrieving an atom that might be cached in the display
@@ -46,7 +48,7 @@ macro_rules! retrieve_atom {
     }};
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 macro_rules! retrieve_atom_async {
     ($dpy: expr, $dgetter: ident, $dsetter: ident, $name: expr) => {{
         match $dpy.$dgetter() {
@@ -157,7 +159,7 @@ impl Window {
     }
 
     /// Map this window to the screen, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn map_async<Dpy: AsyncDisplay + ?Sized>(self, dpy: &mut Dpy) -> crate::Result {
         dpy.exchange_request_async(MapWindowRequest {
@@ -177,7 +179,7 @@ impl Window {
     }
 
     /// Unmap this window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn unmap_async<Dpy: AsyncDisplay + ?Sized>(self, dpy: &mut Dpy) -> crate::Result {
         dpy.exchange_request_async(UnmapWindowRequest {
@@ -282,7 +284,7 @@ impl Window {
     }
 
     /// Change a property of the window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn change_property_async<Dpy: AsyncDisplay + ?Sized, T: AsByteSequence>(
         self,
@@ -318,7 +320,7 @@ impl Window {
     }
 
     /// Delete a property of this window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn delete_property_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -358,7 +360,7 @@ impl Window {
     }
 
     /// Set the WM protocols for this window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn set_wm_protocols_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -397,7 +399,7 @@ impl Window {
     }
 
     /// Set the title for this window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn set_title_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -415,6 +417,85 @@ impl Window {
         .await
     }
 
+    /// Set the icon of this window, which window managers show in taskbars and window switchers, by setting the
+    /// `_NET_WM_ICON` property. Several sizes of the icon can be given, and the window manager will pick the
+    /// one that fits best. An empty slice removes the icon.
+    ///
+    /// Icons easily exceed the maximum request length, so the property is sent in several parts if needed.
+    #[cfg(feature = "image-support")]
+    #[inline]
+    pub fn set_icon<Dpy: Display + ?Sized, Img: image::GenericImageView>(
+        self,
+        dpy: &mut Dpy,
+        icons: &[Img],
+    ) -> crate::Result<()>
+    where
+        <<Img as image::GenericImageView>::Pixel as image::Pixel>::Subpixel: Into<u8>,
+    {
+        let net_wm_icon = dpy.intern_atom_immediate("_NET_WM_ICON", false)?;
+        if net_wm_icon.xid() == 0 {
+            log::error!("Unable to intern _NET_WM_ICON atom");
+            return Ok(());
+        }
+
+        if icons.is_empty() {
+            return self.delete_property(dpy, net_wm_icon);
+        }
+
+        let data = encode_icons(icons);
+        for (mode, chunk) in property_chunks(dpy, &data) {
+            self.change_property(
+                dpy,
+                net_wm_icon,
+                PropertyType::Cardinal,
+                PropertyFormat::ThirtyTwo,
+                mode,
+                chunk,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Set the icon of this window, async redox.
+    #[cfg(all(feature = "image-support", feature = "async-core"))]
+    #[inline]
+    pub async fn set_icon_async<Dpy: AsyncDisplay + ?Sized, Img: image::GenericImageView>(
+        self,
+        dpy: &mut Dpy,
+        icons: &[Img],
+    ) -> crate::Result<()>
+    where
+        <<Img as image::GenericImageView>::Pixel as image::Pixel>::Subpixel: Into<u8>,
+    {
+        let net_wm_icon = dpy
+            .intern_atom_immediate_async("_NET_WM_ICON", false)
+            .await?;
+        if net_wm_icon.xid() == 0 {
+            log::error!("Unable to intern _NET_WM_ICON atom");
+            return Ok(());
+        }
+
+        if icons.is_empty() {
+            return self.delete_property_async(dpy, net_wm_icon).await;
+        }
+
+        let data = encode_icons(icons);
+        for (mode, chunk) in property_chunks(dpy, &data) {
+            self.change_property_async(
+                dpy,
+                net_wm_icon,
+                PropertyType::Cardinal,
+                PropertyFormat::ThirtyTwo,
+                mode,
+                chunk,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
     /// `GetWindowAttributesRequest`
     #[inline]
     fn get_window_attributes_request(self) -> GetWindowAttributesRequest {
@@ -434,7 +515,7 @@ impl Window {
     }
 
     /// Get the current set of window attributes for this window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn window_attributes_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -457,7 +538,7 @@ impl Window {
     }
 
     /// Immediately get the current set of window attributes for this window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn window_attributes_immediate_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -479,7 +560,7 @@ impl Window {
     }
 
     /// Get the geometry of this window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn geometry_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -498,7 +579,7 @@ impl Window {
     }
 
     /// Immediately get the geometry of this window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn geometry_immediate_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -531,7 +612,7 @@ impl Window {
     }
 
     /// Change the properties of this window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn change_attributes_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -557,7 +638,7 @@ impl Window {
     }
 
     /// Set this window's background color, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn set_background_color_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -592,7 +673,7 @@ impl Window {
     }
 
     /// Configure the window's physical properties, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn configure_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -618,7 +699,7 @@ impl Window {
     }
 
     /// Set the border of this window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn set_border_width_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -647,7 +728,7 @@ impl Window {
     }
 
     /// Change the colormap associated with this window.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn set_colormap_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -679,7 +760,7 @@ impl Window {
     }
 
     /// Change the save set for this window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn change_save_set_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -707,7 +788,7 @@ impl Window {
     }
 
     /// Resize the window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn resize_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -742,7 +823,7 @@ impl Window {
     }
 
     /// Move and resize the window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn move_resize_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -782,7 +863,7 @@ impl Window {
     }
 
     /// Circulate this window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn circulate_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -829,7 +910,7 @@ impl Window {
     }
 
     /// Clear an area of the window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn clear_area_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -851,7 +932,7 @@ impl Window {
     }
 
     /// Clear the entire window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn clear_async<Dpy: AsyncDisplay + ?Sized>(self, dpy: &mut Dpy) -> crate::Result {
         self.clear_area_async(dpy, 0, 0, 0, 0, false).await
@@ -889,7 +970,7 @@ impl Window {
     }
 
     /// Convert a selection in this window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn convert_selection_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -916,7 +997,7 @@ impl Window {
     }
 
     /// Set the cursor used by this window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn set_cursor_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -940,7 +1021,7 @@ impl Window {
     }
 
     /// Destroy this window's subwindows, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn destroy_subwindows_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -963,7 +1044,7 @@ impl Window {
     }
 
     /// Free this window, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn free_async<Dpy: AsyncDisplay + ?Sized>(self, dpy: &mut Dpy) -> crate::Result {
         dpy.exchange_request_async(DestroyWindowRequest {
@@ -990,7 +1071,7 @@ impl Window {
     }
 
     /// Set the event mask, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn set_event_mask_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -1026,7 +1107,7 @@ impl Window {
     }
 
     /// Change this window's parent and set its position within the parent, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn reparent_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -1055,7 +1136,7 @@ impl Window {
     }
 
     /// Map this window's subwindows, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn map_subwindows_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -1078,7 +1159,7 @@ impl Window {
     }
 
     /// Unmap this window's subwindows, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn unmap_subwindows_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -1117,7 +1198,7 @@ impl Window {
     }
 
     /// Query information regarding this window's family tree, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn query_tree_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -1131,7 +1212,7 @@ impl Window {
     }
 
     /// Query information regarding this window's family tree and resolve immediately, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn query_tree_immediate_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -1146,7 +1227,7 @@ impl Window {
     }
 
     /// Get a property of this window, async redox
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub fn get_property_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -1167,7 +1248,7 @@ impl Window {
     }
 
     /// Get a property of this window, resolving immediately, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn get_property_immediate_async<Dpy: AsyncDisplay + ?Sized, T: AsByteSequence>(
         self,
@@ -1212,6 +1293,57 @@ fn convert_get_window_attributes_reply(reply: GetWindowAttributesReply) -> Windo
     }
 }
 
+/// Encode images in the format used by `_NET_WM_ICON`: the width and height of every image, followed by its
+/// pixels as ARGB values, row by row.
+#[cfg(feature = "image-support")]
+#[inline]
+fn encode_icons<Img: image::GenericImageView>(icons: &[Img]) -> Vec<u32>
+where
+    <<Img as image::GenericImageView>::Pixel as image::Pixel>::Subpixel: Into<u8>,
+{
+    use image::Pixel;
+
+    let len = icons
+        .iter()
+        .map(|icon| {
+            let (width, height) = icon.dimensions();
+            2 + width as usize * height as usize
+        })
+        .sum();
+    let mut data = Vec::with_capacity(len);
+
+    for icon in icons {
+        let (width, height) = icon.dimensions();
+        data.push(width);
+        data.push(height);
+        data.extend(icon.pixels().map(|(_, _, pixel)| {
+            let [r, g, b, a] = pixel.to_rgba().0;
+            let [r, g, b, a]: [u8; 4] = [r.into(), g.into(), b.into(), a.into()];
+            (u32::from(a) << 24) | (u32::from(r) << 16) | (u32::from(g) << 8) | u32::from(b)
+        }));
+    }
+
+    data
+}
+
+/// Split the data of a property into parts that fit into a single `ChangeProperty` request. The first part
+/// replaces the property, and the rest are appended to it.
+#[cfg(feature = "image-support")]
+#[inline]
+fn property_chunks<'a, Dpy: DisplayBase + ?Sized, T>(
+    dpy: &Dpy,
+    data: &'a [T],
+) -> impl Iterator<Item = (PropMode, &'a [T])> {
+    let chunk_len = max_request_payload(dpy, ChangePropertyRequest::default().size())
+        / mem::size_of::<T>().max(1);
+    data.chunks(chunk_len.max(1))
+        .enumerate()
+        .map(|(i, chunk)| match i {
+            0 => (PropMode::Replace, chunk),
+            _ => (PropMode::Append, chunk),
+        })
+}
+
 /// The type of the property being changed.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(u32)]
@@ -1294,3 +1426,60 @@ pub enum PropertyFormat {
     Sixteen = 16,
     ThirtyTwo = 32,
 }
+
+#[cfg(feature = "image-support")]
+#[test]
+fn set_icon_encodes_argb_and_appends_parts() {
+    use crate::{
+        auto::xproto::{InternAtomReply, InternAtomRequest},
+        display::BasicDisplay,
+    };
+
+    let mut display = BasicDisplay::dummy();
+    display.set_checked(false);
+    // room for four cardinals per request
+    display.max_request_len = 24 + 16;
+
+    let small = image::RgbaImage::from_raw(1, 1, alloc::vec![0x11, 0x22, 0x33, 0x44]).unwrap();
+    let large =
+        image::RgbaImage::from_raw(2, 1, alloc::vec![0xFF, 0, 0, 0xFF, 0, 0, 0xFF, 0x80]).unwrap();
+    assert_eq!(
+        encode_icons(&[small.clone(), large.clone()]),
+        [1, 1, 0x4411_2233, 2, 1, 0xFFFF_0000, 0x8000_00FF]
+    );
+
+    let window = Window::const_from_xid(0x40_0001);
+    let net_wm_icon = Atom::const_from_xid(300);
+    display.connection_mut().reply_padded(InternAtomReply {
+        reply_type: 1,
+        sequence: 2,
+        atom: net_wm_icon,
+        ..Default::default()
+    });
+
+    display.connection_mut().expects(InternAtomRequest {
+        req_type: 16,
+        length: 5,
+        name: "_NET_WM_ICON".into(),
+        ..Default::default()
+    });
+    let parts: [(PropMode, &[u32]); 2] = [
+        (PropMode::Replace, &[1, 1, 0x4411_2233, 2]),
+        (PropMode::Append, &[1, 0xFFFF_0000, 0x8000_00FF]),
+    ];
+    for (mode, part) in parts.iter() {
+        let mut req = window.change_property_request(
+            net_wm_icon,
+            PropertyType::Cardinal,
+            PropertyFormat::ThirtyTwo,
+            *mode,
+            part,
+        );
+        req.req_type = 18;
+        req.length = (6 + part.len()) as u16;
+        display.connection_mut().expects(req);
+    }
+
+    window.set_icon(&mut display, &[small, large]).unwrap();
+    assert!(display.connection().received_all_expected());
+}
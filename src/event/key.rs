@@ -0,0 +1,344 @@
+// MIT/Apache2 License
+
+use super::Event;
+use crate::{
+    auto::xproto::{KeyButMask, KeyPressEvent, KeyReleaseEvent, Keycode, Timestamp, Window},
+    display::{Display, DisplayBase},
+};
+
+#[cfg(feature = "async-core")]
+use crate::display::{AsyncDisplay, AsyncDisplayExt};
+#[cfg(feature = "async-core")]
+use futures_lite::future;
+
+/// A key press or release, decoded from a `KeyPress` or `KeyRelease` event.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct KeyEvent {
+    /// Whether the key was pressed or released.
+    pub pressed: bool,
+    /// Whether this event was generated by the key repeating while being held down.
+    pub repeat: bool,
+    /// The keycode of the key.
+    pub keycode: Keycode,
+    /// The modifier and button state at the time of the event.
+    pub state: KeyButMask,
+    /// The server time at which the event happened.
+    pub time: Timestamp,
+    /// The root window of the screen the event happened on.
+    pub root: Window,
+    /// The window that the event was reported relative to.
+    pub event: Window,
+    /// The child of `event` that contains the pointer, if any.
+    pub child: Window,
+    /// The pointer's X coordinate, relative to `root`.
+    pub root_x: i16,
+    /// The pointer's Y coordinate, relative to `root`.
+    pub root_y: i16,
+    /// The pointer's X coordinate, relative to `event`.
+    pub event_x: i16,
+    /// The pointer's Y coordinate, relative to `event`.
+    pub event_y: i16,
+    /// Whether `event` is on the same screen as `root`.
+    pub same_screen: bool,
+}
+
+impl KeyEvent {
+    #[inline]
+    fn from_press(kpe: &KeyPressEvent, repeat: bool) -> Self {
+        Self {
+            pressed: true,
+            repeat,
+            keycode: kpe.detail,
+            state: kpe.state,
+            time: kpe.time,
+            root: kpe.root,
+            event: kpe.event,
+            child: kpe.child,
+            root_x: kpe.root_x,
+            root_y: kpe.root_y,
+            event_x: kpe.event_x,
+            event_y: kpe.event_y,
+            same_screen: kpe.same_screen,
+        }
+    }
+
+    #[inline]
+    fn from_release(kre: &KeyReleaseEvent) -> Self {
+        Self {
+            pressed: false,
+            repeat: false,
+            keycode: kre.detail,
+            state: kre.state,
+            time: kre.time,
+            root: kre.root,
+            event: kre.event,
+            child: kre.child,
+            root_x: kre.root_x,
+            root_y: kre.root_y,
+            event_x: kre.event_x,
+            event_y: kre.event_y,
+            same_screen: kre.same_screen,
+        }
+    }
+}
+
+/// Decodes key events, telling apart key repeats from real key presses and releases.
+///
+/// With XKB detectable autorepeat enabled (see `DisplayXkbExt::set_xkb_detectable_autorepeat`), a held key
+/// produces a series of `KeyPress` events and a single `KeyRelease`; this filter marks every press after the
+/// first one as a repeat. Without it, the server sends a synthetic `KeyRelease`/`KeyPress` pair with the same
+/// timestamp for every repeat. When this filter sees such a release, it looks at the next event, reading any
+/// data the server has already sent without blocking, and, if that event is the matching press, merges the pair
+/// into a single repeated press.
+///
+/// Displays that cannot peek into their queue (see `DisplayBase::pop_event_if`) are not supported by the
+/// fallback. In particular, `SyncDisplay`, `&SyncDisplay` and the `RequestHandle`s of a split `SyncDisplay`
+/// store their events in a concurrent queue; with those displays, every synthetic repeat comes through as a
+/// release followed by a press. Enable detectable autorepeat instead.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct KeyRepeatFilter {
+    // one bit for every keycode that's currently held down
+    pressed: [u32; 8],
+}
+
+impl KeyRepeatFilter {
+    /// Create a new `KeyRepeatFilter` that considers every key to be released.
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tell whether a key is currently held down, according to the events this filter has seen.
+    #[inline]
+    #[must_use]
+    pub fn is_pressed(&self, keycode: Keycode) -> bool {
+        self.pressed[usize::from(keycode >> 5)] & (1 << (keycode & 31)) != 0
+    }
+
+    #[inline]
+    fn set_pressed(&mut self, keycode: Keycode, pressed: bool) {
+        let word = &mut self.pressed[usize::from(keycode >> 5)];
+        if pressed {
+            *word |= 1 << (keycode & 31);
+        } else {
+            *word &= !(1 << (keycode & 31));
+        }
+    }
+
+    /// Decode a key event, merging it with the next event from the display if the two of them make up a
+    /// synthetic key repeat. Returns `None` if the event is not a `KeyPress` or `KeyRelease`.
+    ///
+    /// # Errors
+    ///
+    /// Looking for the next event may read from the display, so this can return any error that
+    /// `Display::try_wait` can.
+    #[inline]
+    pub fn process_event<D: Display + ?Sized>(
+        &mut self,
+        display: &mut D,
+        event: &Event,
+    ) -> crate::Result<Option<KeyEvent>> {
+        Ok(match event {
+            Event::KeyPress(kpe) => Some(self.press(kpe)),
+            Event::KeyRelease(kre) => {
+                // the matching press may not have been read yet, so read whatever the server already sent
+                let next_press = loop {
+                    if let Some(kpe) = pop_matching_press(display, kre) {
+                        break Some(kpe);
+                    }
+                    if display.has_pending_event() || !display.try_wait()? {
+                        break None;
+                    }
+                };
+                Some(self.release(kre, next_press))
+            }
+            _ => None,
+        })
+    }
+
+    /// Decode a key event, merging it with the next event from the display if the two of them make up a
+    /// synthetic key repeat, async redox.
+    ///
+    /// # Errors
+    ///
+    /// Looking for the next event may read from the display, so this can return any error that
+    /// `AsyncDisplayExt::wait_async` can.
+    #[cfg(feature = "async-core")]
+    #[inline]
+    pub async fn process_event_async<D: AsyncDisplay + ?Sized>(
+        &mut self,
+        display: &mut D,
+        event: &Event,
+    ) -> crate::Result<Option<KeyEvent>> {
+        Ok(match event {
+            Event::KeyPress(kpe) => Some(self.press(kpe)),
+            Event::KeyRelease(kre) => {
+                let next_press = loop {
+                    if let Some(kpe) = pop_matching_press(display, kre) {
+                        break Some(kpe);
+                    }
+                    if display.has_pending_event() {
+                        break None;
+                    }
+                    // only read if the data is already there
+                    match future::poll_once(display.wait_async()).await {
+                        Some(res) => res?,
+                        None => break None,
+                    }
+                };
+                Some(self.release(kre, next_press))
+            }
+            _ => None,
+        })
+    }
+
+    #[inline]
+    fn press(&mut self, kpe: &KeyPressEvent) -> KeyEvent {
+        let repeat = self.is_pressed(kpe.detail);
+        self.set_pressed(kpe.detail, true);
+        KeyEvent::from_press(kpe, repeat)
+    }
+
+    #[inline]
+    fn release(&mut self, kre: &KeyReleaseEvent, next_press: Option<KeyPressEvent>) -> KeyEvent {
+        match next_press {
+            Some(kpe) => KeyEvent::from_press(&kpe, true),
+            None => {
+                self.set_pressed(kre.detail, false);
+                KeyEvent::from_release(kre)
+            }
+        }
+    }
+}
+
+/// Pop the next event off of the display's queue if it is the press that makes up a synthetic repeat together
+/// with the given release.
+#[inline]
+fn pop_matching_press<D: DisplayBase + ?Sized>(
+    display: &mut D,
+    kre: &KeyReleaseEvent,
+) -> Option<KeyPressEvent> {
+    match display.pop_event_if(&mut |next| {
+        matches!(next, Event::KeyPress(kpe) if kpe.detail == kre.detail
+            && kpe.time == kre.time
+            && kpe.event == kre.event)
+    }) {
+        Some(Event::KeyPress(kpe)) => Some(kpe),
+        _ => None,
+    }
+}
+
+#[test]
+fn key_repeat_filter_merges_synthetic_repeats() {
+    use crate::display::BasicDisplay;
+
+    let mut display = BasicDisplay::dummy();
+    let mut filter = KeyRepeatFilter::new();
+    let press = |keycode, time| {
+        Event::KeyPress(KeyPressEvent {
+            detail: keycode,
+            time,
+            ..Default::default()
+        })
+    };
+    let release = |keycode, time| {
+        Event::KeyRelease(KeyReleaseEvent {
+            detail: keycode,
+            time,
+            ..Default::default()
+        })
+    };
+    let decode = |filter: &mut KeyRepeatFilter, display: &mut BasicDisplay<_>, event| {
+        filter
+            .process_event(display, &event)
+            .unwrap()
+            .map(|ke| (ke.keycode, ke.pressed, ke.repeat))
+    };
+
+    // core autorepeat: the release/press pair is merged into a single repeat
+    assert_eq!(
+        decode(&mut filter, &mut display, press(10, 1)),
+        Some((10, true, false))
+    );
+    display.push_event(press(10, 5));
+    assert_eq!(
+        decode(&mut filter, &mut display, release(10, 5)),
+        Some((10, true, true))
+    );
+    assert!(!display.has_pending_event());
+
+    // a real release is left alone, as is an unrelated press behind it
+    display.push_event(press(11, 9));
+    assert_eq!(
+        decode(&mut filter, &mut display, release(10, 8)),
+        Some((10, false, false))
+    );
+    assert!(display.has_pending_event());
+    assert!(!filter.is_pressed(10));
+
+    // detectable autorepeat: presses without releases in between are repeats
+    let next = display.pop_event().unwrap();
+    assert_eq!(
+        decode(&mut filter, &mut display, next),
+        Some((11, true, false))
+    );
+    assert_eq!(
+        decode(&mut filter, &mut display, press(11, 12)),
+        Some((11, true, true))
+    );
+    assert_eq!(
+        decode(&mut filter, &mut display, release(11, 15)),
+        Some((11, false, false))
+    );
+    assert_eq!(
+        decode(
+            &mut filter,
+            &mut display,
+            Event::NoneOfTheAbove {
+                opcode: 0,
+                bytes: Default::default(),
+            }
+        ),
+        None
+    );
+}
+
+#[test]
+fn key_repeat_filter_reads_the_matching_press() {
+    use crate::display::BasicDisplay;
+
+    let mut display = BasicDisplay::dummy();
+    let mut filter = KeyRepeatFilter::new();
+    let press = |time| KeyPressEvent {
+        event_type: 2,
+        detail: 10,
+        time,
+        ..Default::default()
+    };
+    let release = |time| KeyReleaseEvent {
+        event_type: 3,
+        detail: 10,
+        time,
+        ..Default::default()
+    };
+    display.connection_mut().reply_padded(press(1));
+    display.connection_mut().reply_padded(release(5));
+    display.connection_mut().reply_padded(press(5));
+    display.connection_mut().reply_padded(release(8));
+    let next = |filter: &mut KeyRepeatFilter, display: &mut BasicDisplay<_>| {
+        let event = display.wait_for_event().unwrap();
+        filter
+            .process_event(display, &event)
+            .unwrap()
+            .map(|ke| (ke.pressed, ke.repeat, ke.time))
+    };
+
+    assert_eq!(next(&mut filter, &mut display), Some((true, false, 1)));
+    // the press is still on the wire when the release is decoded
+    assert_eq!(next(&mut filter, &mut display), Some((true, true, 5)));
+    assert!(!display.has_pending_event());
+    // with nothing left to read, the release goes through as-is
+    assert_eq!(next(&mut filter, &mut display), Some((false, false, 8)));
+    assert!(!filter.is_pressed(10));
+}
@@ -5,6 +5,9 @@
 #[cfg(feature = "input")]
 pub mod input;
 
+mod key;
+pub use key::*;
+
 use crate::auto::{
     xproto::{
         ButtonPressEvent, ButtonReleaseEvent, CirculateNotifyEvent, CirculateRequestEvent,
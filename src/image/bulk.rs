@@ -0,0 +1,670 @@
+// MIT/Apache2 License
+
+//! Operations that work on whole scanlines of an image at once, rather than on one pixel at a time.
+
+use super::{Image, LOW_BITS_TABLE};
+use crate::auto::xproto::{ImageFormat, ImageOrder, Point, Rectangle};
+use crate::util::roundup;
+use alloc::{borrow::Cow, vec, vec::Vec};
+use core::{
+    convert::TryFrom,
+    ops::{Deref, DerefMut},
+};
+
+/// Decode a row of pixels that take up `n` bytes each.
+#[inline]
+fn decode_row<F: Fn(&[u8]) -> u32>(row: &[u8], n: usize, mask: u32, pixels: &mut [u32], decode: F) {
+    pixels
+        .iter_mut()
+        .zip(row.chunks_exact(n))
+        .for_each(|(pixel, bytes)| *pixel = decode(bytes) & mask);
+}
+
+/// Encode a row of pixels that take up `n` bytes each.
+#[inline]
+fn encode_row<F: Fn(u32, &mut [u8])>(row: &mut [u8], n: usize, pixels: &[u32], encode: F) {
+    row.chunks_exact_mut(n)
+        .zip(pixels.iter())
+        .for_each(|(bytes, pixel)| encode(*pixel, bytes));
+}
+
+/// Encode a single pixel that takes up `bytes.len()` bytes.
+#[inline]
+fn encode_pixel(pixel: u32, order: ImageOrder, bytes: &mut [u8]) {
+    let n = bytes.len();
+    match order {
+        ImageOrder::MsbFirst => bytes.copy_from_slice(&pixel.to_be_bytes()[4 - n..]),
+        ImageOrder::LsbFirst => bytes.copy_from_slice(&pixel.to_le_bytes()[..n]),
+    }
+}
+
+/// Clip a rectangle to an area of `width` by `height` pixels, returning its position and size.
+#[inline]
+fn clip(rect: &Rectangle, width: usize, height: usize) -> Option<(usize, usize, usize, usize)> {
+    let clip_axis = |start: i16, len: u16, max: usize| {
+        let end = usize::try_from(i32::from(start) + i32::from(len)).unwrap_or(0);
+        let start = usize::try_from(start).unwrap_or(0);
+        let end = end.min(max);
+        if start < end {
+            Some((start, end - start))
+        } else {
+            None
+        }
+    };
+
+    let (x, width) = clip_axis(rect.x, rect.width, width)?;
+    let (y, height) = clip_axis(rect.y, rect.height, height)?;
+    Some((x, y, width, height))
+}
+
+/// Clip one axis of an area that is copied from an image of `src_max` pixels to one of `dest_max` pixels,
+/// returning its start in both images and its length. The math is done in `i32`, since moving an `i16` can
+/// overflow.
+#[inline]
+fn clip_blit_axis(
+    src_start: i16,
+    dest_start: i16,
+    len: u16,
+    src_max: usize,
+    dest_max: usize,
+) -> Option<(usize, usize, usize)> {
+    // move the area so it doesn't start at a negative coordinate in either image
+    let (src_start, dest_start) = (i32::from(src_start), i32::from(dest_start));
+    let shift = src_start.min(dest_start).min(0);
+    let src_start = usize::try_from(src_start - shift).ok()?;
+    let dest_start = usize::try_from(dest_start - shift).ok()?;
+    let len = usize::try_from(i32::from(len) + shift).ok()?;
+
+    let len = len
+        .min(src_max.saturating_sub(src_start))
+        .min(dest_max.saturating_sub(dest_start));
+    if len == 0 {
+        None
+    } else {
+        Some((src_start, dest_start, len))
+    }
+}
+
+/// Maps one channel of a pixel value to another, scaling it if the channels are of different widths.
+#[derive(Debug, Copy, Clone)]
+struct ChannelMap {
+    src_shift: u32,
+    src_max: u32,
+    dest_shift: u32,
+    dest_max: u32,
+}
+
+impl ChannelMap {
+    #[inline]
+    fn new(src_mask: u32, dest_mask: u32) -> Self {
+        let shift = |mask: u32| if mask == 0 { 0 } else { mask.trailing_zeros() };
+        Self {
+            src_shift: shift(src_mask),
+            src_max: src_mask >> shift(src_mask),
+            dest_shift: shift(dest_mask),
+            dest_max: dest_mask >> shift(dest_mask),
+        }
+    }
+
+    #[inline]
+    fn map(self, pixel: u32) -> u32 {
+        let value = (pixel >> self.src_shift) & self.src_max;
+        let value = if self.src_max == self.dest_max {
+            value
+        } else if self.src_max == 0 {
+            0
+        } else {
+            let (src_max, dest_max) = (u64::from(self.src_max), u64::from(self.dest_max));
+            ((u64::from(value) * dest_max + src_max / 2) / src_max) as u32
+        };
+        value << self.dest_shift
+    }
+}
+
+impl<Data> Image<Data>
+where
+    Data: Deref<Target = [u8]>,
+{
+    /// The number of bytes each pixel takes up, if the pixels of this image are whole bytes.
+    #[inline]
+    fn byte_width(&self) -> Option<usize> {
+        match (self.format, self.bits_per_pixel) {
+            (ImageFormat::ZPixmap, bpp @ (8 | 16 | 24 | 32)) => Some(usize::from(bpp >> 3)),
+            _ => None,
+        }
+    }
+
+    /// The byte order of the pixels in a scanline. Like the generic `pixel`, this treats 24-bit pixels with
+    /// either order set to `MsbFirst` as big-endian.
+    #[inline]
+    fn row_byte_order(&self) -> ImageOrder {
+        match (self.bits_per_pixel, self.bit_order) {
+            (24, ImageOrder::MsbFirst) => ImageOrder::MsbFirst,
+            _ => self.byte_order,
+        }
+    }
+
+    /// The bits of a pixel value that are not covered by the color masks, e.g. an alpha channel.
+    #[inline]
+    fn extra_mask(&self) -> u32 {
+        LOW_BITS_TABLE[usize::from(self.depth.min(32))]
+            & !(self.red_mask | self.green_mask | self.blue_mask)
+    }
+
+    /// Iterate over the scanlines of this image. For `XyPixmap` images, this covers the scanlines of every
+    /// plane, one plane after another. The last scanline of an image created by `sub_image` may be shorter
+    /// than `bytes_per_line`.
+    #[inline]
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &[u8]> + ExactSizeIterator {
+        let len = (self.bytes_per_line * self.height * self.planes()).min(self.data.len());
+        self.data[..len].chunks(self.bytes_per_line.max(1))
+    }
+
+    /// The number of planes in the image data.
+    #[inline]
+    fn planes(&self) -> usize {
+        match self.format {
+            ImageFormat::XyPixmap => usize::from(self.depth),
+            _ => 1,
+        }
+    }
+
+    /// Get an area of this image as an image of its own. The area is clipped to the image.
+    ///
+    /// `ZPixmap` images with 8, 16, 24 or 32-bit pixels, as well as images with a single plane of bits, are
+    /// borrowed: the new image shares this image's data and scanline length, and starts partway into it. Other
+    /// images, such as `XyPixmap` images with more than one plane, have their area copied.
+    #[inline]
+    pub fn sub_image(&self, rect: Rectangle) -> Image<Cow<'_, [u8]>> {
+        let (x, y, width, height) = clip(&rect, self.width, self.height).unwrap_or_default();
+        let bytes_per_line = self.bytes_per_line;
+        let borrow = |start: usize| {
+            let end = (start + height * bytes_per_line).min(self.data.len());
+            Cow::Borrowed(self.data.get(start..end).unwrap_or(&[]))
+        };
+
+        if let Some(n) = self.byte_width() {
+            let data = borrow(y * bytes_per_line + x * n);
+            self.with_data(data, width, height, self.x_offset, bytes_per_line)
+        } else if self.planes() == 1
+            && (self.bits_per_pixel == 1 || self.format != ImageFormat::ZPixmap)
+        {
+            // bitmaps can start at any bit, thanks to the X offset
+            let data = borrow(y * bytes_per_line);
+            self.with_data(data, width, height, self.x_offset + x, bytes_per_line)
+        } else {
+            let bits_per_line = match self.format {
+                ImageFormat::ZPixmap => usize::from(self.bits_per_pixel) * width,
+                _ => width,
+            };
+            let bytes_per_line = roundup(bits_per_line, self.bitmap_pad as usize) >> 3;
+            let data = vec![0; bytes_per_line * height * self.planes()];
+            let mut image = self.with_data(data, width, height, 0, bytes_per_line);
+            let area = Rectangle {
+                x: x as i16,
+                y: y as i16,
+                width: width as u16,
+                height: height as u16,
+            };
+            image.blit(self, area, Point { x: 0, y: 0 });
+            self.with_data(Cow::Owned(image.data), width, height, 0, bytes_per_line)
+        }
+    }
+
+    /// Create an image with the same format as this one, but with different data and dimensions.
+    #[inline]
+    fn with_data<Target>(
+        &self,
+        data: Target,
+        width: usize,
+        height: usize,
+        x_offset: usize,
+        bytes_per_line: usize,
+    ) -> Image<Target> {
+        Image {
+            width,
+            height,
+            x_offset,
+            format: self.format,
+            depth: self.depth,
+            bit_order: self.bit_order,
+            byte_order: self.byte_order,
+            bitmap_unit: self.bitmap_unit,
+            bitmap_pad: self.bitmap_pad,
+            bytes_per_line,
+            bits_per_pixel: self.bits_per_pixel,
+            red_mask: self.red_mask,
+            green_mask: self.green_mask,
+            blue_mask: self.blue_mask,
+            data,
+        }
+    }
+
+    /// Read the values of the pixels in a row, starting at `(x, y)`, until `pixels` is full. Images whose
+    /// pixels are 8, 16, 24 or 32 bits wide are decoded a scanline at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pixels are not inside of the image.
+    #[inline]
+    pub fn read_row(&self, x: usize, y: usize, pixels: &mut [u32]) {
+        assert!(
+            y < self.height && x + pixels.len() <= self.width,
+            "Row is outside of the image"
+        );
+
+        let n = if let Some(n) = self.byte_width() {
+            n
+        } else {
+            pixels
+                .iter_mut()
+                .enumerate()
+                .for_each(|(i, pixel)| *pixel = self.pixel(x + i, y));
+            return;
+        };
+
+        let start = y * self.bytes_per_line + x * n;
+        let row = &self.data[start..start + pixels.len() * n];
+        let mask = LOW_BITS_TABLE[usize::from(self.depth.min(32))];
+        match (n, self.row_byte_order()) {
+            (1, _) => decode_row(row, n, mask, pixels, |b| u32::from(b[0])),
+            (2, ImageOrder::MsbFirst) => decode_row(row, n, mask, pixels, |b| {
+                u32::from(u16::from_be_bytes([b[0], b[1]]))
+            }),
+            (2, ImageOrder::LsbFirst) => decode_row(row, n, mask, pixels, |b| {
+                u32::from(u16::from_le_bytes([b[0], b[1]]))
+            }),
+            (3, ImageOrder::MsbFirst) => decode_row(row, n, mask, pixels, |b| {
+                u32::from_be_bytes([0, b[0], b[1], b[2]])
+            }),
+            (3, ImageOrder::LsbFirst) => decode_row(row, n, mask, pixels, |b| {
+                u32::from_le_bytes([b[0], b[1], b[2], 0])
+            }),
+            (_, ImageOrder::MsbFirst) => decode_row(row, n, mask, pixels, |b| {
+                u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+            }),
+            (_, ImageOrder::LsbFirst) => decode_row(row, n, mask, pixels, |b| {
+                u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+            }),
+        }
+    }
+
+    /// Convert the pixels of this image into the format of `dest`, a scanline at a time.
+    ///
+    /// Color channels are moved and scaled according to the masks of both images, so this can be used to
+    /// e.g. turn a BGRA image into an RGBA one, or a 16-bit image into a 32-bit one. Bits outside of the
+    /// color masks, such as an alpha channel, are carried over if both images have them, and set if only
+    /// `dest` does. If either image has no color masks, pixel values are copied as they are. Only the area
+    /// that both images cover is converted.
+    #[inline]
+    pub fn convert_into<Target: Deref<Target = [u8]> + DerefMut>(&self, dest: &mut Image<Target>) {
+        let width = self.width.min(dest.width);
+        let height = self.height.min(dest.height);
+        let src_masks = self.red_mask | self.green_mask | self.blue_mask;
+        let dest_masks = dest.red_mask | dest.green_mask | dest.blue_mask;
+
+        if src_masks == 0
+            || dest_masks == 0
+            || (
+                self.red_mask,
+                self.green_mask,
+                self.blue_mask,
+                self.extra_mask(),
+            ) == (
+                dest.red_mask,
+                dest.green_mask,
+                dest.blue_mask,
+                dest.extra_mask(),
+            )
+        {
+            dest.blit(
+                self,
+                Rectangle {
+                    x: 0,
+                    y: 0,
+                    width: u16::try_from(width).unwrap_or(u16::MAX),
+                    height: u16::try_from(height).unwrap_or(u16::MAX),
+                },
+                Point { x: 0, y: 0 },
+            );
+            return;
+        }
+
+        let channels = [
+            ChannelMap::new(self.red_mask, dest.red_mask),
+            ChannelMap::new(self.green_mask, dest.green_mask),
+            ChannelMap::new(self.blue_mask, dest.blue_mask),
+        ];
+        let (src_extra, dest_extra) = (self.extra_mask(), dest.extra_mask());
+        let extra = ChannelMap::new(src_extra, dest_extra);
+
+        let mut row = vec![0; width];
+        for y in 0..height {
+            self.read_row(0, y, &mut row);
+            row.iter_mut().for_each(|pixel| {
+                let color = channels.iter().fold(0, |acc, c| acc | c.map(*pixel));
+                *pixel = color
+                    | match (src_extra, dest_extra) {
+                        (_, 0) => 0,
+                        (0, dest_extra) => dest_extra,
+                        _ => extra.map(*pixel),
+                    };
+            });
+            dest.write_row(0, y, &row);
+        }
+    }
+}
+
+impl<Data> Image<Data>
+where
+    Data: Deref<Target = [u8]> + DerefMut,
+{
+    /// Iterate mutably over the scanlines of this image. For `XyPixmap` images, this covers the scanlines of
+    /// every plane, one plane after another. The last scanline of an image created by `sub_image` may be
+    /// shorter than `bytes_per_line`.
+    #[inline]
+    pub fn rows_mut(&mut self) -> impl DoubleEndedIterator<Item = &mut [u8]> + ExactSizeIterator {
+        let len = (self.bytes_per_line * self.height * self.planes()).min(self.data.len());
+        let bytes_per_line = self.bytes_per_line.max(1);
+        self.data[..len].chunks_mut(bytes_per_line)
+    }
+
+    /// Set the values of the pixels in a row, starting at `(x, y)`. Images whose pixels are 8, 16, 24 or 32
+    /// bits wide are encoded a scanline at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pixels are not inside of the image.
+    #[inline]
+    pub fn write_row(&mut self, x: usize, y: usize, pixels: &[u32]) {
+        assert!(
+            y < self.height && x + pixels.len() <= self.width,
+            "Row is outside of the image"
+        );
+
+        let n = if let Some(n) = self.byte_width() {
+            n
+        } else {
+            pixels
+                .iter()
+                .enumerate()
+                .for_each(|(i, pixel)| self.set_pixel(x + i, y, *pixel));
+            return;
+        };
+
+        let start = y * self.bytes_per_line + x * n;
+        let order = self.row_byte_order();
+        let row = &mut self.data[start..start + pixels.len() * n];
+        match (n, order) {
+            (1, _) => encode_row(row, n, pixels, |p, b| b[0] = p as u8),
+            (2, ImageOrder::MsbFirst) => encode_row(row, n, pixels, |p, b| {
+                b.copy_from_slice(&(p as u16).to_be_bytes());
+            }),
+            (2, ImageOrder::LsbFirst) => encode_row(row, n, pixels, |p, b| {
+                b.copy_from_slice(&(p as u16).to_le_bytes());
+            }),
+            (3, ImageOrder::MsbFirst) => encode_row(row, n, pixels, |p, b| {
+                b.copy_from_slice(&p.to_be_bytes()[1..]);
+            }),
+            (3, ImageOrder::LsbFirst) => encode_row(row, n, pixels, |p, b| {
+                b.copy_from_slice(&p.to_le_bytes()[..3]);
+            }),
+            (_, ImageOrder::MsbFirst) => encode_row(row, n, pixels, |p, b| {
+                b.copy_from_slice(&p.to_be_bytes());
+            }),
+            (_, ImageOrder::LsbFirst) => encode_row(row, n, pixels, |p, b| {
+                b.copy_from_slice(&p.to_le_bytes());
+            }),
+        }
+    }
+
+    /// Set every pixel in an area of the image to a value. The area is clipped to the image.
+    #[inline]
+    pub fn fill_rect(&mut self, rect: Rectangle, pixel: u32) {
+        let (x, y, width, height) = match clip(&rect, self.width, self.height) {
+            Some(area) => area,
+            None => return,
+        };
+
+        let n = if let Some(n) = self.byte_width() {
+            n
+        } else {
+            let row = vec![pixel; width];
+            (y..y + height).for_each(|y| self.write_row(x, y, &row));
+            return;
+        };
+
+        // fill in the first row, then copy it into the others
+        let mut bytes = [0; 4];
+        encode_pixel(pixel, self.row_byte_order(), &mut bytes[..n]);
+        let bytes_per_line = self.bytes_per_line;
+        let first = y * bytes_per_line + x * n;
+        let len = width * n;
+        self.data[first..first + len]
+            .chunks_exact_mut(n)
+            .for_each(|dest| dest.copy_from_slice(&bytes[..n]));
+        for row in 1..height {
+            self.data
+                .copy_within(first..first + len, first + row * bytes_per_line);
+        }
+    }
+
+    /// Copy an area of another image into this one, with its upper left corner at `dest`. The area is
+    /// clipped to both images.
+    ///
+    /// Pixel values are copied as they are; use `convert_into` to copy between images with different color
+    /// masks. Images with the same format, depth, byte order and pixel size are copied a scanline at a time
+    /// without decoding their pixels.
+    #[inline]
+    pub fn blit<Source: Deref<Target = [u8]>>(
+        &mut self,
+        src: &Image<Source>,
+        src_rect: Rectangle,
+        dest: Point,
+    ) {
+        let (src_x, dest_x, width) =
+            match clip_blit_axis(src_rect.x, dest.x, src_rect.width, src.width, self.width) {
+                Some(axis) => axis,
+                None => return,
+            };
+        let (src_y, dest_y, height) =
+            match clip_blit_axis(src_rect.y, dest.y, src_rect.height, src.height, self.height) {
+                Some(axis) => axis,
+                None => return,
+            };
+
+        match (self.byte_width(), src.byte_width()) {
+            (Some(n), Some(src_n))
+                if n == src_n
+                    && self.depth == src.depth
+                    && self.row_byte_order() == src.row_byte_order() =>
+            {
+                let len = width * n;
+                for row in 0..height {
+                    let from = (src_y + row) * src.bytes_per_line + src_x * n;
+                    let to = (dest_y + row) * self.bytes_per_line + dest_x * n;
+                    self.data[to..to + len].copy_from_slice(&src.data[from..from + len]);
+                }
+            }
+            _ => {
+                let mut pixels: Vec<u32> = vec![0; width];
+                for row in 0..height {
+                    src.read_row(src_x, src_y + row, &mut pixels);
+                    self.write_row(dest_x, dest_y + row, &pixels);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn bulk_ops_match_per_pixel_access() {
+    use alloc::boxed::Box;
+
+    let image = |bpp: u8, depth: u8, byte_order, masks: (u32, u32, u32)| {
+        let (width, height) = (5, 4);
+        let bytes_per_line = crate::util::roundup(usize::from(bpp) * width, 32) >> 3;
+        Image {
+            width,
+            height,
+            x_offset: 0,
+            format: ImageFormat::ZPixmap,
+            depth,
+            bit_order: ImageOrder::LsbFirst,
+            byte_order,
+            bitmap_unit: 32,
+            bitmap_pad: 32,
+            bytes_per_line,
+            bits_per_pixel: bpp,
+            red_mask: masks.0,
+            green_mask: masks.1,
+            blue_mask: masks.2,
+            data: vec![0; bytes_per_line * height].into_boxed_slice(),
+        }
+    };
+    let rect = |x, y, width, height| Rectangle {
+        x,
+        y,
+        width,
+        height,
+    };
+    let bgr = (0xff_0000, 0xff00, 0xff);
+    let rgb = (0xff, 0xff00, 0xff_0000);
+
+    // scanlines decode and encode the same values as single pixels do
+    for &(bpp, depth) in &[(8, 8), (16, 16), (24, 24), (32, 24), (32, 32)] {
+        for &order in &[ImageOrder::LsbFirst, ImageOrder::MsbFirst] {
+            let mut img: Image<Box<[u8]>> = image(bpp, depth, order, (0, 0, 0));
+            let mask = LOW_BITS_TABLE[usize::from(depth)];
+            let row: Vec<u32> = (0..5)
+                .map(|i| 0x1234_5678_u32.rotate_left(i * 7) & mask)
+                .collect();
+            img.write_row(0, 2, &row);
+            let pixels: Vec<u32> = (0..5).map(|x| img.pixel(x, 2)).collect();
+            assert_eq!(pixels, row, "{} bpp, {:?}", bpp, order);
+
+            img.set_pixel(3, 1, row[0]);
+            let mut read = [0; 2];
+            img.read_row(2, 1, &mut read);
+            assert_eq!(read, [0, row[0]], "{} bpp, {:?}", bpp, order);
+
+            img.fill_rect(rect(-1, 2, 3, 9), row[4]);
+            assert_eq!(img.pixel(0, 3), row[4]);
+            assert_eq!(img.pixel(1, 2), row[4]);
+            assert_eq!(img.pixel(2, 2), row[2]);
+            assert_eq!(img.pixel(3, 1), row[0]);
+        }
+    }
+
+    // blitting clips against both images
+    let mut src = image(32, 24, ImageOrder::LsbFirst, bgr);
+    (0..4).for_each(|y| (0..5).for_each(|x| src.set_pixel(x, y, (y * 16 + x) as u32)));
+    let mut dest = image(24, 24, ImageOrder::MsbFirst, bgr);
+    dest.blit(&src, rect(1, 0, 10, 2), Point { x: -1, y: 3 });
+    assert_eq!(dest.pixel(0, 3), 0x02);
+    assert_eq!(dest.pixel(2, 3), 0x04);
+    assert_eq!(dest.pixel(3, 3), 0);
+    assert_eq!(dest.pixel(0, 2), 0);
+    assert_eq!(src.rows().len(), 4);
+    assert_eq!(dest.rows_mut().map(|row| row.len()).max(), Some(16));
+    // coordinates at the ends of the i16 range are moved without overflowing
+    dest.blit(&src, rect(i16::MAX, 0, 2, 2), Point { x: i16::MIN, y: 0 });
+    dest.blit(
+        &src,
+        rect(i16::MIN, 0, u16::MAX, 2),
+        Point { x: i16::MAX, y: 0 },
+    );
+    assert_eq!(dest.pixel(0, 3), 0x02);
+    assert_eq!(dest.pixel(0, 0), 0);
+
+    // channels are moved and scaled between formats
+    let mut bgra = image(32, 32, ImageOrder::LsbFirst, bgr);
+    bgra.set_pixel(1, 1, 0x80_11_22_33);
+    let mut rgba = image(32, 32, ImageOrder::LsbFirst, rgb);
+    bgra.convert_into(&mut rgba);
+    assert_eq!(rgba.pixel(1, 1), 0x80_33_22_11);
+    assert_eq!(rgba.pixel(0, 0), 0);
+
+    let mut rgb565 = image(16, 16, ImageOrder::MsbFirst, (0xf800, 0x07e0, 0x001f));
+    rgb565.set_pixel(2, 0, 0xf81f);
+    let mut xrgb = image(32, 32, ImageOrder::MsbFirst, bgr);
+    rgb565.convert_into(&mut xrgb);
+    assert_eq!(xrgb.pixel(2, 0), 0xff_ff_00_ff);
+    assert_eq!(xrgb.pixel(0, 0), 0xff_00_00_00);
+}
+
+#[test]
+fn sub_image_borrows_and_puts_dirty_rects() {
+    use crate::{
+        display::{BasicDisplay, DisplayBase},
+        image::put::put_image_req,
+    };
+
+    let mut display = BasicDisplay::dummy();
+    let mut image = Image::new(
+        &display,
+        Some(display.default_visual()),
+        24,
+        ImageFormat::ZPixmap,
+        0,
+        vec![0; 8 * 4 * 4].into_boxed_slice(),
+        8,
+        4,
+        32,
+        None,
+    )
+    .unwrap();
+    (0..4).for_each(|y| (0..8).for_each(|x| image.set_pixel(x, y, (y * 16 + x) as u32)));
+    let rect = |x, y, width, height| Rectangle {
+        x,
+        y,
+        width,
+        height,
+    };
+
+    // whole-byte pixels are borrowed, and clipped to the image
+    let sub = image.sub_image(rect(6, 2, 5, 5));
+    assert!(matches!(sub.data, Cow::Borrowed(_)));
+    assert_eq!((sub.width, sub.height), (2, 2));
+    assert_eq!((sub.pixel(0, 0), sub.pixel(1, 1)), (0x26, 0x37));
+    assert_eq!(sub.rows().map(<[u8]>::len).collect::<Vec<_>>(), [32, 8]);
+    assert_eq!(image.sub_image(rect(9, 0, 1, 1)).width, 0);
+
+    // bitmaps are borrowed using the X offset, while multi-plane images are copied
+    let mut bitmap = image.with_data(vec![0; 4], 8, 4, 0, 1);
+    bitmap.format = ImageFormat::XyBitmap;
+    bitmap.depth = 1;
+    bitmap.bitmap_unit = 8;
+    bitmap.bits_per_pixel = 1;
+    bitmap.set_pixel(5, 1, 1);
+    let sub = bitmap.sub_image(rect(4, 1, 2, 2));
+    assert!(matches!(sub.data, Cow::Borrowed(_)));
+    assert_eq!((sub.x_offset, sub.pixel(1, 0), sub.pixel(0, 0)), (4, 1, 0));
+
+    let mut planes = image.with_data(vec![0; 4 * 3], 8, 4, 0, 1);
+    planes.format = ImageFormat::XyPixmap;
+    planes.depth = 3;
+    planes.bitmap_unit = 8;
+    planes.bits_per_pixel = 1;
+    planes.set_pixel(3, 2, 5);
+    planes.set_pixel(2, 3, 2);
+    let sub = planes.sub_image(rect(2, 2, 2, 2));
+    assert!(matches!(sub.data, Cow::Owned(_)));
+    assert_eq!(
+        (sub.pixel(1, 0), sub.pixel(0, 1), sub.pixel(0, 0)),
+        (5, 2, 0)
+    );
+
+    // a dirty rectangle only sends its own scanlines, whether taken from the image or from a sub-image
+    let root = display.default_root().into();
+    let gc = Default::default();
+    let from_image = put_image_req(&mut display, root, gc, &image, 1, 1, 1, 3, 6, 1);
+    assert_eq!(from_image.len(), 1);
+    assert_eq!((from_image[0].width, from_image[0].height), (6, 1));
+    assert_eq!(from_image[0].data.len(), 6 * 4);
+    assert_eq!(&from_image[0].data[..4], &[0x11, 0, 0, 0]);
+
+    let sub = image.sub_image(rect(1, 1, 6, 1));
+    let from_sub = put_image_req(&mut display, root, gc, &sub, 0, 0, 1, 3, 6, 1);
+    assert_eq!(from_sub[0].data, from_image[0].data);
+}
@@ -16,15 +16,16 @@ pub(crate) fn no_swap(
     linelen: usize,
     srcinc: usize,
     destinc: usize,
-    _height: usize,
+    height: usize,
     _half_order: ImageOrder,
 ) {
-    if srcinc == destinc {
+    if srcinc == destinc && source.len() == dest.len() {
         // this operation should be vectorized
         dest.copy_from_slice(source);
     } else {
         dest.chunks_mut(destinc)
             .zip(source.chunks(srcinc))
+            .take(height)
             .for_each(|(d, s)| (&mut d[..linelen]).copy_from_slice(&s[..linelen]));
     }
 }
@@ -4,8 +4,22 @@
 
 #![allow(clippy::similar_names, clippy::unreadable_literal)]
 
+mod bulk;
 pub(crate) mod fit;
+mod pixel_format;
 pub(crate) mod put;
+mod resource;
+mod rgb;
+pub(crate) mod shm;
+mod xbm;
+mod xpm;
+
+pub use pixel_format::PixelFormat;
+pub use resource::ResourceImage;
+pub use rgb::lookup_color_name;
+#[cfg(all(feature = "shm", feature = "std", unix))]
+pub use shm::ShmImage;
+pub use xpm::{Xpm, XpmColor};
 
 use crate::{
     auto::xproto::{GetImageReply, ImageFormat, ImageOrder, Visualtype},
@@ -136,7 +150,7 @@ const OS_BYTE_ORDER: ImageOrder = ImageOrder::MsbFirst;
 
 /// Helper function to get the bits per pixel and scanline pad for a given depth.
 #[inline]
-fn bits_per_pixel<Dpy: DisplayBase + ?Sized>(dpy: &Dpy, depth: u8) -> u8 {
+pub(crate) fn bits_per_pixel<Dpy: DisplayBase + ?Sized>(dpy: &Dpy, depth: u8) -> u8 {
     dpy.setup()
         .pixmap_formats
         .iter()
@@ -358,7 +372,7 @@ where
 
         let min_bytes_per_line = match format {
             ImageFormat::ZPixmap => roundup(bits_per_pixel as usize * width, quantum as usize) >> 3,
-            _ => roundup(width + x_offset, quantum as usize),
+            _ => roundup(width + x_offset, quantum as usize) >> 3,
         };
 
         let bytes_per_line = match bytes_per_line {
@@ -397,6 +411,28 @@ where
         &*self.data
     }
 
+    /// Borrow this image, with a slice as its data.
+    #[inline]
+    pub fn as_image(&self) -> Image<&[u8]> {
+        Image {
+            width: self.width,
+            height: self.height,
+            format: self.format,
+            byte_order: self.byte_order,
+            bitmap_unit: self.bitmap_unit,
+            bit_order: self.bit_order,
+            red_mask: self.red_mask,
+            green_mask: self.green_mask,
+            blue_mask: self.blue_mask,
+            x_offset: self.x_offset,
+            bitmap_pad: self.bitmap_pad,
+            depth: self.depth,
+            bits_per_pixel: self.bits_per_pixel,
+            bytes_per_line: self.bytes_per_line,
+            data: &*self.data,
+        }
+    }
+
     /// Clone this image to an equivalent but with a boxed slice as its data.
     #[inline]
     pub fn clone_to_boxed_slice(&self) -> Image<Box<[u8]>> {
@@ -478,29 +514,38 @@ impl Image<Box<[u8]>> {
     ) -> crate::Result<Self> {
         let (width, height) = img.dimensions();
         let (width, height) = (width as usize, height as usize);
+        let quantum = match format {
+            ImageFormat::ZPixmap => dpy.get_scanline_pad(depth) as u32,
+            _ => dpy.setup().bitmap_format_scanline_pad.into(),
+        };
 
-        // create the heap space necessary for the image
-        let heapspace: Box<[u8]> = iter::repeat(0)
-            .take(width * height * Img::Pixel::CHANNEL_COUNT as usize)
-            .collect();
-
-        // use the heap space to create the new image
-        Image::new(
+        let mut image = Image::new(
             &dpy,
             visual,
             depth,
             format,
             0,
-            heapspace,
+            Box::default(),
             width,
             height,
-            (Img::Pixel::CHANNEL_COUNT * 8).into(),
+            quantum,
             None,
         )
-        .ok_or(crate::BreadError::StaticMsg("Failed to create base image"))
+        .ok_or(crate::BreadError::StaticMsg("Failed to create base image"))?;
+
+        // create the heap space necessary for the image; XY images have one plane per bit of depth
+        let planes = match format {
+            ImageFormat::XyPixmap => usize::from(depth),
+            _ => 1,
+        };
+        image.data = iter::repeat(0)
+            .take(image.bytes_per_line * height * planes)
+            .collect();
+        Ok(image)
     }
 
-    /// Create a new `Image` based off of an `image::GenericImageView`.
+    /// Create a new `Image` based off of an `image::GenericImageView`. Colors are converted to pixel values
+    /// using a `PixelFormat` for the given visual and depth.
     #[inline]
     pub fn from_image<Dpy: DisplayBase + ?Sized, Img: image::GenericImageView>(
         dpy: &mut Dpy,
@@ -509,29 +554,147 @@ impl Image<Box<[u8]>> {
         format: ImageFormat,
         img: &Img,
     ) -> crate::Result<Self>
+    where
+        <<Img as image::GenericImageView>::Pixel as image::Pixel>::Subpixel: Into<u8>,
+    {
+        Self::from_image_with_format(
+            dpy,
+            visual,
+            depth,
+            format,
+            &PixelFormat::new(visual, depth),
+            img,
+        )
+    }
+
+    /// Create a new `Image` based off of an `image::GenericImageView`, using `pixel_format` to convert
+    /// colors to pixel values. This is useful for colormapped visuals, which need a palette.
+    #[inline]
+    pub fn from_image_with_format<Dpy: DisplayBase + ?Sized, Img: image::GenericImageView>(
+        dpy: &mut Dpy,
+        visual: Option<&Visualtype>,
+        depth: u8,
+        format: ImageFormat,
+        pixel_format: &PixelFormat,
+        img: &Img,
+    ) -> crate::Result<Self>
     where
         <<Img as image::GenericImageView>::Pixel as image::Pixel>::Subpixel: Into<u8>,
     {
         let mut image = Self::from_image_empty(dpy, visual, depth, format, img)?;
 
-        // fill the image
+        // fill the image, a scanline at a time
+        let mut row = alloc::vec![0; image.width];
         for (x, y, pixel) in img.pixels() {
-            let mut xpixel: u32 = 0;
-            for (i, channel) in pixel.channels().iter().copied().enumerate() {
-                let channel: u8 = channel.into();
-                xpixel |= (channel as u32) << (i * 8);
+            let (x, y) = (x as usize, y as usize);
+            let rgba = pixel.to_rgba().0;
+            let rgba = [rgba[0].into(), rgba[1].into(), rgba[2].into(), rgba[3].into()];
+            row[x] = pixel_format.pixel_at(x, y, rgba);
+            if x + 1 == image.width {
+                image.write_row(0, y, &row);
             }
-            image.set_pixel(x as usize, y as usize, xpixel);
         }
 
         Ok(image)
     }
 }
 
+#[cfg(feature = "image-support")]
+impl<Data> Image<Data>
+where
+    Data: Deref<Target = [u8]>,
+{
+    /// Get the `PixelFormat` described by this image's masks and depth.
+    #[inline]
+    fn pixel_format(&self) -> PixelFormat {
+        PixelFormat::from_masks(self.depth, self.red_mask, self.green_mask, self.blue_mask)
+    }
+
+    /// Convert this image into an `image::RgbaImage`, using its masks and depth to decode pixel values.
+    /// Images without masks, such as bitmaps, are treated as gray levels.
+    #[inline]
+    pub fn to_rgba_image(&self) -> image::RgbaImage {
+        self.to_rgba_image_with_format(&self.pixel_format())
+    }
+
+    /// Convert this image into an `image::RgbaImage`, using `pixel_format` to decode pixel values. This is
+    /// useful for images taken from colormapped visuals, which need a palette.
+    #[inline]
+    pub fn to_rgba_image_with_format(&self, pixel_format: &PixelFormat) -> image::RgbaImage {
+        let mut out = image::RgbaImage::new(self.width as u32, self.height as u32);
+        self.decode_rows(&mut out, 4, |pixel, dest| {
+            dest.copy_from_slice(&pixel_format.rgba(pixel));
+        });
+        out
+    }
+
+    /// Convert this image into an `image::RgbImage`, discarding any alpha channel.
+    #[inline]
+    pub fn to_rgb_image(&self) -> image::RgbImage {
+        let pixel_format = self.pixel_format();
+        let mut out = image::RgbImage::new(self.width as u32, self.height as u32);
+        self.decode_rows(&mut out, 3, |pixel, dest| {
+            dest.copy_from_slice(&pixel_format.rgba(pixel)[..3]);
+        });
+        out
+    }
+
+    /// Convert this image into an `image::GrayImage`, using the luminance of every pixel.
+    #[inline]
+    pub fn to_luma_image(&self) -> image::GrayImage {
+        let pixel_format = self.pixel_format();
+        let mut out = image::GrayImage::new(self.width as u32, self.height as u32);
+        self.decode_rows(&mut out, 1, |pixel, dest| {
+            let [r, g, b, _] = pixel_format.rgba(pixel);
+            dest[0] = pixel_format::luminance(r, g, b);
+        });
+        out
+    }
+
+    /// Decode this image a scanline at a time, calling `f` with every pixel value and the `channels` bytes
+    /// of `out` that it corresponds to.
+    #[inline]
+    fn decode_rows<F: FnMut(u32, &mut [u8])>(&self, out: &mut [u8], channels: usize, mut f: F) {
+        if self.width == 0 {
+            return;
+        }
+
+        let mut row = alloc::vec![0; self.width];
+        for (y, dest) in out.chunks_exact_mut(self.width * channels).enumerate() {
+            self.read_row(0, y, &mut row);
+            row.iter()
+                .zip(dest.chunks_exact_mut(channels))
+                .for_each(|(pixel, dest)| f(*pixel, dest));
+        }
+    }
+}
+
 impl<Data> Image<Data>
 where
     Data: Deref<Target = [u8]> + DerefMut,
 {
+    /// Mutably borrow this image, with a mutable slice as its data.
+    #[inline]
+    pub fn as_mut_image(&mut self) -> Image<&mut [u8]> {
+        Image {
+            width: self.width,
+            height: self.height,
+            format: self.format,
+            byte_order: self.byte_order,
+            bitmap_unit: self.bitmap_unit,
+            bit_order: self.bit_order,
+            red_mask: self.red_mask,
+            green_mask: self.green_mask,
+            blue_mask: self.blue_mask,
+            x_offset: self.x_offset,
+            bitmap_pad: self.bitmap_pad,
+            depth: self.depth,
+            bits_per_pixel: self.bits_per_pixel,
+            bytes_per_line: self.bytes_per_line,
+            data: &mut *self.data,
+        }
+    }
+
     /// Assuming this is a 32 bit `ZPixmap`, set a pixel's value.
     #[inline]
     fn set_pixel32(&mut self, x: usize, y: usize, pixel: u32) {
@@ -652,7 +815,7 @@ where
                     px >>= 8;
                 }
 
-                plane -= self.bytes_per_line * self.height;
+                plane = plane.saturating_sub(self.bytes_per_line * self.height);
             }
         } else {
             // is a ZImage
@@ -762,7 +925,8 @@ fn put_bits(source: &[u8], mut dstoffset: usize, mut numbits: usize, dest: &mut
         dst_index += 1;
         numbits -= hibits;
 
-        chlo = (source[src_index] & HIMASK[hibits]) >> hibits;
+        // hibits is 8 when the destination is byte-aligned, which would overflow a u8 shift
+        chlo = (u16::from(source[src_index] & HIMASK[hibits]) >> hibits) as u8;
         src_index += 1;
 
         if numbits <= dstoffset {
@@ -0,0 +1,517 @@
+// MIT/Apache2 License
+
+use super::LOW_BITS_TABLE;
+use crate::auto::xproto::{VisualClass, Visualtype};
+use alloc::boxed::Box;
+
+/// Ordered dithering thresholds, in sixteenths.
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// The position and width of a channel within a pixel value.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+struct Channel {
+    shift: u32,
+    bits: u32,
+}
+
+impl Channel {
+    #[inline]
+    fn from_mask(mask: u32) -> Self {
+        if mask == 0 {
+            return Self::default();
+        }
+
+        let shift = mask.trailing_zeros();
+        Self {
+            shift,
+            bits: (mask >> shift).trailing_ones(),
+        }
+    }
+
+    /// Scale an 8-bit channel value to the width of this channel, and shift it into place.
+    #[inline]
+    fn encode(self, value: u8) -> u32 {
+        if self.bits == 0 {
+            return 0;
+        }
+
+        let max = u64::from(LOW_BITS_TABLE[self.bits as usize]);
+        let scaled = (u64::from(value) * max + 127) / 255;
+        (scaled as u32) << self.shift
+    }
+
+    /// Take this channel out of a pixel value and scale it to 8 bits.
+    #[inline]
+    fn decode(self, pixel: u32) -> Option<u8> {
+        if self.bits == 0 {
+            return None;
+        }
+
+        let max = u64::from(LOW_BITS_TABLE[self.bits as usize]);
+        let value = u64::from(pixel >> self.shift) & max;
+        Some(((value * 255 + max / 2) / max) as u8)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Kind {
+    /// `TrueColor` and `DirectColor`: every channel has its own bits in the pixel.
+    Masks {
+        red: Channel,
+        green: Channel,
+        blue: Channel,
+        alpha: Channel,
+    },
+    /// Gray ramps, where the pixel value is the intensity level.
+    Gray { levels: u32 },
+    /// Colormapped visuals, where the pixel value is an index into the palette.
+    Palette(Box<[[u8; 3]]>),
+}
+
+/// Describes how RGBA colors are turned into pixel values for a certain visual and depth, and back.
+///
+/// For `TrueColor` and `DirectColor` visuals, each channel is scaled to the width of its mask and shifted into
+/// place, so BGR, 16-bit 565 and 30-bit visuals all receive the right colors. If the depth has bits left over
+/// after the red, green and blue masks (e.g. a 32-bit ARGB visual), the alpha channel is stored in them.
+///
+/// Gray visuals and 1-bit images use the luminance of the color, and are dithered unless `set_dither` turns it
+/// off. Colormapped visuals map every color to the nearest entry of a palette, which has to be provided with
+/// `with_palette`, since the colormap's contents are not known ahead of time. Without a palette, they are
+/// treated as a gray ramp over the colormap's entries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PixelFormat {
+    kind: Kind,
+    dither: bool,
+}
+
+impl PixelFormat {
+    /// Create a `PixelFormat` for the given visual and depth. Without a visual, `TrueColor` masks are assumed
+    /// for depths 15, 16, 24, 30 and 32, and a gray ramp is assumed for any other depth.
+    #[inline]
+    #[must_use]
+    pub fn new(visual: Option<&Visualtype>, depth: u8) -> Self {
+        let depth = depth.min(32);
+        let kind = match visual {
+            _ if depth <= 1 => Kind::Gray { levels: 2 },
+            Some(Visualtype {
+                class: VisualClass::TrueColor | VisualClass::DirectColor,
+                red_mask,
+                green_mask,
+                blue_mask,
+                ..
+            }) => Self::masks(depth, *red_mask, *green_mask, *blue_mask),
+            Some(Visualtype {
+                colormap_entries, ..
+            }) => Kind::Gray {
+                levels: u32::from(*colormap_entries).min(1 << depth.min(16)).max(2),
+            },
+            None => match depth {
+                15 => Self::masks(depth, 0x7c00, 0x03e0, 0x001f),
+                16 => Self::masks(depth, 0xf800, 0x07e0, 0x001f),
+                24 | 32 => Self::masks(depth, 0x00ff_0000, 0x0000_ff00, 0x0000_00ff),
+                30 => Self::masks(depth, 0x3ff0_0000, 0x000f_fc00, 0x0000_03ff),
+                depth => Kind::Gray {
+                    levels: 1 << depth.min(16),
+                },
+            },
+        };
+
+        Self { kind, dither: true }
+    }
+
+    /// Create a `PixelFormat` for a colormapped visual, where `palette[pixel]` is the RGB color of `pixel`.
+    /// The palette can be built from the reply to a `QueryColors` request for every pixel in the colormap.
+    #[inline]
+    #[must_use]
+    pub fn with_palette<Palette: Into<Box<[[u8; 3]]>>>(palette: Palette) -> Self {
+        Self {
+            kind: Kind::Palette(palette.into()),
+            dither: true,
+        }
+    }
+
+    /// Create a `PixelFormat` from the masks stored in an image. If there are no masks, as is the case for
+    /// images of pixmaps, deep pixel values are assumed to use the usual `TrueColor` layout for their depth and
+    /// shallow ones are assumed to be gray levels.
+    #[cfg(feature = "image-support")]
+    #[inline]
+    pub(crate) fn from_masks(depth: u8, red_mask: u32, green_mask: u32, blue_mask: u32) -> Self {
+        let depth = depth.min(32);
+        let kind = if red_mask | green_mask | blue_mask != 0 {
+            Self::masks(depth, red_mask, green_mask, blue_mask)
+        } else if depth > 8 {
+            return Self::new(None, depth);
+        } else {
+            Kind::Gray {
+                levels: 1 << depth.max(1),
+            }
+        };
+
+        Self { kind, dither: true }
+    }
+
+    #[inline]
+    fn masks(depth: u8, red_mask: u32, green_mask: u32, blue_mask: u32) -> Kind {
+        let alpha_mask = LOW_BITS_TABLE[usize::from(depth)] & !(red_mask | green_mask | blue_mask);
+        Kind::Masks {
+            red: Channel::from_mask(red_mask),
+            green: Channel::from_mask(green_mask),
+            blue: Channel::from_mask(blue_mask),
+            alpha: Channel::from_mask(alpha_mask),
+        }
+    }
+
+    /// Set whether or not colors are dithered when they are converted into a gray level. This is on by
+    /// default.
+    #[inline]
+    pub fn set_dither(&mut self, dither: bool) {
+        self.dither = dither;
+    }
+
+    /// Convert an RGBA color into a pixel value, without dithering.
+    #[inline]
+    #[must_use]
+    pub fn pixel(&self, rgba: [u8; 4]) -> u32 {
+        self.convert(rgba, None)
+    }
+
+    /// Convert an RGBA color at the given position in an image into a pixel value. The position is used to
+    /// dither the color, if dithering applies.
+    #[inline]
+    #[must_use]
+    pub fn pixel_at(&self, x: usize, y: usize, rgba: [u8; 4]) -> u32 {
+        self.convert(
+            rgba,
+            if self.dither {
+                Some(BAYER_4X4[y & 3][x & 3])
+            } else {
+                None
+            },
+        )
+    }
+
+    /// Convert a pixel value back into an RGBA color. Pixels without an alpha channel are opaque, and pixels
+    /// outside of the palette are black.
+    #[inline]
+    #[must_use]
+    pub fn rgba(&self, pixel: u32) -> [u8; 4] {
+        match self.kind {
+            Kind::Masks {
+                red,
+                green,
+                blue,
+                alpha,
+            } => [
+                red.decode(pixel).unwrap_or(0),
+                green.decode(pixel).unwrap_or(0),
+                blue.decode(pixel).unwrap_or(0),
+                alpha.decode(pixel).unwrap_or(0xff),
+            ],
+            Kind::Gray { levels } => {
+                let level = pixel.min(levels - 1);
+                let value = ((level * 255 + (levels - 1) / 2) / (levels - 1)) as u8;
+                [value, value, value, 0xff]
+            }
+            Kind::Palette(ref palette) => {
+                let [r, g, b] = palette.get(pixel as usize).copied().unwrap_or_default();
+                [r, g, b, 0xff]
+            }
+        }
+    }
+
+    #[inline]
+    fn convert(&self, [r, g, b, a]: [u8; 4], threshold: Option<u8>) -> u32 {
+        match self.kind {
+            Kind::Masks {
+                red,
+                green,
+                blue,
+                alpha,
+            } => red.encode(r) | green.encode(g) | blue.encode(b) | alpha.encode(a),
+            Kind::Gray { levels } => {
+                let scaled = u32::from(luminance(r, g, b)) * (levels - 1);
+                match threshold {
+                    // rounds up when the fraction is above the threshold, which is 0.5 on average
+                    Some(threshold) => {
+                        (scaled * 32 + (u32::from(threshold) * 2 + 1) * 255) / (255 * 32)
+                    }
+                    None => (scaled + 127) / 255,
+                }
+            }
+            Kind::Palette(ref palette) => nearest(palette, [r, g, b]),
+        }
+    }
+}
+
+/// The luminance of an RGB color, using the ITU-R BT.601 weights.
+#[inline]
+pub(crate) fn luminance(r: u8, g: u8, b: u8) -> u8 {
+    ((u32::from(r) * 299 + u32::from(g) * 587 + u32::from(b) * 114 + 500) / 1000) as u8
+}
+
+/// Find the index of the palette entry closest to the given color.
+#[inline]
+fn nearest(palette: &[[u8; 3]], color: [u8; 3]) -> u32 {
+    let distance = |entry: &[u8; 3]| -> u32 {
+        entry
+            .iter()
+            .zip(color.iter())
+            .map(|(e, c)| {
+                let d = (i32::from(*e) - i32::from(*c)).unsigned_abs();
+                d * d
+            })
+            .sum()
+    };
+
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, entry)| distance(entry))
+        .map_or(0, |(index, _)| index as u32)
+}
+
+#[test]
+fn pixel_format_golden_pixels() {
+    use crate::display::{BasicDisplay, DisplayBase};
+
+    let visual = |class, red_mask, green_mask, blue_mask| Visualtype {
+        class,
+        colormap_entries: 256,
+        red_mask,
+        green_mask,
+        blue_mask,
+        ..Default::default()
+    };
+    let orange = [0xff, 0x80, 0x00, 0xff];
+
+    // the visuals of the dummy display: 24-bit TrueColor and DirectColor, and a 32-bit ARGB visual
+    let display = BasicDisplay::dummy();
+    for (depth, visual) in display
+        .default_screen()
+        .allowed_depths
+        .iter()
+        .flat_map(|d| d.visuals.iter().map(move |v| (d.depth, v)))
+    {
+        let format = PixelFormat::new(Some(visual), depth);
+        let expected = if depth == 32 {
+            0xffff_8000
+        } else {
+            0x00ff_8000
+        };
+        assert_eq!(format.pixel(orange), expected, "visual {:?}", visual);
+    }
+
+    let bgr = PixelFormat::new(
+        Some(&visual(VisualClass::TrueColor, 0xff, 0xff00, 0xff_0000)),
+        24,
+    );
+    assert_eq!(bgr.pixel(orange), 0x0000_80ff);
+    let rgb565 = PixelFormat::new(
+        Some(&visual(VisualClass::TrueColor, 0xf800, 0x07e0, 0x1f)),
+        16,
+    );
+    assert_eq!(rgb565.pixel(orange), 0xfc00);
+    assert_eq!(rgb565.pixel([0xff, 0xff, 0xff, 0]), 0xffff);
+    let deep = PixelFormat::new(
+        Some(&visual(VisualClass::TrueColor, 0x3ff0_0000, 0xffc00, 0x3ff)),
+        30,
+    );
+    assert_eq!(deep.pixel(orange), 0x3ff8_0800);
+    assert_eq!(PixelFormat::new(None, 16), rgb565);
+
+    // gray levels are dithered, but average out to the right intensity
+    let mut gray = PixelFormat::new(
+        Some(&Visualtype {
+            colormap_entries: 4,
+            ..visual(VisualClass::StaticGray, 0, 0, 0)
+        }),
+        2,
+    );
+    assert_eq!(gray.pixel([0xff, 0xff, 0xff, 0xff]), 3);
+    assert_eq!(gray.pixel([0x80, 0x80, 0x80, 0xff]), 2);
+    let total: u32 = (0..4)
+        .flat_map(|y| (0..4).map(move |x| (x, y)))
+        .map(|(x, y)| gray.pixel_at(x, y, [0x55, 0x55, 0x55, 0xff]))
+        .sum();
+    assert_eq!(total, 16);
+    gray.set_dither(false);
+    assert_eq!(gray.pixel_at(3, 3, [0x60, 0x60, 0x60, 0xff]), 1);
+
+    let mono = PixelFormat::new(None, 1);
+    assert_eq!(mono.pixel([0xff, 0xff, 0xff, 0xff]), 1);
+    assert_eq!(mono.pixel([0x20, 0x20, 0x20, 0xff]), 0);
+    let lit: u32 = (0..4)
+        .flat_map(|y| (0..4).map(move |x| (x, y)))
+        .map(|(x, y)| mono.pixel_at(x, y, [0x80, 0x80, 0x80, 0xff]))
+        .sum();
+    assert_eq!(lit, 8);
+
+    let palette =
+        PixelFormat::with_palette(alloc::vec![[0, 0, 0], [0xff, 0xff, 0xff], [0xff, 0x7f, 0]]);
+    assert_eq!(palette.pixel(orange), 2);
+    assert_eq!(palette.pixel([0xe0, 0xe0, 0xe0, 0xff]), 1);
+}
+
+#[cfg(feature = "image-support")]
+#[test]
+fn from_image_uses_visual_masks() {
+    use crate::{auto::xproto::ImageFormat, display::BasicDisplay, image::Image};
+
+    let mut display = BasicDisplay::dummy();
+    let bgr = Visualtype {
+        class: VisualClass::TrueColor,
+        red_mask: 0xff,
+        green_mask: 0xff00,
+        blue_mask: 0xff_0000,
+        ..Default::default()
+    };
+    let img = image::RgbImage::from_pixel(3, 2, image::Rgb([0x12, 0x34, 0x56]));
+    let image =
+        Image::from_image(&mut display, Some(&bgr), 24, ImageFormat::ZPixmap, &img).unwrap();
+    assert_eq!(image.pixel(2, 1), 0x0056_3412);
+
+    let gray = image::GrayImage::from_pixel(4, 4, image::Luma([0x80]));
+    let image = Image::from_image(&mut display, None, 1, ImageFormat::XyPixmap, &gray).unwrap();
+    let lit: u32 = (0..4)
+        .flat_map(|y| (0..4).map(move |x| (x, y)))
+        .map(|(x, y)| image.pixel(x, y))
+        .sum();
+    assert_eq!(lit, 8);
+
+    // images of pixmaps come without masks
+    let mut image = Image::from_image(&mut display, None, 24, ImageFormat::ZPixmap, &img).unwrap();
+    image.red_mask = 0;
+    image.green_mask = 0;
+    image.blue_mask = 0;
+    assert_eq!(
+        image.to_rgba_image().get_pixel(2, 1).0,
+        [0x12, 0x34, 0x56, 0xff]
+    );
+}
+
+#[cfg(feature = "image-support")]
+#[allow(clippy::too_many_lines)]
+#[test]
+fn to_rgba_image_handles_every_layout() {
+    use crate::{
+        auto::xproto::{ImageFormat, ImageOrder},
+        image::Image,
+    };
+    use alloc::vec;
+
+    let colors = [
+        [0xff, 0x00, 0x00, 0xff],
+        [0x00, 0xff, 0x00, 0xff],
+        [0x00, 0x00, 0xff, 0xff],
+        [0xff, 0xff, 0xff, 0xff],
+        [0x00, 0x00, 0x00, 0xff],
+    ];
+    let orders = [ImageOrder::MsbFirst, ImageOrder::LsbFirst];
+
+    // (format, depth, bits per pixel, masks)
+    let layouts = [
+        (ImageFormat::ZPixmap, 24, 32, (0xff_0000, 0xff00, 0xff)),
+        (ImageFormat::ZPixmap, 24, 24, (0xff, 0xff00, 0xff_0000)),
+        (ImageFormat::ZPixmap, 16, 16, (0xf800, 0x07e0, 0x001f)),
+        (ImageFormat::ZPixmap, 8, 8, (0xe0, 0x1c, 0x03)),
+        (ImageFormat::XyPixmap, 16, 1, (0xf800, 0x07e0, 0x001f)),
+        (ImageFormat::XyBitmap, 1, 1, (0, 0, 0)),
+    ];
+
+    for &(format, depth, bits_per_pixel, (red_mask, green_mask, blue_mask)) in &layouts {
+        for &byte_order in &orders {
+            for &bit_order in &orders {
+                for &bitmap_unit in &[8u8, 16, 32] {
+                    let (width, height) = (colors.len(), 3);
+                    let bytes_per_line = match format {
+                        ImageFormat::ZPixmap => {
+                            crate::util::roundup(width * usize::from(bits_per_pixel), 32) >> 3
+                        }
+                        _ => 4,
+                    };
+                    let planes = if format == ImageFormat::XyPixmap {
+                        usize::from(depth)
+                    } else {
+                        1
+                    };
+                    let mut image = Image {
+                        width,
+                        height,
+                        x_offset: 0,
+                        format,
+                        depth,
+                        bit_order,
+                        byte_order,
+                        bitmap_unit,
+                        bitmap_pad: 32,
+                        bytes_per_line,
+                        bits_per_pixel,
+                        red_mask,
+                        green_mask,
+                        blue_mask,
+                        data: vec![0; bytes_per_line * height * planes].into_boxed_slice(),
+                    };
+                    let pixel_format =
+                        PixelFormat::from_masks(depth, red_mask, green_mask, blue_mask);
+
+                    for y in 0..height {
+                        for (x, color) in colors.iter().enumerate() {
+                            image.set_pixel(x, y, pixel_format.pixel(*color));
+                        }
+                    }
+
+                    let rgba = image.to_rgba_image();
+                    let luma = image.to_luma_image();
+                    for (x, color) in colors.iter().enumerate() {
+                        let expected = if depth == 1 {
+                            let value = if luminance(color[0], color[1], color[2]) >= 0x80 {
+                                0xff
+                            } else {
+                                0
+                            };
+                            [value, value, value, 0xff]
+                        } else {
+                            *color
+                        };
+                        assert_eq!(
+                            rgba.get_pixel(x as u32, 2).0,
+                            expected,
+                            "{:?} depth {} with {:?} bytes, {:?} bits and {}-bit units",
+                            format,
+                            depth,
+                            byte_order,
+                            bit_order,
+                            bitmap_unit
+                        );
+                        assert_eq!(
+                            luma.get_pixel(x as u32, 1).0[0],
+                            luminance(expected[0], expected[1], expected[2])
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // a big-endian 565 image, written by hand
+    let image = Image {
+        width: 2,
+        height: 1,
+        x_offset: 0,
+        format: ImageFormat::ZPixmap,
+        depth: 16,
+        bit_order: ImageOrder::MsbFirst,
+        byte_order: ImageOrder::MsbFirst,
+        bitmap_unit: 32,
+        bitmap_pad: 32,
+        bytes_per_line: 4,
+        bits_per_pixel: 16,
+        red_mask: 0xf800,
+        green_mask: 0x07e0,
+        blue_mask: 0x001f,
+        data: vec![0xf8, 0x00, 0x07, 0xff],
+    };
+    let rgb = image.to_rgb_image();
+    assert_eq!(rgb.get_pixel(0, 0).0, [0xff, 0, 0]);
+    assert_eq!(rgb.get_pixel(1, 0).0, [0, 0xff, 0xff]);
+}
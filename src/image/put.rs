@@ -14,7 +14,7 @@ use crate::{
         xproto::{Drawable, Gcontext, ImageFormat, ImageOrder, PutImageRequest},
         AsByteSequence,
     },
-    display::DisplayBase,
+    display::{max_request_payload, DisplayBase},
     util::roundup,
 };
 use alloc::{borrow::Cow, boxed::Box, vec, vec::Vec};
@@ -64,19 +64,21 @@ fn prepare_xy_image<Dpy: DisplayBase + ?Sized, Data: Deref<Target = [u8]>>(
 
     let src_data = &image.data()[(image.bytes_per_line() * src_y) + total_xoffset..];
 
-    // if we don't need to preform any modifications to the data, just use to_vec() and set it
+    let length = roundup(bytes_per_dest_plane * image.depth() as usize, 4);
+
+    // if we don't need to preform any modifications to the data, just copy the planes over
     if ptr::eq(
         swap_function as *const _ as *const (),
         &no_swap as *const _ as *const (),
     ) && image.bytes_per_line() == bytes_per_dest
-        && (total_xoffset == 0 && (image.depth() == 1 || image.height() == req.height as usize))
-        || (image.depth() == 1 && (src_y + req.height as usize) < image.height())
+        && ((total_xoffset == 0 && (image.depth() == 1 || image.height() == req.height as usize))
+            || (image.depth() == 1 && (src_y + req.height as usize) < image.height()))
+        && src_data.len() >= length
     {
-        req.data = Cow::Owned(src_data.to_vec());
+        req.data = Cow::Owned(src_data[..length].to_vec());
         return;
     }
 
-    let length = roundup(bytes_per_dest_plane * image.depth() as usize, 4);
     // allocate a vector for it
     let mut buffer: Vec<u8> = iter::repeat(0).take(length).collect();
 
@@ -148,6 +150,7 @@ fn prepare_z_image<Dpy: DisplayBase + ?Sized, Data: Deref<Target = [u8]>>(
     if (image.byte_order() == dpy.setup().image_byte_order || image.bits_per_pixel() == 8)
         && image.bytes_per_line() == bytes_per_dest
         && (src_x == 0 || (src_y + req.height as usize) < image.height())
+        && src_data.len() >= length
     {
         req.data = match src_data {
             Cow::Borrowed(src_data) => Cow::Owned(src_data[..length].to_vec()),
@@ -217,8 +220,53 @@ fn prepare_z_image<Dpy: DisplayBase + ?Sized, Data: Deref<Target = [u8]>>(
     req.data = Cow::Owned(buffer);
 }
 
-/// Generate a series of requests for a sub-part of an image.
-#[allow(clippy::too_many_lines)] // TODO: find good way to split
+/// Generate a single request for a band of an image that fits into one request.
+#[inline]
+fn put_band_req<Dpy: DisplayBase + ?Sized, Data: Deref<Target = [u8]>>(
+    dpy: &mut Dpy,
+    drawable: Drawable,
+    gc: Gcontext,
+    image: &Image<Data>,
+    src_x: usize,
+    src_y: usize,
+    dst_x: isize,
+    dst_y: isize,
+    width: usize,
+    height: usize,
+    dest_bits_per_pixel: usize,
+    dest_scanline_pad: usize,
+) -> PutImageRequest<'static> {
+    let mut req = PutImageRequest {
+        drawable,
+        gc,
+        dst_x: dst_x as _,
+        dst_y: dst_y as _,
+        width: width as _,
+        height: height as _,
+        depth: image.depth(),
+        format: image.format(),
+        ..Default::default()
+    };
+
+    if image.bits_per_pixel() == 1 || image.format() != ImageFormat::ZPixmap {
+        prepare_xy_image(dpy, &mut req, image, src_x, src_y);
+    } else {
+        prepare_z_image(
+            dpy,
+            &mut req,
+            image,
+            src_x,
+            src_y,
+            dest_bits_per_pixel,
+            dest_scanline_pad,
+        );
+    }
+
+    req
+}
+
+/// Generate a series of requests for a sub-part of an image. The image is split into bands of scanlines
+/// that fit into the display's maximum request length; rows that don't fit on their own are split further.
 #[inline]
 fn put_sub_image_req<Dpy: DisplayBase + ?Sized, Data: Deref<Target = [u8]>>(
     dpy: &mut Dpy,
@@ -239,133 +287,95 @@ fn put_sub_image_req<Dpy: DisplayBase + ?Sized, Data: Deref<Target = [u8]>>(
         return vec![];
     }
 
-    // How many bits are available to use.
-    let mut req: PutImageRequest = Default::default();
-
-    #[allow(unused_comparisons, clippy::absurd_extreme_comparisons)]
-    let available: usize = if dpy.setup().maximum_request_length > 65535 {
-        65536 << 2
+    let available = max_request_payload(dpy, PutImageRequest::default().size());
+    let is_xy = image.bits_per_pixel() == 1 || image.format() != ImageFormat::ZPixmap;
+    let (left_pad, bytes_per_row) = if is_xy {
+        let left_pad =
+            (image.x_offset() + src_x) & (dpy.setup().bitmap_format_scanline_unit as usize - 1);
+        (
+            left_pad,
+            (roundup(
+                width + left_pad,
+                dpy.setup().bitmap_format_scanline_pad as usize,
+            ) >> 3)
+                * image.depth() as usize,
+        )
     } else {
-        (dpy.setup().maximum_request_length as usize) << 2
-    } - req.size();
-
-    let (left_pad, bytes_per_row) =
-        if image.bits_per_pixel() == 1 || image.format() != ImageFormat::ZPixmap {
-            let left_pad =
-                (image.x_offset() + src_x) & (dpy.setup().bitmap_format_scanline_unit as usize - 1);
-            (
-                left_pad,
-                (roundup(
-                    width + left_pad,
-                    dpy.setup().bitmap_format_scanline_pad as usize,
-                ) >> 3)
-                    * image.depth() as usize,
-            )
-        } else {
-            (
-                0,
-                roundup(width * dest_bits_per_pixel, dest_scanline_pad) >> 3,
-            )
-        };
-
-    // If we can fit our image in the available bits, create and return the image request.
-    if bytes_per_row * height <= available {
-        req.drawable = drawable;
-        req.gc = gc;
-        req.dst_x = dst_x as _;
-        req.dst_y = dst_y as _;
-        req.width = width as _;
-        req.height = height as _;
-        req.depth = image.depth();
-        req.format = image.format();
-
-        if image.bits_per_pixel() == 1 || image.format() != ImageFormat::ZPixmap {
-            prepare_xy_image(dpy, &mut req, image, src_x, src_y);
-        } else {
-            prepare_z_image(
-                dpy,
-                &mut req,
-                image,
-                src_x,
-                src_y,
-                dest_bits_per_pixel,
-                dest_scanline_pad,
-            );
-        }
+        (
+            0,
+            roundup(width * dest_bits_per_pixel, dest_scanline_pad) >> 3,
+        )
+    };
 
-        vec![req]
-    } else if height > 1 {
-        // figure out how many rows we have
-        let mut sub_image_height = available / bytes_per_row;
-        if sub_image_height == 0 {
-            sub_image_height = 1;
-        }
+    // a single pixel can't be split up any further, so send it as it is
+    let rows_per_band = available / bytes_per_row.max(1);
+    if rows_per_band > 0 || width == 1 {
+        // send as many scanlines as will fit into every request
+        let rows_per_band = rows_per_band.max(1);
+        return (0..height)
+            .step_by(rows_per_band)
+            .map(|row| {
+                put_band_req(
+                    dpy,
+                    drawable,
+                    gc,
+                    image,
+                    src_x,
+                    src_y + row,
+                    dst_x,
+                    dst_y + row as isize,
+                    width,
+                    rows_per_band.min(height - row),
+                    dest_bits_per_pixel,
+                    dest_scanline_pad,
+                )
+            })
+            .collect();
+    }
 
-        // divide and conquer into two images based on rows
-        let mut reqs = put_sub_image_req(
-            dpy,
-            drawable,
-            gc,
-            image,
-            src_x,
-            src_y,
-            dst_x,
-            dst_y,
-            width,
-            sub_image_height,
-            dest_bits_per_pixel,
-            dest_scanline_pad,
-        );
-        reqs.extend(put_sub_image_req(
-            dpy,
-            drawable,
-            gc,
-            image,
-            src_x,
-            src_y + sub_image_height,
-            dst_x,
-            dst_y + sub_image_height as isize,
-            width,
-            height - sub_image_height,
-            dest_bits_per_pixel,
-            dest_scanline_pad,
-        ));
-        reqs
+    // not even a single row fits, so divide every row based on width
+    let sub_image_width = if is_xy {
+        let pad = dpy.setup().bitmap_format_scanline_pad as usize;
+        let bits_per_plane = (available / image.depth().max(1) as usize) << 3;
+        ((bits_per_plane / pad) * pad).saturating_sub(left_pad)
     } else {
-        // we've already divided and conquered down to 1 row. divide based on width
-        let sub_image_width =
-            (((available << 3) / dest_scanline_pad) * dest_scanline_pad) - left_pad;
+        (((available << 3) / dest_scanline_pad) * dest_scanline_pad) / dest_bits_per_pixel
+    }
+    .max(1)
+    .min(width - 1);
 
-        let mut reqs = put_sub_image_req(
+    let mut reqs = vec![];
+    for row in 0..height {
+        reqs.extend(put_sub_image_req(
             dpy,
             drawable,
             gc,
             image,
             src_x,
-            src_y,
+            src_y + row,
             dst_x,
-            dst_y,
+            dst_y + row as isize,
             sub_image_width,
             1,
             dest_bits_per_pixel,
             dest_scanline_pad,
-        );
+        ));
         reqs.extend(put_sub_image_req(
             dpy,
             drawable,
             gc,
             image,
             src_x + sub_image_width,
-            src_y,
+            src_y + row,
             dst_x + sub_image_width as isize,
-            dst_y,
+            dst_y + row as isize,
             width - sub_image_width,
             1,
             dest_bits_per_pixel,
             dest_scanline_pad,
         ));
-        reqs
     }
+    reqs
 }
 
 /// Begins the recursion for the image requests.
@@ -401,7 +411,7 @@ pub(crate) fn put_image_req<Dpy: DisplayBase + ?Sized, Data: Deref<Target = [u8]
     if src_x + width > image.width() {
         width = image.width().saturating_sub(src_x);
     }
-    if src_y + width > image.height() {
+    if src_y + height > image.height() {
         height = image.height().saturating_sub(src_y);
     }
 
@@ -486,3 +496,59 @@ pub(crate) fn put_image_req<Dpy: DisplayBase + ?Sized, Data: Deref<Target = [u8]
         dest_scanline_pad,
     )
 }
+
+#[test]
+fn put_image_req_splits_into_bands() {
+    use crate::display::{BasicDisplay, DisplayBase};
+
+    let mut display = BasicDisplay::dummy();
+    let image = Image::new(
+        &display,
+        Some(display.default_visual()),
+        24,
+        ImageFormat::ZPixmap,
+        0,
+        vec![0; 8 * 4 * 5].into_boxed_slice(),
+        8,
+        5,
+        32,
+        None,
+    )
+    .unwrap();
+    let root = display.default_root().into();
+    let bands = |display: &mut BasicDisplay<_>, width| {
+        put_image_req(
+            display,
+            root,
+            Gcontext::const_from_xid(1),
+            &image,
+            0,
+            0,
+            0,
+            0,
+            width,
+            5,
+        )
+        .into_iter()
+        .map(|req| (req.dst_x, req.dst_y, req.width, req.height, req.data.len()))
+        .collect::<Vec<_>>()
+    };
+
+    // everything fits into a single request
+    assert_eq!(bands(&mut display, 8), [(0, 0, 8, 5, 160)]);
+
+    // with room for two scanlines of 32 bytes, the image is split into bands
+    display.max_request_len = 24 + 64;
+    assert_eq!(
+        bands(&mut display, 8),
+        [(0, 0, 8, 2, 64), (0, 2, 8, 2, 64), (0, 4, 8, 1, 32)]
+    );
+
+    // BIG-REQUESTS takes up four more bytes of the header, and rows that don't fit are split up
+    display.bigreq_enabled = true;
+    display.max_request_len = 24 + 4 + 20;
+    let reqs = bands(&mut display, 8);
+    assert_eq!(reqs.len(), 10);
+    assert_eq!(&reqs[..2], [(0, 0, 5, 1, 20), (5, 0, 3, 1, 12)]);
+    assert_eq!(reqs[9], (5, 4, 3, 1, 12));
+}
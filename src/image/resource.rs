@@ -0,0 +1,112 @@
+// MIT/Apache2 License
+
+//! Images loaded from the classic X resource formats, XBM and XPM, and helpers for turning them into pixmaps
+//! and cursors.
+
+use super::Image;
+use crate::{
+    auto::xproto::{Cursor, Drawable, ImageFormat, ImageOrder, Pixmap},
+    display::{prelude::*, Display},
+    BreadError,
+};
+use alloc::boxed::Box;
+
+/// An image loaded from an X resource file, along with its mask and hotspot.
+#[derive(Clone, Debug)]
+pub struct ResourceImage {
+    /// The image itself. XBM files produce bitmaps, while XPM files produce images of the depth that they
+    /// were converted for.
+    pub image: Image<Box<[u8]>>,
+    /// A bitmap where set bits mark the opaque pixels of `image`. This is `None` if every pixel is opaque.
+    pub mask: Option<Image<Box<[u8]>>>,
+    /// The hotspot of the image, if the file specifies one. This is used as the hotspot of cursors.
+    pub hotspot: Option<(u16, u16)>,
+}
+
+impl ResourceImage {
+    /// Create a pixmap containing the image, as well as one containing the mask if there is a mask. The
+    /// pixmaps are created on the same screen as `target`.
+    #[inline]
+    pub fn create_pixmaps<Dpy: Display + ?Sized, Target: Clone + Into<Drawable>>(
+        &self,
+        dpy: &mut Dpy,
+        target: Target,
+    ) -> crate::Result<(Pixmap, Option<Pixmap>)> {
+        let pixmap = dpy.create_pixmap_from_image(target.clone(), &self.image)?;
+        let mask = match self.mask {
+            Some(ref mask) => match dpy.create_pixmap_from_image(target, mask) {
+                Ok(mask) => Some(mask),
+                Err(e) => {
+                    pixmap.free(dpy)?;
+                    return Err(e);
+                }
+            },
+            None => None,
+        };
+
+        Ok((pixmap, mask))
+    }
+
+    /// Create a cursor from this image, which has to be a bitmap. Set bits are drawn in the `foreground` color
+    /// and unset bits are drawn in the `background` color, unless the mask hides them. The colors are given as
+    /// 16-bit red, green and blue values.
+    #[inline]
+    pub fn create_cursor<Dpy: Display + ?Sized, Target: Clone + Into<Drawable>>(
+        &self,
+        dpy: &mut Dpy,
+        target: Target,
+        foreground: [u16; 3],
+        background: [u16; 3],
+    ) -> crate::Result<Cursor> {
+        if self.image.depth != 1 {
+            return Err(BreadError::StaticMsg(
+                "Cursors can only be created from bitmaps",
+            ));
+        }
+
+        let (source, mask) = self.create_pixmaps(dpy, target)?;
+        let (x, y) = self.hotspot.unwrap_or((0, 0));
+        let cursor = dpy.create_cursor(
+            source,
+            mask.unwrap_or_else(|| Pixmap::const_from_xid(0)),
+            foreground[0],
+            foreground[1],
+            foreground[2],
+            background[0],
+            background[1],
+            background[2],
+            x,
+            y,
+        );
+
+        // the server keeps its own copy of the cursor's pixmaps
+        source.free(dpy)?;
+        if let Some(mask) = mask {
+            mask.free(dpy)?;
+        }
+        cursor
+    }
+}
+
+/// Create a bitmap with the same layout as XBM data: every row starts on a byte boundary, and the first pixel
+/// is stored in the least significant bit of a byte.
+#[inline]
+pub(crate) fn xbm_bitmap(width: usize, height: usize, data: Box<[u8]>) -> Image<Box<[u8]>> {
+    Image {
+        width,
+        height,
+        x_offset: 0,
+        format: ImageFormat::XyPixmap,
+        depth: 1,
+        bit_order: ImageOrder::LsbFirst,
+        byte_order: ImageOrder::LsbFirst,
+        bitmap_unit: 8,
+        bitmap_pad: 8,
+        bytes_per_line: (width + 7) >> 3,
+        bits_per_pixel: 1,
+        red_mask: 0,
+        green_mask: 0,
+        blue_mask: 0,
+        data,
+    }
+}
@@ -0,0 +1,519 @@
+// MIT/Apache2 License
+
+//! Shared memory segments, as used by the MIT-SHM extension. Rather than mapping the segment into our address
+//! space, which would require `unsafe` code, we read from and write to the file descriptor that backs it.
+
+#![cfg(all(feature = "shm", feature = "std", unix))]
+
+use super::{bits_per_pixel, Image};
+use crate::{
+    auto::{
+        shm::{
+            CompletionEvent, CreateSegmentRequest, DetachRequest, GetImageRequest, PutImageRequest,
+            QueryVersionRequest, Seg,
+        },
+        xproto::{
+            Drawable, Gcontext, GetImageRequest as CoreGetImageRequest, ImageFormat,
+            QueryExtensionRequest, Visualid,
+        },
+        AsByteSequence,
+    },
+    display::{generate_xid, prelude::*, Display},
+    util::{convert_nix_error, roundup},
+    BreadError, Event, Fd,
+};
+use alloc::{boxed::Box, vec};
+use nix::{
+    sys::uio::{pread, pwrite},
+    unistd::close,
+};
+
+#[cfg(target_os = "linux")]
+use crate::auto::shm::AttachFdRequest;
+#[cfg(target_os = "linux")]
+use nix::{
+    sys::memfd::{memfd_create, MemFdCreateFlag},
+    unistd::ftruncate,
+};
+#[cfg(target_os = "linux")]
+use std::ffi::CStr;
+
+/// A shared memory segment that has been attached to the X server.
+#[derive(Debug)]
+pub(crate) struct ShmSegment {
+    pub(crate) seg: Seg,
+    pub(crate) fd: Fd,
+}
+
+impl ShmSegment {
+    /// Have the X server create a new segment of `size` bytes, and send us its file descriptor.
+    #[inline]
+    pub(crate) fn create<D: Display + ?Sized>(display: &mut D, size: usize) -> crate::Result<Self> {
+        let seg = Seg::const_from_xid(generate_xid(display)?);
+        let mut reply = display.exchange_request(CreateSegmentRequest {
+            shmseg: seg,
+            size: size as u32,
+            read_only: false,
+            ..Default::default()
+        })?;
+
+        if let Some(fd) = reply.shm_fd.pop() {
+            reply.shm_fd.into_iter().for_each(|fd| {
+                let _ = close(fd);
+            });
+            Ok(Self { seg, fd })
+        } else {
+            let _ = display.exchange_request(DetachRequest {
+                shmseg: seg,
+                ..Default::default()
+            });
+            Err(BreadError::StaticMsg(
+                "X server did not send a file descriptor for the segment",
+            ))
+        }
+    }
+
+    /// Create a new anonymous file of `size` bytes, and pass it to the X server so it can be attached as a
+    /// segment. This requires version 1.2 of the MIT-SHM extension.
+    #[cfg(target_os = "linux")]
+    #[inline]
+    pub(crate) fn attach_memfd<D: Display + ?Sized>(
+        display: &mut D,
+        size: usize,
+    ) -> crate::Result<Self> {
+        let name = CStr::from_bytes_with_nul(b"breadx-shm\0").unwrap();
+        let fd = memfd_create(name, MemFdCreateFlag::MFD_CLOEXEC).map_err(convert_nix_error)?;
+        if let Err(e) = ftruncate(fd, size as _) {
+            let _ = close(fd);
+            return Err(convert_nix_error(e).into());
+        }
+
+        let seg = match generate_xid(display) {
+            Ok(xid) => Seg::const_from_xid(xid),
+            Err(e) => {
+                let _ = close(fd);
+                return Err(e);
+            }
+        };
+
+        // AttachFd has no reply, so errors (e.g. a server that can't map the file) only show up in checked mode
+        let checked = display.checked();
+        display.set_checked(true);
+        let res = display.exchange_request(AttachFdRequest {
+            shmseg: seg,
+            read_only: false,
+            shm_fd: vec![fd],
+            ..Default::default()
+        });
+        display.set_checked(checked);
+
+        match res {
+            Ok(()) => Ok(Self { seg, fd }),
+            Err(e) => {
+                let _ = close(fd);
+                Err(e)
+            }
+        }
+    }
+
+    /// Read bytes from the segment, starting at `offset`.
+    #[inline]
+    pub(crate) fn read(&self, offset: usize, buffer: &mut [u8]) -> crate::Result {
+        let mut read = 0;
+        while read < buffer.len() {
+            match pread(self.fd, &mut buffer[read..], (offset + read) as _) {
+                Ok(0) => {
+                    return Err(BreadError::StaticMsg(
+                        "Shared memory segment is smaller than expected",
+                    ))
+                }
+                Ok(n) => read += n,
+                Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => {}
+                Err(e) => return Err(convert_nix_error(e).into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Write bytes to the segment, starting at `offset`.
+    #[inline]
+    pub(crate) fn write(&self, offset: usize, buffer: &[u8]) -> crate::Result {
+        let mut written = 0;
+        while written < buffer.len() {
+            match pwrite(self.fd, &buffer[written..], (offset + written) as _) {
+                Ok(0) => {
+                    return Err(BreadError::StaticMsg(
+                        "Unable to write to shared memory segment",
+                    ))
+                }
+                Ok(n) => written += n,
+                Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => {}
+                Err(e) => return Err(convert_nix_error(e).into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Detach the segment from the X server, and close our file descriptor.
+    #[inline]
+    pub(crate) fn destroy<D: Display + ?Sized>(self, display: &mut D) -> crate::Result {
+        let res = display.exchange_request(DetachRequest {
+            shmseg: self.seg,
+            ..Default::default()
+        });
+        close(self.fd).map_err(convert_nix_error)?;
+        res
+    }
+}
+
+/// An image whose contents are transferred to and from the X server through a shared memory segment, using the
+/// MIT-SHM extension.
+///
+/// The segment is either a `memfd` that is passed to the server, or a segment that the server creates and
+/// passes back to us. Either way, the image data lives in an ordinary buffer, which is copied into or out of
+/// the segment through its file descriptor. If the server does not support MIT-SHM, or cannot share memory
+/// with us because it is running on another machine, the image falls back to the `PutImage` and `GetImage`
+/// requests.
+///
+/// The segment is not freed automatically; call `destroy` once the image is no longer needed.
+#[derive(Debug)]
+pub struct ShmImage {
+    image: Image<Box<[u8]>>,
+    segment: Option<ShmSegment>,
+    completion_event: u8,
+    put_pending: bool,
+}
+
+impl ShmImage {
+    /// Create a new `ZPixmap` image in the server's native format for the given depth, and try to share it
+    /// with the X server. If `visual` is provided, the image carries its color masks.
+    #[inline]
+    pub fn new<D: Display + ?Sized>(
+        display: &mut D,
+        visual: Option<Visualid>,
+        depth: u8,
+        width: u16,
+        height: u16,
+    ) -> crate::Result<Self> {
+        let (width, height) = (usize::from(width), usize::from(height));
+        let scanline_pad = display.get_scanline_pad(depth);
+        let bytes_per_line = roundup(
+            usize::from(bits_per_pixel(display, depth)) * width,
+            scanline_pad,
+        ) >> 3;
+        let size = bytes_per_line * height;
+
+        let image = Image::new(
+            display,
+            visual.and_then(|visual| display.visual_id_to_visual(visual)),
+            depth,
+            ImageFormat::ZPixmap,
+            0,
+            vec![0; size].into_boxed_slice(),
+            width,
+            height,
+            scanline_pad as u32,
+            None,
+        )
+        .ok_or(BreadError::StaticMsg("Failed to create shared image"))?;
+
+        let (segment, completion_event) = match Self::share(display, size) {
+            Ok((segment, completion_event)) => (Some(segment), completion_event),
+            Err(e) => {
+                log::debug!("Unable to share image using MIT-SHM: {:?}", e);
+                (None, 0)
+            }
+        };
+
+        Ok(Self {
+            image,
+            segment,
+            completion_event,
+            put_pending: false,
+        })
+    }
+
+    /// Allocate a segment for an image of `size` bytes, returning it along with the completion event's opcode.
+    #[inline]
+    fn share<D: Display + ?Sized>(display: &mut D, size: usize) -> crate::Result<(ShmSegment, u8)> {
+        let extension = display.exchange_request(QueryExtensionRequest {
+            name: "MIT-SHM".into(),
+            ..Default::default()
+        })?;
+        if !extension.present {
+            return Err(BreadError::ExtensionNotPresent("MIT-SHM".into()));
+        }
+
+        let version = display.exchange_request(QueryVersionRequest::default())?;
+        if (version.major_version, version.minor_version) < (1, 2) {
+            return Err(BreadError::StaticMsg(
+                "MIT-SHM 1.2 is required to pass file descriptors",
+            ));
+        }
+
+        #[cfg(target_os = "linux")]
+        let segment = match ShmSegment::attach_memfd(display, size) {
+            Ok(segment) => segment,
+            Err(e) => {
+                log::debug!(
+                    "Unable to attach a memfd, having the server create a segment: {:?}",
+                    e
+                );
+                ShmSegment::create(display, size)?
+            }
+        };
+        #[cfg(not(target_os = "linux"))]
+        let segment = ShmSegment::create(display, size)?;
+
+        Ok((segment, extension.first_event))
+    }
+
+    /// Whether the image is backed by a shared memory segment. If not, `put` and `get` go through the socket.
+    #[inline]
+    #[must_use]
+    pub fn is_shared(&self) -> bool {
+        self.segment.is_some()
+    }
+
+    /// Whether a `put` has been sent that the server hasn't reported as complete yet.
+    #[inline]
+    #[must_use]
+    pub fn is_busy(&self) -> bool {
+        self.put_pending
+    }
+
+    /// Get the image.
+    #[inline]
+    #[must_use]
+    pub fn image(&self) -> Image<&[u8]> {
+        self.image.as_image()
+    }
+
+    /// Get the image, in order to draw on it.
+    #[inline]
+    pub fn image_mut(&mut self) -> Image<&mut [u8]> {
+        self.image.as_mut_image()
+    }
+
+    /// Write an area of the image to a drawable.
+    ///
+    /// When the image is shared, the server sends an MIT-SHM `Completion` event once it is done reading the
+    /// segment; pass events to `process_event` to keep track of this. If a previous `put` is still in progress,
+    /// this waits for the server to catch up before overwriting the segment.
+    #[inline]
+    pub fn put<D: Display + ?Sized, Target: Into<Drawable>>(
+        &mut self,
+        display: &mut D,
+        target: Target,
+        gc: Gcontext,
+        src_x: u16,
+        src_y: u16,
+        dest_x: i16,
+        dest_y: i16,
+        width: u16,
+        height: u16,
+    ) -> crate::Result {
+        let segment = match self.segment {
+            Some(ref segment) => segment,
+            None => {
+                return display.put_image(
+                    target,
+                    gc,
+                    &self.image,
+                    src_x as isize,
+                    src_y as isize,
+                    dest_x.into(),
+                    dest_y.into(),
+                    width.into(),
+                    height.into(),
+                )
+            }
+        };
+
+        // clip the area to the image
+        let (src_x, src_y) = (usize::from(src_x), usize::from(src_y));
+        let width = usize::from(width).min(self.image.width.saturating_sub(src_x));
+        let height = usize::from(height).min(self.image.height.saturating_sub(src_y));
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        if self.put_pending {
+            // once the server has processed a later request, it is done with the earlier PutImage
+            display.synchronize()?;
+            self.put_pending = false;
+        }
+
+        // only the rows that are being drawn need to be copied into the segment
+        let bpl = self.image.bytes_per_line;
+        let rows = &self.image.data[src_y * bpl..(src_y + height) * bpl];
+        segment.write(src_y * bpl, rows)?;
+
+        display.exchange_request(PutImageRequest {
+            drawable: target.into(),
+            gc,
+            total_width: self.image.width as u16,
+            total_height: self.image.height as u16,
+            src_x: src_x as u16,
+            src_y: src_y as u16,
+            src_width: width as u16,
+            src_height: height as u16,
+            dst_x: dest_x,
+            dst_y: dest_y,
+            depth: self.image.depth,
+            format: ImageFormat::ZPixmap as u8,
+            send_event: true,
+            shmseg: segment.seg,
+            offset: 0,
+            ..Default::default()
+        })?;
+        self.put_pending = true;
+        Ok(())
+    }
+
+    /// Fill the image with the contents of a drawable, starting at the given coordinates. The image's color
+    /// masks are updated to match the drawable's visual.
+    #[inline]
+    pub fn get<D: Display + ?Sized, Target: Into<Drawable>>(
+        &mut self,
+        display: &mut D,
+        target: Target,
+        x: i16,
+        y: i16,
+    ) -> crate::Result {
+        let (width, height) = (self.image.width as u16, self.image.height as u16);
+        let visual = if let Some(ref segment) = self.segment {
+            let reply = display.exchange_request(GetImageRequest {
+                drawable: target.into(),
+                x,
+                y,
+                width,
+                height,
+                plane_mask: u32::MAX,
+                format: ImageFormat::ZPixmap as u8,
+                shmseg: segment.seg,
+                offset: 0,
+                ..Default::default()
+            })?;
+            // the server has handled every earlier request by now, including any PutImage
+            self.put_pending = false;
+            segment.read(0, &mut self.image.data)?;
+            reply.visual
+        } else {
+            let reply = display.exchange_request(CoreGetImageRequest {
+                drawable: target.into(),
+                x,
+                y,
+                width,
+                height,
+                plane_mask: u32::MAX,
+                format: ImageFormat::ZPixmap,
+                ..Default::default()
+            })?;
+            let len = self.image.data.len().min(reply.data.len());
+            self.image.data[..len].copy_from_slice(&reply.data[..len]);
+            reply.visual
+        };
+
+        if let Some(visual) = display.visual_id_to_visual(visual) {
+            self.image.red_mask = visual.red_mask;
+            self.image.green_mask = visual.green_mask;
+            self.image.blue_mask = visual.blue_mask;
+        }
+        Ok(())
+    }
+
+    /// Check whether an event is the completion event for this image's last `put`. Returns `true` if it is,
+    /// in which case the image is no longer busy.
+    #[inline]
+    pub fn process_event(&mut self, event: &Event) -> bool {
+        let (segment, bytes) = match (&self.segment, event) {
+            (Some(segment), Event::NoneOfTheAbove { opcode, bytes })
+                if *opcode == self.completion_event =>
+            {
+                (segment, bytes)
+            }
+            _ => return false,
+        };
+
+        match CompletionEvent::from_bytes(bytes) {
+            Some((completion, _)) if completion.shmseg == segment.seg => {
+                self.put_pending = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Free the shared memory segment, returning the image.
+    #[inline]
+    pub fn destroy<D: Display + ?Sized>(self, display: &mut D) -> crate::Result<Image<Box<[u8]>>> {
+        if let Some(segment) = self.segment {
+            segment.destroy(display)?;
+        }
+        Ok(self.image)
+    }
+}
+
+#[test]
+fn shm_image_falls_back_and_tracks_completion() {
+    use crate::{
+        auto::xproto::{GetImageReply, QueryExtensionReply},
+        display::BasicDisplay,
+    };
+    use alloc::borrow::Cow;
+    use tinyvec::TinyVec;
+
+    // without MIT-SHM, the image goes through the socket
+    let mut display = BasicDisplay::dummy();
+    display.connection_mut().reply_padded(QueryExtensionReply {
+        reply_type: 1,
+        sequence: 2,
+        present: false,
+        ..Default::default()
+    });
+    let mut image = ShmImage::new(&mut display, None, 24, 2, 2).unwrap();
+    assert!(!image.is_shared());
+    assert_eq!(image.image().bytes_per_line, 8);
+
+    let visual = display.default_visual().visual_id;
+    display.connection_mut().reply_padded(GetImageReply {
+        reply_type: 1,
+        depth: 24,
+        sequence: 3,
+        length: 4,
+        visual,
+        data: Cow::Borrowed(&[1, 2, 3, 0, 4, 5, 6, 0, 7, 8, 9, 0, 10, 11, 12, 0]),
+    });
+    let root = display.default_root();
+    image.get(&mut display, root, 0, 0).unwrap();
+    assert_eq!(image.image().red_mask, display.default_visual().red_mask);
+    assert_eq!(image.image().pixel(1, 1), 0x000c_0b0a);
+    image.image_mut().set_pixel(0, 0, 0x0012_3456);
+    assert_eq!(image.image().pixel(0, 0), 0x0012_3456);
+
+    // completion events are only accepted for the image's own segment
+    let seg = Seg::const_from_xid(0x42);
+    image.segment = Some(ShmSegment { seg, fd: -1 });
+    image.completion_event = 70;
+    image.put_pending = true;
+    let completion = |shmseg| {
+        let mut bytes: TinyVec<[u8; 32]> = TinyVec::from([0; 32]);
+        CompletionEvent {
+            event_type: 70,
+            shmseg,
+            ..Default::default()
+        }
+        .as_bytes(&mut bytes);
+        Event::NoneOfTheAbove { opcode: 70, bytes }
+    };
+
+    assert!(!image.process_event(&completion(Seg::const_from_xid(0x43))));
+    assert!(image.is_busy());
+    assert!(image.process_event(&completion(seg)));
+    assert!(!image.is_busy());
+
+    // don't close the made-up file descriptor
+    image.segment = None;
+    image.destroy(&mut display).unwrap();
+}
@@ -0,0 +1,155 @@
+// MIT/Apache2 License
+
+//! Parser for the XBM format, which stores a bitmap as C source code.
+
+use super::resource::{xbm_bitmap, ResourceImage};
+use crate::BreadError;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+const INVALID_XBM: BreadError = BreadError::StaticMsg("Invalid XBM data");
+
+/// Parse a number in the C syntax used by XBM files, either decimal or hexadecimal.
+#[inline]
+fn parse_number(s: &str) -> Option<usize> {
+    let s = s.trim();
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+impl ResourceImage {
+    /// Parse an XBM file. Both the X11 format, which stores the bits in an array of `char`s, and the older
+    /// X10 format, which uses an array of `short`s, are supported.
+    ///
+    /// XBM files do not contain a mask, so `mask` is always `None`. Cursors usually come with a second XBM
+    /// file for the mask; its `image` can be moved into the `mask` field of the first.
+    #[inline]
+    pub fn from_xbm(text: &str) -> crate::Result<Self> {
+        let (mut width, mut height, mut x_hot, mut y_hot) = (None, None, None, None);
+
+        // the dimensions are stored in #defines that precede the array
+        let brace = text.find('{').ok_or(INVALID_XBM)?;
+        for line in text[..brace].lines() {
+            let mut words = line.split_whitespace();
+            if words.next() != Some("#define") {
+                continue;
+            }
+
+            let (name, value) = match (words.next(), words.next().and_then(parse_number)) {
+                (Some(name), Some(value)) => (name, value),
+                _ => return Err(INVALID_XBM),
+            };
+            if name.ends_with("_width") {
+                width = Some(value);
+            } else if name.ends_with("_height") {
+                height = Some(value);
+            } else if name.ends_with("_x_hot") {
+                x_hot = Some(value);
+            } else if name.ends_with("_y_hot") {
+                y_hot = Some(value);
+            }
+        }
+
+        let (width, height) = match (width, height) {
+            (Some(width), Some(height)) if width > 0 && height > 0 => (width, height),
+            _ => return Err(INVALID_XBM),
+        };
+        let hotspot = match (x_hot, y_hot) {
+            (Some(x), Some(y)) => Some((
+                x.try_into().map_err(|_| INVALID_XBM)?,
+                y.try_into().map_err(|_| INVALID_XBM)?,
+            )),
+            _ => None,
+        };
+
+        let end = brace + text[brace..].find('}').ok_or(INVALID_XBM)?;
+        let values = text[brace + 1..end]
+            .split(',')
+            .filter(|value| !value.trim().is_empty())
+            .map(|value| parse_number(value).ok_or(INVALID_XBM))
+            .collect::<crate::Result<Vec<usize>>>()?;
+
+        // the sizes come from the file, so they may be large enough to overflow
+        let bytes_per_line = width.checked_add(7).ok_or(INVALID_XBM)? >> 3;
+        let data: Vec<u8> = if text[..brace].contains("short") {
+            // every row of an X10 bitmap is padded to a whole number of shorts, with the low byte first
+            let shorts_per_line = width.checked_add(15).ok_or(INVALID_XBM)? >> 4;
+            let len = shorts_per_line.checked_mul(height).ok_or(INVALID_XBM)?;
+            if values.len() < len {
+                return Err(INVALID_XBM);
+            }
+
+            values
+                .chunks(shorts_per_line)
+                .take(height)
+                .flat_map(|row| {
+                    row.iter()
+                        .flat_map(|value| [*value as u8, (*value >> 8) as u8])
+                        .take(bytes_per_line)
+                })
+                .collect()
+        } else {
+            let len = bytes_per_line.checked_mul(height).ok_or(INVALID_XBM)?;
+            if values.len() < len {
+                return Err(INVALID_XBM);
+            }
+
+            values.iter().take(len).map(|value| *value as u8).collect()
+        };
+
+        Ok(Self {
+            image: xbm_bitmap(width, height, data.into_boxed_slice()),
+            mask: None,
+            hotspot,
+        })
+    }
+}
+
+#[test]
+fn xbm_parses_both_formats() {
+    const X11: &str = "#define arrow_width 10
+#define arrow_height 3
+#define arrow_x_hot 1
+#define arrow_y_hot 2
+static unsigned char arrow_bits[] = {
+   0x01, 0x02, 0xff, 0x03,
+   0x00, 0x00 };
+";
+    const X10: &str = "#define arrow_width 10
+#define arrow_height 3
+static short arrow_bits[] = {
+   0x0201, 0x03ff, 0x0000 };
+";
+
+    for text in &[X11, X10] {
+        let xbm = ResourceImage::from_xbm(text).unwrap();
+        assert_eq!((xbm.image.width, xbm.image.height), (10, 3));
+        assert!(xbm.mask.is_none());
+
+        let row = |y| (0..10).map(|x| xbm.image.pixel(x, y)).collect::<Vec<_>>();
+        assert_eq!(row(0), [1, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(row(1), [1; 10]);
+        assert_eq!(row(2), [0; 10]);
+    }
+
+    assert_eq!(ResourceImage::from_xbm(X11).unwrap().hotspot, Some((1, 2)));
+    assert_eq!(ResourceImage::from_xbm(X10).unwrap().hotspot, None);
+    assert!(
+        ResourceImage::from_xbm("#define a_width 8\nstatic char a_bits[] = { 0x00 };").is_err()
+    );
+
+    // sizes that overflow are rejected rather than wrapping around
+    let huge = alloc::format!(
+        "#define a_width {}\n#define a_height {}\nstatic char a_bits[] = {{ 0x00 }};",
+        usize::MAX,
+        usize::MAX
+    );
+    assert!(ResourceImage::from_xbm(&huge).is_err());
+    let wide = alloc::format!(
+        "#define a_width {}\n#define a_height 4\nstatic short a_bits[] = {{ 0x00 }};",
+        usize::MAX - 3
+    );
+    assert!(ResourceImage::from_xbm(&wide).is_err());
+}
@@ -0,0 +1,495 @@
+// MIT/Apache2 License
+
+//! Parser for the XPM format, which stores a colored image as an array of C strings.
+
+use super::{
+    pixel_format::luminance,
+    resource::{xbm_bitmap, ResourceImage},
+    rgb::lookup_color_name,
+    Image, PixelFormat,
+};
+use crate::{
+    auto::xproto::{AllocNamedColorRequest, Colormap, ImageFormat, Visualtype},
+    display::{prelude::*, Display, DisplayBase},
+    BreadError,
+};
+use alloc::{
+    borrow::Cow,
+    boxed::Box,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::convert::TryInto;
+
+const INVALID_XPM: BreadError = BreadError::StaticMsg("Invalid XPM data");
+
+/// The keys that can precede a color in the color table, in order of preference. The last one, `s`, gives a
+/// symbolic name rather than a color.
+const COLOR_KEYS: [&str; 5] = ["c", "g", "g4", "m", "s"];
+
+/// A color from the color table of an XPM file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum XpmColor {
+    /// Pixels of this color are transparent.
+    None,
+    /// An RGB color with 16 bits per channel, written as a `#` followed by hex digits.
+    Rgb([u16; 3]),
+    /// A color name, like `"light blue"`.
+    Named(String),
+}
+
+impl XpmColor {
+    #[inline]
+    fn parse(s: &str) -> crate::Result<Self> {
+        if s.eq_ignore_ascii_case("none") {
+            return Ok(Self::None);
+        }
+
+        let hex = match s.strip_prefix('#') {
+            Some(hex) => hex,
+            None => return Ok(Self::Named(s.to_string())),
+        };
+        let digits = hex.len() / 3;
+        if digits == 0 || digits > 4 || hex.len() % 3 != 0 {
+            return Err(INVALID_XPM);
+        }
+
+        // scale every channel to 16 bits, so that "#fff" and "#ffffff" are both white
+        let max = (1u32 << (digits * 4)) - 1;
+        let mut rgb = [0; 3];
+        for (i, channel) in rgb.iter_mut().enumerate() {
+            let value = hex
+                .get(i * digits..(i + 1) * digits)
+                .and_then(|value| u32::from_str_radix(value, 16).ok())
+                .ok_or(INVALID_XPM)?;
+            *channel = (value * 0xFFFF / max) as u16;
+        }
+        Ok(Self::Rgb(rgb))
+    }
+
+    /// Get the RGB value of this color, with 16 bits per channel. Color names are looked up in the built-in
+    /// color database. Returns `None` for transparent pixels, and an error for unknown color names.
+    #[inline]
+    pub fn rgb(&self) -> crate::Result<Option<[u16; 3]>> {
+        match self {
+            Self::None => Ok(None),
+            Self::Rgb(rgb) => Ok(Some(*rgb)),
+            Self::Named(name) => match lookup_color_name(name) {
+                Some([r, g, b]) => Ok(Some([
+                    u16::from(r) * 0x101,
+                    u16::from(g) * 0x101,
+                    u16::from(b) * 0x101,
+                ])),
+                None => Err(BreadError::Msg(format!("Unknown color name: {}", name))),
+            },
+        }
+    }
+}
+
+/// An image parsed from an XPM file, before its colors are turned into pixel values.
+#[derive(Clone, Debug)]
+pub struct Xpm {
+    /// The width of the image.
+    pub width: usize,
+    /// The height of the image.
+    pub height: usize,
+    /// The hotspot of the image, if the file specifies one.
+    pub hotspot: Option<(u16, u16)>,
+    /// The color table of the image. For every color, the first key out of `c` (color), `g` (grayscale),
+    /// `g4` (four-level grayscale) and `m` (monochrome) that the file defines is used.
+    pub colors: Vec<XpmColor>,
+    /// The pixels of the image, row by row, as indices into `colors`.
+    pub pixels: Box<[u32]>,
+}
+
+/// Collect the C string literals in an XPM file, skipping comments.
+#[inline]
+fn string_literals(text: &str) -> crate::Result<Vec<String>> {
+    let mut strings = vec![];
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = '\0';
+                loop {
+                    match chars.next() {
+                        Some('/') if last == '*' => break,
+                        Some(c) => last = c,
+                        None => return Err(INVALID_XPM),
+                    }
+                }
+            }
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => s.push(chars.next().ok_or(INVALID_XPM)?),
+                        Some(c) => s.push(c),
+                        None => return Err(INVALID_XPM),
+                    }
+                }
+                strings.push(s);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(strings)
+}
+
+impl Xpm {
+    /// Parse an XPM file in the XPM3 format, which is C source code that declares an array of strings.
+    /// Extensions are ignored.
+    #[inline]
+    pub fn parse(text: &str) -> crate::Result<Self> {
+        let strings = string_literals(text)?;
+        let mut strings = strings.iter();
+
+        let values = strings
+            .next()
+            .ok_or(INVALID_XPM)?
+            .split_whitespace()
+            .take_while(|value| *value != "XPMEXT")
+            .map(|value| value.parse::<usize>().map_err(|_| INVALID_XPM))
+            .collect::<crate::Result<Vec<usize>>>()?;
+        let (width, height, ncolors, cpp) = match values[..] {
+            [width, height, ncolors, cpp, ..] if cpp > 0 => (width, height, ncolors, cpp),
+            _ => return Err(INVALID_XPM),
+        };
+        let hotspot = match values[4..] {
+            [x, y] => Some((
+                x.try_into().map_err(|_| INVALID_XPM)?,
+                y.try_into().map_err(|_| INVALID_XPM)?,
+            )),
+            _ => None,
+        };
+
+        // read the color table
+        let mut keys = BTreeMap::new();
+        // the sizes come from the file, so don't reserve more than its strings could fill
+        let mut colors = Vec::with_capacity(ncolors.min(strings.len()));
+        for (index, line) in strings.by_ref().take(ncolors).enumerate() {
+            let key = line.get(..cpp).ok_or(INVALID_XPM)?;
+            let rest = &line[cpp..];
+
+            // every definition is a key followed by a color, which may contain spaces itself
+            let mut definitions: [Option<String>; 5] = Default::default();
+            let mut current: Option<usize> = None;
+            for word in rest.split_whitespace() {
+                let started = current.map_or(true, |current| {
+                    definitions[current]
+                        .as_ref()
+                        .map_or(false, |value| !value.is_empty())
+                });
+                match COLOR_KEYS.iter().position(|key| *key == word) {
+                    Some(slot) if started => {
+                        current = Some(slot);
+                        definitions[slot] = Some(String::new());
+                    }
+                    _ => {
+                        let value = definitions[current.ok_or(INVALID_XPM)?]
+                            .get_or_insert_with(String::new);
+                        if !value.is_empty() {
+                            value.push(' ');
+                        }
+                        value.push_str(word);
+                    }
+                }
+            }
+
+            // symbolic names are not colors, so they are skipped
+            let color = definitions[..4]
+                .iter()
+                .flatten()
+                .find(|value| !value.is_empty())
+                .ok_or(INVALID_XPM)?;
+            colors.push(XpmColor::parse(color)?);
+            keys.insert(key, index as u32);
+        }
+        if colors.len() != ncolors {
+            return Err(INVALID_XPM);
+        }
+
+        // read the pixels
+        let row_len = width.checked_mul(cpp).ok_or(INVALID_XPM)?;
+        let len = width.checked_mul(height).ok_or(INVALID_XPM)?;
+        let available = strings.as_slice().iter().map(String::len).sum::<usize>() / cpp;
+        let mut pixels = Vec::with_capacity(len.min(available));
+        for _ in 0..height {
+            let row = strings.next().ok_or(INVALID_XPM)?;
+            if row.len() < row_len {
+                return Err(INVALID_XPM);
+            }
+            for x in 0..width {
+                let key = row.get(x * cpp..(x + 1) * cpp).ok_or(INVALID_XPM)?;
+                pixels.push(*keys.get(key).ok_or(INVALID_XPM)?);
+            }
+        }
+
+        Ok(Self {
+            width,
+            height,
+            hotspot,
+            colors,
+            pixels: pixels.into_boxed_slice(),
+        })
+    }
+
+    /// Convert this XPM into an image of the given visual and depth. Colors are converted to pixel values
+    /// using a `PixelFormat`, and color names are looked up in the built-in color database, so no requests
+    /// are sent to the server. This works best for `TrueColor` visuals; for colormapped visuals, use
+    /// `to_image_with_colormap`.
+    #[inline]
+    pub fn to_image<Dpy: DisplayBase + ?Sized>(
+        &self,
+        dpy: &Dpy,
+        visual: Option<&Visualtype>,
+        depth: u8,
+    ) -> crate::Result<ResourceImage> {
+        let format = PixelFormat::new(visual, depth);
+        let pixels = self
+            .colors
+            .iter()
+            .map(|color| {
+                Ok(color.rgb()?.map(|[r, g, b]| {
+                    format.pixel([(r >> 8) as u8, (g >> 8) as u8, (b >> 8) as u8, 0xFF])
+                }))
+            })
+            .collect::<crate::Result<Vec<Option<u32>>>>()?;
+
+        self.build(dpy, visual, depth, &pixels)
+    }
+
+    /// Convert this XPM into an image of the given visual and depth, allocating its colors in `colormap`.
+    /// Color names are resolved by the server, using `AllocNamedColor`.
+    #[inline]
+    pub fn to_image_with_colormap<Dpy: Display + ?Sized>(
+        &self,
+        dpy: &mut Dpy,
+        colormap: Colormap,
+        visual: Option<&Visualtype>,
+        depth: u8,
+    ) -> crate::Result<ResourceImage> {
+        enum Allocation<Named, Rgb> {
+            Transparent,
+            Named(Named),
+            Rgb(Rgb),
+        }
+
+        // send every allocation before waiting for any of the replies
+        let mut allocations = Vec::with_capacity(self.colors.len());
+        for color in &self.colors {
+            allocations.push(match color {
+                XpmColor::None => Allocation::Transparent,
+                XpmColor::Named(name) => {
+                    Allocation::Named(dpy.send_request(AllocNamedColorRequest {
+                        cmap: colormap,
+                        name: Cow::Owned(name.clone()),
+                        ..Default::default()
+                    })?)
+                }
+                XpmColor::Rgb([r, g, b]) => Allocation::Rgb(colormap.alloc_color(dpy, *r, *g, *b)?),
+            });
+        }
+
+        let pixels = allocations
+            .into_iter()
+            .map(|allocation| match allocation {
+                Allocation::Transparent => Ok(None),
+                Allocation::Named(tok) => dpy.resolve_request(tok).map(|reply| Some(reply.pixel)),
+                Allocation::Rgb(tok) => dpy.resolve_request(tok).map(|reply| Some(reply.pixel)),
+            })
+            .collect::<Vec<crate::Result<Option<u32>>>>()
+            .into_iter()
+            .collect::<crate::Result<Vec<Option<u32>>>>()?;
+
+        self.build(dpy, visual, depth, &pixels)
+    }
+
+    /// Convert this XPM into a bitmap, which can be used to create a cursor. Pixels with dark colors are set,
+    /// and pixels with light colors are unset.
+    #[inline]
+    pub fn to_bitmap(&self) -> crate::Result<ResourceImage> {
+        let set = self
+            .colors
+            .iter()
+            .map(|color| {
+                Ok(color.rgb()?.map(|[r, g, b]| {
+                    luminance((r >> 8) as u8, (g >> 8) as u8, (b >> 8) as u8) < 0x80
+                }))
+            })
+            .collect::<crate::Result<Vec<Option<bool>>>>()?;
+
+        let bytes_per_line = (self.width + 7) >> 3;
+        let mut data = vec![0; bytes_per_line * self.height].into_boxed_slice();
+        for (i, pixel) in self.pixels.iter().enumerate() {
+            if let Some(true) = set[*pixel as usize] {
+                let (x, y) = (i % self.width, i / self.width);
+                data[y * bytes_per_line + (x >> 3)] |= 1 << (x & 7);
+            }
+        }
+
+        Ok(ResourceImage {
+            image: xbm_bitmap(self.width, self.height, data),
+            mask: self.mask(&set),
+            hotspot: self.hotspot,
+        })
+    }
+
+    /// Create an image out of the pixel values for every color in the color table. Transparent colors are
+    /// represented by `None`.
+    #[inline]
+    fn build<Dpy: DisplayBase + ?Sized>(
+        &self,
+        dpy: &Dpy,
+        visual: Option<&Visualtype>,
+        depth: u8,
+        pixels: &[Option<u32>],
+    ) -> crate::Result<ResourceImage> {
+        let mut image = Image::new(
+            dpy,
+            visual,
+            depth,
+            ImageFormat::ZPixmap,
+            0,
+            Box::default(),
+            self.width,
+            self.height,
+            dpy.get_scanline_pad(depth) as u32,
+            None,
+        )
+        .ok_or(BreadError::StaticMsg("Failed to create base image"))?;
+        image.data = vec![0; image.bytes_per_line * self.height].into_boxed_slice();
+
+        if self.width > 0 {
+            let mut row = vec![0; self.width];
+            for (y, indices) in self.pixels.chunks(self.width).enumerate() {
+                for (pixel, index) in row.iter_mut().zip(indices) {
+                    *pixel = pixels[*index as usize].unwrap_or(0);
+                }
+                image.write_row(0, y, &row);
+            }
+        }
+
+        Ok(ResourceImage {
+            image,
+            mask: self.mask(pixels),
+            hotspot: self.hotspot,
+        })
+    }
+
+    /// Create a mask where the opaque pixels are set, if any pixel is transparent.
+    #[inline]
+    fn mask<T>(&self, colors: &[Option<T>]) -> Option<Image<Box<[u8]>>> {
+        if !self
+            .pixels
+            .iter()
+            .any(|pixel| colors[*pixel as usize].is_none())
+        {
+            return None;
+        }
+
+        let bytes_per_line = (self.width + 7) >> 3;
+        let mut data = vec![0; bytes_per_line * self.height].into_boxed_slice();
+        for (i, pixel) in self.pixels.iter().enumerate() {
+            if colors[*pixel as usize].is_some() {
+                let (x, y) = (i % self.width, i / self.width);
+                data[y * bytes_per_line + (x >> 3)] |= 1 << (x & 7);
+            }
+        }
+
+        Some(xbm_bitmap(self.width, self.height, data))
+    }
+}
+
+impl ResourceImage {
+    /// Parse an XPM file and convert it into an image of the given visual and depth. See `Xpm::parse` and
+    /// `Xpm::to_image` for more information.
+    #[inline]
+    pub fn from_xpm<Dpy: DisplayBase + ?Sized>(
+        dpy: &Dpy,
+        text: &str,
+        visual: Option<&Visualtype>,
+        depth: u8,
+    ) -> crate::Result<Self> {
+        Xpm::parse(text)?.to_image(dpy, visual, depth)
+    }
+}
+
+#[test]
+fn xpm_parses_colors_and_masks() {
+    use crate::{auto::xproto::VisualClass, display::BasicDisplay};
+
+    const XPM: &str = r#"/* XPM */
+static char * test_xpm[] = {
+/* width height ncolors cpp x_hot y_hot */
+"3 2 4 2 1 0",
+"  c None",
+"r. c #ff0000 s red",
+"lb c light blue",
+"w. m black c #fff",
+"r.lbw.",
+"  r.  "};
+"#;
+
+    let xpm = Xpm::parse(XPM).unwrap();
+    assert_eq!((xpm.width, xpm.height, xpm.hotspot), (3, 2, Some((1, 0))));
+    assert_eq!(
+        xpm.colors,
+        [
+            XpmColor::None,
+            XpmColor::Rgb([0xFFFF, 0, 0]),
+            XpmColor::Named("light blue".into()),
+            XpmColor::Rgb([0xFFFF; 3]),
+        ]
+    );
+    assert_eq!(&*xpm.pixels, &[1, 2, 3, 0, 1, 0]);
+
+    let display = BasicDisplay::dummy();
+    let visual = Visualtype {
+        class: VisualClass::TrueColor,
+        bits_per_rgb_value: 8,
+        red_mask: 0xFF_0000,
+        green_mask: 0xFF00,
+        blue_mask: 0xFF,
+        ..Default::default()
+    };
+    let image = xpm.to_image(&display, Some(&visual), 24).unwrap();
+    let pixels = |image: &Image<Box<[u8]>>| {
+        (0..6)
+            .map(|i| image.pixel(i % 3, i / 3))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        pixels(&image.image),
+        [0xFF_0000, 0xAD_D8E6, 0xFF_FFFF, 0, 0xFF_0000, 0]
+    );
+    assert_eq!(pixels(image.mask.as_ref().unwrap()), [1, 1, 1, 0, 1, 0]);
+
+    let bitmap = xpm.to_bitmap().unwrap();
+    assert_eq!(pixels(&bitmap.image), [1, 0, 0, 0, 1, 0]);
+    assert_eq!(bitmap.hotspot, Some((1, 0)));
+
+    assert!(Xpm::parse("\"1 1 1 1\", \"a c nosuchcolor\", \"a\"")
+        .unwrap()
+        .to_bitmap()
+        .is_err());
+
+    // headers with sizes that the rest of the file can't back up are rejected without reserving memory
+    let huge = usize::MAX;
+    for header in &[
+        alloc::format!("1 1 {} 1", huge),
+        alloc::format!("{} {} 1 1", huge, huge),
+        alloc::format!("{} 1 1 2", huge),
+        alloc::format!("{} 2 1 1", 1_u64 << 40),
+    ] {
+        let text = alloc::format!("\"{}\", \"a c #000\", \"aa\"", header);
+        assert!(Xpm::parse(&text).is_err());
+    }
+}
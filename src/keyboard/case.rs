@@ -0,0 +1,88 @@
+// MIT/Apache2 License
+
+use crate::auto::xproto::Keysym;
+
+/// Get the lowercase and uppercase forms of a keysym, in that order. If the keysym doesn't have a case, both
+/// forms are the keysym itself.
+///
+/// This covers the same keysyms as Xlib's `XConvertCase`: Latin-1 through Latin-4, Latin-9, Cyrillic, Greek and
+/// Unicode keysyms.
+// keysyms are grouped by script, even if that means repeating arms
+#[allow(clippy::match_same_arms)]
+#[inline]
+#[must_use]
+pub fn convert_case(keysym: Keysym) -> (Keysym, Keysym) {
+    let mut lower = keysym;
+    let mut upper = keysym;
+
+    match keysym {
+        // Latin-1
+        0x41..=0x5a | 0xc0..=0xd6 | 0xd8..=0xde => lower += 0x20,
+        0x61..=0x7a | 0xe0..=0xf6 | 0xf8..=0xfe => upper -= 0x20,
+        0xff => upper = 0x13be,
+        // Latin-2
+        0x1a1 => lower = 0x1b1,
+        0x1b1 => upper = 0x1a1,
+        0x1a3..=0x1a6 | 0x1a9..=0x1ac | 0x1ae..=0x1af => lower += 0x10,
+        0x1b3..=0x1b6 | 0x1b9..=0x1bc | 0x1be..=0x1bf => upper -= 0x10,
+        0x1c0..=0x1de => lower += 0x20,
+        0x1e0..=0x1fe => upper -= 0x20,
+        // Latin-3
+        0x2a1..=0x2a6 | 0x2ab..=0x2ac => lower += 0x10,
+        0x2b1..=0x2b6 | 0x2bb..=0x2bc => upper -= 0x10,
+        0x2c5..=0x2de => lower += 0x20,
+        0x2e5..=0x2fe => upper -= 0x20,
+        // Latin-4
+        0x3a3..=0x3ac => lower += 0x10,
+        0x3b3..=0x3bc => upper -= 0x10,
+        0x3bd => lower = 0x3bf,
+        0x3bf => upper = 0x3bd,
+        0x3c0..=0x3de => lower += 0x20,
+        0x3e0..=0x3fe => upper -= 0x20,
+        // Cyrillic
+        0x6b1..=0x6bf => lower -= 0x10,
+        0x6a1..=0x6af => upper += 0x10,
+        0x6e0..=0x6ff => lower -= 0x20,
+        0x6c0..=0x6df => upper += 0x20,
+        // Greek
+        0x7a1..=0x7ab => lower += 0x10,
+        0x7b1..=0x7bb if keysym != 0x7b6 && keysym != 0x7ba => upper -= 0x10,
+        0x7c1..=0x7d9 => lower += 0x20,
+        0x7e1..=0x7f9 if keysym != 0x7f3 => upper -= 0x20,
+        // Latin-9
+        0x13bc => lower = 0x13bd,
+        0x13bd => upper = 0x13bc,
+        0x13be => lower = 0xff,
+        // Unicode
+        0x0100_0000..=0x01ff_ffff => {
+            if let Some(c) = core::char::from_u32(keysym & 0x00ff_ffff) {
+                let mut lowers = c.to_lowercase();
+                if let (Some(l), None) = (lowers.next(), lowers.next()) {
+                    lower = 0x0100_0000 | u32::from(l);
+                }
+                let mut uppers = c.to_uppercase();
+                if let (Some(u), None) = (uppers.next(), uppers.next()) {
+                    upper = 0x0100_0000 | u32::from(u);
+                }
+            }
+        }
+        _ => {}
+    }
+
+    (lower, upper)
+}
+
+#[test]
+fn convert_case_handles_legacy_and_unicode_keysyms() {
+    // a, A
+    assert_eq!(convert_case(0x61), (0x61, 0x41));
+    assert_eq!(convert_case(0x41), (0x61, 0x41));
+    // digits don't have a case
+    assert_eq!(convert_case(0x31), (0x31, 0x31));
+    // Cyrillic_ef, Cyrillic_EF
+    assert_eq!(convert_case(0x6c6), (0x6c6, 0x6e6));
+    // Greek_finalsmallsigma has no uppercase form
+    assert_eq!(convert_case(0x7f3), (0x7f3, 0x7f3));
+    // U+0142 LATIN SMALL LETTER L WITH STROKE
+    assert_eq!(convert_case(0x0100_0142), (0x0100_0142, 0x0100_0141));
+}
@@ -0,0 +1,644 @@
+// MIT/Apache2 License
+
+use super::{keysym_from_name, keysym_to_char};
+use crate::auto::xproto::Keysym;
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+#[cfg(feature = "std")]
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// The directory that X11 locale data, including the system Compose files, is usually installed to.
+pub const DEFAULT_LOCALE_DIR: &str = "/usr/share/X11/locale";
+
+// how deeply include directives may be nested, so that include loops don't recurse forever
+#[cfg(feature = "std")]
+const MAX_INCLUDE_DEPTH: usize = 5;
+
+/// A table of compose sequences, such as those found in X11 Compose files.
+///
+/// Compose sequences map a sequence of keysyms, such as `dead_acute e` or `Multi_key a e`, onto a string. The
+/// table itself is immutable once loaded; use a [`ComposeState`] to feed keysyms through it.
+#[derive(Debug, Clone)]
+pub struct ComposeTable {
+    // a trie of sequences, where the first node is the root
+    nodes: Vec<ComposeNode>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ComposeNode {
+    // the keysyms that continue the sequence, sorted by keysym
+    children: Vec<(Keysym, usize)>,
+    // the text produced by the sequence ending at this node, if this node ends a sequence
+    result: Option<String>,
+}
+
+impl Default for ComposeTable {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            nodes: alloc::vec![ComposeNode::default()],
+        }
+    }
+}
+
+impl ComposeTable {
+    /// Create a new, empty `ComposeTable`.
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tell whether or not this table contains any sequences.
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.nodes[0].children.is_empty()
+    }
+
+    /// Add a sequence to this table.
+    ///
+    /// As with Xlib, later sequences take precedence over earlier ones. If the new sequence is a prefix of
+    /// existing sequences, those sequences are removed; if an existing sequence is a prefix of the new one, the
+    /// existing sequence is removed.
+    #[inline]
+    pub fn insert(&mut self, sequence: &[Keysym], result: String) {
+        if sequence.is_empty() {
+            return;
+        }
+
+        let mut node = 0;
+        for &keysym in sequence {
+            if node != 0 {
+                self.nodes[node].result = None;
+            }
+
+            node = match self.nodes[node]
+                .children
+                .binary_search_by_key(&keysym, |&(k, _)| k)
+            {
+                Ok(i) => self.nodes[node].children[i].1,
+                Err(i) => {
+                    let child = self.nodes.len();
+                    self.nodes.push(ComposeNode::default());
+                    self.nodes[node].children.insert(i, (keysym, child));
+                    child
+                }
+            };
+        }
+
+        // note: the nodes of removed sequences are left in place, since they are no longer reachable
+        let node = &mut self.nodes[node];
+        node.children.clear();
+        node.result = Some(result);
+    }
+
+    /// Add the sequences in the given text, in the X11 Compose file format, to this table.
+    ///
+    /// `include` directives are ignored, since they require access to the file system; see
+    /// [`ComposeTable::parse_with_includes`]. Lines that can't be parsed, or that depend on modifier state,
+    /// are skipped.
+    #[inline]
+    pub fn parse(&mut self, text: &str) {
+        self.parse_impl(text, &mut |_, path| {
+            log::warn!("Ignoring include of \"{}\" in Compose file", path);
+        });
+    }
+
+    #[inline]
+    fn parse_impl(&mut self, text: &str, include: &mut dyn FnMut(&mut Self, &str)) {
+        for line in text.lines() {
+            match parse_line(line) {
+                Some(Line::Empty) => {}
+                Some(Line::Include(path)) => include(self, &path),
+                Some(Line::Sequence(sequence, result)) => self.insert(&sequence, result),
+                None => log::warn!("Unable to parse line in Compose file: {}", line),
+            }
+        }
+    }
+
+    /// Find the node reached by following `keysym` from `node`.
+    #[inline]
+    fn child(&self, node: usize, keysym: Keysym) -> Option<&(Keysym, usize)> {
+        let children = &self.nodes[node].children;
+        children
+            .binary_search_by_key(&keysym, |&(k, _)| k)
+            .ok()
+            .map(|i| &children[i])
+    }
+}
+
+#[cfg(feature = "std")]
+impl ComposeTable {
+    /// Add the sequences in the given text, in the X11 Compose file format, to this table, following `include`
+    /// directives.
+    ///
+    /// Included paths may use `%L` for the Compose file of the configured locale, `%S` for the configured locale
+    /// directory and `%H` for the user's home directory. Includes that can't be read are skipped.
+    #[inline]
+    pub fn parse_with_includes(&mut self, text: &str, config: &ComposeConfig) {
+        self.parse_with_depth(text, config, 0);
+    }
+
+    #[inline]
+    fn parse_with_depth(&mut self, text: &str, config: &ComposeConfig, depth: usize) {
+        self.parse_impl(text, &mut |table, path| {
+            if depth >= MAX_INCLUDE_DEPTH {
+                log::warn!(
+                    "Compose file includes are nested too deeply, skipping \"{}\"",
+                    path
+                );
+                return;
+            }
+
+            let expanded = if let Some(expanded) = config.expand_path(path) {
+                expanded
+            } else {
+                log::warn!("Unable to resolve include of \"{}\" in Compose file", path);
+                return;
+            };
+
+            match fs::read_to_string(&expanded) {
+                Ok(text) => table.parse_with_depth(&text, config, depth + 1),
+                Err(e) => log::warn!(
+                    "Unable to include Compose file {}: {}",
+                    expanded.display(),
+                    e
+                ),
+            }
+        });
+    }
+
+    /// Load the Compose file at the given path.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file could not be read. Errors reading included files are not reported.
+    #[inline]
+    pub fn from_file<P: AsRef<Path>>(path: P, config: &ComposeConfig) -> crate::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut table = Self::new();
+        table.parse_with_includes(&text, config);
+        Ok(table)
+    }
+
+    /// Load the system Compose file for the configured locale.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the locale doesn't have a Compose file, or if it could not be read.
+    #[inline]
+    pub fn from_locale(config: &ComposeConfig) -> crate::Result<Self> {
+        let path = config.locale_compose_file().ok_or_else(|| {
+            crate::BreadError::Msg(alloc::format!(
+                "No Compose file found for locale {}",
+                &config.locale
+            ))
+        })?;
+        Self::from_file(path, config)
+    }
+
+    /// Load the Compose file that Xlib would use, given the current environment.
+    ///
+    /// This is the file named by the `XCOMPOSEFILE` environment variable if it is set, otherwise
+    /// `~/.XCompose` if it exists, otherwise the system Compose file for the current locale.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no Compose file could be found or read.
+    #[inline]
+    pub fn from_env() -> crate::Result<Self> {
+        let config = ComposeConfig::from_env();
+
+        if let Some(path) = env::var_os("XCOMPOSEFILE") {
+            return Self::from_file(path, &config);
+        }
+
+        if let Some(home) = env::var_os("HOME") {
+            let path = Path::new(&home).join(".XCompose");
+            if path.is_file() {
+                return Self::from_file(path, &config);
+            }
+        }
+
+        Self::from_locale(&config)
+    }
+}
+
+/// Where to look for system Compose files, and which locale to look for them for.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComposeConfig {
+    /// The directory containing X11 locale data, including the `compose.dir` and `locale.alias` files.
+    pub locale_dir: PathBuf,
+    /// The name of the locale, e.g. `en_US.UTF-8`.
+    pub locale: String,
+}
+
+#[cfg(feature = "std")]
+impl Default for ComposeConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            locale_dir: PathBuf::from(DEFAULT_LOCALE_DIR),
+            locale: String::from("C"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ComposeConfig {
+    /// Create a new `ComposeConfig` using the default locale directory and the locale set in the
+    /// environment, through `LC_ALL`, `LC_CTYPE` or `LANG`.
+    #[inline]
+    #[must_use]
+    pub fn from_env() -> Self {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|locale| !locale.is_empty());
+
+        match locale {
+            Some(locale) => Self {
+                locale,
+                ..Default::default()
+            },
+            None => Self::default(),
+        }
+    }
+
+    /// Find the system Compose file for the configured locale, by looking it up in the `compose.dir` file of
+    /// the locale directory. Locale aliases from `locale.alias` are resolved if needed.
+    #[inline]
+    #[must_use]
+    pub fn locale_compose_file(&self) -> Option<PathBuf> {
+        let lookup = |locale: &str| {
+            lookup_locale_file(&self.locale_dir.join("compose.dir"), locale, true)
+                .map(|path| self.locale_dir.join(path))
+        };
+
+        lookup(&self.locale).or_else(|| {
+            let alias =
+                lookup_locale_file(&self.locale_dir.join("locale.alias"), &self.locale, false)?;
+            lookup(&alias)
+        })
+    }
+
+    /// Expand the `%L`, `%S` and `%H` substitutions in an included path.
+    #[inline]
+    fn expand_path(&self, path: &str) -> Option<PathBuf> {
+        let mut expanded = String::with_capacity(path.len());
+        let mut chars = path.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                expanded.push(c);
+                continue;
+            }
+
+            match chars.next()? {
+                '%' => expanded.push('%'),
+                'L' => expanded.push_str(self.locale_compose_file()?.to_str()?),
+                'S' => expanded.push_str(self.locale_dir.to_str()?),
+                'H' => expanded.push_str(&env::var("HOME").ok()?),
+                _ => return None,
+            }
+        }
+
+        Some(PathBuf::from(expanded))
+    }
+}
+
+/// Look up a key in one of the two-column files in the locale directory. `compose.dir` maps file names
+/// onto locales, while `locale.alias` maps aliases onto locales.
+#[cfg(feature = "std")]
+#[inline]
+fn lookup_locale_file(file: &Path, key: &str, reversed: bool) -> Option<String> {
+    let text = fs::read_to_string(file).ok()?;
+    text.lines().find_map(|line| {
+        let mut columns = line.split_whitespace();
+        let first = columns.next()?;
+        let second = columns.next()?;
+        if first.starts_with('#') {
+            return None;
+        }
+
+        let first = first.trim_end_matches(':');
+        let (value, name) = if reversed {
+            (first, second)
+        } else {
+            (second, first)
+        };
+
+        if name.eq_ignore_ascii_case(key) {
+            Some(value.into())
+        } else {
+            None
+        }
+    })
+}
+
+/// A line in a Compose file.
+#[derive(Debug)]
+enum Line {
+    Empty,
+    Include(String),
+    Sequence(Vec<Keysym>, String),
+}
+
+/// Parse a single line of a Compose file.
+#[inline]
+fn parse_line(line: &str) -> Option<Line> {
+    let mut rest = line.trim_start();
+
+    if rest.is_empty() || rest.starts_with('#') {
+        return Some(Line::Empty);
+    }
+
+    if let Some(include) = rest.strip_prefix("include") {
+        let (path, rest) = parse_string(include.trim_start())?;
+        return if is_comment(rest) {
+            Some(Line::Include(path))
+        } else {
+            None
+        };
+    }
+
+    // sequences that depend on modifier state aren't supported
+    let mut sequence = Vec::new();
+    while let Some(keysym) = rest.strip_prefix('<') {
+        let end = keysym.find('>')?;
+        sequence.push(keysym_from_name(&keysym[..end])?);
+        rest = keysym[end + 1..].trim_start();
+    }
+
+    let rest = rest.strip_prefix(':')?.trim_start();
+    let (string, rest) = if rest.starts_with('"') {
+        let (string, rest) = parse_string(rest)?;
+        (Some(string), rest.trim_start())
+    } else {
+        (None, rest)
+    };
+
+    // the keysym after the string is optional, but the result needs one of the two
+    let end = rest
+        .find(|c: char| c.is_whitespace() || c == '#')
+        .unwrap_or(rest.len());
+    let (keysym, rest) = rest.split_at(end);
+    if !is_comment(rest) {
+        return None;
+    }
+
+    let result = match (string, keysym) {
+        (Some(string), _) => string,
+        (None, "") => return None,
+        (None, keysym) => keysym_to_char(keysym_from_name(keysym)?)?.into(),
+    };
+
+    if sequence.is_empty() {
+        None
+    } else {
+        Some(Line::Sequence(sequence, result))
+    }
+}
+
+/// Tell whether the rest of a line is empty or a comment.
+#[inline]
+fn is_comment(rest: &str) -> bool {
+    let rest = rest.trim_start();
+    rest.is_empty() || rest.starts_with('#')
+}
+
+/// Parse a quoted string, with escapes, at the start of `text`. Returns the string and the rest of the text.
+#[inline]
+fn parse_string(text: &str) -> Option<(String, &str)> {
+    let text = text.strip_prefix('"')?;
+
+    // escapes produce bytes, which may be combined into UTF-8 sequences
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.char_indices();
+
+    loop {
+        let (i, c) = chars.next()?;
+        match c {
+            '"' => {
+                let string = String::from_utf8(bytes).ok()?;
+                return Some((string, &text[i + 1..]));
+            }
+            '\\' => {
+                let (i, c) = chars.next()?;
+                let (radix, start, max_len) = match c {
+                    'x' | 'X' => (16, i + 1, 2),
+                    '0'..='7' => (8, i, 3),
+                    _ => {
+                        let mut buf = [0; 4];
+                        bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                        continue;
+                    }
+                };
+
+                let len = text[start..]
+                    .chars()
+                    .take(max_len)
+                    .take_while(|c| c.is_digit(radix))
+                    .count();
+                if len == 0 {
+                    return None;
+                }
+
+                bytes.push(u8::from_str_radix(&text[start..start + len], radix).ok()?);
+                // skip the digits we just parsed
+                let skip = start + len - (i + 1);
+                for _ in 0..skip {
+                    chars.next();
+                }
+            }
+            c => {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+    }
+}
+
+/// The result of feeding a keysym into a [`ComposeState`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ComposeStatus {
+    /// The keysym isn't part of a compose sequence, and should be handled as usual.
+    Nothing,
+    /// The keysym started or continued a compose sequence.
+    Composing,
+    /// The keysym finished a compose sequence, which produced the given text.
+    Committed(String),
+    /// The keysym doesn't continue the current compose sequence, so the sequence was abandoned. The keysym is
+    /// consumed by the abandoned sequence.
+    Cancelled,
+}
+
+/// The progress through a compose sequence, using a [`ComposeTable`].
+#[derive(Debug, Clone)]
+pub struct ComposeState {
+    table: Arc<ComposeTable>,
+    // the node of the table we're currently at
+    node: usize,
+}
+
+impl ComposeState {
+    /// Create a new `ComposeState` using the given table.
+    #[inline]
+    #[must_use]
+    pub fn new(table: Arc<ComposeTable>) -> Self {
+        Self { table, node: 0 }
+    }
+
+    /// Get the table used by this `ComposeState`.
+    #[inline]
+    #[must_use]
+    pub fn table(&self) -> &Arc<ComposeTable> {
+        &self.table
+    }
+
+    /// Tell whether or not a compose sequence is in progress.
+    #[inline]
+    #[must_use]
+    pub fn is_composing(&self) -> bool {
+        self.node != 0
+    }
+
+    /// Abandon the current compose sequence, if there is one.
+    #[inline]
+    pub fn reset(&mut self) {
+        self.node = 0;
+    }
+
+    /// Feed the keysym of a key press into the compose state. Modifier keysyms are ignored, so that e.g. Shift
+    /// can be used in the middle of a sequence.
+    #[inline]
+    pub fn feed(&mut self, keysym: Keysym) -> ComposeStatus {
+        if is_modifier_keysym(keysym) {
+            return if self.is_composing() {
+                ComposeStatus::Composing
+            } else {
+                ComposeStatus::Nothing
+            };
+        }
+
+        match self.table.child(self.node, keysym) {
+            Some(&(_, child)) => {
+                if let Some(ref result) = self.table.nodes[child].result {
+                    self.node = 0;
+                    ComposeStatus::Committed(result.clone())
+                } else {
+                    self.node = child;
+                    ComposeStatus::Composing
+                }
+            }
+            None if self.is_composing() => {
+                self.node = 0;
+                ComposeStatus::Cancelled
+            }
+            None => ComposeStatus::Nothing,
+        }
+    }
+}
+
+/// Tell whether a keysym belongs to a modifier key, e.g. `Shift_L` or `ISO_Level3_Shift`.
+#[inline]
+fn is_modifier_keysym(keysym: Keysym) -> bool {
+    matches!(keysym, 0xffe1..=0xffee | 0xff7e | 0xff7f | 0xfe01..=0xfe13)
+}
+
+#[test]
+fn compose_table_parses_and_composes() {
+    let mut table = ComposeTable::new();
+    table.parse(
+        r#"
+# a comment
+<dead_acute> <e>            : "é"   eacute # LATIN SMALL LETTER E WITH ACUTE
+<Multi_key> <a> <e>         : "æ"
+<Multi_key> <slash> <slash> : "\\"  backslash
+<Multi_key> <x> <x>         : "\x41\102"
+<Multi_key> <o> <c>         : copyright
+<Multi_key> <U0142>         : "ł"
+<dead_grave> <a>            : "à"
+<dead_grave> <a>            : "A"
+!Ctrl <dead_grave> <b>      : "unsupported"
+include "%L"
+"#,
+    );
+
+    let mut state = ComposeState::new(Arc::new(table));
+    assert_eq!(state.feed(0x61), ComposeStatus::Nothing);
+    // dead_acute e
+    assert_eq!(state.feed(0xfe51), ComposeStatus::Composing);
+    assert_eq!(state.feed(0x65), ComposeStatus::Committed("é".into()));
+    // Multi_key Shift_L a e
+    assert_eq!(state.feed(0xff20), ComposeStatus::Composing);
+    assert_eq!(state.feed(0xffe1), ComposeStatus::Composing);
+    assert_eq!(state.feed(0x61), ComposeStatus::Composing);
+    assert_eq!(state.feed(0x65), ComposeStatus::Committed("æ".into()));
+    // Multi_key a z
+    assert_eq!(state.feed(0xff20), ComposeStatus::Composing);
+    assert_eq!(state.feed(0x61), ComposeStatus::Composing);
+    assert_eq!(state.feed(0x7a), ComposeStatus::Cancelled);
+    assert!(!state.is_composing());
+
+    let mut compose = |keysyms: &[Keysym]| {
+        let status = keysyms.iter().map(|&k| state.feed(k)).last().unwrap();
+        match status {
+            ComposeStatus::Committed(s) => Some(s),
+            _ => None,
+        }
+    };
+    assert_eq!(compose(&[0xff20, 0x2f, 0x2f]).as_deref(), Some("\\"));
+    assert_eq!(compose(&[0xff20, 0x78, 0x78]).as_deref(), Some("AB"));
+    assert_eq!(compose(&[0xff20, 0x6f, 0x63]).as_deref(), Some("\u{a9}"));
+    assert_eq!(compose(&[0xff20, 0x0100_0142]).as_deref(), Some("ł"));
+    // later definitions win
+    assert_eq!(compose(&[0xfe50, 0x61]).as_deref(), Some("A"));
+    assert_eq!(compose(&[0xfe50, 0x62]), None);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn compose_table_follows_includes() {
+    let dir = env::temp_dir().join(alloc::format!("breadx-compose-{}", std::process::id()));
+    fs::create_dir_all(dir.join("xx_XX.UTF-8")).unwrap();
+    fs::write(
+        dir.join("compose.dir"),
+        "# comment\nxx_XX.UTF-8/Compose:\txx_XX.UTF-8\n",
+    )
+    .unwrap();
+    fs::write(dir.join("locale.alias"), "xx_XX.utf8\t\txx_XX.UTF-8\n").unwrap();
+    fs::write(
+        dir.join("xx_XX.UTF-8").join("Compose"),
+        "<dead_acute> <e> : \"é\"\n<dead_acute> <a> : \"á\"\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("user"),
+        "include \"%L\"\n<dead_acute> <a> : \"A\"\n",
+    )
+    .unwrap();
+
+    let config = ComposeConfig {
+        locale_dir: dir.clone(),
+        locale: "xx_XX.utf8".into(),
+    };
+    assert_eq!(
+        config.locale_compose_file(),
+        Some(dir.join("xx_XX.UTF-8").join("Compose"))
+    );
+
+    let table = ComposeTable::from_file(dir.join("user"), &config);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let mut state = ComposeState::new(Arc::new(table.unwrap()));
+    assert_eq!(state.feed(0xfe51), ComposeStatus::Composing);
+    assert_eq!(state.feed(0x65), ComposeStatus::Committed("é".into()));
+    assert_eq!(state.feed(0xfe51), ComposeStatus::Composing);
+    assert_eq!(state.feed(0x61), ComposeStatus::Committed("A".into()));
+}
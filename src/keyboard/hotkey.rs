@@ -0,0 +1,817 @@
+// MIT/Apache2 License
+
+use super::{convert_case, keysym_from_name, KeyboardState, Keymap};
+use crate::{
+    auto::xproto::{
+        GrabKeyRequest, GrabMode, Keycode, Keysym, ModMask, Timestamp, UngrabKeyRequest, Window,
+    },
+    display::{prelude::*, Display, DisplayBase},
+    event::Event,
+    BreadError,
+};
+use alloc::vec::Vec;
+use core::str::FromStr;
+
+#[cfg(feature = "async-core")]
+use crate::display::AsyncDisplay;
+
+const SHIFT: u16 = 1;
+const LOCK: u16 = 1 << 1;
+const CONTROL: u16 = 1 << 2;
+
+/// The modifiers that need to be held for a [`Hotkey`] to be activated.
+///
+/// Apart from Shift and Control, these are virtual modifiers; the real modifiers they correspond to are looked
+/// up in the server's modifier mapping.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct HotkeyModifiers {
+    pub shift: bool,
+    pub control: bool,
+    pub alt: bool,
+    pub super_key: bool,
+    pub hyper: bool,
+    pub meta: bool,
+}
+
+/// A key combination that can be registered as a global hotkey, such as `Ctrl+Alt+T`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Hotkey {
+    /// The keysym of the key. Letters are always stored in their lowercase form.
+    pub keysym: Keysym,
+    pub modifiers: HotkeyModifiers,
+}
+
+impl Hotkey {
+    /// Create a new `Hotkey` from a keysym and the modifiers that need to be held.
+    #[inline]
+    #[must_use]
+    pub fn new(keysym: Keysym, modifiers: HotkeyModifiers) -> Self {
+        Self {
+            keysym: convert_case(keysym).0,
+            modifiers,
+        }
+    }
+}
+
+impl FromStr for Hotkey {
+    type Err = BreadError;
+
+    /// Parse a hotkey of the form `Ctrl+Alt+T`. The modifier names `Shift`, `Ctrl` (or `Control`), `Alt`,
+    /// `Super` (or `Win`), `Hyper` and `Meta` are recognized regardless of case. The key is either a keysym
+    /// name such as `F12` or `Return`, or a single character.
+    #[inline]
+    fn from_str(s: &str) -> crate::Result<Self> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let key = parts.pop().filter(|key| !key.is_empty()).ok_or_else(|| {
+            BreadError::Msg(alloc::format!("Hotkey \"{}\" doesn't have a key", s))
+        })?;
+
+        let mut modifiers = HotkeyModifiers::default();
+        for part in parts {
+            let modifier = match part.to_ascii_lowercase().as_str() {
+                "shift" => &mut modifiers.shift,
+                "ctrl" | "control" => &mut modifiers.control,
+                "alt" => &mut modifiers.alt,
+                "super" | "win" => &mut modifiers.super_key,
+                "hyper" => &mut modifiers.hyper,
+                "meta" => &mut modifiers.meta,
+                _ => {
+                    return Err(BreadError::Msg(alloc::format!(
+                        "Unknown modifier \"{}\" in hotkey \"{}\"",
+                        part,
+                        s
+                    )))
+                }
+            };
+            *modifier = true;
+        }
+
+        let keysym = keysym_from_name(key)
+            .or_else(|| {
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some(char_to_keysym(c)),
+                    _ => None,
+                }
+            })
+            .ok_or_else(|| {
+                BreadError::Msg(alloc::format!(
+                    "Unknown key \"{}\" in hotkey \"{}\"",
+                    key,
+                    s
+                ))
+            })?;
+
+        Ok(Self::new(keysym, modifiers))
+    }
+}
+
+/// Get the keysym for a character.
+#[inline]
+fn char_to_keysym(c: char) -> Keysym {
+    match u32::from(c) {
+        code @ (0x20..=0x7e | 0xa0..=0xff) => code,
+        code => code | 0x0100_0000,
+    }
+}
+
+/// Identifies a hotkey registered with a [`HotkeyManager`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HotkeyId(u32);
+
+/// A registered hotkey was pressed or released.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct HotkeyEvent {
+    pub id: HotkeyId,
+    pub hotkey: Hotkey,
+    /// Whether the hotkey was pressed or released.
+    pub pressed: bool,
+    pub time: Timestamp,
+}
+
+/// Registers global hotkeys by grabbing keys on the root window.
+///
+/// The X server only reports a grabbed key if the modifier state matches the grab exactly, so every hotkey is
+/// grabbed once for every combination of the Caps Lock, Num Lock and Scroll Lock modifiers. Pass every event to
+/// [`HotkeyManager::process_event`] to find out when a hotkey is used.
+///
+/// If the keyboard or modifier mapping changes, update the [`KeyboardState`] and then call
+/// [`HotkeyManager::refresh`] to grab the new keycodes.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use breadx::{prelude::*, DisplayConnection, Event, HotkeyManager, KeyboardState};
+///
+/// let mut conn = DisplayConnection::create(None, None).unwrap();
+/// let keyboard = KeyboardState::new(&mut conn).unwrap();
+/// let mut hotkeys = HotkeyManager::new(&mut conn, &keyboard).unwrap();
+/// let terminal = hotkeys
+///     .register(&mut conn, &keyboard, "Ctrl+Alt+T".parse().unwrap())
+///     .unwrap();
+///
+/// loop {
+///     let event = conn.wait_for_event().unwrap();
+///     if let Some(hotkey) = hotkeys.process_event(&event) {
+///         if hotkey.id == terminal && hotkey.pressed {
+///             println!("Opening a terminal...");
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct HotkeyManager {
+    root: Window,
+    masks: ModifierMasks,
+    grabs: Vec<HotkeyGrab>,
+    next_id: u32,
+}
+
+/// The real modifiers that virtual modifiers are bound to.
+#[derive(Debug, Default, Copy, Clone)]
+struct ModifierMasks {
+    alt: u16,
+    super_key: u16,
+    hyper: u16,
+    meta: u16,
+    num_lock: u16,
+    scroll_lock: u16,
+}
+
+#[derive(Debug, Clone)]
+struct HotkeyGrab {
+    id: HotkeyId,
+    hotkey: Hotkey,
+    // the keycodes that produce the hotkey's keysym, and the full modifier masks they're grabbed with
+    keys: Vec<(Keycode, u16)>,
+}
+
+impl ModifierMasks {
+    /// Figure out which modifier keys are bound to which modifiers.
+    #[inline]
+    fn new<Km: Keymap + ?Sized>(mapping: &ModifierMapping, keyboard: &KeyboardState<Km>) -> Self {
+        let mut masks = Self::default();
+        let per_modifier = usize::from(mapping.keycodes_per_modifier);
+        if per_modifier == 0 {
+            return masks;
+        }
+
+        // Mod1 through Mod5
+        for (index, keycodes) in mapping.keycodes.chunks(per_modifier).enumerate().skip(3) {
+            let bit = 1 << index;
+            keycodes
+                .iter()
+                .flat_map(|&keycode| keyboard.lookup_keysyms(keycode))
+                .for_each(|&keysym| match keysym {
+                    // Alt_L, Alt_R
+                    0xffe9 | 0xffea => masks.alt |= bit,
+                    // Super_L, Super_R
+                    0xffeb | 0xffec => masks.super_key |= bit,
+                    // Hyper_L, Hyper_R
+                    0xffed | 0xffee => masks.hyper |= bit,
+                    // Meta_L, Meta_R
+                    0xffe7 | 0xffe8 => masks.meta |= bit,
+                    // Num_Lock
+                    0xff7f => masks.num_lock |= bit,
+                    // Scroll_Lock
+                    0xff14 => masks.scroll_lock |= bit,
+                    _ => {}
+                });
+        }
+
+        masks
+    }
+
+    /// Get the real modifiers for a set of hotkey modifiers.
+    #[inline]
+    fn real_modifiers(&self, modifiers: HotkeyModifiers) -> crate::Result<u16> {
+        let virtuals = [
+            (modifiers.alt, self.alt),
+            (modifiers.super_key, self.super_key),
+            (modifiers.hyper, self.hyper),
+            (modifiers.meta, self.meta),
+        ];
+
+        let mut mask = 0;
+        if modifiers.shift {
+            mask |= SHIFT;
+        }
+        if modifiers.control {
+            mask |= CONTROL;
+        }
+
+        for &(wanted, real) in &virtuals {
+            if wanted {
+                // pick the lowest modifier bit, in case several modifiers have the key bound
+                if real == 0 {
+                    return Err(BreadError::StaticMsg(
+                        "Hotkey modifier is not bound to any modifier key",
+                    ));
+                }
+                mask |= real & real.wrapping_neg();
+            }
+        }
+
+        Ok(mask)
+    }
+
+    /// The lock modifiers, which are ignored when matching hotkeys.
+    #[inline]
+    fn locks(&self) -> u16 {
+        LOCK | self.num_lock | self.scroll_lock
+    }
+
+    /// Every combination of lock modifiers that a hotkey has to be grabbed with.
+    #[inline]
+    fn lock_variants(&self) -> Vec<u16> {
+        let locks = [LOCK, self.num_lock, self.scroll_lock];
+        let mut variants: Vec<u16> = (0..8)
+            .map(|i| {
+                locks
+                    .iter()
+                    .enumerate()
+                    .filter(|(bit, _)| i & (1 << bit) != 0)
+                    .fold(0, |mask, (_, lock)| mask | lock)
+            })
+            .collect();
+        variants.sort_unstable();
+        variants.dedup();
+        variants
+    }
+}
+
+impl HotkeyManager {
+    /// Create a new `HotkeyManager` for the default root window, which looks up modifier keys using the
+    /// given keyboard state.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the modifier mapping could not be fetched.
+    #[inline]
+    pub fn new<Dpy: Display + ?Sized, Km: Keymap + ?Sized>(
+        display: &mut Dpy,
+        keyboard: &KeyboardState<Km>,
+    ) -> crate::Result<Self> {
+        let mapping = display.get_modifier_mapping_immediate()?;
+        Ok(Self::from_modifier_mapping(
+            display.default_root(),
+            &mapping,
+            keyboard,
+        ))
+    }
+
+    /// Create a new `HotkeyManager`, async redox.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the modifier mapping could not be fetched.
+    #[cfg(feature = "async-core")]
+    #[inline]
+    pub async fn new_async<Dpy: AsyncDisplay + ?Sized, Km: Keymap + ?Sized>(
+        display: &mut Dpy,
+        keyboard: &KeyboardState<Km>,
+    ) -> crate::Result<Self> {
+        let mapping = display.get_modifier_mapping_immediate_async().await?;
+        Ok(Self::from_modifier_mapping(
+            display.default_root(),
+            &mapping,
+            keyboard,
+        ))
+    }
+
+    /// Create a new `HotkeyManager` that grabs keys on the given window, using an already known modifier
+    /// mapping.
+    #[inline]
+    pub fn from_modifier_mapping<Km: Keymap + ?Sized>(
+        root: Window,
+        mapping: &ModifierMapping,
+        keyboard: &KeyboardState<Km>,
+    ) -> Self {
+        Self {
+            root,
+            masks: ModifierMasks::new(mapping, keyboard),
+            grabs: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Get the hotkey registered with the given ID.
+    #[inline]
+    #[must_use]
+    pub fn hotkey(&self, id: HotkeyId) -> Option<Hotkey> {
+        self.grabs
+            .iter()
+            .find(|grab| grab.id == id)
+            .map(|grab| grab.hotkey)
+    }
+
+    /// Register a hotkey, grabbing every keycode that produces its keysym.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no keycode produces the hotkey's keysym, if one of its modifiers isn't bound to a
+    /// modifier key, or if the key could not be grabbed (e.g. because another client already grabbed it). The
+    /// grabs are checked even if the display isn't, and are released again if any of them fail.
+    #[inline]
+    pub fn register<Dpy: Display + ?Sized, Km: Keymap + ?Sized>(
+        &mut self,
+        display: &mut Dpy,
+        keyboard: &KeyboardState<Km>,
+        hotkey: Hotkey,
+    ) -> crate::Result<HotkeyId> {
+        let grab = self.new_grab(keyboard, hotkey)?;
+
+        // GrabKey has no reply, so a failed grab (e.g. BadAccess) only shows up in checked mode
+        let checked = display.checked();
+        display.set_checked(true);
+        let res = display
+            .send_requests(self.grab_requests(&grab))
+            .and_then(|tokens| display.resolve_requests(tokens));
+        display.set_checked(checked);
+
+        match res {
+            Ok(_) => Ok(self.push_grab(grab)),
+            Err(e) => {
+                // some of the variants may have been grabbed before the failure; since the hotkey isn't
+                // registered, unregister could never release them
+                for req in self.ungrab_requests(&grab) {
+                    if let Err(e) = display.exchange_request(req) {
+                        log::error!("Failed to release a partially grabbed hotkey: {:?}", e);
+                    }
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Register a hotkey, async redox.
+    ///
+    /// # Errors
+    ///
+    /// See [`HotkeyManager::register`].
+    #[cfg(feature = "async-core")]
+    #[inline]
+    pub async fn register_async<Dpy: AsyncDisplay + ?Sized, Km: Keymap + ?Sized>(
+        &mut self,
+        display: &mut Dpy,
+        keyboard: &KeyboardState<Km>,
+        hotkey: Hotkey,
+    ) -> crate::Result<HotkeyId> {
+        let grab = self.new_grab(keyboard, hotkey)?;
+
+        let checked = display.checked();
+        display.set_checked(true);
+        let res = match display.send_requests_async(self.grab_requests(&grab)).await {
+            Ok(tokens) => display.resolve_requests_async(tokens).await,
+            Err(e) => Err(e),
+        };
+        display.set_checked(checked);
+
+        match res {
+            Ok(_) => Ok(self.push_grab(grab)),
+            Err(e) => {
+                for req in self.ungrab_requests(&grab) {
+                    if let Err(e) = display.exchange_request_async(req).await {
+                        log::error!("Failed to release a partially grabbed hotkey: {:?}", e);
+                    }
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Unregister a hotkey, releasing its grabs. Unknown IDs are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the X server returns an error.
+    #[inline]
+    pub fn unregister<Dpy: Display + ?Sized>(
+        &mut self,
+        display: &mut Dpy,
+        id: HotkeyId,
+    ) -> crate::Result {
+        if let Some(grab) = self.remove_grab(id) {
+            for req in self.ungrab_requests(&grab) {
+                display.exchange_request(req)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Unregister a hotkey, async redox.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the X server returns an error.
+    #[cfg(feature = "async-core")]
+    #[inline]
+    pub async fn unregister_async<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        display: &mut Dpy,
+        id: HotkeyId,
+    ) -> crate::Result {
+        if let Some(grab) = self.remove_grab(id) {
+            for req in self.ungrab_requests(&grab) {
+                display.exchange_request_async(req).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Grab every hotkey again, after the keyboard or modifier mapping has changed. The keyboard state should
+    /// be updated first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the modifier mapping could not be fetched, or if a hotkey could no longer be
+    /// grabbed. Hotkeys that could not be grabbed stay registered, and are retried on the next refresh.
+    #[inline]
+    pub fn refresh<Dpy: Display + ?Sized, Km: Keymap + ?Sized>(
+        &mut self,
+        display: &mut Dpy,
+        keyboard: &KeyboardState<Km>,
+    ) -> crate::Result {
+        for req in self
+            .grabs
+            .iter()
+            .flat_map(|grab| self.ungrab_requests(grab))
+        {
+            display.exchange_request(req)?;
+        }
+
+        let mapping = display.get_modifier_mapping_immediate()?;
+        let (result, requests) = self.regrab(&mapping, keyboard);
+        for req in requests {
+            display.exchange_request(req)?;
+        }
+        result
+    }
+
+    /// Grab every hotkey again, async redox.
+    ///
+    /// # Errors
+    ///
+    /// See [`HotkeyManager::refresh`].
+    #[cfg(feature = "async-core")]
+    #[inline]
+    pub async fn refresh_async<Dpy: AsyncDisplay + ?Sized, Km: Keymap + ?Sized>(
+        &mut self,
+        display: &mut Dpy,
+        keyboard: &KeyboardState<Km>,
+    ) -> crate::Result {
+        let ungrabs: Vec<UngrabKeyRequest> = self
+            .grabs
+            .iter()
+            .flat_map(|grab| self.ungrab_requests(grab))
+            .collect();
+        for req in ungrabs {
+            display.exchange_request_async(req).await?;
+        }
+
+        let mapping = display.get_modifier_mapping_immediate_async().await?;
+        let (result, requests) = self.regrab(&mapping, keyboard);
+        for req in requests {
+            display.exchange_request_async(req).await?;
+        }
+        result
+    }
+
+    /// Check whether an event activates one of the registered hotkeys.
+    #[inline]
+    #[must_use]
+    pub fn process_event(&self, event: &Event) -> Option<HotkeyEvent> {
+        let (keycode, state, time, window, pressed) = match event {
+            Event::KeyPress(kpe) => (kpe.detail, kpe.state, kpe.time, kpe.event, true),
+            Event::KeyRelease(kre) => (kre.detail, kre.state, kre.time, kre.event, false),
+            _ => return None,
+        };
+
+        if window != self.root {
+            return None;
+        }
+
+        // ignore lock modifiers, as well as pointer buttons and the XKB group
+        let modifiers = state.inner & 0xff & !self.masks.locks();
+        self.grabs
+            .iter()
+            .find(|grab| grab.keys.contains(&(keycode, modifiers)))
+            .map(|grab| HotkeyEvent {
+                id: grab.id,
+                hotkey: grab.hotkey,
+                pressed,
+                time,
+            })
+    }
+
+    /// Figure out which keys need to be grabbed for a hotkey.
+    #[inline]
+    fn new_grab<Km: Keymap + ?Sized>(
+        &self,
+        keyboard: &KeyboardState<Km>,
+        hotkey: Hotkey,
+    ) -> crate::Result<HotkeyGrab> {
+        let modifiers = self.masks.real_modifiers(hotkey.modifiers)?;
+        let keys: Vec<(Keycode, u16)> = keyboard
+            .keysym_to_keycodes(hotkey.keysym)
+            .into_iter()
+            // keys in other groups can't be grabbed
+            .filter(|(_, mask)| mask.inner & !0xff == 0)
+            .map(|(keycode, mask)| (keycode, modifiers | mask.inner))
+            .collect();
+
+        if keys.is_empty() {
+            return Err(BreadError::StaticMsg(
+                "No keycode produces the hotkey's keysym",
+            ));
+        }
+
+        Ok(HotkeyGrab {
+            id: HotkeyId(self.next_id),
+            hotkey,
+            keys,
+        })
+    }
+
+    #[inline]
+    fn push_grab(&mut self, grab: HotkeyGrab) -> HotkeyId {
+        let id = grab.id;
+        self.next_id += 1;
+        self.grabs.push(grab);
+        id
+    }
+
+    #[inline]
+    fn remove_grab(&mut self, id: HotkeyId) -> Option<HotkeyGrab> {
+        let index = self.grabs.iter().position(|grab| grab.id == id)?;
+        Some(self.grabs.remove(index))
+    }
+
+    /// Look up the keys for every hotkey again, and get the requests needed to grab them.
+    #[inline]
+    fn regrab<Km: Keymap + ?Sized>(
+        &mut self,
+        mapping: &ModifierMapping,
+        keyboard: &KeyboardState<Km>,
+    ) -> (crate::Result, Vec<GrabKeyRequest>) {
+        self.masks = ModifierMasks::new(mapping, keyboard);
+
+        let mut result = Ok(());
+        let mut requests = Vec::new();
+        let mut grabs = core::mem::take(&mut self.grabs);
+        for grab in &mut grabs {
+            match self.new_grab(keyboard, grab.hotkey) {
+                Ok(new_grab) => {
+                    grab.keys = new_grab.keys;
+                    requests.extend(self.grab_requests(grab));
+                }
+                Err(err) => {
+                    grab.keys.clear();
+                    result = Err(err);
+                }
+            }
+        }
+        self.grabs = grabs;
+
+        (result, requests)
+    }
+
+    #[inline]
+    fn grab_requests<'a>(
+        &'a self,
+        grab: &'a HotkeyGrab,
+    ) -> impl Iterator<Item = GrabKeyRequest> + 'a {
+        let root = self.root;
+        self.key_variants(grab)
+            .map(move |(key, modifiers)| GrabKeyRequest {
+                owner_events: false,
+                grab_window: root,
+                modifiers,
+                key,
+                pointer_mode: GrabMode::Async,
+                keyboard_mode: GrabMode::Async,
+                ..Default::default()
+            })
+    }
+
+    #[inline]
+    fn ungrab_requests<'a>(
+        &'a self,
+        grab: &'a HotkeyGrab,
+    ) -> impl Iterator<Item = UngrabKeyRequest> + 'a {
+        let root = self.root;
+        self.key_variants(grab)
+            .map(move |(key, modifiers)| UngrabKeyRequest {
+                key,
+                grab_window: root,
+                modifiers,
+                ..Default::default()
+            })
+    }
+
+    /// Every keycode and modifier combination that a hotkey is grabbed with.
+    #[inline]
+    fn key_variants<'a>(
+        &'a self,
+        grab: &'a HotkeyGrab,
+    ) -> impl Iterator<Item = (Keycode, ModMask)> + 'a {
+        let variants = self.masks.lock_variants();
+        grab.keys.iter().flat_map(move |&(keycode, modifiers)| {
+            variants.clone().into_iter().map(move |locks| {
+                (
+                    keycode,
+                    ModMask {
+                        inner: modifiers | locks,
+                    },
+                )
+            })
+        })
+    }
+}
+
+#[test]
+fn hotkeys_parse_and_match_events() {
+    use crate::auto::xproto::{KeyButMask, KeyPressEvent};
+    use alloc::boxed::Box;
+
+    struct TestKeymap;
+
+    impl Keymap for TestKeymap {
+        fn lookup_keysyms(&self, keycode: Keycode) -> &[Keysym] {
+            match keycode {
+                // t T
+                8 => &[0x74, 0x54],
+                // Alt_L Meta_L
+                9 => &[0xffe9, 0xffe7],
+                // Num_Lock
+                10 => &[0xff7f],
+                // F12
+                11 => &[0xffc9],
+                _ => &[],
+            }
+        }
+    }
+
+    let hotkey: Hotkey = "Ctrl + Alt+T".parse().unwrap();
+    assert_eq!(hotkey.keysym, 0x74);
+    assert!(hotkey.modifiers.control && hotkey.modifiers.alt && !hotkey.modifiers.shift);
+    assert_eq!("shift+F12".parse::<Hotkey>().unwrap().keysym, 0xffc9);
+    assert_eq!("Super+/".parse::<Hotkey>().unwrap().keysym, 0x2f);
+    assert!("Ctrl+".parse::<Hotkey>().is_err());
+    assert!("Fn+T".parse::<Hotkey>().is_err());
+
+    // Mod1 -> Alt_L, Mod2 -> Num_Lock
+    let mapping = ModifierMapping {
+        keycodes_per_modifier: 1,
+        keycodes: Box::new([0, 0, 0, 9, 10, 0, 0, 0]),
+    };
+    let keyboard = KeyboardState::from_keymap(TestKeymap);
+    let root = Window::const_from_xid(1);
+    let mut manager = HotkeyManager::from_modifier_mapping(root, &mapping, &keyboard);
+
+    let grab = manager.new_grab(&keyboard, hotkey).unwrap();
+    assert_eq!(grab.keys, &[(8, CONTROL | (1 << 3))]);
+    // Lock, NumLock, and both
+    assert_eq!(manager.grab_requests(&grab).count(), 4);
+    let id = manager.push_grab(grab);
+    assert!(manager
+        .new_grab(&keyboard, "Hyper+T".parse().unwrap())
+        .is_err());
+
+    let press = |state: u16| {
+        Event::KeyPress(KeyPressEvent {
+            detail: 8,
+            event: root,
+            state: KeyButMask { inner: state },
+            ..Default::default()
+        })
+    };
+    let activated = manager.process_event(&press(CONTROL | (1 << 3) | LOCK | (1 << 4)));
+    assert_eq!(
+        activated.map(|event| (event.id, event.pressed)),
+        Some((id, true))
+    );
+    assert_eq!(manager.process_event(&press(CONTROL)), None);
+    assert_eq!(
+        manager.process_event(&press(CONTROL | SHIFT | (1 << 3))),
+        None
+    );
+}
+
+#[test]
+fn failed_registration_releases_grabs() {
+    use crate::{
+        auto::xproto::{GetInputFocusReply, GetInputFocusRequest},
+        display::BasicDisplay,
+    };
+    use alloc::boxed::Box;
+
+    struct TestKeymap;
+
+    impl Keymap for TestKeymap {
+        fn lookup_keysyms(&self, keycode: Keycode) -> &[Keysym] {
+            match keycode {
+                // t T
+                8 => &[0x74, 0x54],
+                // Num_Lock
+                10 => &[0xff7f],
+                _ => &[],
+            }
+        }
+    }
+
+    let mapping = ModifierMapping {
+        keycodes_per_modifier: 1,
+        keycodes: Box::new([0, 0, 0, 0, 10, 0, 0, 0]),
+    };
+    let keyboard = KeyboardState::from_keymap(TestKeymap);
+    let root = Window::const_from_xid(1);
+    let mut manager = HotkeyManager::from_modifier_mapping(root, &mapping, &keyboard);
+    let hotkey = "Ctrl+T".parse().unwrap();
+    let grab = manager.new_grab(&keyboard, hotkey).unwrap();
+
+    // the grabs are checked even if the display isn't
+    let mut display = BasicDisplay::dummy();
+    display.set_checked(false);
+    let conn = display.connection_mut();
+    for mut req in manager.grab_requests(&grab) {
+        req.req_type = 33;
+        req.length = 4;
+        conn.expects(req);
+    }
+    conn.expects(GetInputFocusRequest {
+        req_type: 43,
+        length: 1,
+    });
+    for mut req in manager.ungrab_requests(&grab) {
+        req.req_type = 34;
+        req.length = 3;
+        conn.expects(req);
+    }
+
+    // the second variant is already grabbed by another client
+    let mut error = [0; 32];
+    error[1] = 10;
+    error[2..4].copy_from_slice(&3u16.to_ne_bytes());
+    error[10] = 33;
+    conn.reply_bytes(error.iter().copied());
+    conn.reply_padded(GetInputFocusReply {
+        reply_type: 1,
+        sequence: 6,
+        ..Default::default()
+    });
+
+    match manager.register(&mut display, &keyboard, hotkey) {
+        Err(BreadError::XProtocol {
+            error_code,
+            major_code: 33,
+            sequence: 3,
+            ..
+        }) => assert_eq!(error_code.0, 10),
+        res => panic!("Unexpected result: {:?}", res),
+    }
+    assert!(display.connection().received_all_expected());
+    assert!(!display.checked());
+    assert_eq!(manager.hotkey(HotkeyId(0)), None);
+}
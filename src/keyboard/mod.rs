@@ -5,7 +5,7 @@
 There are two ways to handle keyboard input in the context of X11:
 
 1). Using xproto's natural GetKeyboardMapping functions.
-2). Using the xkb extension, which we don't support yet.
+2). Using the xkb extension, if the xkb feature is enabled and the server supports it.
 
 We keep stuff in the given KeyboardState, which acts as a unifying interface between
 both of the above.
@@ -13,48 +13,122 @@ both of the above.
 */
 
 use crate::{
-    auto::xproto::{KeyButMask, Keycode, Keysym},
-    display::Display,
+    auto::xproto::{ChangeKeyboardMappingRequest, KeyButMask, Keycode, Keysym},
+    display::{prelude::*, Display},
+    event::Event,
+    BreadError,
 };
+use alloc::{borrow::Cow, string::String, sync::Arc, vec, vec::Vec};
 use gluten_keyboard::Key;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::display::AsyncDisplay;
 
+mod case;
+pub use case::*;
+
+mod compose;
+pub use compose::*;
+
 mod convert;
 pub use convert::*;
 
+mod hotkey;
+pub use hotkey::*;
+
 mod xproto;
 pub use xproto::*;
 
+#[cfg(feature = "xkb")]
+mod xkb;
+#[cfg(feature = "xkb")]
+pub use xkb::*;
+
+#[cfg(not(feature = "xkb"))]
 pub type DefaultKeymap = XprotoKeymap;
+#[cfg(feature = "xkb")]
+pub type DefaultKeymap = AnyKeymap;
 
 /// Keep track of keys and currently tracked modifiers.
 #[derive(Debug, Clone)]
 pub struct KeyboardState<Km: ?Sized = DefaultKeymap> {
+    compose: Option<ComposeState>,
     keymap: Km,
 }
 
 impl<Km> KeyboardState<Km> {
     #[inline]
     pub fn from_keymap(keymap: Km) -> Self {
-        Self { keymap }
+        Self {
+            compose: None,
+            keymap,
+        }
+    }
+}
+
+impl<Km: ?Sized> KeyboardState<Km> {
+    /// Get the keymap used by this `KeyboardState`.
+    #[inline]
+    pub fn keymap(&self) -> &Km {
+        &self.keymap
+    }
+
+    /// Set the table of compose sequences that [`process_text`] feeds keysyms through, or `None` to stop
+    /// handling compose sequences. Any sequence in progress is abandoned.
+    ///
+    /// [`process_text`]: KeyboardState::process_text
+    #[inline]
+    pub fn set_compose_table(&mut self, table: Option<Arc<ComposeTable>>) {
+        self.compose = table.map(ComposeState::new);
+    }
+
+    /// Get the state of the current compose sequence, if compose sequences are being handled.
+    #[inline]
+    pub fn compose_state(&self) -> Option<&ComposeState> {
+        self.compose.as_ref()
     }
 }
 
-impl KeyboardState<XprotoKeymap> {
+impl KeyboardState<DefaultKeymap> {
+    /// Create a new `KeyboardState` using the display's keyboard mapping. If the `xkb` feature is enabled,
+    /// the XKB extension is used if the server supports it.
     #[inline]
     pub fn new<Dpy: Display + ?Sized>(display: &mut Dpy) -> crate::Result<Self> {
-        Ok(Self::from_keymap(XprotoKeymap::init_from(display)?))
+        Ok(Self::from_keymap(DefaultKeymap::init_from(display)?))
     }
 
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn new_async<Dpy: AsyncDisplay + ?Sized>(display: &mut Dpy) -> crate::Result<Self> {
         Ok(Self::from_keymap(
-            XprotoKeymap::init_from_async(display).await?,
+            DefaultKeymap::init_from_async(display).await?,
         ))
     }
+
+    /// Keep the keyboard state up to date with changes on the server. This should be called with every event
+    /// that the display receives.
+    ///
+    /// On `MappingNotify` events, the changed part of the keyboard or modifier mapping is fetched again. If XKB
+    /// is in use, `StateNotify` events update the tracked modifier and group state, while `MapNotify` and
+    /// `NewKeyboardNotify` events cause the keymap to be fetched again.
+    #[inline]
+    pub fn update<Dpy: Display + ?Sized>(
+        &mut self,
+        event: &Event,
+        display: &mut Dpy,
+    ) -> crate::Result {
+        self.keymap.update(event, display)
+    }
+
+    #[cfg(feature = "async-core")]
+    #[inline]
+    pub async fn update_async<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        event: &Event,
+        display: &mut Dpy,
+    ) -> crate::Result {
+        self.keymap.update_async(event, display).await
+    }
 }
 
 impl<Km: Keymap + ?Sized> KeyboardState<Km> {
@@ -65,6 +139,134 @@ impl<Km: Keymap + ?Sized> KeyboardState<Km> {
 
     #[inline]
     pub fn process_keycode(&mut self, keycode: Keycode, modifiers: KeyButMask) -> Option<Key> {
+        self.keymap
+            .lookup_keysym(keycode, modifiers)
+            .and_then(keysym_to_key)
+    }
+
+    /// Get the text produced by the key with the given keycode, given the modifier state of the key event.
+    /// This should be called for key presses.
+    ///
+    /// Returns `None` if the key doesn't produce any text. This includes keys that only produce control
+    /// characters, such as `Return` or `BackSpace`, as well as any key pressed while Control is held, since
+    /// those are usually shortcuts rather than text input. Use [`process_keycode`] to handle those keys.
+    ///
+    /// If a compose table has been set, keys are fed through it first. Keys that start or continue a compose
+    /// sequence don't produce any text until the sequence is finished.
+    ///
+    /// [`process_keycode`]: KeyboardState::process_keycode
+    #[inline]
+    pub fn process_text(&mut self, keycode: Keycode, modifiers: KeyButMask) -> Option<String> {
+        if modifiers.control() {
+            return None;
+        }
+
+        let keysym = self.keymap.lookup_keysym(keycode, modifiers)?;
+
+        if let Some(ref mut compose) = self.compose {
+            match compose.feed(keysym) {
+                ComposeStatus::Nothing => {}
+                ComposeStatus::Committed(text) => return Some(text),
+                ComposeStatus::Composing | ComposeStatus::Cancelled => return None,
+            }
+        }
+
+        keysym_to_char(keysym)
+            .filter(|c| !c.is_control())
+            .map(String::from)
+    }
+
+    /// Find the keycodes that produce the given keysym, along with the modifiers that need to be held for each
+    /// of them to do so.
+    #[inline]
+    pub fn keysym_to_keycodes(&self, keysym: Keysym) -> Vec<(Keycode, KeyButMask)> {
+        self.keymap.keysym_to_keycodes(keysym)
+    }
+
+    /// Find a keycode that produces the given keysym, along with the modifiers it needs. If no keycode produces
+    /// the keysym, a keycode that isn't mapped to any keysyms is remapped to produce it, using
+    /// `ChangeKeyboardMapping`.
+    ///
+    /// The keymap is only changed once the resulting `MappingNotify` event is passed to [`update`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are no unmapped keycodes, or if the X server returns an error.
+    ///
+    /// [`update`]: KeyboardState::update
+    #[inline]
+    pub fn keycode_for_keysym<Dpy: Display + ?Sized>(
+        &self,
+        display: &mut Dpy,
+        keysym: Keysym,
+    ) -> crate::Result<(Keycode, KeyButMask)> {
+        if let Some(&found) = self.keysym_to_keycodes(keysym).first() {
+            return Ok(found);
+        }
+
+        let req = self.remap_spare_keycode(
+            display.setup().min_keycode,
+            display.setup().max_keycode,
+            keysym,
+        )?;
+        let keycode = req.first_keycode;
+        display.exchange_request(req)?;
+        Ok((keycode, KeyButMask::default()))
+    }
+
+    /// Find a keycode that produces the given keysym, async redox. See [`keycode_for_keysym`] for more
+    /// information.
+    ///
+    /// [`keycode_for_keysym`]: KeyboardState::keycode_for_keysym
+    #[cfg(feature = "async-core")]
+    #[inline]
+    pub async fn keycode_for_keysym_async<Dpy: AsyncDisplay + ?Sized>(
+        &self,
+        display: &mut Dpy,
+        keysym: Keysym,
+    ) -> crate::Result<(Keycode, KeyButMask)> {
+        if let Some(&found) = self.keysym_to_keycodes(keysym).first() {
+            return Ok(found);
+        }
+
+        let req = self.remap_spare_keycode(
+            display.setup().min_keycode,
+            display.setup().max_keycode,
+            keysym,
+        )?;
+        let keycode = req.first_keycode;
+        display.exchange_request_async(req).await?;
+        Ok((keycode, KeyButMask::default()))
+    }
+
+    /// Create the request that maps the first keycode without any keysyms onto the given keysym.
+    #[inline]
+    fn remap_spare_keycode(
+        &self,
+        min_keycode: Keycode,
+        max_keycode: Keycode,
+        keysym: Keysym,
+    ) -> crate::Result<ChangeKeyboardMappingRequest<'static>> {
+        let spare = (min_keycode..=max_keycode)
+            .find(|&keycode| self.lookup_keysyms(keycode).iter().all(|&sym| sym == 0))
+            .ok_or(BreadError::StaticMsg("No unmapped keycodes are available"))?;
+
+        Ok(ChangeKeyboardMappingRequest {
+            keycode_count: 1,
+            first_keycode: spare,
+            keysyms_per_keycode: 2,
+            keysyms: Cow::Owned(vec![keysym, keysym]),
+            ..Default::default()
+        })
+    }
+}
+
+pub trait Keymap {
+    fn lookup_keysyms(&self, keycode: Keycode) -> &[Keysym];
+
+    /// Get the keysym produced by the key with the given keycode, given the modifier state of the key event.
+    #[inline]
+    fn lookup_keysym(&self, keycode: Keycode, modifiers: KeyButMask) -> Option<Keysym> {
         // get the index we need
         let index = if modifiers.shift() { 1 } else { 0 };
 
@@ -72,13 +274,164 @@ impl<Km: Keymap + ?Sized> KeyboardState<Km> {
         if syms.is_empty() {
             None
         } else if syms.len() == 1 {
-            keysym_to_key(syms[0])
+            Some(syms[0])
         } else {
-            keysym_to_key(syms[index])
+            Some(syms[index])
         }
     }
+
+    /// Find the keycodes that produce the given keysym, along with the modifiers that need to be held for each
+    /// of them to do so. For each way of producing the keysym, only the fewest modifiers needed are reported.
+    ///
+    /// The default implementation tries every keycode with every combination of Shift and `Mod1` through
+    /// `Mod5`, using `lookup_keysym`.
+    #[inline]
+    fn keysym_to_keycodes(&self, keysym: Keysym) -> Vec<(Keycode, KeyButMask)> {
+        // Shift and Mod1 through Mod5
+        const CANDIDATE_MODS: u16 = 0x01 | 0xf8;
+
+        let mut masks: Vec<u16> = (0..=0xff)
+            .filter(|mask| mask & !CANDIDATE_MODS == 0)
+            .collect();
+        masks.sort_by_key(|mask| mask.count_ones());
+
+        let mut found: Vec<(Keycode, KeyButMask)> = Vec::new();
+        for keycode in 0..=Keycode::MAX {
+            let start = found.len();
+            for &mask in &masks {
+                // a subset of these modifiers already produces the keysym
+                if found[start..]
+                    .iter()
+                    .any(|(_, found)| found.inner & mask == found.inner)
+                {
+                    continue;
+                }
+
+                let modifiers = KeyButMask { inner: mask };
+                if self.lookup_keysym(keycode, modifiers) == Some(keysym) {
+                    found.push((keycode, modifiers));
+                }
+            }
+        }
+
+        found
+    }
 }
 
-pub trait Keymap {
-    fn lookup_keysyms(&self, keycode: Keycode) -> &[Keysym];
+#[test]
+fn process_text_maps_keysyms_to_chars() {
+    struct TestKeymap;
+
+    impl Keymap for TestKeymap {
+        fn lookup_keysyms(&self, keycode: Keycode) -> &[Keysym] {
+            match keycode {
+                // a A
+                8 => &[0x61, 0x41],
+                // Cyrillic_ef Cyrillic_EF
+                9 => &[0x6c6, 0x6e6],
+                // U+1F600 GRINNING FACE
+                10 => &[0x0101_f600],
+                // Return
+                11 => &[0xff0d],
+                _ => &[],
+            }
+        }
+    }
+
+    let mut state = KeyboardState::from_keymap(TestKeymap);
+    let none = KeyButMask::default();
+    let shift = KeyButMask { inner: 1 };
+    let control = KeyButMask { inner: 1 << 2 };
+
+    assert_eq!(state.process_text(8, none).as_deref(), Some("a"));
+    assert_eq!(state.process_text(8, shift).as_deref(), Some("A"));
+    assert_eq!(state.process_text(9, shift).as_deref(), Some("\u{424}"));
+    assert_eq!(state.process_text(10, none).as_deref(), Some("\u{1f600}"));
+    assert_eq!(state.process_text(11, none), None);
+    assert_eq!(state.process_text(8, control), None);
+    assert_eq!(keysym_to_char(0xff0d), Some('\r'));
+    assert_eq!(keysym_to_char(0xffb7), Some('7'));
+}
+
+#[test]
+fn process_text_composes_dead_keys() {
+    struct TestKeymap;
+
+    impl Keymap for TestKeymap {
+        fn lookup_keysyms(&self, keycode: Keycode) -> &[Keysym] {
+            match keycode {
+                // dead_acute
+                8 => &[0xfe51],
+                // e E
+                9 => &[0x65, 0x45],
+                // x X
+                10 => &[0x78, 0x58],
+                _ => &[],
+            }
+        }
+    }
+
+    let mut table = ComposeTable::new();
+    table.parse("<dead_acute> <E> : \"\u{c9}\"");
+
+    let mut state = KeyboardState::from_keymap(TestKeymap);
+    state.set_compose_table(Some(Arc::new(table)));
+    let none = KeyButMask::default();
+    let shift = KeyButMask { inner: 1 };
+
+    assert_eq!(state.process_text(8, none), None);
+    assert_eq!(state.process_text(9, shift).as_deref(), Some("\u{c9}"));
+    assert_eq!(state.process_text(8, none), None);
+    assert_eq!(state.process_text(10, none), None);
+    assert_eq!(state.process_text(10, none).as_deref(), Some("x"));
+}
+
+#[test]
+fn keycode_for_keysym_remaps_a_spare_keycode() {
+    use crate::{auto::xproto::GetInputFocusReply, display::BasicDisplay};
+
+    struct TestKeymap;
+
+    impl Keymap for TestKeymap {
+        fn lookup_keysyms(&self, keycode: Keycode) -> &[Keysym] {
+            match keycode {
+                // a A
+                8 => &[0x61, 0x41],
+                // b B
+                9 => &[0x62, 0x42],
+                _ => &[],
+            }
+        }
+    }
+
+    let state = KeyboardState::from_keymap(TestKeymap);
+    let mut display = BasicDisplay::dummy();
+    let shift = KeyButMask { inner: 1 };
+
+    // keysyms that are already mapped don't change the keymap
+    assert_eq!(
+        state.keycode_for_keysym(&mut display, 0x42).unwrap(),
+        (9, shift)
+    );
+
+    // "c" is mapped onto the first keycode without any keysyms
+    let conn = display.connection_mut();
+    conn.expects(ChangeKeyboardMappingRequest {
+        req_type: 100,
+        keycode_count: 1,
+        length: 4,
+        first_keycode: 10,
+        keysyms_per_keycode: 2,
+        keysyms: Cow::Borrowed(&[0x63, 0x63]),
+    });
+    conn.reply_padded(GetInputFocusReply {
+        reply_type: 1,
+        sequence: 3,
+        ..Default::default()
+    });
+    assert_eq!(
+        state.keycode_for_keysym(&mut display, 0x63).unwrap(),
+        (10, KeyButMask::default())
+    );
+    assert!(display.connection().received_all_expected());
 }
@@ -0,0 +1,938 @@
+// MIT/Apache2 License
+
+use super::{Keymap, XprotoKeymap};
+use crate::{
+    auto::{
+        xkb::{
+            DeviceSpec, EventType, GetMapRequest, GetNamesRequest, GetStateRequest, Id, MapPart,
+            NameDetail, SelectEventsRequest, UseExtensionRequest,
+        },
+        xproto::{KeyButMask, Keycode, Keysym, QueryExtensionRequest},
+    },
+    display::{prelude::*, Display},
+    event::Event,
+    Atom, BreadError,
+};
+use alloc::{borrow::Cow, boxed::Box, vec, vec::Vec};
+
+#[cfg(feature = "async-core")]
+use crate::display::AsyncDisplay;
+
+mod text;
+pub use text::*;
+
+/// The version of XKB that we ask the server for.
+const XKB_MAJOR_VERSION: u16 = 1;
+const XKB_MINOR_VERSION: u16 = 0;
+
+/// The device spec for the core keyboard.
+const CORE_KEYBOARD: DeviceSpec = Id::UseCoreKbd as DeviceSpec;
+
+/// The values of the `xkbType` field of the XKB events we're interested in.
+const NEW_KEYBOARD_NOTIFY: u8 = 0;
+const MAP_NOTIFY: u8 = 1;
+const STATE_NOTIFY: u8 = 2;
+
+/// A keymap built from the XKB extension's description of the core keyboard.
+///
+/// Unlike the [`XprotoKeymap`], this knows about key types, so it picks the right shift level for keys that
+/// aren't affected by `Shift` in the usual way (e.g. keypad keys, or keys that use `AltGr`), and it knows how
+/// many groups each key has, so switching between layouts works as expected. The group is taken from bits 13
+/// and 14 of the modifier state of the key event, which the server fills in once XKB is in use.
+#[derive(Debug, Clone)]
+pub struct XkbKeymap {
+    min_keycode: Keycode,
+    max_keycode: Keycode,
+    types: Box<[KeyType]>,
+    keys: Box<[KeyInfo]>,
+    keysyms: Box<[Keysym]>,
+    modmap: Box<[u8]>,
+    state: XkbState,
+    group_names: Box<[Atom]>,
+    // the event code of XKB events
+    event_base: u8,
+}
+
+/// The state of the keyboard's modifiers and groups, as tracked by XKB.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct XkbState {
+    /// The effective modifiers.
+    pub mods: u8,
+    /// The modifiers that are set because their keys are held down.
+    pub base_mods: u8,
+    /// The modifiers that are latched until the next key press.
+    pub latched_mods: u8,
+    /// The modifiers that are locked.
+    pub locked_mods: u8,
+    /// The effective group.
+    pub group: u8,
+    /// The group that is set because its key is held down.
+    pub base_group: i16,
+    /// The group that is latched until the next key press.
+    pub latched_group: i16,
+    /// The locked group.
+    pub locked_group: u8,
+}
+
+/// A key type, which maps modifier combinations to shift levels.
+#[derive(Debug, Clone, Default)]
+struct KeyType {
+    mods_mask: u8,
+    num_levels: u8,
+    // pairs of modifier combinations and the levels they map to
+    map: Box<[(u8, u8)]>,
+    // pairs of modifier combinations and the modifiers they preserve
+    preserve: Box<[(u8, u8)]>,
+}
+
+impl KeyType {
+    /// Get the shift level picked by the given modifiers.
+    #[inline]
+    fn level(&self, mods: u8) -> u8 {
+        let mods = mods & self.mods_mask;
+        self.map
+            .iter()
+            .find(|(entry_mods, _)| *entry_mods == mods)
+            .map_or(0, |(_, level)| *level)
+    }
+}
+
+/// Information about the keysyms of a single key.
+#[derive(Debug, Copy, Clone, Default)]
+struct KeyInfo {
+    types: [u8; 4],
+    group_info: u8,
+    width: u8,
+    offset: usize,
+}
+
+impl KeyInfo {
+    #[inline]
+    fn num_groups(self) -> u8 {
+        self.group_info & 0x0F
+    }
+
+    /// Bring the given group into the range of groups this key has, using the key's out-of-range action.
+    #[inline]
+    fn effective_group(self, group: u8) -> Option<u8> {
+        let num_groups = self.num_groups();
+        if num_groups == 0 {
+            None
+        } else if group < num_groups {
+            Some(group)
+        } else {
+            Some(match self.group_info & 0xC0 {
+                // redirect into the group stored in the info
+                0x80 => {
+                    let group = (self.group_info >> 4) & 0x03;
+                    if group < num_groups {
+                        group
+                    } else {
+                        0
+                    }
+                }
+                // clamp into range
+                0x40 => num_groups - 1,
+                // wrap into range
+                _ => group % num_groups,
+            })
+        }
+    }
+}
+
+impl XkbKeymap {
+    #[inline]
+    pub(crate) fn init_from<Dpy: Display + ?Sized>(display: &mut Dpy) -> crate::Result<Self> {
+        // we need the event code in order to recognize XKB events later
+        let qer = display.exchange_request(query_extension_request())?;
+        if !qer.present {
+            return Err(BreadError::ExtensionNotPresent("XKEYBOARD".into()));
+        }
+
+        let reply = display.exchange_request(use_extension_request())?;
+        if !reply.supported {
+            return Err(BreadError::ExtensionNotPresent("XKEYBOARD".into()));
+        }
+        display.exchange_request(select_events_request())?;
+
+        Self::fetch(display, qer.first_event)
+    }
+
+    #[cfg(feature = "async-core")]
+    #[inline]
+    pub(crate) async fn init_from_async<Dpy: AsyncDisplay + ?Sized>(
+        display: &mut Dpy,
+    ) -> crate::Result<Self> {
+        let qer = display
+            .exchange_request_async(query_extension_request())
+            .await?;
+        if !qer.present {
+            return Err(BreadError::ExtensionNotPresent("XKEYBOARD".into()));
+        }
+
+        let reply = display
+            .exchange_request_async(use_extension_request())
+            .await?;
+        if !reply.supported {
+            return Err(BreadError::ExtensionNotPresent("XKEYBOARD".into()));
+        }
+        display
+            .exchange_request_async(select_events_request())
+            .await?;
+
+        Self::fetch_async(display, qer.first_event).await
+    }
+
+    /// Fetch the keymap, the keyboard state and the group names from the server.
+    #[inline]
+    fn fetch<Dpy: Display + ?Sized>(display: &mut Dpy, event_base: u8) -> crate::Result<Self> {
+        let map_tok = display.send_request(map_request())?;
+        let state_tok = display.send_request(state_request())?;
+        let names_tok = display.send_request(names_request())?;
+
+        let map = display.resolve_request_raw(map_tok.sequence())?;
+        let state = display.resolve_request_raw(state_tok.sequence())?;
+        let names = display.resolve_request_raw(names_tok.sequence())?;
+
+        Self::from_replies(&map.data, &state.data, &names.data, event_base)
+    }
+
+    #[cfg(feature = "async-core")]
+    #[inline]
+    async fn fetch_async<Dpy: AsyncDisplay + ?Sized>(
+        display: &mut Dpy,
+        event_base: u8,
+    ) -> crate::Result<Self> {
+        let map_tok = display.send_request_async(map_request()).await?;
+        let state_tok = display.send_request_async(state_request()).await?;
+        let names_tok = display.send_request_async(names_request()).await?;
+
+        let map = display
+            .resolve_request_raw_async(map_tok.sequence())
+            .await?;
+        let state = display
+            .resolve_request_raw_async(state_tok.sequence())
+            .await?;
+        let names = display
+            .resolve_request_raw_async(names_tok.sequence())
+            .await?;
+
+        Self::from_replies(&map.data, &state.data, &names.data, event_base)
+    }
+
+    /// Build the keymap from the raw bytes of the `GetMap`, `GetState` and `GetNames` replies.
+    ///
+    /// Note: the generated deserializers for these replies treat XKB's one-byte modifier masks as two bytes
+    /// wide and pad lists incorrectly, so we read the parts we need ourselves.
+    #[inline]
+    fn from_replies(map: &[u8], state: &[u8], names: &[u8], event_base: u8) -> crate::Result<Self> {
+        let mut this = parse_map(map).ok_or(BreadError::BadObjectRead(Some("GetMapReply")))?;
+        this.state = parse_state(state).ok_or(BreadError::BadObjectRead(Some("GetStateReply")))?;
+        this.group_names =
+            parse_names(names).ok_or(BreadError::BadObjectRead(Some("GetNamesReply")))?;
+        this.event_base = event_base;
+        Ok(this)
+    }
+
+    /// Update the keymap in response to an XKB event. `StateNotify` events update the tracked state, while
+    /// `MapNotify` and `NewKeyboardNotify` events cause the keymap to be fetched again.
+    #[inline]
+    pub(crate) fn update<Dpy: Display + ?Sized>(
+        &mut self,
+        event: &Event,
+        display: &mut Dpy,
+    ) -> crate::Result {
+        if self.apply_event(event) {
+            *self = Self::fetch(display, self.event_base)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "async-core")]
+    #[inline]
+    pub(crate) async fn update_async<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        event: &Event,
+        display: &mut Dpy,
+    ) -> crate::Result {
+        if self.apply_event(event) {
+            *self = Self::fetch_async(display, self.event_base).await?;
+        }
+        Ok(())
+    }
+
+    /// Apply the changes in an XKB event to this keymap. Returns `true` if the keymap needs to be fetched again.
+    #[inline]
+    fn apply_event(&mut self, event: &Event) -> bool {
+        let bytes = match event {
+            Event::NoneOfTheAbove { opcode, bytes } if *opcode == self.event_base => bytes,
+            _ => return false,
+        };
+
+        match bytes.get(1).copied() {
+            Some(NEW_KEYBOARD_NOTIFY | MAP_NOTIFY) => true,
+            Some(STATE_NOTIFY) => {
+                match parse_state_notify(bytes) {
+                    Some(state) => self.state = state,
+                    None => log::warn!("Received a truncated XKB StateNotify event"),
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// The current state of the keyboard.
+    #[inline]
+    #[must_use]
+    pub fn state(&self) -> XkbState {
+        self.state
+    }
+
+    /// The currently effective group.
+    #[inline]
+    #[must_use]
+    pub fn group(&self) -> u8 {
+        self.state.group
+    }
+
+    /// The names of the groups (i.e. the layouts) of the keyboard, as atoms.
+    #[inline]
+    #[must_use]
+    pub fn group_names(&self) -> &[Atom] {
+        &self.group_names
+    }
+
+    /// The number of groups the key with the given keycode has.
+    #[inline]
+    #[must_use]
+    pub fn num_groups(&self, keycode: Keycode) -> u8 {
+        self.key_info(keycode).map_or(0, KeyInfo::num_groups)
+    }
+
+    /// The real modifiers that the key with the given keycode is bound to.
+    #[inline]
+    #[must_use]
+    pub fn modifier_map(&self, keycode: Keycode) -> KeyButMask {
+        let mods = if keycode < self.min_keycode || keycode > self.max_keycode {
+            0
+        } else {
+            self.modmap[usize::from(keycode - self.min_keycode)]
+        };
+
+        KeyButMask {
+            inner: u16::from(mods),
+        }
+    }
+
+    #[inline]
+    fn key_info(&self, keycode: Keycode) -> Option<KeyInfo> {
+        if keycode < self.min_keycode {
+            None
+        } else {
+            self.keys
+                .get(usize::from(keycode - self.min_keycode))
+                .copied()
+        }
+    }
+}
+
+impl Keymap for XkbKeymap {
+    #[inline]
+    fn lookup_keysyms(&self, keycode: Keycode) -> &[Keysym] {
+        match self.key_info(keycode) {
+            Some(info) => {
+                let len = usize::from(info.num_groups()) * usize::from(info.width);
+                &self.keysyms[info.offset..info.offset + len]
+            }
+            None => &[],
+        }
+    }
+
+    #[inline]
+    fn lookup_keysym(&self, keycode: Keycode, modifiers: KeyButMask) -> Option<Keysym> {
+        let info = self.key_info(keycode)?;
+        // the group lives in bits 13 and 14 of the state
+        let group = info.effective_group(((modifiers.inner >> 13) & 0x03) as u8)?;
+
+        // figure out the shift level using the key type for the group
+        #[allow(clippy::cast_possible_truncation)]
+        let mods = modifiers.inner as u8;
+        let level = self
+            .types
+            .get(usize::from(info.types[usize::from(group)]))
+            .map_or(0, |ty| ty.level(mods));
+
+        if level >= info.width {
+            return None;
+        }
+
+        let index = info.offset + usize::from(group) * usize::from(info.width) + usize::from(level);
+        match self.keysyms.get(index) {
+            Some(0) | None => None,
+            Some(sym) => Some(*sym),
+        }
+    }
+
+    /// Find the keycodes that produce the given keysym. The group that produces the keysym is stored in bits
+    /// 13 and 14 of the modifiers, as in the state of key events.
+    #[inline]
+    fn keysym_to_keycodes(&self, keysym: Keysym) -> Vec<(Keycode, KeyButMask)> {
+        let mut found = Vec::new();
+
+        for (keycode, info) in (self.min_keycode..=self.max_keycode).zip(self.keys.iter()) {
+            for group in 0..info.num_groups() {
+                let ty = match self.types.get(usize::from(info.types[usize::from(group)])) {
+                    Some(ty) => ty,
+                    None => continue,
+                };
+                let offset = info.offset + usize::from(group) * usize::from(info.width);
+
+                for level in 0..info.width {
+                    if self.keysyms.get(offset + usize::from(level)) != Some(&keysym) {
+                        continue;
+                    }
+
+                    // the modifier combination with the fewest modifiers that picks this level
+                    let mods = core::iter::once(0)
+                        .chain(ty.map.iter().map(|(mods, _)| *mods))
+                        .filter(|&mods| ty.level(mods) == level)
+                        .min_by_key(|mods| mods.count_ones());
+
+                    if let Some(mods) = mods {
+                        found.push((
+                            keycode,
+                            KeyButMask {
+                                inner: u16::from(mods) | (u16::from(group) << 13),
+                            },
+                        ));
+                    }
+                }
+            }
+        }
+
+        found
+    }
+}
+
+/// A keymap that uses XKB if the server supports it, and the core protocol's keyboard mapping otherwise.
+#[derive(Debug, Clone)]
+pub enum AnyKeymap {
+    Xkb(XkbKeymap),
+    Xproto(XprotoKeymap),
+}
+
+impl AnyKeymap {
+    #[inline]
+    pub(crate) fn init_from<Dpy: Display + ?Sized>(display: &mut Dpy) -> crate::Result<Self> {
+        match XkbKeymap::init_from(display) {
+            Ok(keymap) => Ok(Self::Xkb(keymap)),
+            Err(BreadError::ExtensionNotPresent(_)) => {
+                log::debug!("XKB is not available, falling back to the core keyboard mapping");
+                Ok(Self::Xproto(XprotoKeymap::init_from(display)?))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    #[cfg(feature = "async-core")]
+    #[inline]
+    pub(crate) async fn init_from_async<Dpy: AsyncDisplay + ?Sized>(
+        display: &mut Dpy,
+    ) -> crate::Result<Self> {
+        match XkbKeymap::init_from_async(display).await {
+            Ok(keymap) => Ok(Self::Xkb(keymap)),
+            Err(BreadError::ExtensionNotPresent(_)) => {
+                log::debug!("XKB is not available, falling back to the core keyboard mapping");
+                Ok(Self::Xproto(XprotoKeymap::init_from_async(display).await?))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl AnyKeymap {
+    #[inline]
+    pub(crate) fn update<Dpy: Display + ?Sized>(
+        &mut self,
+        event: &Event,
+        display: &mut Dpy,
+    ) -> crate::Result {
+        match self {
+            Self::Xkb(xkb) => xkb.update(event, display),
+            Self::Xproto(xproto) => xproto.update(event, display),
+        }
+    }
+
+    #[cfg(feature = "async-core")]
+    #[inline]
+    pub(crate) async fn update_async<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        event: &Event,
+        display: &mut Dpy,
+    ) -> crate::Result {
+        match self {
+            Self::Xkb(xkb) => xkb.update_async(event, display).await,
+            Self::Xproto(xproto) => xproto.update_async(event, display).await,
+        }
+    }
+}
+
+impl Keymap for AnyKeymap {
+    #[inline]
+    fn lookup_keysyms(&self, keycode: Keycode) -> &[Keysym] {
+        match self {
+            Self::Xkb(xkb) => xkb.lookup_keysyms(keycode),
+            Self::Xproto(xproto) => xproto.lookup_keysyms(keycode),
+        }
+    }
+
+    #[inline]
+    fn lookup_keysym(&self, keycode: Keycode, modifiers: KeyButMask) -> Option<Keysym> {
+        match self {
+            Self::Xkb(xkb) => xkb.lookup_keysym(keycode, modifiers),
+            Self::Xproto(xproto) => xproto.lookup_keysym(keycode, modifiers),
+        }
+    }
+
+    #[inline]
+    fn keysym_to_keycodes(&self, keysym: Keysym) -> Vec<(Keycode, KeyButMask)> {
+        match self {
+            Self::Xkb(xkb) => xkb.keysym_to_keycodes(keysym),
+            Self::Xproto(xproto) => xproto.keysym_to_keycodes(keysym),
+        }
+    }
+}
+
+#[inline]
+fn query_extension_request() -> QueryExtensionRequest<'static> {
+    QueryExtensionRequest {
+        name: Cow::Borrowed("XKEYBOARD"),
+        ..Default::default()
+    }
+}
+
+#[inline]
+fn use_extension_request() -> UseExtensionRequest {
+    UseExtensionRequest {
+        wanted_major: XKB_MAJOR_VERSION,
+        wanted_minor: XKB_MINOR_VERSION,
+        ..Default::default()
+    }
+}
+
+#[inline]
+fn select_events_request() -> SelectEventsRequest {
+    let mut events = EventType::default();
+    events
+        .set_new_keyboard_notify(true)
+        .set_map_notify(true)
+        .set_state_notify(true);
+
+    let mut map = MapPart::default();
+    map.set_key_types(true)
+        .set_key_syms(true)
+        .set_modifier_map(true);
+
+    SelectEventsRequest {
+        device_spec: CORE_KEYBOARD,
+        affect_which: events,
+        select_all: events,
+        affect_map: map,
+        map,
+        ..Default::default()
+    }
+}
+
+#[inline]
+fn map_request() -> GetMapRequest {
+    let mut full = MapPart::default();
+    full.set_key_types(true)
+        .set_key_syms(true)
+        .set_modifier_map(true);
+
+    GetMapRequest {
+        device_spec: CORE_KEYBOARD,
+        full,
+        ..Default::default()
+    }
+}
+
+#[inline]
+fn state_request() -> GetStateRequest {
+    GetStateRequest {
+        device_spec: CORE_KEYBOARD,
+        ..Default::default()
+    }
+}
+
+#[inline]
+fn names_request() -> GetNamesRequest {
+    let mut which = NameDetail::default();
+    which.set_group_names(true);
+
+    GetNamesRequest {
+        device_spec: CORE_KEYBOARD,
+        which,
+        ..Default::default()
+    }
+}
+
+/// Reads values out of the raw bytes of a reply.
+struct ReplyReader<'a> {
+    bytes: &'a [u8],
+    index: usize,
+}
+
+impl<'a> ReplyReader<'a> {
+    #[inline]
+    fn new(bytes: &'a [u8], index: usize) -> Self {
+        Self { bytes, index }
+    }
+
+    #[inline]
+    fn skip(&mut self, len: usize) -> Option<()> {
+        if self.index + len > self.bytes.len() {
+            None
+        } else {
+            self.index += len;
+            Some(())
+        }
+    }
+
+    #[inline]
+    fn pad(&mut self) -> Option<()> {
+        self.skip(self.index.wrapping_neg() & 3)
+    }
+
+    #[inline]
+    fn u8(&mut self) -> Option<u8> {
+        let val = *self.bytes.get(self.index)?;
+        self.index += 1;
+        Some(val)
+    }
+
+    #[inline]
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.bytes.get(self.index..self.index + 2)?;
+        self.index += 2;
+        Some(u16::from_ne_bytes([bytes[0], bytes[1]]))
+    }
+
+    #[inline]
+    fn i16(&mut self) -> Option<i16> {
+        self.u16().map(|val| val as i16)
+    }
+
+    #[inline]
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.bytes.get(self.index..self.index + 4)?;
+        self.index += 4;
+        Some(u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+/// Parse a `GetMap` reply containing the key types, key symbols and modifier map.
+#[allow(clippy::too_many_lines)]
+#[inline]
+fn parse_map(bytes: &[u8]) -> Option<XkbKeymap> {
+    let mut header = ReplyReader::new(bytes, 10);
+    let min_keycode = header.u8()?;
+    let max_keycode = header.u8()?;
+    let present = MapPart {
+        inner: header.u16()?,
+    };
+    header.skip(1)?; // first type
+    let n_types = header.u8()?;
+    header.skip(1)?; // total types
+    let first_key_sym = header.u8()?;
+    header.skip(2)?; // total syms
+    let n_key_syms = header.u8()?;
+    header.skip(12)?; // actions, behaviors, explicit components
+    let total_modmap_keys = header.u8()?;
+
+    if !present.key_types() || !present.key_syms() || !present.modifier_map() {
+        return None;
+    }
+
+    // the variable-length parts start after the 40-byte header
+    let mut reader = ReplyReader::new(bytes, 40);
+
+    let mut types = Vec::with_capacity(usize::from(n_types));
+    for _ in 0..n_types {
+        let mods_mask = reader.u8()?;
+        reader.skip(3)?; // real mods, virtual mods
+        let num_levels = reader.u8()?;
+        let n_map_entries = reader.u8()?;
+        let has_preserve = reader.u8()? != 0;
+        reader.skip(1)?;
+
+        let mut entries = Vec::with_capacity(usize::from(n_map_entries));
+        for _ in 0..n_map_entries {
+            let active = reader.u8()? != 0;
+            let mods = reader.u8()?;
+            let level = reader.u8()?;
+            reader.skip(5)?; // real mods, virtual mods, padding
+            entries.push((active, mods, level));
+        }
+
+        let mut preserve = Vec::new();
+        if has_preserve {
+            for &(active, mods, _) in &entries {
+                let preserved = reader.u8()?;
+                reader.skip(3)?; // real mods, virtual mods
+                if active && preserved != 0 {
+                    preserve.push((mods, preserved));
+                }
+            }
+        }
+
+        let map = entries
+            .into_iter()
+            .filter(|(active, _, _)| *active)
+            .map(|(_, mods, level)| (mods, level))
+            .collect::<Vec<_>>();
+
+        types.push(KeyType {
+            mods_mask,
+            num_levels,
+            map: map.into_boxed_slice(),
+            preserve: preserve.into_boxed_slice(),
+        });
+    }
+
+    // keys below the first key with symbols don't have any
+    let mut keys =
+        vec![KeyInfo::default(); usize::from(max_keycode.saturating_sub(min_keycode)) + 1];
+    let mut keysyms = Vec::new();
+    for i in 0..n_key_syms {
+        let mut types = [0; 4];
+        for ty in &mut types {
+            *ty = reader.u8()?;
+        }
+        let group_info = reader.u8()?;
+        let width = reader.u8()?;
+        let n_syms = reader.u16()?;
+
+        let info = KeyInfo {
+            types,
+            group_info,
+            width,
+            offset: keysyms.len(),
+        };
+        for _ in 0..n_syms {
+            keysyms.push(reader.u32()?);
+        }
+
+        // make sure the symbols are actually there
+        if usize::from(info.num_groups()) * usize::from(width) > usize::from(n_syms) {
+            return None;
+        }
+
+        let keycode = first_key_sym.checked_add(i)?;
+        if let Some(slot) = keycode
+            .checked_sub(min_keycode)
+            .and_then(|idx| keys.get_mut(usize::from(idx)))
+        {
+            *slot = info;
+        }
+    }
+
+    let mut modmap = vec![0; keys.len()].into_boxed_slice();
+    for _ in 0..total_modmap_keys {
+        let keycode = reader.u8()?;
+        let mods = reader.u8()?;
+        if let Some(slot) = keycode
+            .checked_sub(min_keycode)
+            .and_then(|idx| modmap.get_mut(usize::from(idx)))
+        {
+            *slot = mods;
+        }
+    }
+    reader.pad()?;
+
+    Some(XkbKeymap {
+        min_keycode,
+        max_keycode,
+        types: types.into_boxed_slice(),
+        keys: keys.into_boxed_slice(),
+        keysyms: keysyms.into_boxed_slice(),
+        modmap,
+        state: XkbState::default(),
+        group_names: Box::new([]),
+        event_base: 0,
+    })
+}
+
+/// Parse a `GetState` reply.
+#[inline]
+fn parse_state(bytes: &[u8]) -> Option<XkbState> {
+    let mut reader = ReplyReader::new(bytes, 8);
+    let mods = reader.u8()?;
+    let base_mods = reader.u8()?;
+    let latched_mods = reader.u8()?;
+    let locked_mods = reader.u8()?;
+    let group = reader.u8()?;
+    let locked_group = reader.u8()?;
+    let base_group = reader.i16()?;
+    let latched_group = reader.i16()?;
+
+    Some(XkbState {
+        mods,
+        base_mods,
+        latched_mods,
+        locked_mods,
+        group,
+        base_group,
+        latched_group,
+        locked_group,
+    })
+}
+
+/// Parse a `StateNotify` event.
+#[inline]
+fn parse_state_notify(bytes: &[u8]) -> Option<XkbState> {
+    let mut reader = ReplyReader::new(bytes, 9);
+    let mods = reader.u8()?;
+    let base_mods = reader.u8()?;
+    let latched_mods = reader.u8()?;
+    let locked_mods = reader.u8()?;
+    let group = reader.u8()?;
+    let base_group = reader.i16()?;
+    let latched_group = reader.i16()?;
+    let locked_group = reader.u8()?;
+
+    Some(XkbState {
+        mods,
+        base_mods,
+        latched_mods,
+        locked_mods,
+        group,
+        base_group,
+        latched_group,
+        locked_group,
+    })
+}
+
+/// Parse a `GetNames` reply containing the group names.
+#[inline]
+fn parse_names(bytes: &[u8]) -> Option<Box<[Atom]>> {
+    let mut header = ReplyReader::new(bytes, 8);
+    let which = NameDetail {
+        inner: header.u32()?,
+    };
+    header.skip(3)?;
+    let groups = header.u8()?;
+
+    if !which.group_names() {
+        return Some(Box::new([]));
+    }
+
+    let mut reader = ReplyReader::new(bytes, 32);
+    (0..groups.count_ones())
+        .map(|_| reader.u32().map(Atom::const_from_xid))
+        .collect()
+}
+
+#[test]
+fn xkb_keymap_picks_group_and_level() {
+    let mut map = vec![0; 40];
+    map[0] = 1;
+    map[10] = 8; // min keycode
+    map[11] = 10; // max keycode
+    map[12..14].copy_from_slice(&7u16.to_ne_bytes()); // types, syms and modmap
+    map[15] = 2; // types
+    map[17] = 8; // first key with syms
+    map[20] = 3; // keys with syms
+    map[33] = 1; // keys in the modmap
+
+    // ONE_LEVEL
+    map.extend_from_slice(&[0, 0, 0, 0, 1, 0, 0, 0]);
+    // TWO_LEVEL, Shift picks level 2
+    map.extend_from_slice(&[1, 1, 0, 0, 2, 1, 0, 0]);
+    map.extend_from_slice(&[1, 1, 1, 1, 0, 0, 0, 0]);
+
+    let mut push_key = |types: [u8; 4], group_info: u8, width: u8, syms: &[Keysym]| {
+        map.extend_from_slice(&types);
+        map.extend_from_slice(&[group_info, width]);
+        map.extend_from_slice(&(syms.len() as u16).to_ne_bytes());
+        syms.iter()
+            .for_each(|sym| map.extend_from_slice(&sym.to_ne_bytes()));
+    };
+    // a key with a US and a RU group
+    push_key([1, 1, 0, 0], 2, 2, &[0x61, 0x41, 0x6c6, 0x6e6]);
+    // Escape, with only one group
+    push_key([0, 0, 0, 0], 1, 1, &[0xff1b]);
+    // Shift_L
+    push_key([0, 0, 0, 0], 1, 1, &[0xffe1]);
+
+    // Shift_L is bound to Shift
+    map.extend_from_slice(&[10, 1, 0, 0]);
+
+    let mut state = vec![0; 32];
+    state[12] = 1;
+    let mut names = vec![0; 32];
+    names[8..12].copy_from_slice(&(1u32 << 12).to_ne_bytes());
+    names[15] = 0b11;
+    names.extend_from_slice(&100u32.to_ne_bytes());
+    names.extend_from_slice(&200u32.to_ne_bytes());
+
+    let mut keymap = XkbKeymap::from_replies(&map, &state, &names, 85).unwrap();
+    assert_eq!(keymap.group(), 1);
+    assert_eq!(
+        keymap.group_names(),
+        &[Atom::const_from_xid(100), Atom::const_from_xid(200)]
+    );
+    assert_eq!(keymap.lookup_keysyms(8), &[0x61, 0x41, 0x6c6, 0x6e6]);
+    assert_eq!(keymap.num_groups(8), 2);
+
+    let state = |mods: u16, group: u16| KeyButMask {
+        inner: mods | (group << 13),
+    };
+    assert_eq!(keymap.lookup_keysym(8, state(0, 0)), Some(0x61));
+    assert_eq!(keymap.lookup_keysym(8, state(1, 0)), Some(0x41));
+    assert_eq!(keymap.lookup_keysym(8, state(0, 1)), Some(0x6c6));
+    assert_eq!(keymap.lookup_keysym(8, state(1, 1)), Some(0x6e6));
+    // group 3 wraps around to the first group
+    assert_eq!(keymap.lookup_keysym(8, state(0, 2)), Some(0x61));
+    // Control doesn't affect the level of TWO_LEVEL keys
+    assert_eq!(keymap.lookup_keysym(8, state(1 << 2, 1)), Some(0x6c6));
+    assert_eq!(keymap.lookup_keysym(9, state(1, 1)), Some(0xff1b));
+    assert_eq!(keymap.lookup_keysym(11, state(0, 0)), None);
+
+    assert_eq!(keymap.keysym_to_keycodes(0x6e6), &[(8, state(1, 1))]);
+    assert_eq!(keymap.keysym_to_keycodes(0xff1b), &[(9, state(0, 0))]);
+    assert!(keymap.keysym_to_keycodes(0xffbe).is_empty());
+
+    assert!(keymap.modifier_map(10).shift());
+    assert!(!keymap.modifier_map(9).shift());
+
+    // switching to the second group with Shift latched
+    let mut bytes: tinyvec::TinyVec<[u8; 32]> = [0; 32].into();
+    bytes[0] = 85;
+    bytes[1] = STATE_NOTIFY;
+    bytes[9] = 1;
+    bytes[11] = 1;
+    bytes[13] = 1;
+    bytes[18] = 1;
+    let event = Event::NoneOfTheAbove { opcode: 85, bytes };
+    assert!(!keymap.apply_event(&event));
+    assert_eq!(
+        keymap.state(),
+        XkbState {
+            mods: 1,
+            latched_mods: 1,
+            group: 1,
+            locked_group: 1,
+            ..Default::default()
+        }
+    );
+
+    // a changed map has to be fetched again, but other extensions' events are ignored
+    let mut bytes: tinyvec::TinyVec<[u8; 32]> = [0; 32].into();
+    bytes[0] = 85;
+    bytes[1] = MAP_NOTIFY;
+    assert!(keymap.apply_event(&Event::NoneOfTheAbove { opcode: 85, bytes }));
+    let bytes: tinyvec::TinyVec<[u8; 32]> = [0; 32].into();
+    assert!(!keymap.apply_event(&Event::NoneOfTheAbove { opcode: 86, bytes }));
+}
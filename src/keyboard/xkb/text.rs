@@ -0,0 +1,2208 @@
+// MIT/Apache2 License
+
+use super::{map_request, parse_map, use_extension_request, ReplyReader, XkbKeymap, CORE_KEYBOARD};
+use crate::{
+    auto::{
+        xkb::{GetNamesRequest, NameDetail},
+        xproto::{Keycode, Keysym},
+        AsByteSequence,
+    },
+    display::{prelude::*, Display},
+    keyboard::{convert_case, keysym_from_name, keysym_name},
+    Atom, BreadError, Request,
+};
+use alloc::{
+    borrow::Cow,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{convert::TryFrom, fmt, str::FromStr};
+
+#[cfg(feature = "async-core")]
+use crate::display::AsyncDisplay;
+
+/// The names of the real modifiers, in the order of their bits.
+const MODIFIER_NAMES: [&str; 8] = [
+    "Shift", "Lock", "Control", "Mod1", "Mod2", "Mod3", "Mod4", "Mod5",
+];
+
+/// The key types that the server requires to be the first four types of every keymap.
+const REQUIRED_TYPES: [&str; 4] = ["ONE_LEVEL", "TWO_LEVEL", "ALPHABETIC", "KEYPAD"];
+
+/// The maximum number of groups a key can have.
+const MAX_GROUPS: usize = 4;
+
+// flags for the SetMap request
+const SET_MAP_RESIZE_TYPES: u16 = 1 << 0;
+const SET_MAP_RECOMPUTE_ACTIONS: u16 = 1 << 1;
+
+/// A complete description of an XKB keymap: the names of the keys, the key types, the keysyms bound to every
+/// key and the modifier map.
+///
+/// A description can be fetched from the server, written out in the `xkb_keymap { ... }` text format that
+/// `xkbcomp` uses, parsed back from that format and uploaded to the server again. This makes it possible to
+/// save a user's keyboard configuration and restore it in another session.
+///
+/// Virtual modifiers are stored as the real modifiers they are bound to, and key actions are not part of the
+/// description; when a description is uploaded, the server recomputes the actions of each key using its
+/// current compatibility map. The parser accepts the subset of the text format needed to describe the above,
+/// skipping over the statements it doesn't understand, such as actions, indicators and geometry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XkbKeymapDescription {
+    /// The name of the `xkb_keycodes` section, e.g. `evdev+aliases(qwerty)`.
+    pub keycodes_name: String,
+    /// The name of the `xkb_types` section, e.g. `complete`.
+    pub types_name: String,
+    /// The name of the `xkb_compatibility` section, e.g. `complete`.
+    pub compat_name: String,
+    /// The name of the `xkb_symbols` section, e.g. `pc+us+ru:2+inet(evdev)`.
+    pub symbols_name: String,
+    /// The lowest keycode of the keyboard.
+    pub min_keycode: Keycode,
+    /// The highest keycode of the keyboard.
+    pub max_keycode: Keycode,
+    /// The key types, which map modifier combinations to shift levels.
+    pub key_types: Vec<XkbKeyTypeDescription>,
+    /// The keys that have a name, keysyms or modifiers, ordered by keycode.
+    pub keys: Vec<XkbKeyDescription>,
+    /// The names of the groups, i.e. the layouts, of the keyboard.
+    pub group_names: Vec<String>,
+}
+
+/// A key type in an [`XkbKeymapDescription`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XkbKeyTypeDescription {
+    /// The name of the key type, e.g. `TWO_LEVEL`.
+    pub name: String,
+    /// The real modifiers that affect the shift level of keys of this type.
+    pub modifiers: u8,
+    /// The number of shift levels of this type.
+    pub num_levels: u8,
+    /// Pairs of modifier combinations and the zero-based shift levels they pick. Combinations not listed here
+    /// pick the first level.
+    pub map: Vec<(u8, u8)>,
+    /// Pairs of modifier combinations and the modifiers they leave in the state used by other keys.
+    pub preserve: Vec<(u8, u8)>,
+    /// The names of the levels, e.g. `Base` and `Shift`. Unnamed levels have an empty name.
+    pub level_names: Vec<String>,
+}
+
+/// A key in an [`XkbKeymapDescription`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XkbKeyDescription {
+    /// The keycode of the key.
+    pub keycode: Keycode,
+    /// The name of the key, without the angle brackets, e.g. `AE01`.
+    pub name: String,
+    /// The groups of the key.
+    pub groups: Vec<XkbKeyGroup>,
+    /// What happens when the effective group is higher than the number of groups the key has.
+    pub out_of_range: XkbGroupsOutOfRange,
+    /// The real modifiers the key is bound to in the modifier map.
+    pub modifiers: u8,
+}
+
+/// A group of a key in an [`XkbKeymapDescription`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XkbKeyGroup {
+    /// The name of the key type of this group.
+    pub key_type: String,
+    /// The keysyms of each shift level.
+    pub keysyms: Vec<Keysym>,
+}
+
+/// What happens to the effective group when a key doesn't have that many groups.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum XkbGroupsOutOfRange {
+    /// The group is wrapped into the range of groups the key has.
+    Wrap,
+    /// The group is clamped into the range of groups the key has.
+    Clamp,
+    /// The given (zero-based) group is used instead.
+    Redirect(u8),
+}
+
+impl Default for XkbGroupsOutOfRange {
+    #[inline]
+    fn default() -> Self {
+        Self::Wrap
+    }
+}
+
+impl XkbKeymapDescription {
+    /// Fetch the keymap of the core keyboard from the server.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BreadError::ExtensionNotPresent` if the server does not support XKB, or any error that occurs
+    /// while talking to the server.
+    #[inline]
+    pub fn fetch<Dpy: Display + ?Sized>(display: &mut Dpy) -> crate::Result<Self> {
+        let reply = display.exchange_request(use_extension_request())?;
+        if !reply.supported {
+            return Err(BreadError::ExtensionNotPresent("XKEYBOARD".into()));
+        }
+
+        let map_tok = display.send_request(map_request())?;
+        let names_tok = display.send_request(keymap_names_request())?;
+        let map = display.resolve_request_raw(map_tok.sequence())?;
+        let names = display.resolve_request_raw(names_tok.sequence())?;
+        let (keymap, names) = parse_replies(&map.data, &names.data)?;
+
+        // resolve the names of all of the atoms at once
+        let atoms = names.atoms();
+        let tokens = atoms
+            .iter()
+            .map(|atom| display.atom_name(*atom))
+            .collect::<crate::Result<Vec<_>>>()?;
+        let atom_names = atoms
+            .into_iter()
+            .zip(tokens)
+            .map(|(atom, tok)| Ok((atom, display.resolve_request(tok)?.name.into_owned())))
+            .collect::<crate::Result<BTreeMap<_, _>>>()?;
+
+        Ok(Self::from_parts(&keymap, &names, &atom_names))
+    }
+
+    /// Fetch the keymap of the core keyboard from the server, async redox.
+    #[cfg(feature = "async-core")]
+    #[inline]
+    pub async fn fetch_async<Dpy: AsyncDisplay + ?Sized>(display: &mut Dpy) -> crate::Result<Self> {
+        let reply = display
+            .exchange_request_async(use_extension_request())
+            .await?;
+        if !reply.supported {
+            return Err(BreadError::ExtensionNotPresent("XKEYBOARD".into()));
+        }
+
+        let map_tok = display.send_request_async(map_request()).await?;
+        let names_tok = display.send_request_async(keymap_names_request()).await?;
+        let map = display
+            .resolve_request_raw_async(map_tok.sequence())
+            .await?;
+        let names = display
+            .resolve_request_raw_async(names_tok.sequence())
+            .await?;
+        let (keymap, names) = parse_replies(&map.data, &names.data)?;
+
+        let atoms = names.atoms();
+        let mut tokens = Vec::with_capacity(atoms.len());
+        for atom in &atoms {
+            tokens.push(display.atom_name_async(*atom).await?);
+        }
+        let mut atom_names = BTreeMap::new();
+        for (atom, tok) in atoms.into_iter().zip(tokens) {
+            let name = display.resolve_request_async(tok).await?.name.into_owned();
+            atom_names.insert(atom, name);
+        }
+
+        Ok(Self::from_parts(&keymap, &names, &atom_names))
+    }
+
+    /// Upload this keymap to the server, replacing the key types, keysyms and modifier map of the core
+    /// keyboard as well as the names of its keys, key types and groups.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keymap is invalid, e.g. if it lacks one of the four key types every keymap has
+    /// to start with (`ONE_LEVEL`, `TWO_LEVEL`, `ALPHABETIC` and `KEYPAD`), or if the server rejects it.
+    #[inline]
+    pub fn upload<Dpy: Display + ?Sized>(&self, display: &mut Dpy) -> crate::Result {
+        let order = self.upload_order()?;
+        let set_map = self.set_map_request(&order)?;
+
+        let reply = display.exchange_request(use_extension_request())?;
+        if !reply.supported {
+            return Err(BreadError::ExtensionNotPresent("XKEYBOARD".into()));
+        }
+
+        // intern all of the names at once
+        let names = self.names_to_intern(&order);
+        let tokens = names
+            .iter()
+            .map(|name| display.intern_atom(*name, false))
+            .collect::<crate::Result<Vec<_>>>()?;
+        let atoms = names
+            .into_iter()
+            .zip(tokens)
+            .map(|(name, tok)| Ok((name, display.resolve_request(tok)?.atom)))
+            .collect::<crate::Result<BTreeMap<_, _>>>()?;
+
+        display.exchange_request(set_map)?;
+        display.exchange_request(self.set_names_request(&order, &atoms)?)
+    }
+
+    /// Upload this keymap to the server, async redox.
+    #[cfg(feature = "async-core")]
+    #[inline]
+    pub async fn upload_async<Dpy: AsyncDisplay + ?Sized>(
+        &self,
+        display: &mut Dpy,
+    ) -> crate::Result {
+        let order = self.upload_order()?;
+        let set_map = self.set_map_request(&order)?;
+
+        let reply = display
+            .exchange_request_async(use_extension_request())
+            .await?;
+        if !reply.supported {
+            return Err(BreadError::ExtensionNotPresent("XKEYBOARD".into()));
+        }
+
+        let names = self.names_to_intern(&order);
+        let mut tokens = Vec::with_capacity(names.len());
+        for name in &names {
+            tokens.push(display.intern_atom_async(*name, false).await?);
+        }
+        let mut atoms = BTreeMap::new();
+        for (name, tok) in names.into_iter().zip(tokens) {
+            atoms.insert(name, display.resolve_request_async(tok).await?.atom);
+        }
+
+        display.exchange_request_async(set_map).await?;
+        display
+            .exchange_request_async(self.set_names_request(&order, &atoms)?)
+            .await
+    }
+
+    /// Parse a keymap from the `xkb_keymap { ... }` text format, as written by `xkbcomp` or by this type's
+    /// `Display` implementation.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BreadError::Msg` describing the problem and the line it occurred on if the text isn't a valid
+    /// keymap.
+    #[inline]
+    pub fn parse(text: &str) -> crate::Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            pos: 0,
+            builder: KeymapBuilder::default(),
+        };
+        parser.keymap()?;
+        parser.builder.finish()
+    }
+
+    /// Build the description from a parsed `GetMap` reply, the names from the `GetNames` reply and the names
+    /// of the atoms in it.
+    #[inline]
+    fn from_parts(
+        keymap: &XkbKeymap,
+        names: &KeymapNames,
+        atom_names: &BTreeMap<Atom, String>,
+    ) -> Self {
+        let name = |atom: Atom| atom_names.get(&atom).cloned().unwrap_or_default();
+
+        let key_types = keymap
+            .types
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| {
+                let level_names = names.level_names.get(i);
+                XkbKeyTypeDescription {
+                    name: names.type_names.get(i).copied().map_or_else(
+                        || format!("TYPE{}", i),
+                        |atom| match name(atom) {
+                            name if name.is_empty() => format!("TYPE{}", i),
+                            name => name,
+                        },
+                    ),
+                    modifiers: ty.mods_mask,
+                    num_levels: ty.num_levels,
+                    map: ty.map.to_vec(),
+                    preserve: ty.preserve.to_vec(),
+                    level_names: (0..usize::from(ty.num_levels))
+                        .map(|level| {
+                            level_names
+                                .and_then(|names| names.get(level))
+                                .map_or_else(String::new, |atom| name(*atom))
+                        })
+                        .collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let keys = (keymap.min_keycode..=keymap.max_keycode)
+            .filter_map(|keycode| {
+                let key_name = keycode
+                    .checked_sub(names.first_key)
+                    .and_then(|idx| names.key_names.get(usize::from(idx)))
+                    .map(|name| {
+                        name.iter()
+                            .take_while(|b| **b != 0)
+                            .map(|b| char::from(*b))
+                            .collect::<String>()
+                    })
+                    .unwrap_or_default();
+                let modifiers = keymap.modifier_map(keycode).inner as u8;
+
+                let info = keymap.key_info(keycode).unwrap_or_default();
+                let width = usize::from(info.width);
+                let groups = (0..usize::from(info.num_groups()))
+                    .map(|group| {
+                        let ty = usize::from(info.types[group]);
+                        let num_levels = keymap
+                            .types
+                            .get(ty)
+                            .map_or(width, |ty| usize::from(ty.num_levels));
+                        let start = info.offset + group * width;
+                        let mut keysyms =
+                            keymap.keysyms[start..start + width.min(num_levels)].to_vec();
+                        // levels without keysyms at the end don't need to be listed
+                        while keysyms.len() > 1 && keysyms.last() == Some(&0) {
+                            keysyms.pop();
+                        }
+
+                        XkbKeyGroup {
+                            key_type: key_types
+                                .get(ty)
+                                .map(|ty| ty.name.clone())
+                                .unwrap_or_default(),
+                            keysyms,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                if key_name.is_empty() && groups.is_empty() && modifiers == 0 {
+                    return None;
+                }
+
+                Some(XkbKeyDescription {
+                    keycode,
+                    name: if key_name.is_empty() {
+                        format!("I{}", keycode)
+                    } else {
+                        key_name
+                    },
+                    groups,
+                    out_of_range: match info.group_info & 0xC0 {
+                        0x40 => XkbGroupsOutOfRange::Clamp,
+                        0x80 => XkbGroupsOutOfRange::Redirect((info.group_info >> 4) & 0x03),
+                        _ => XkbGroupsOutOfRange::Wrap,
+                    },
+                    modifiers,
+                })
+            })
+            .collect();
+
+        Self {
+            keycodes_name: name(names.keycodes),
+            types_name: name(names.types),
+            compat_name: name(names.compat),
+            symbols_name: name(names.symbols),
+            min_keycode: keymap.min_keycode,
+            max_keycode: keymap.max_keycode,
+            key_types,
+            keys,
+            group_names: names.group_names.iter().map(|atom| name(*atom)).collect(),
+        }
+    }
+
+    /// Get the key with the given keycode.
+    #[inline]
+    #[must_use]
+    pub fn key(&self, keycode: Keycode) -> Option<&XkbKeyDescription> {
+        self.keys.iter().find(|key| key.keycode == keycode)
+    }
+
+    /// Get the key type with the given name.
+    #[inline]
+    #[must_use]
+    pub fn key_type(&self, name: &str) -> Option<&XkbKeyTypeDescription> {
+        self.key_types.iter().find(|ty| ty.name == name)
+    }
+
+    /// The order the key types are uploaded in; the server requires the four standard types to come first.
+    #[inline]
+    fn upload_order(&self) -> crate::Result<Vec<usize>> {
+        let mut order = REQUIRED_TYPES
+            .iter()
+            .map(|name| {
+                self.key_types
+                    .iter()
+                    .position(|ty| ty.name == *name)
+                    .ok_or_else(|| {
+                        BreadError::Msg(format!("Keymap is missing the {} key type", name))
+                    })
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+        let rest = (0..self.key_types.len())
+            .filter(|i| !order.contains(i))
+            .collect::<Vec<_>>();
+        order.extend(rest);
+
+        if order.len() > usize::from(u8::MAX) {
+            return Err(BreadError::StaticMsg("Keymap has too many key types"));
+        }
+        Ok(order)
+    }
+
+    /// All of the names that need to be interned before the names can be uploaded.
+    #[inline]
+    fn names_to_intern(&self, order: &[usize]) -> Vec<&str> {
+        let mut names = vec![
+            self.keycodes_name.as_str(),
+            self.symbols_name.as_str(),
+            self.types_name.as_str(),
+            self.compat_name.as_str(),
+        ];
+        for &i in order {
+            let ty = &self.key_types[i];
+            names.push(&ty.name);
+            names.extend(ty.level_names.iter().map(String::as_str));
+        }
+        names.extend(self.group_names.iter().take(MAX_GROUPS).map(String::as_str));
+
+        names.retain(|name| !name.is_empty());
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// Encode the `SetMap` request that uploads the key types, keysyms and modifier map.
+    #[allow(clippy::too_many_lines)]
+    #[inline]
+    fn set_map_request(&self, order: &[usize]) -> crate::Result<RawSetMapRequest> {
+        let type_index = |name: &str| {
+            order
+                .iter()
+                .position(|&i| self.key_types[i].name == name)
+                .ok_or_else(|| BreadError::Msg(format!("Unknown key type \"{}\"", name)))
+        };
+
+        let mut types = RequestWriter::default();
+        for &i in order {
+            let ty = &self.key_types[i];
+            types.u8(ty.modifiers);
+            types.u8(ty.modifiers);
+            types.u16(0); // virtual mods
+            types.u8(ty.num_levels);
+            types.u8(ty.map.len() as u8);
+            types.u8((!ty.preserve.is_empty()).into());
+            types.u8(0);
+            for (mods, level) in &ty.map {
+                types.u8(*level);
+                types.u8(mods & ty.modifiers);
+                types.u16(0);
+            }
+            if !ty.preserve.is_empty() {
+                for (mods, _) in &ty.map {
+                    let preserved = ty
+                        .preserve
+                        .iter()
+                        .find(|(entry, _)| entry == mods)
+                        .map_or(0, |(_, preserved)| *preserved);
+                    types.u8(preserved);
+                    types.u8(preserved);
+                    types.u16(0);
+                }
+            }
+        }
+
+        let mut syms = RequestWriter::default();
+        let mut total_syms = 0usize;
+        let mut modmap = RequestWriter::default();
+        let mut total_modmap_keys = 0u8;
+        for keycode in self.min_keycode..=self.max_keycode {
+            let key = self.key(keycode);
+            let groups = key.map_or(&[][..], |key| &key.groups);
+            if groups.len() > MAX_GROUPS {
+                return Err(BreadError::Msg(format!(
+                    "Key <{}> has more than {} groups",
+                    key.map_or("", |key| &key.name),
+                    MAX_GROUPS
+                )));
+            }
+
+            let mut kt_index = [0; MAX_GROUPS];
+            let mut width = 0;
+            for (group, slot) in groups.iter().zip(kt_index.iter_mut()) {
+                let index = type_index(&group.key_type)?;
+                *slot = index as u8;
+                width = width
+                    .max(self.key_types[order[index]].num_levels)
+                    .max(group.keysyms.len() as u8);
+            }
+
+            let mut group_info = groups.len() as u8;
+            match key.map(|key| key.out_of_range) {
+                Some(XkbGroupsOutOfRange::Clamp) => group_info |= 0x40,
+                Some(XkbGroupsOutOfRange::Redirect(group)) => {
+                    group_info |= 0x80 | ((group & 0x03) << 4);
+                }
+                _ => {}
+            }
+
+            kt_index.iter().for_each(|index| syms.u8(*index));
+            syms.u8(group_info);
+            syms.u8(width);
+            let n_syms = usize::from(width) * groups.len();
+            syms.u16(n_syms as u16);
+            for group in groups {
+                (0..usize::from(width))
+                    .for_each(|level| syms.u32(group.keysyms.get(level).copied().unwrap_or(0)));
+            }
+            total_syms += n_syms;
+
+            if let Some(mods) = key.map(|key| key.modifiers).filter(|mods| *mods != 0) {
+                modmap.u8(keycode);
+                modmap.u8(mods);
+                total_modmap_keys += 1;
+            }
+        }
+        modmap.pad();
+
+        let n_keys = self.n_keys()?;
+        let mut body = RequestWriter::default();
+        body.u16(CORE_KEYBOARD);
+        // key types, key syms and the modifier map
+        body.u16(0b111);
+        body.u16(SET_MAP_RESIZE_TYPES | SET_MAP_RECOMPUTE_ACTIONS);
+        body.u8(self.min_keycode);
+        body.u8(self.max_keycode);
+        body.u8(0); // first type
+        body.u8(order.len() as u8);
+        body.u8(self.min_keycode); // first key sym
+        body.u8(n_keys);
+        body.u16(total_syms as u16);
+        // actions, behaviors and explicit components
+        (0..10).for_each(|_| body.u8(0));
+        body.u8(self.min_keycode); // first modmap key
+        body.u8(n_keys);
+        body.u8(total_modmap_keys);
+        // virtual modifier map and virtual modifiers
+        (0..5).for_each(|_| body.u8(0));
+
+        body.bytes.extend(types.bytes);
+        body.bytes.extend(syms.bytes);
+        body.bytes.extend(modmap.bytes);
+
+        Ok(RawSetMapRequest {
+            body: body.bytes,
+            ..Default::default()
+        })
+    }
+
+    /// The number of keycodes this keymap covers. The requests that upload a keymap can't describe all 256 of
+    /// them.
+    #[inline]
+    fn n_keys(&self) -> crate::Result<u8> {
+        self.max_keycode
+            .saturating_sub(self.min_keycode)
+            .checked_add(1)
+            .ok_or_else(|| {
+                BreadError::Msg("Cannot upload a keymap that covers all 256 keycodes".into())
+            })
+    }
+
+    /// Encode the `SetNames` request that uploads the names of the keymap, its key types and its keys.
+    #[inline]
+    fn set_names_request(
+        &self,
+        order: &[usize],
+        atoms: &BTreeMap<&str, Atom>,
+    ) -> crate::Result<RawSetNamesRequest> {
+        let atom = |name: &str| atoms.get(name).copied().unwrap_or_default().xid;
+        let group_names = &self.group_names[..self.group_names.len().min(MAX_GROUPS)];
+        let n_keys = self.n_keys()?;
+
+        let mut which = NameDetail::default();
+        which
+            .set_keycodes(true)
+            .set_symbols(true)
+            .set_types(true)
+            .set_compat(true)
+            .set_key_type_names(true)
+            .set_kt_level_names(true)
+            .set_group_names(true)
+            .set_key_names(true);
+
+        let mut body = RequestWriter::default();
+        body.u16(CORE_KEYBOARD);
+        body.u16(0); // virtual mods
+        body.u32(which.inner);
+        body.u8(0); // first type
+        body.u8(order.len() as u8);
+        body.u8(0); // first type with level names
+        body.u8(order.len() as u8);
+        body.u32(0); // indicators
+        body.u8((1u8 << group_names.len()).wrapping_sub(1));
+        body.u8(0); // radio groups
+        body.u8(self.min_keycode);
+        body.u8(n_keys);
+        body.u8(0); // key aliases
+        body.u8(0);
+        body.u16(
+            order
+                .iter()
+                .map(|&i| u16::from(self.key_types[i].num_levels))
+                .sum(),
+        );
+
+        body.u32(atom(&self.keycodes_name));
+        body.u32(atom(&self.symbols_name));
+        body.u32(atom(&self.types_name));
+        body.u32(atom(&self.compat_name));
+        order
+            .iter()
+            .for_each(|&i| body.u32(atom(&self.key_types[i].name)));
+        order
+            .iter()
+            .for_each(|&i| body.u8(self.key_types[i].num_levels));
+        body.pad();
+        for &i in order {
+            let ty = &self.key_types[i];
+            (0..usize::from(ty.num_levels)).for_each(|level| {
+                body.u32(ty.level_names.get(level).map_or(0, |name| atom(name)));
+            });
+        }
+        group_names.iter().for_each(|name| body.u32(atom(name)));
+        for keycode in self.min_keycode..=self.max_keycode {
+            let mut name = [0; 4];
+            if let Some(key) = self.key(keycode) {
+                name.iter_mut()
+                    .zip(key.name.bytes())
+                    .for_each(|(slot, b)| *slot = b);
+            }
+            name.iter().for_each(|b| body.u8(*b));
+        }
+
+        Ok(RawSetNamesRequest {
+            body: body.bytes,
+            ..Default::default()
+        })
+    }
+}
+
+impl fmt::Display for XkbKeymapDescription {
+    #[allow(clippy::too_many_lines)]
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("xkb_keymap {\n")?;
+
+        writeln!(f, "\txkb_keycodes {} {{", Quoted(&self.keycodes_name))?;
+        writeln!(f, "\t\tminimum = {};", self.min_keycode)?;
+        writeln!(f, "\t\tmaximum = {};", self.max_keycode)?;
+        for key in &self.keys {
+            writeln!(f, "\t\t<{}> = {};", key.name, key.keycode)?;
+        }
+        f.write_str("\t};\n\n")?;
+
+        writeln!(f, "\txkb_types {} {{", Quoted(&self.types_name))?;
+        for ty in &self.key_types {
+            writeln!(f, "\t\ttype {} {{", Quoted(&ty.name))?;
+            writeln!(f, "\t\t\tmodifiers = {};", Modifiers(ty.modifiers))?;
+            for (mods, level) in &ty.map {
+                writeln!(f, "\t\t\tmap[{}] = Level{};", Modifiers(*mods), level + 1)?;
+            }
+            for (mods, preserved) in &ty.preserve {
+                writeln!(
+                    f,
+                    "\t\t\tpreserve[{}] = {};",
+                    Modifiers(*mods),
+                    Modifiers(*preserved)
+                )?;
+            }
+
+            // the number of levels is implied by the map and the level names, so make sure the last level
+            // is mentioned if it wouldn't be otherwise
+            let implied_levels = ty.map.iter().map(|(_, level)| level + 1).max().unwrap_or(1);
+            for (level, name) in ty.level_names.iter().enumerate() {
+                let last = level + 1 == usize::from(ty.num_levels);
+                if !name.is_empty() || (last && ty.num_levels > implied_levels) {
+                    writeln!(
+                        f,
+                        "\t\t\tlevel_name[Level{}] = {};",
+                        level + 1,
+                        Quoted(name)
+                    )?;
+                }
+            }
+            f.write_str("\t\t};\n")?;
+        }
+        f.write_str("\t};\n\n")?;
+
+        writeln!(f, "\txkb_compatibility {} {{", Quoted(&self.compat_name))?;
+        f.write_str("\t};\n\n")?;
+
+        writeln!(f, "\txkb_symbols {} {{", Quoted(&self.symbols_name))?;
+        for (group, name) in self.group_names.iter().enumerate() {
+            writeln!(f, "\t\tname[Group{}] = {};", group + 1, Quoted(name))?;
+        }
+        for key in &self.keys {
+            if key.groups.is_empty() && key.out_of_range == XkbGroupsOutOfRange::Wrap {
+                continue;
+            }
+
+            write!(f, "\t\tkey <{}> {{ ", key.name)?;
+            let mut first = true;
+            let mut separator = |f: &mut fmt::Formatter<'_>| {
+                if first {
+                    first = false;
+                    Ok(())
+                } else {
+                    f.write_str(", ")
+                }
+            };
+            match key.out_of_range {
+                XkbGroupsOutOfRange::Wrap => {}
+                XkbGroupsOutOfRange::Clamp => {
+                    separator(f)?;
+                    f.write_str("groupsClamp")?;
+                }
+                XkbGroupsOutOfRange::Redirect(group) => {
+                    separator(f)?;
+                    write!(f, "groupsRedirect = Group{}", group + 1)?;
+                }
+            }
+            for (i, group) in key.groups.iter().enumerate() {
+                separator(f)?;
+                write!(
+                    f,
+                    "type[Group{}] = {}, symbols[Group{}] = [ ",
+                    i + 1,
+                    Quoted(&group.key_type),
+                    i + 1
+                )?;
+                for (j, keysym) in group.keysyms.iter().enumerate() {
+                    if j != 0 {
+                        f.write_str(", ")?;
+                    }
+                    f.write_str(&keysym_text(*keysym))?;
+                }
+                f.write_str(" ]")?;
+            }
+            f.write_str(" };\n")?;
+        }
+        for (bit, modifier) in MODIFIER_NAMES.iter().enumerate() {
+            let mut keys = self
+                .keys
+                .iter()
+                .filter(|key| key.modifiers & (1 << bit) != 0)
+                .peekable();
+            if keys.peek().is_none() {
+                continue;
+            }
+
+            write!(f, "\t\tmodifier_map {} {{ ", modifier)?;
+            for (i, key) in keys.enumerate() {
+                if i != 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "<{}>", key.name)?;
+            }
+            f.write_str(" };\n")?;
+        }
+        f.write_str("\t};\n};\n")
+    }
+}
+
+impl FromStr for XkbKeymapDescription {
+    type Err = BreadError;
+
+    #[inline]
+    fn from_str(s: &str) -> crate::Result<Self> {
+        Self::parse(s)
+    }
+}
+
+/// Writes a string in quotes, escaping it as needed.
+struct Quoted<'a>(&'a str);
+
+impl<'a> fmt::Display for Quoted<'a> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+/// Writes a set of real modifiers, e.g. `Shift+Mod5`.
+struct Modifiers(u8);
+
+impl fmt::Display for Modifiers {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == 0 {
+            return f.write_str("none");
+        }
+
+        let mut first = true;
+        for (bit, name) in MODIFIER_NAMES.iter().enumerate() {
+            if self.0 & (1 << bit) != 0 {
+                if !first {
+                    f.write_str("+")?;
+                }
+                first = false;
+                f.write_str(name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The textual form of a keysym: its name if it has one, and its code otherwise.
+#[inline]
+fn keysym_text(keysym: Keysym) -> Cow<'static, str> {
+    match keysym {
+        0 => Cow::Borrowed("NoSymbol"),
+        keysym => match keysym_name(keysym) {
+            Some(name) => Cow::Borrowed(name),
+            None if (0x0100_0100..=0x0110_ffff).contains(&keysym) => {
+                Cow::Owned(format!("U{:04X}", keysym - 0x0100_0000))
+            }
+            None => Cow::Owned(format!("{:#x}", keysym)),
+        },
+    }
+}
+
+#[inline]
+fn keymap_names_request() -> GetNamesRequest {
+    let mut which = NameDetail::default();
+    which
+        .set_keycodes(true)
+        .set_symbols(true)
+        .set_types(true)
+        .set_compat(true)
+        .set_key_type_names(true)
+        .set_kt_level_names(true)
+        .set_group_names(true)
+        .set_key_names(true);
+
+    GetNamesRequest {
+        device_spec: CORE_KEYBOARD,
+        which,
+        ..Default::default()
+    }
+}
+
+/// Parse the raw bytes of the `GetMap` and `GetNames` replies.
+#[inline]
+fn parse_replies(map: &[u8], names: &[u8]) -> crate::Result<(XkbKeymap, KeymapNames)> {
+    let keymap = parse_map(map).ok_or(BreadError::BadObjectRead(Some("GetMapReply")))?;
+    let names =
+        parse_keymap_names(names).ok_or(BreadError::BadObjectRead(Some("GetNamesReply")))?;
+    Ok((keymap, names))
+}
+
+/// The names in a `GetNames` reply, before their atoms have been resolved.
+#[derive(Debug, Default)]
+struct KeymapNames {
+    keycodes: Atom,
+    symbols: Atom,
+    types: Atom,
+    compat: Atom,
+    type_names: Vec<Atom>,
+    level_names: Vec<Vec<Atom>>,
+    group_names: Vec<Atom>,
+    first_key: Keycode,
+    key_names: Vec<[u8; 4]>,
+}
+
+impl KeymapNames {
+    /// The distinct atoms that need to be resolved.
+    #[inline]
+    fn atoms(&self) -> Vec<Atom> {
+        let mut atoms = vec![self.keycodes, self.symbols, self.types, self.compat];
+        atoms.extend(&self.type_names);
+        self.level_names
+            .iter()
+            .for_each(|names| atoms.extend(names));
+        atoms.extend(&self.group_names);
+
+        atoms.retain(|atom| atom.xid != 0);
+        atoms.sort_unstable();
+        atoms.dedup();
+        atoms
+    }
+}
+
+/// Parse a `GetNames` reply.
+#[inline]
+fn parse_keymap_names(bytes: &[u8]) -> Option<KeymapNames> {
+    #[inline]
+    fn atom(reader: &mut ReplyReader<'_>) -> Option<Atom> {
+        reader.u32().map(Atom::const_from_xid)
+    }
+
+    #[inline]
+    fn atoms(reader: &mut ReplyReader<'_>, count: usize) -> Option<Vec<Atom>> {
+        (0..count).map(|_| atom(reader)).collect()
+    }
+
+    let mut header = ReplyReader::new(bytes, 8);
+    let which = NameDetail {
+        inner: header.u32()?,
+    };
+    header.skip(2)?; // min and max keycode
+    let n_types = usize::from(header.u8()?);
+    let groups = header.u8()?;
+    let virtual_mods = header.u16()?;
+    let first_key = header.u8()?;
+    let n_keys = usize::from(header.u8()?);
+    let indicators = header.u32()?;
+
+    let mut names = KeymapNames {
+        first_key,
+        ..Default::default()
+    };
+    let mut reader = ReplyReader::new(bytes, 32);
+    if which.keycodes() {
+        names.keycodes = atom(&mut reader)?;
+    }
+    if which.geometry() {
+        reader.skip(4)?;
+    }
+    if which.symbols() {
+        names.symbols = atom(&mut reader)?;
+    }
+    if which.phys_symbols() {
+        reader.skip(4)?;
+    }
+    if which.types() {
+        names.types = atom(&mut reader)?;
+    }
+    if which.compat() {
+        names.compat = atom(&mut reader)?;
+    }
+    if which.key_type_names() {
+        names.type_names = atoms(&mut reader, n_types)?;
+    }
+    if which.kt_level_names() {
+        let counts = (0..n_types)
+            .map(|_| reader.u8())
+            .collect::<Option<Vec<_>>>()?;
+        reader.pad()?;
+        names.level_names = counts
+            .into_iter()
+            .map(|count| atoms(&mut reader, usize::from(count)))
+            .collect::<Option<Vec<_>>>()?;
+    }
+    if which.indicator_names() {
+        reader.skip(indicators.count_ones() as usize * 4)?;
+    }
+    if which.virtual_mod_names() {
+        reader.skip(virtual_mods.count_ones() as usize * 4)?;
+    }
+    if which.group_names() {
+        names.group_names = atoms(&mut reader, groups.count_ones() as usize)?;
+    }
+    if which.key_names() {
+        names.key_names = (0..n_keys)
+            .map(|_| {
+                let mut name = [0; 4];
+                for b in &mut name {
+                    *b = reader.u8()?;
+                }
+                Some(name)
+            })
+            .collect::<Option<Vec<_>>>()?;
+    }
+
+    Some(names)
+}
+
+/// Writes values into the body of a request.
+#[derive(Debug, Default)]
+struct RequestWriter {
+    bytes: Vec<u8>,
+}
+
+impl RequestWriter {
+    #[inline]
+    fn u8(&mut self, val: u8) {
+        self.bytes.push(val);
+    }
+
+    #[inline]
+    fn u16(&mut self, val: u16) {
+        self.bytes.extend_from_slice(&val.to_ne_bytes());
+    }
+
+    #[inline]
+    fn u32(&mut self, val: u32) {
+        self.bytes.extend_from_slice(&val.to_ne_bytes());
+    }
+
+    #[inline]
+    fn pad(&mut self) {
+        let len = self.bytes.len();
+        self.bytes.resize((len + 3) & !3, 0);
+    }
+}
+
+/// Declares an XKB request whose body is encoded by hand. The generated `SetMap` and `SetNames` requests treat
+/// XKB's one-byte modifier masks as two bytes wide, so they can't be used.
+macro_rules! raw_xkb_request {
+    ($(#[$meta: meta])* $name: ident, $opcode: expr) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Default)]
+        struct $name {
+            req_type: u8,
+            length: u16,
+            body: Vec<u8>,
+        }
+
+        impl AsByteSequence for $name {
+            #[inline]
+            fn size(&self) -> usize {
+                4 + self.body.len()
+            }
+
+            #[inline]
+            fn as_bytes(&self, bytes: &mut [u8]) -> usize {
+                self.req_type.as_bytes(&mut bytes[0..]);
+                self.length.as_bytes(&mut bytes[2..]);
+                bytes[4..4 + self.body.len()].copy_from_slice(&self.body);
+                4 + self.body.len()
+            }
+
+            #[inline]
+            fn from_bytes(bytes: &[u8]) -> Option<(Self, usize)> {
+                let (req_type, _) = u8::from_bytes(bytes)?;
+                let (length, _) = u16::from_bytes(bytes.get(2..)?)?;
+                Some((
+                    Self {
+                        req_type,
+                        length,
+                        body: bytes[4..].to_vec(),
+                    },
+                    bytes.len(),
+                ))
+            }
+        }
+
+        impl Request for $name {
+            const OPCODE: u8 = $opcode;
+            const EXTENSION: Option<&'static str> = Some("XKEYBOARD");
+            const REPLY_EXPECTS_FDS: bool = false;
+            type Reply = ();
+        }
+    };
+}
+
+raw_xkb_request! {
+    /// The `SetMap` request.
+    RawSetMapRequest, 9
+}
+
+raw_xkb_request! {
+    /// The `SetNames` request.
+    RawSetNamesRequest, 18
+}
+
+/// A token of the keymap text format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token<'a> {
+    /// An identifier, keyword, keysym name or number.
+    Ident(&'a str),
+    /// A string literal, with its escapes processed.
+    Str(String),
+    /// A key name, without the angle brackets.
+    KeyName(&'a str),
+    /// Any other character.
+    Punct(char),
+}
+
+/// Split the text into tokens, along with the lines they're on.
+#[inline]
+fn tokenize(text: &str) -> crate::Result<Vec<(Token<'_>, usize)>> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut rest = text;
+
+    loop {
+        let trimmed = rest.trim_start_matches(|c: char| c.is_whitespace() && c != '\n');
+        let mut chars = trimmed.chars();
+        let c = match chars.next() {
+            Some(c) => c,
+            None => break,
+        };
+
+        rest = if c == '\n' {
+            line += 1;
+            chars.as_str()
+        } else if c == '#' || trimmed.starts_with("//") {
+            // comments run until the end of the line
+            trimmed.find('\n').map_or("", |end| &trimmed[end..])
+        } else if trimmed.starts_with("/*") {
+            let end = trimmed
+                .find("*/")
+                .ok_or_else(|| parse_error(line, "unterminated comment"))?;
+            line += trimmed[..end].matches('\n').count();
+            &trimmed[end + 2..]
+        } else if c == '"' {
+            let (string, after) = parse_string(chars.as_str())
+                .ok_or_else(|| parse_error(line, "unterminated string"))?;
+            tokens.push((Token::Str(string), line));
+            after
+        } else if c == '<' {
+            let name_end = chars
+                .as_str()
+                .find(['>', '\n'])
+                .filter(|end| chars.as_str()[*end..].starts_with('>'))
+                .ok_or_else(|| parse_error(line, "unterminated key name"))?;
+            tokens.push((Token::KeyName(&chars.as_str()[..name_end]), line));
+            &chars.as_str()[name_end + 1..]
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let end = trimmed
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(trimmed.len());
+            tokens.push((Token::Ident(&trimmed[..end]), line));
+            &trimmed[end..]
+        } else {
+            tokens.push((Token::Punct(c), line));
+            chars.as_str()
+        };
+    }
+
+    Ok(tokens)
+}
+
+/// Parse a string literal, after the opening quote. Returns the string and the text after the closing quote.
+#[inline]
+fn parse_string(text: &str) -> Option<(String, &str)> {
+    let mut string = String::new();
+    let mut chars = text.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((string, &text[i + 1..])),
+            '\n' => return None,
+            '\\' => match chars.next()?.1 {
+                'n' => string.push('\n'),
+                't' => string.push('\t'),
+                'r' => string.push('\r'),
+                'e' => string.push('\x1b'),
+                c if c.is_digit(8) => {
+                    // up to three octal digits
+                    let mut code = c.to_digit(8)?;
+                    for _ in 0..2 {
+                        match chars.clone().next() {
+                            Some((_, c)) if c.is_digit(8) => {
+                                chars.next();
+                                code = code * 8 + c.to_digit(8)?;
+                            }
+                            _ => break,
+                        }
+                    }
+                    string.push(core::char::from_u32(code)?);
+                }
+                c => string.push(c),
+            },
+            c => string.push(c),
+        }
+    }
+
+    None
+}
+
+#[inline]
+fn parse_error(line: usize, msg: &str) -> BreadError {
+    BreadError::Msg(format!("Invalid XKB keymap on line {}: {}", line, msg))
+}
+
+/// The parts of a keymap as they're parsed, before they're checked and put together.
+#[derive(Debug, Default)]
+struct KeymapBuilder {
+    desc: XkbKeymapDescription,
+    minimum: Option<Keycode>,
+    maximum: Option<Keycode>,
+    keys: BTreeMap<Keycode, XkbKeyDescription>,
+    key_codes: BTreeMap<String, Keycode>,
+    // virtual modifiers and the real modifiers they're bound to
+    virtual_mods: Vec<(String, Option<u8>)>,
+}
+
+impl KeymapBuilder {
+    #[inline]
+    fn finish(self) -> crate::Result<XkbKeymapDescription> {
+        let Self {
+            mut desc,
+            minimum,
+            maximum,
+            keys,
+            ..
+        } = self;
+        desc.min_keycode = minimum.or_else(|| keys.keys().next().copied()).unwrap_or(8);
+        desc.max_keycode = maximum
+            .or_else(|| keys.keys().next_back().copied())
+            .unwrap_or(255);
+
+        for key in keys.values() {
+            for group in &key.groups {
+                if !desc.key_types.iter().any(|ty| ty.name == group.key_type) {
+                    return Err(BreadError::Msg(format!(
+                        "Invalid XKB keymap: key <{}> uses the undefined key type \"{}\"",
+                        key.name, group.key_type
+                    )));
+                }
+            }
+        }
+
+        desc.keys = keys.into_values().collect();
+        Ok(desc)
+    }
+}
+
+/// A recursive descent parser for the keymap text format.
+struct Parser<'a> {
+    tokens: Vec<(Token<'a>, usize)>,
+    pos: usize,
+    builder: KeymapBuilder,
+}
+
+impl<'a> Parser<'a> {
+    #[inline]
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    #[inline]
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or_else(|| self.tokens.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    #[inline]
+    fn error(&self, msg: &str) -> BreadError {
+        parse_error(self.line(), msg)
+    }
+
+    #[inline]
+    fn next(&mut self) -> crate::Result<Token<'a>> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .map(|(token, _)| token.clone())
+            .ok_or_else(|| self.error("unexpected end of file"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    #[inline]
+    fn eat_punct(&mut self, c: char) -> bool {
+        if self.peek() == Some(&Token::Punct(c)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    #[inline]
+    fn expect_punct(&mut self, c: char) -> crate::Result {
+        if self.eat_punct(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", c)))
+        }
+    }
+
+    #[inline]
+    fn expect_ident(&mut self) -> crate::Result<&'a str> {
+        if let Token::Ident(ident) = self.next()? {
+            Ok(ident)
+        } else {
+            self.pos -= 1;
+            Err(self.error("expected an identifier"))
+        }
+    }
+
+    #[inline]
+    fn expect_string(&mut self) -> crate::Result<String> {
+        if let Token::Str(string) = self.next()? {
+            Ok(string)
+        } else {
+            self.pos -= 1;
+            Err(self.error("expected a string"))
+        }
+    }
+
+    #[inline]
+    fn expect_key_name(&mut self) -> crate::Result<&'a str> {
+        if let Token::KeyName(name) = self.next()? {
+            Ok(name)
+        } else {
+            self.pos -= 1;
+            Err(self.error("expected a key name"))
+        }
+    }
+
+    #[inline]
+    fn expect_number(&mut self) -> crate::Result<u32> {
+        let ident = self.expect_ident()?;
+        parse_number(ident).ok_or_else(|| self.error("expected a number"))
+    }
+
+    /// Skip tokens until the end of the current statement, including the semicolon.
+    #[inline]
+    fn skip_statement(&mut self) -> crate::Result {
+        let mut depth = 0usize;
+        loop {
+            match self.next()? {
+                Token::Punct(';') if depth == 0 => return Ok(()),
+                Token::Punct('{' | '[' | '(') => depth += 1,
+                Token::Punct('}' | ']' | ')') => {
+                    depth = depth
+                        .checked_sub(1)
+                        .ok_or_else(|| self.error("unbalanced brackets"))?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Skip tokens until the end of the current item in a key's body, not including the `,` or `}`.
+    #[inline]
+    fn skip_item(&mut self) -> crate::Result {
+        let mut depth = 0usize;
+        loop {
+            match self.peek() {
+                Some(Token::Punct(',' | '}')) if depth == 0 => return Ok(()),
+                Some(Token::Punct('{' | '[' | '(')) => depth += 1,
+                Some(Token::Punct('}' | ']' | ')')) => {
+                    depth = depth
+                        .checked_sub(1)
+                        .ok_or_else(|| self.error("unbalanced brackets"))?;
+                }
+                Some(_) => {}
+                None => return Err(self.error("unexpected end of file")),
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// Parse an optional index in square brackets, e.g. `[Group1]`.
+    #[inline]
+    fn index(&mut self, parse: fn(&str) -> Option<u8>, what: &str) -> crate::Result<Option<u8>> {
+        if !self.eat_punct('[') {
+            return Ok(None);
+        }
+
+        let ident = self.expect_ident()?;
+        let index = parse(ident).ok_or_else(|| self.error(&format!("invalid {}", what)))?;
+        self.expect_punct(']')?;
+        Ok(Some(index))
+    }
+
+    /// Parse a combination of modifiers, e.g. `Shift+LevelThree`. Returns `None` if it contains a virtual
+    /// modifier that isn't bound to a real modifier.
+    #[inline]
+    fn modifiers(&mut self) -> crate::Result<Option<u8>> {
+        let mut mods = Some(0);
+        loop {
+            let name = self.expect_ident()?;
+            let real = if name.eq_ignore_ascii_case("none") {
+                Some(0)
+            } else if name.eq_ignore_ascii_case("all") {
+                Some(0xFF)
+            } else if let Some(bit) = MODIFIER_NAMES
+                .iter()
+                .position(|modifier| modifier.eq_ignore_ascii_case(name))
+            {
+                Some(1 << bit)
+            } else if let Some((_, real)) = self
+                .builder
+                .virtual_mods
+                .iter()
+                .find(|(vmod, _)| vmod.eq_ignore_ascii_case(name))
+            {
+                *real
+            } else {
+                return Err(self.error(&format!("unknown modifier {}", name)));
+            };
+
+            mods = mods.and_then(|mods| real.map(|real| mods | real));
+            if !self.eat_punct('+') && !self.eat_punct('|') {
+                return Ok(mods);
+            }
+        }
+    }
+
+    /// Parse a keymap: either an `xkb_keymap` block, or the sections on their own.
+    #[inline]
+    fn keymap(&mut self) -> crate::Result {
+        if self.peek() == Some(&Token::Ident("xkb_keymap")) {
+            self.pos += 1;
+            if let Some(Token::Str(_)) = self.peek() {
+                self.pos += 1;
+            }
+            self.expect_punct('{')?;
+            while !self.eat_punct('}') {
+                self.section()?;
+            }
+            self.eat_punct(';');
+        }
+
+        while self.peek().is_some() {
+            self.section()?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn section(&mut self) -> crate::Result {
+        // skip flags such as "default" or "partial"
+        let kind = loop {
+            let ident = self.expect_ident()?;
+            if ident.starts_with("xkb_") {
+                break ident;
+            }
+        };
+        let name = match self.peek() {
+            Some(Token::Str(_)) => self.expect_string()?,
+            _ => String::new(),
+        };
+        self.expect_punct('{')?;
+
+        match kind {
+            "xkb_keycodes" => {
+                self.builder.desc.keycodes_name = name;
+                self.block(Self::keycodes_statement)?;
+            }
+            "xkb_types" => {
+                self.builder.desc.types_name = name;
+                self.block(Self::types_statement)?;
+            }
+            "xkb_compatibility" | "xkb_compatibility_map" | "xkb_compat" => {
+                self.builder.desc.compat_name = name;
+                self.block(Self::compat_statement)?;
+            }
+            "xkb_symbols" => {
+                self.builder.desc.symbols_name = name;
+                self.block(Self::symbols_statement)?;
+            }
+            "xkb_geometry" => self.block(Self::skip_statement)?,
+            _ => return Err(self.error(&format!("unknown section {}", kind))),
+        }
+
+        self.eat_punct(';');
+        Ok(())
+    }
+
+    /// Parse statements until the closing brace of a block.
+    #[inline]
+    fn block(&mut self, mut statement: impl FnMut(&mut Self) -> crate::Result) -> crate::Result {
+        while !self.eat_punct('}') {
+            if self.peek() == Some(&Token::Ident("include")) {
+                log::warn!("Ignoring include on line {} of XKB keymap", self.line());
+                self.pos += 1;
+                self.expect_string()?;
+                self.eat_punct(';');
+                continue;
+            }
+            statement(self)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn keycodes_statement(&mut self) -> crate::Result {
+        match self.peek() {
+            Some(Token::KeyName(name)) => {
+                let name = name.to_string();
+                self.pos += 1;
+                self.expect_punct('=')?;
+                let keycode = self.expect_number()?;
+                let keycode =
+                    Keycode::try_from(keycode).map_err(|_| self.error("keycode out of range"))?;
+                self.expect_punct(';')?;
+
+                self.builder.key_codes.insert(name.clone(), keycode);
+                self.builder
+                    .keys
+                    .entry(keycode)
+                    .or_insert_with(|| XkbKeyDescription {
+                        keycode,
+                        name,
+                        ..Default::default()
+                    });
+                Ok(())
+            }
+            Some(Token::Ident(ident @ ("minimum" | "maximum"))) => {
+                let is_min = *ident == "minimum";
+                self.pos += 1;
+                self.expect_punct('=')?;
+                let keycode = self.expect_number()?;
+                let keycode =
+                    Keycode::try_from(keycode).map_err(|_| self.error("keycode out of range"))?;
+                self.expect_punct(';')?;
+
+                if is_min {
+                    self.builder.minimum = Some(keycode);
+                } else {
+                    self.builder.maximum = Some(keycode);
+                }
+                Ok(())
+            }
+            Some(Token::Ident("alias")) => {
+                self.pos += 1;
+                let alias = self.expect_key_name()?;
+                self.expect_punct('=')?;
+                let name = self.expect_key_name()?;
+                self.expect_punct(';')?;
+
+                if let Some(keycode) = self.builder.key_codes.get(name).copied() {
+                    self.builder.key_codes.insert(alias.to_string(), keycode);
+                }
+                Ok(())
+            }
+            _ => self.skip_statement(),
+        }
+    }
+
+    #[inline]
+    fn virtual_modifiers(&mut self) -> crate::Result {
+        self.pos += 1;
+        loop {
+            let name = self.expect_ident()?.to_string();
+            let real = if self.eat_punct('=') {
+                self.modifiers()?
+            } else {
+                None
+            };
+            self.builder.virtual_mods.push((name, real));
+
+            if !self.eat_punct(',') {
+                return self.expect_punct(';');
+            }
+        }
+    }
+
+    #[inline]
+    fn types_statement(&mut self) -> crate::Result {
+        match self.peek() {
+            Some(Token::Ident("virtual_modifiers")) => self.virtual_modifiers(),
+            Some(Token::Ident("type")) => {
+                self.pos += 1;
+                let name = self.expect_string()?;
+                self.expect_punct('{')?;
+
+                let mut ty = XkbKeyTypeDescription {
+                    name,
+                    ..Default::default()
+                };
+                let mut level_names = BTreeMap::new();
+                self.block(|this| this.type_statement(&mut ty, &mut level_names))?;
+                self.eat_punct(';');
+
+                // the number of levels is implied by the levels that are used
+                let modifiers = ty.modifiers;
+                ty.map.iter_mut().for_each(|(mods, _)| *mods &= modifiers);
+                let num_levels = ty
+                    .map
+                    .iter()
+                    .map(|(_, level)| *level)
+                    .chain(level_names.keys().copied())
+                    .max()
+                    .map_or(1, |level| level + 1);
+                ty.num_levels = num_levels;
+                ty.level_names = (0..num_levels)
+                    .map(|level| level_names.remove(&level).unwrap_or_default())
+                    .collect();
+
+                match self
+                    .builder
+                    .desc
+                    .key_types
+                    .iter_mut()
+                    .find(|other| other.name == ty.name)
+                {
+                    Some(other) => *other = ty,
+                    None => self.builder.desc.key_types.push(ty),
+                }
+                Ok(())
+            }
+            _ => self.skip_statement(),
+        }
+    }
+
+    #[inline]
+    fn type_statement(
+        &mut self,
+        ty: &mut XkbKeyTypeDescription,
+        level_names: &mut BTreeMap<u8, String>,
+    ) -> crate::Result {
+        match self.peek() {
+            Some(Token::Ident("modifiers")) => {
+                self.pos += 1;
+                self.expect_punct('=')?;
+                ty.modifiers = self.modifiers()?.unwrap_or(0);
+                self.expect_punct(';')
+            }
+            Some(Token::Ident("map")) => {
+                self.pos += 1;
+                self.expect_punct('[')?;
+                let mods = self.modifiers()?;
+                self.expect_punct(']')?;
+                self.expect_punct('=')?;
+                let level = self.expect_ident()?;
+                let level = parse_level(level).ok_or_else(|| self.error("invalid level"))?;
+                self.expect_punct(';')?;
+
+                // entries with unbound virtual modifiers are inactive
+                if let Some(mods) = mods {
+                    ty.map.push((mods, level));
+                }
+                Ok(())
+            }
+            Some(Token::Ident("preserve")) => {
+                self.pos += 1;
+                self.expect_punct('[')?;
+                let mods = self.modifiers()?;
+                self.expect_punct(']')?;
+                self.expect_punct('=')?;
+                let preserved = self.modifiers()?;
+                self.expect_punct(';')?;
+
+                if let (Some(mods), Some(preserved)) = (mods, preserved) {
+                    ty.preserve.push((mods, preserved));
+                }
+                Ok(())
+            }
+            Some(Token::Ident("level_name")) => {
+                self.pos += 1;
+                let level = self
+                    .index(parse_level, "level")?
+                    .ok_or_else(|| self.error("expected a level"))?;
+                self.expect_punct('=')?;
+                let name = self.expect_string()?;
+                self.expect_punct(';')?;
+
+                level_names.insert(level, name);
+                Ok(())
+            }
+            _ => self.skip_statement(),
+        }
+    }
+
+    #[inline]
+    fn compat_statement(&mut self) -> crate::Result {
+        match self.peek() {
+            Some(Token::Ident("virtual_modifiers")) => self.virtual_modifiers(),
+            _ => self.skip_statement(),
+        }
+    }
+
+    #[inline]
+    fn symbols_statement(&mut self) -> crate::Result {
+        match self.peek() {
+            Some(Token::Ident("name")) => {
+                self.pos += 1;
+                let group = self
+                    .index(parse_group, "group")?
+                    .ok_or_else(|| self.error("expected a group"))?;
+                self.expect_punct('=')?;
+                let name = self.expect_string()?;
+                self.expect_punct(';')?;
+
+                let names = &mut self.builder.desc.group_names;
+                if names.len() <= usize::from(group) {
+                    names.resize(usize::from(group) + 1, String::new());
+                }
+                names[usize::from(group)] = name;
+                Ok(())
+            }
+            Some(Token::Ident("key")) => {
+                self.pos += 1;
+                self.key()
+            }
+            Some(Token::Ident("modifier_map")) => {
+                self.pos += 1;
+                self.modifier_map()
+            }
+            Some(Token::Ident("virtual_modifiers")) => self.virtual_modifiers(),
+            _ => self.skip_statement(),
+        }
+    }
+
+    #[inline]
+    fn key(&mut self) -> crate::Result {
+        let name = self.expect_key_name()?;
+        let keycode = *self
+            .builder
+            .key_codes
+            .get(name)
+            .ok_or_else(|| self.error(&format!("undefined key <{}>", name)))?;
+        self.expect_punct('{')?;
+
+        let mut default_type = None;
+        let mut types: [Option<String>; MAX_GROUPS] = Default::default();
+        let mut symbols: [Option<Vec<Keysym>>; MAX_GROUPS] = Default::default();
+        let mut out_of_range = None;
+        let mut next_group = 0;
+
+        loop {
+            if self.eat_punct('}') {
+                break;
+            }
+            if self.eat_punct(',') {
+                continue;
+            }
+
+            match self.peek() {
+                Some(Token::Punct('[')) => {
+                    let slot = symbols
+                        .get_mut(next_group)
+                        .ok_or_else(|| parse_error(self.line(), "too many groups"))?;
+                    *slot = Some(self.keysyms()?);
+                    next_group += 1;
+                }
+                Some(Token::Ident("type")) => {
+                    self.pos += 1;
+                    let group = self.index(parse_group, "group")?;
+                    self.expect_punct('=')?;
+                    let ty = self.expect_string()?;
+                    match group {
+                        Some(group) => types[usize::from(group)] = Some(ty),
+                        None => default_type = Some(ty),
+                    }
+                }
+                Some(Token::Ident("symbols")) => {
+                    self.pos += 1;
+                    let group = self
+                        .index(parse_group, "group")?
+                        .ok_or_else(|| self.error("expected a group"))?;
+                    self.expect_punct('=')?;
+                    symbols[usize::from(group)] = Some(self.keysyms()?);
+                    next_group = next_group.max(usize::from(group) + 1);
+                }
+                Some(Token::Ident(ident)) if ident.eq_ignore_ascii_case("groupsWrap") => {
+                    self.pos += 1;
+                    out_of_range = Some(XkbGroupsOutOfRange::Wrap);
+                }
+                Some(Token::Ident(ident)) if ident.eq_ignore_ascii_case("groupsClamp") => {
+                    self.pos += 1;
+                    out_of_range = Some(XkbGroupsOutOfRange::Clamp);
+                }
+                Some(Token::Ident(ident)) if ident.eq_ignore_ascii_case("groupsRedirect") => {
+                    self.pos += 1;
+                    self.expect_punct('=')?;
+                    let group = self.expect_ident()?;
+                    let group = parse_group(group).ok_or_else(|| self.error("invalid group"))?;
+                    out_of_range = Some(XkbGroupsOutOfRange::Redirect(group));
+                }
+                // actions, virtual modifiers, repeat and the like
+                _ => self.skip_item()?,
+            }
+        }
+        self.expect_punct(';')?;
+
+        let num_groups = symbols
+            .iter()
+            .rposition(Option::is_some)
+            .map_or(0, |last| last + 1);
+        let mut groups = Vec::with_capacity(num_groups);
+        for (keysyms, ty) in symbols.iter_mut().zip(types.iter_mut()).take(num_groups) {
+            let keysyms = keysyms.take().unwrap_or_else(|| vec![0]);
+            let key_type = match ty.take().or_else(|| default_type.clone()) {
+                Some(ty) => ty,
+                None => automatic_type(&keysyms)
+                    .ok_or_else(|| self.error("no key type fits the key's keysyms"))?
+                    .to_string(),
+            };
+            groups.push(XkbKeyGroup { key_type, keysyms });
+        }
+
+        let key = self
+            .builder
+            .keys
+            .get_mut(&keycode)
+            .expect("Keys are added along with their keycodes");
+        if num_groups != 0 {
+            key.groups = groups;
+        }
+        if let Some(out_of_range) = out_of_range {
+            key.out_of_range = out_of_range;
+        }
+        Ok(())
+    }
+
+    /// Parse a list of keysyms, e.g. `[ a, A ]`.
+    #[inline]
+    fn keysyms(&mut self) -> crate::Result<Vec<Keysym>> {
+        self.expect_punct('[')?;
+        let mut keysyms = Vec::new();
+        if self.eat_punct(']') {
+            return Ok(keysyms);
+        }
+
+        loop {
+            let name = self.expect_ident()?;
+            keysyms.push(parse_keysym(name).unwrap_or_else(|| {
+                log::warn!(
+                    "Unknown keysym {} on line {} of XKB keymap",
+                    name,
+                    self.line()
+                );
+                0
+            }));
+
+            if self.eat_punct(']') {
+                return Ok(keysyms);
+            }
+            self.expect_punct(',')?;
+        }
+    }
+
+    #[inline]
+    fn modifier_map(&mut self) -> crate::Result {
+        let name = self.expect_ident()?;
+        let bit = MODIFIER_NAMES
+            .iter()
+            .position(|modifier| modifier.eq_ignore_ascii_case(name))
+            .ok_or_else(|| self.error(&format!("{} is not a real modifier", name)))?;
+        self.expect_punct('{')?;
+
+        loop {
+            let keycode = match self.next()? {
+                Token::KeyName(key) => self.builder.key_codes.get(key).copied(),
+                // keys may also be given by one of their keysyms
+                Token::Ident(keysym) => parse_keysym(keysym).and_then(|keysym| {
+                    self.builder
+                        .keys
+                        .values()
+                        .find(|key| {
+                            key.groups
+                                .iter()
+                                .any(|group| group.keysyms.contains(&keysym))
+                        })
+                        .map(|key| key.keycode)
+                }),
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.error("expected a key"));
+                }
+            };
+
+            match keycode.and_then(|keycode| self.builder.keys.get_mut(&keycode)) {
+                Some(key) => key.modifiers |= 1 << bit,
+                None => log::warn!(
+                    "Ignoring unknown key in modifier map on line {} of XKB keymap",
+                    self.line()
+                ),
+            }
+
+            if self.eat_punct('}') {
+                break;
+            }
+            self.expect_punct(',')?;
+        }
+        self.expect_punct(';')
+    }
+}
+
+/// Parse a decimal or hexadecimal number.
+#[inline]
+fn parse_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Parse a shift level, e.g. `Level2` or `2`, into a zero-based level.
+#[inline]
+fn parse_level(text: &str) -> Option<u8> {
+    let number = match text.get(..5) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("level") => &text[5..],
+        _ => text,
+    };
+    number.parse::<u8>().ok()?.checked_sub(1)
+}
+
+/// Parse a group, e.g. `Group2` or `2`, into a zero-based group.
+#[inline]
+fn parse_group(text: &str) -> Option<u8> {
+    let number = match text.get(..5) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("group") => &text[5..],
+        _ => text,
+    };
+    match number.parse::<u8>().ok()? {
+        group @ 1..=4 => Some(group - 1),
+        _ => None,
+    }
+}
+
+/// Parse a keysym, either by name or as a number.
+#[inline]
+fn parse_keysym(text: &str) -> Option<Keysym> {
+    if text.eq_ignore_ascii_case("NoSymbol") {
+        Some(0)
+    } else if let Some(keysym) = keysym_from_name(text) {
+        Some(keysym)
+    } else if text.bytes().all(|b| b.is_ascii_digit()) {
+        text.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Pick a key type for a group that doesn't specify one, the same way `xkbcomp` does.
+#[inline]
+fn automatic_type(keysyms: &[Keysym]) -> Option<&'static str> {
+    #[inline]
+    fn is_alphabetic_pair(lower: Keysym, upper: Keysym) -> bool {
+        let (lower_case, upper_case) = convert_case(lower);
+        lower == lower_case && upper == upper_case && lower != upper
+    }
+
+    #[inline]
+    fn is_keypad(keysym: Keysym) -> bool {
+        (0xff80..=0xffbd).contains(&keysym)
+    }
+
+    let pair = |first: usize| {
+        (
+            keysyms.get(first).copied().unwrap_or(0),
+            keysyms.get(first + 1).copied().unwrap_or(0),
+        )
+    };
+    let (first, second) = pair(0);
+
+    Some(match keysyms.len() {
+        0 | 1 => "ONE_LEVEL",
+        2 if is_alphabetic_pair(first, second) => "ALPHABETIC",
+        2 if is_keypad(first) || is_keypad(second) => "KEYPAD",
+        2 => "TWO_LEVEL",
+        3 | 4 if is_alphabetic_pair(first, second) => {
+            let (third, fourth) = pair(2);
+            if keysyms.len() == 3 || is_alphabetic_pair(third, fourth) {
+                "FOUR_LEVEL_ALPHABETIC"
+            } else {
+                "FOUR_LEVEL_SEMIALPHABETIC"
+            }
+        }
+        3 | 4 if is_keypad(first) || is_keypad(second) => "FOUR_LEVEL_KEYPAD",
+        3 | 4 => "FOUR_LEVEL",
+        _ => return None,
+    })
+}
+
+#[test]
+fn xkb_keymap_text_round_trips() {
+    let text = include_str!("fixtures/us_ru.xkb");
+    let desc = XkbKeymapDescription::parse(text).unwrap();
+    assert_eq!(desc.to_string(), text);
+
+    // the output of xkbcomp describes the same keymap, once the parts we don't keep are dropped
+    let xkbcomp = XkbKeymapDescription::parse(include_str!("fixtures/us_ru_xkbcomp.xkb")).unwrap();
+    assert_eq!(xkbcomp, desc);
+
+    assert_eq!(desc.symbols_name, "pc+us+ru:2");
+    assert_eq!((desc.min_keycode, desc.max_keycode), (8, 255));
+    assert_eq!(desc.group_names, &["English (US)", "Russian"]);
+    let keypad = desc.key_type("KEYPAD").unwrap();
+    assert_eq!(keypad.modifiers, 0x11);
+    assert_eq!(keypad.map, &[(0, 0), (0x01, 1), (0x10, 1), (0x11, 0)]);
+    assert_eq!(desc.key_type("SPARE").unwrap().num_levels, 3);
+
+    let q = desc.key(24).unwrap();
+    assert_eq!(q.name, "AD01");
+    assert_eq!(q.groups[1].key_type, "ALPHABETIC");
+    assert_eq!(q.groups[1].keysyms, &[0x6ca, 0x6ea]);
+    assert_eq!(desc.key(108).unwrap().modifiers, 0x80);
+    assert_eq!(
+        desc.key(108).unwrap().out_of_range,
+        XkbGroupsOutOfRange::Redirect(0)
+    );
+    assert!(desc.key(120).unwrap().groups.is_empty());
+
+    let err = XkbKeymapDescription::parse("xkb_keymap { xkb_symbols { key <AE01> { [ 1 ] }; }; };");
+    assert!(err.is_err());
+    let text = include_str!("fixtures/us_ru.xkb").replacen("key <AD01> {", "key <AD01> { ] ,", 1);
+    assert!(XkbKeymapDescription::parse(&text).is_err());
+}
+
+#[allow(clippy::too_many_lines)]
+#[test]
+fn xkb_keymap_description_from_replies() {
+    let mut map = vec![0; 40];
+    map[0] = 1;
+    map[10] = 8; // min keycode
+    map[11] = 10; // max keycode
+    map[12..14].copy_from_slice(&7u16.to_ne_bytes()); // types, syms and modmap
+    map[15] = 4; // types
+    map[17] = 8; // first key with syms
+    map[20] = 3; // keys with syms
+    map[33] = 1; // keys in the modmap
+
+    // ONE_LEVEL
+    map.extend_from_slice(&[0, 0, 0, 0, 1, 0, 0, 0]);
+    // TWO_LEVEL
+    map.extend_from_slice(&[1, 1, 0, 0, 2, 1, 0, 0]);
+    map.extend_from_slice(&[1, 1, 1, 1, 0, 0, 0, 0]);
+    // ALPHABETIC, which preserves Lock
+    map.extend_from_slice(&[3, 3, 0, 0, 2, 2, 1, 0]);
+    map.extend_from_slice(&[1, 1, 1, 1, 0, 0, 0, 0]);
+    map.extend_from_slice(&[1, 2, 1, 2, 0, 0, 0, 0]);
+    map.extend_from_slice(&[0, 0, 0, 0, 2, 2, 0, 0]);
+    // KEYPAD
+    map.extend_from_slice(&[0x11, 0x11, 0, 0, 2, 1, 0, 0]);
+    map.extend_from_slice(&[1, 0x10, 1, 0x10, 0, 0, 0, 0]);
+
+    let mut push_key = |types: [u8; 4], group_info: u8, width: u8, syms: &[Keysym]| {
+        map.extend_from_slice(&types);
+        map.extend_from_slice(&[group_info, width]);
+        map.extend_from_slice(&(syms.len() as u16).to_ne_bytes());
+        syms.iter()
+            .for_each(|sym| map.extend_from_slice(&sym.to_ne_bytes()));
+    };
+    push_key([2, 2, 0, 0], 2, 2, &[0x71, 0x51, 0x6ca, 0x6ea]);
+    push_key([0, 0, 0, 0], 1, 1, &[0xff1b]);
+    // clamps the group
+    push_key([0, 0, 0, 0], 0x41, 1, &[0xffe1]);
+    map.extend_from_slice(&[10, 1, 0, 0]);
+
+    let mut names = vec![0; 32];
+    names[0] = 1;
+    names[8..12].copy_from_slice(&0b1_0010_1111_0101u32.to_ne_bytes());
+    names[12] = 8;
+    names[13] = 10;
+    names[14] = 4; // types
+    names[15] = 0b11; // groups
+    names[18] = 8; // first key
+    names[19] = 3; // keys
+    names[26..28].copy_from_slice(&7u16.to_ne_bytes());
+    let atoms: &[u32] = &[
+        1, 2, 3, 4, // section names
+        10, 11, 12, 13, // type names
+    ];
+    atoms
+        .iter()
+        .for_each(|atom| names.extend_from_slice(&atom.to_ne_bytes()));
+    names.extend_from_slice(&[1, 2, 2, 2]);
+    let atoms: &[u32] = &[
+        20, 21, 22, 21, 23, 21, 24, // level names
+        30, 31, // group names
+    ];
+    atoms
+        .iter()
+        .for_each(|atom| names.extend_from_slice(&atom.to_ne_bytes()));
+    names.extend_from_slice(b"AD01ESC\0LFSH");
+
+    let atom_names = [
+        (1, "evdev"),
+        (2, "pc+us+ru:2"),
+        (3, "complete"),
+        (4, "complete"),
+        (10, "ONE_LEVEL"),
+        (11, "TWO_LEVEL"),
+        (12, "ALPHABETIC"),
+        (13, "KEYPAD"),
+        (20, "Any"),
+        (21, "Base"),
+        (22, "Shift"),
+        (23, "Caps"),
+        (24, "Number"),
+        (30, "English (US)"),
+        (31, "Russian"),
+    ]
+    .iter()
+    .map(|(atom, name)| (Atom::const_from_xid(*atom), name.to_string()))
+    .collect::<BTreeMap<_, _>>();
+
+    let (keymap, names) = parse_replies(&map, &names).unwrap();
+    let mut atoms = names.atoms();
+    atoms.sort_unstable();
+    assert_eq!(atoms, atom_names.keys().copied().collect::<Vec<_>>());
+    let desc = XkbKeymapDescription::from_parts(&keymap, &names, &atom_names);
+
+    let expected = XkbKeymapDescription::parse(
+        r#"
+        xkb_keymap {
+            xkb_keycodes "evdev" { minimum = 8; maximum = 10; <AD01> = 8; <ESC> = 9; <LFSH> = 10; };
+            xkb_types "complete" {
+                type "ONE_LEVEL" { modifiers = none; level_name[Level1] = "Any"; };
+                type "TWO_LEVEL" {
+                    modifiers = Shift;
+                    map[Shift] = Level2;
+                    level_name[Level1] = "Base";
+                    level_name[Level2] = "Shift";
+                };
+                type "ALPHABETIC" {
+                    modifiers = Shift+Lock;
+                    map[Shift] = Level2;
+                    map[Lock] = Level2;
+                    preserve[Lock] = Lock;
+                    level_name[Level1] = "Base";
+                    level_name[Level2] = "Caps";
+                };
+                type "KEYPAD" {
+                    modifiers = Shift+Mod2;
+                    map[Mod2] = Level2;
+                    level_name[Level1] = "Base";
+                    level_name[Level2] = "Number";
+                };
+            };
+            xkb_compatibility "complete" { };
+            xkb_symbols "pc+us+ru:2" {
+                name[Group1] = "English (US)";
+                name[Group2] = "Russian";
+                key <AD01> { [ q, Q ], [ Cyrillic_shorti, Cyrillic_SHORTI ] };
+                key <ESC> { [ Escape ] };
+                key <LFSH> { groupsClamp, [ Shift_L ] };
+                modifier_map Shift { <LFSH> };
+            };
+        };
+        "#,
+    )
+    .unwrap();
+    assert_eq!(desc, expected);
+}
+
+#[test]
+fn xkb_keymap_upload_requests() {
+    let desc = XkbKeymapDescription::parse(include_str!("fixtures/us_ru.xkb")).unwrap();
+    let order = desc.upload_order().unwrap();
+    assert_eq!(&order[..4], &[0, 1, 2, 3]);
+
+    let set_map = desc.set_map_request(&order).unwrap();
+    let body = &set_map.body;
+    assert_eq!(u16::from_ne_bytes([body[0], body[1]]), CORE_KEYBOARD);
+    // 7 types for keys 8 to 255
+    assert_eq!(body[9], 7);
+    assert_eq!((body[10], body[11]), (8, 248));
+    // 11 keys have keysyms, two of which have four levels
+    let total_syms = u16::from_ne_bytes([body[12], body[13]]);
+    assert_eq!(
+        total_syms,
+        1 + 2 * 2 + 2 * 2 + 2 * 2 + 1 + 4 * 2 + 1 + 1 + 1 + 2 + 1
+    );
+    // 4 keys are in the modifier map
+    assert_eq!(body[24..27], [8, 248, 4]);
+    assert_eq!(set_map.size() % 4, 0);
+
+    let names = desc.names_to_intern(&order);
+    let atoms = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (*name, Atom::const_from_xid(i as u32 + 1)))
+        .collect::<BTreeMap<_, _>>();
+    let set_names = desc.set_names_request(&order, &atoms).unwrap();
+    // 3 levels in the spare type
+    let total_levels = u16::from_ne_bytes([set_names.body[22], set_names.body[23]]);
+    assert_eq!(total_levels, 1 + 2 + 2 + 2 + 4 + 4 + 3);
+    assert_eq!(set_names.size() % 4, 0);
+
+    // the key counts are a single byte, so every keycode is one too many
+    let mut full = desc.clone();
+    full.min_keycode = 0;
+    full.max_keycode = 255;
+    assert!(full.set_map_request(&order).is_err());
+    assert!(full.set_names_request(&order, &atoms).is_err());
+}
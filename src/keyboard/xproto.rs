@@ -1,21 +1,44 @@
 // MIT/Apache2 License
 
-use super::Keymap;
+use super::{convert_case, Keymap};
 use crate::{
-    auto::xproto::{Keycode, Keysym},
+    auto::xproto::{GetKeyboardMappingRequest, KeyButMask, Keycode, Keysym, Mapping},
     display::{prelude::*, Display, DisplayBase, KeyboardMapping},
+    event::Event,
 };
 use alloc::boxed::Box;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::display::AsyncDisplay;
 
+const NO_SYMBOL: Keysym = 0;
+const XK_MODE_SWITCH: Keysym = 0xff7e;
+const XK_NUM_LOCK: Keysym = 0xff7f;
+const XK_CAPS_LOCK: Keysym = 0xffe5;
+const XK_SHIFT_LOCK: Keysym = 0xffe6;
+
+/// The index of the `Lock` modifier in the modifier mapping.
+const LOCK_INDEX: usize = 1;
+
 #[derive(Debug, Clone)]
 pub struct XprotoKeymap {
     min_keycode: Keycode,
     max_keycode: Keycode,
     keysyms_per_keycode: u8,
     keysyms: Box<[Keysym]>,
+    modifier_map: ModifierMapping,
+    // the modifier bits that Mode_switch and Num_Lock are bound to
+    mode_switch: u16,
+    num_lock: u16,
+    lock_meaning: LockMeaning,
+}
+
+/// How the `Lock` modifier is interpreted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum LockMeaning {
+    None,
+    CapsLock,
+    ShiftLock,
 }
 
 impl XprotoKeymap {
@@ -23,16 +46,17 @@ impl XprotoKeymap {
     pub(crate) fn init_from<Dpy: Display + ?Sized>(display: &mut Dpy) -> crate::Result<Self> {
         let keyboard_tok = display.get_keyboard_mapping()?;
         let keyboard_map: KeyboardMapping = display.resolve_request(keyboard_tok)?.into();
+        let modifier_map = display.get_modifier_mapping_immediate()?;
 
-        Ok(Self {
-            min_keycode: display.setup().min_keycode,
-            max_keycode: display.setup().max_keycode,
-            keysyms_per_keycode: keyboard_map.keysyms_per_keycode,
-            keysyms: keyboard_map.keysyms,
-        })
+        Ok(Self::new(
+            display.setup().min_keycode,
+            display.setup().max_keycode,
+            keyboard_map,
+            &modifier_map,
+        ))
     }
 
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub(crate) async fn init_from_async<Dpy: AsyncDisplay + ?Sized>(
         display: &mut Dpy,
@@ -40,20 +64,388 @@ impl XprotoKeymap {
         let keyboard_tok = display.get_keyboard_mapping_async().await?;
         let keyboard_map: KeyboardMapping =
             display.resolve_request_async(keyboard_tok).await?.into();
+        let modifier_map = display.get_modifier_mapping_immediate_async().await?;
+
+        Ok(Self::new(
+            display.setup().min_keycode,
+            display.setup().max_keycode,
+            keyboard_map,
+            &modifier_map,
+        ))
+    }
+
+    /// Update the keymap in response to a `MappingNotify` event, refetching the parts that changed.
+    #[inline]
+    pub(crate) fn update<Dpy: Display + ?Sized>(
+        &mut self,
+        event: &Event,
+        display: &mut Dpy,
+    ) -> crate::Result {
+        let mne = match event {
+            Event::MappingNotify(mne) => mne,
+            _ => return Ok(()),
+        };
+
+        match mne.request {
+            Mapping::Keyboard => {
+                let keyboard_map: KeyboardMapping = display
+                    .exchange_request(GetKeyboardMappingRequest {
+                        first_keycode: mne.first_keycode,
+                        count: mne.count,
+                        ..Default::default()
+                    })?
+                    .into();
+
+                if !self.apply_keyboard_mapping(mne.first_keycode, keyboard_map) {
+                    // the number of keysyms per keycode changed, so we need all of them again
+                    let keyboard_map = display.get_keyboard_mapping_immediate()?;
+                    self.apply_keyboard_mapping(self.min_keycode, keyboard_map);
+                }
+            }
+            Mapping::Modifier => {
+                self.modifier_map = display.get_modifier_mapping_immediate()?;
+                self.apply_modifier_mapping();
+            }
+            Mapping::Pointer => {}
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "async-core")]
+    #[inline]
+    pub(crate) async fn update_async<Dpy: AsyncDisplay + ?Sized>(
+        &mut self,
+        event: &Event,
+        display: &mut Dpy,
+    ) -> crate::Result {
+        let mne = match event {
+            Event::MappingNotify(mne) => mne,
+            _ => return Ok(()),
+        };
+
+        match mne.request {
+            Mapping::Keyboard => {
+                let keyboard_map: KeyboardMapping = display
+                    .exchange_request_async(GetKeyboardMappingRequest {
+                        first_keycode: mne.first_keycode,
+                        count: mne.count,
+                        ..Default::default()
+                    })
+                    .await?
+                    .into();
+
+                if !self.apply_keyboard_mapping(mne.first_keycode, keyboard_map) {
+                    // the number of keysyms per keycode changed, so we need all of them again
+                    let keyboard_map: KeyboardMapping =
+                        display.get_keyboard_mapping_immediate_async().await?.into();
+                    self.apply_keyboard_mapping(self.min_keycode, keyboard_map);
+                }
+            }
+            Mapping::Modifier => {
+                self.modifier_map = display.get_modifier_mapping_immediate_async().await?;
+                self.apply_modifier_mapping();
+            }
+            Mapping::Pointer => {}
+        }
 
-        Ok(Self {
-            min_keycode: display.setup().min_keycode,
-            max_keycode: display.setup().max_keycode,
+        Ok(())
+    }
+
+    #[inline]
+    fn new(
+        min_keycode: Keycode,
+        max_keycode: Keycode,
+        keyboard_map: KeyboardMapping,
+        modifier_map: &ModifierMapping,
+    ) -> Self {
+        let mut this = Self {
+            min_keycode,
+            max_keycode,
             keysyms_per_keycode: keyboard_map.keysyms_per_keycode,
             keysyms: keyboard_map.keysyms,
-        })
+            modifier_map: modifier_map.clone(),
+            mode_switch: 0,
+            num_lock: 0,
+            lock_meaning: LockMeaning::None,
+        };
+        this.apply_modifier_mapping();
+        this
+    }
+
+    /// Replace the keysyms for a range of keycodes, starting at `first_keycode`. If the mapping covers every
+    /// keycode, it replaces the entire map. Otherwise, it has to have the same number of keysyms per keycode as
+    /// the current map; returns `false` if it doesn't.
+    #[inline]
+    fn apply_keyboard_mapping(
+        &mut self,
+        first_keycode: Keycode,
+        keyboard_map: KeyboardMapping,
+    ) -> bool {
+        let per = usize::from(keyboard_map.keysyms_per_keycode);
+        let count = usize::from(self.max_keycode - self.min_keycode) + 1;
+
+        if first_keycode == self.min_keycode && keyboard_map.keysyms.len() >= count * per {
+            self.keysyms_per_keycode = keyboard_map.keysyms_per_keycode;
+            self.keysyms = keyboard_map.keysyms;
+        } else if keyboard_map.keysyms_per_keycode == self.keysyms_per_keycode
+            && first_keycode >= self.min_keycode
+        {
+            let start = usize::from(first_keycode - self.min_keycode) * per;
+            let end = (start + keyboard_map.keysyms.len()).min(self.keysyms.len());
+            if start < end {
+                self.keysyms[start..end].copy_from_slice(&keyboard_map.keysyms[..end - start]);
+            }
+        } else {
+            return false;
+        }
+
+        // the keysyms of the modifier keys may have changed
+        self.apply_modifier_mapping();
+        true
+    }
+
+    /// Figure out which modifiers carry `Mode_switch`, `Num_Lock` and the meaning of `Lock`, by looking at the
+    /// keysyms of the keycodes bound to each modifier.
+    #[inline]
+    fn apply_modifier_mapping(&mut self) {
+        let modifier_map = &self.modifier_map;
+        let mut mode_switch = 0;
+        let mut num_lock = 0;
+        let mut lock_meaning = LockMeaning::None;
+
+        let per_modifier = usize::from(modifier_map.keycodes_per_modifier).max(1);
+        for (index, keycodes) in modifier_map.keycodes.chunks(per_modifier).enumerate() {
+            let bit = 1 << index;
+            for &keycode in keycodes.iter().filter(|&&keycode| keycode != 0) {
+                for &sym in self.lookup_keysyms(keycode) {
+                    match sym {
+                        XK_MODE_SWITCH => mode_switch |= bit,
+                        XK_NUM_LOCK => num_lock |= bit,
+                        // if Lock could be both, CapsLock wins
+                        XK_CAPS_LOCK if index == LOCK_INDEX => lock_meaning = LockMeaning::CapsLock,
+                        XK_SHIFT_LOCK
+                            if index == LOCK_INDEX && lock_meaning == LockMeaning::None =>
+                        {
+                            lock_meaning = LockMeaning::ShiftLock;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        self.mode_switch = mode_switch;
+        self.num_lock = num_lock;
+        self.lock_meaning = lock_meaning;
+    }
+
+    /// Get the two keysyms for the given group (0 or 1) of the key, according to the rules in section 5 of the
+    /// X11 protocol specification.
+    #[inline]
+    fn group_keysyms(&self, keycode: Keycode, group: usize) -> Option<(Keysym, Keysym)> {
+        let syms = self.lookup_keysyms(keycode);
+        // trailing NoSymbol entries are ignored
+        let len = syms.iter().rposition(|&sym| sym != NO_SYMBOL)? + 1;
+
+        // "K" is treated as "K NoSymbol K NoSymbol", and "K1 K2" as "K1 K2 K1 K2"
+        let index = if len <= 2 { 0 } else { group * 2 };
+        let first = syms.get(index).copied().unwrap_or(NO_SYMBOL);
+        let second = syms.get(index + 1).copied().unwrap_or(NO_SYMBOL);
+
+        // if the second element is NoSymbol, the group is treated as if the second element were the same as the
+        // first, unless the first is alphabetic and has both cases
+        if second == NO_SYMBOL {
+            let (lower, upper) = convert_case(first);
+            if lower == upper {
+                Some((first, first))
+            } else {
+                Some((lower, upper))
+            }
+        } else {
+            Some((first, second))
+        }
     }
 }
 
+/// Whether the keysym is on the keypad.
+#[inline]
+fn is_keypad_keysym(keysym: Keysym) -> bool {
+    matches!(keysym, 0xff80..=0xffbd | 0x1100_0000..=0x1100_ffff)
+}
+
 impl Keymap for XprotoKeymap {
     #[inline]
     fn lookup_keysyms(&self, keycode: Keycode) -> &[Keysym] {
-        let start = (keycode - self.min_keycode) as usize * self.keysyms_per_keycode as usize;
-        &self.keysyms[start..start + self.keysyms_per_keycode as usize]
+        let per = self.keysyms_per_keycode as usize;
+        if keycode < self.min_keycode || keycode > self.max_keycode {
+            return &[];
+        }
+
+        let start = (keycode - self.min_keycode) as usize * per;
+        self.keysyms.get(start..start + per).unwrap_or(&[])
+    }
+
+    #[inline]
+    fn lookup_keysym(&self, keycode: Keycode, modifiers: KeyButMask) -> Option<Keysym> {
+        let group = usize::from(modifiers.inner & self.mode_switch != 0);
+        let (first, second) = self.group_keysyms(keycode, group)?;
+
+        let shift = modifiers.shift();
+        let caps_lock = modifiers.lock() && self.lock_meaning == LockMeaning::CapsLock;
+        let shift_lock = modifiers.lock() && self.lock_meaning == LockMeaning::ShiftLock;
+
+        let sym = if modifiers.inner & self.num_lock != 0 && is_keypad_keysym(second) {
+            if shift || shift_lock {
+                first
+            } else {
+                second
+            }
+        } else if !shift && !caps_lock && !shift_lock {
+            first
+        } else if !shift && caps_lock {
+            convert_case(first).1
+        } else if shift && caps_lock {
+            convert_case(second).1
+        } else {
+            second
+        };
+
+        if sym == NO_SYMBOL {
+            None
+        } else {
+            Some(sym)
+        }
     }
 }
+
+#[test]
+fn xproto_keymap_selects_keysyms_per_protocol() {
+    // keycode 8: a, keycode 9: 1 exclam, keycode 10: KP_End KP_1, keycode 11: e E eacute Eacute
+    // keycode 12: Caps_Lock, keycode 13: Num_Lock, keycode 14: Mode_switch
+    let keyboard_map = KeyboardMapping {
+        keysyms_per_keycode: 4,
+        keysyms: [
+            [0x61, 0, 0, 0],
+            [0x31, 0x21, 0, 0],
+            [0xff9c, 0xffb1, 0, 0],
+            [0x65, 0x45, 0xe9, 0xc9],
+            [XK_CAPS_LOCK, 0, 0, 0],
+            [XK_NUM_LOCK, 0, 0, 0],
+            [XK_MODE_SWITCH, 0, 0, 0],
+        ]
+        .concat()
+        .into_boxed_slice(),
+    };
+    // Lock -> Caps_Lock, Mod2 -> Num_Lock, Mod5 -> Mode_switch
+    let modifier_map = ModifierMapping {
+        keycodes_per_modifier: 1,
+        keycodes: Box::new([0, 12, 0, 0, 13, 0, 0, 14]),
+    };
+    let keymap = XprotoKeymap::new(8, 14, keyboard_map, &modifier_map);
+
+    let state = |inner: u16| KeyButMask { inner };
+    const SHIFT: u16 = 1;
+    const LOCK: u16 = 1 << 1;
+    const MOD2: u16 = 1 << 4;
+    const MOD5: u16 = 1 << 7;
+
+    // a single lowercase keysym gets its uppercase form as the second element
+    assert_eq!(keymap.lookup_keysym(8, state(0)), Some(0x61));
+    assert_eq!(keymap.lookup_keysym(8, state(SHIFT)), Some(0x41));
+    assert_eq!(keymap.lookup_keysym(8, state(LOCK)), Some(0x41));
+    assert_eq!(keymap.lookup_keysym(8, state(SHIFT | LOCK)), Some(0x41));
+
+    // CapsLock doesn't affect keys without case
+    assert_eq!(keymap.lookup_keysym(9, state(LOCK)), Some(0x31));
+    assert_eq!(keymap.lookup_keysym(9, state(SHIFT | LOCK)), Some(0x21));
+
+    // NumLock picks the second keysym on the keypad, and Shift undoes it
+    assert_eq!(keymap.lookup_keysym(10, state(0)), Some(0xff9c));
+    assert_eq!(keymap.lookup_keysym(10, state(MOD2)), Some(0xffb1));
+    assert_eq!(keymap.lookup_keysym(10, state(MOD2 | SHIFT)), Some(0xff9c));
+
+    // Mode_switch picks the second group
+    assert_eq!(keymap.lookup_keysym(11, state(MOD5)), Some(0xe9));
+    assert_eq!(keymap.lookup_keysym(11, state(MOD5 | SHIFT)), Some(0xc9));
+    assert_eq!(keymap.lookup_keysym(11, state(MOD5 | LOCK)), Some(0xc9));
+    // keys with only one group use it for both groups
+    assert_eq!(keymap.lookup_keysym(9, state(MOD5 | SHIFT)), Some(0x21));
+
+    // out of range keycodes don't have keysyms
+    assert_eq!(keymap.lookup_keysym(15, state(0)), None);
+
+    // the fewest modifiers needed to produce a keysym are found
+    assert_eq!(keymap.keysym_to_keycodes(0x41), &[(8, state(SHIFT))]);
+    assert_eq!(keymap.keysym_to_keycodes(0x21), &[(9, state(SHIFT))]);
+    assert_eq!(
+        keymap.keysym_to_keycodes(0xffb1),
+        &[(10, state(SHIFT)), (10, state(MOD2))]
+    );
+    assert_eq!(
+        keymap.keysym_to_keycodes(0xc9),
+        &[(11, state(MOD5 | SHIFT))]
+    );
+    assert!(keymap.keysym_to_keycodes(0xffc9).is_empty());
+}
+
+#[test]
+fn xproto_keymap_shift_lock() {
+    let keyboard_map = KeyboardMapping {
+        keysyms_per_keycode: 2,
+        keysyms: Box::new([0x31, 0x21, XK_SHIFT_LOCK, 0]),
+    };
+    let modifier_map = ModifierMapping {
+        keycodes_per_modifier: 1,
+        keycodes: Box::new([0, 9, 0, 0, 0, 0, 0, 0]),
+    };
+    let keymap = XprotoKeymap::new(8, 9, keyboard_map, &modifier_map);
+
+    // ShiftLock acts like Shift, even for keys without case
+    assert_eq!(
+        keymap.lookup_keysym(8, KeyButMask { inner: 1 << 1 }),
+        Some(0x21)
+    );
+}
+
+#[test]
+fn xproto_keymap_refetches_changed_keycodes() {
+    use crate::{
+        auto::xproto::{GetKeyboardMappingReply, MappingNotifyEvent},
+        display::BasicDisplay,
+    };
+    use alloc::borrow::Cow;
+
+    let keyboard_map = KeyboardMapping {
+        keysyms_per_keycode: 2,
+        keysyms: Box::new([0x61, 0x41, 0x31, 0x21]),
+    };
+    let modifier_map = ModifierMapping {
+        keycodes_per_modifier: 0,
+        keycodes: Box::new([]),
+    };
+    let mut keymap = XprotoKeymap::new(8, 9, keyboard_map, &modifier_map);
+
+    // xmodmap -e "keycode 8 = b B"
+    let mut display = BasicDisplay::dummy();
+    display
+        .connection_mut()
+        .reply_padded(GetKeyboardMappingReply {
+            reply_type: 1,
+            keysyms_per_keycode: 2,
+            sequence: 2,
+            length: 2,
+            keysyms: Cow::Borrowed(&[0x62, 0x42]),
+        });
+    let event = Event::MappingNotify(MappingNotifyEvent {
+        event_type: 34,
+        sequence: 2,
+        request: Mapping::Keyboard,
+        first_keycode: 8,
+        count: 1,
+    });
+    keymap.update(&event, &mut display).unwrap();
+
+    assert_eq!(keymap.lookup_keysyms(8), &[0x62, 0x42]);
+    assert_eq!(keymap.lookup_keysyms(9), &[0x31, 0x21]);
+}
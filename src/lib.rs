@@ -93,22 +93,29 @@
 //!           the standard library; however, it requires the programmer to provide a connection, rather
 //!           than building a connection itself.
 //! * `async` - Enables the `_async` suffix family of functions. These functions and methods are similar
-//!             to their blocking variants, but they use non-blocking variants of network calls. This uses
-//!             the [`async-io`](https://crates.io/crates/async-io) crate to provide non-blocking calls.
-//!             However, it nearly triples the size of this package's dependency tree.
+//!             to their blocking variants, but they use non-blocking variants of network calls. This enables
+//!             `async-core` and `async-io-support`.
+//! * `async-core` - Enables the `_async` suffix family of functions without tying them to any runtime.
+//!                  `BasicDisplay::from_connection_async` accepts any `AsyncRead + AsyncWrite` stream through
+//!                  `StreamConnection`, which makes it possible to use `breadx` with a custom executor.
+//! * `async-io-support` - Implements `AsyncConnection` for the [`async-io`](https://crates.io/crates/async-io)
+//!                        crate's `Async` type, and enables `AsyncDisplayConnection::create_async`. However, it
+//!                        nearly triples the size of this package's dependency tree.
 //! * `image-support` - Adds the `from_image` method to the `Image` class, allowing one to convert a struct of
 //!                     type `image::Image` from the [`image`](https://crates.io/crates/image) crate into this
-//!                     image.
+//!                     image, as well as the `to_rgba_image` family of methods for converting it back.
 //! * `sync-display` - Enables the `SyncDisplay` struct, which allows usage of the display in thread-safe
 //!                    contexts. However, it does require importing more dependencies (although some of these
 //!                    dependencies overlap with those of the `async` feature), and technically violates the
 //!                    "lock-free" idea of `breadx`, since `SyncDisplay` does require a mutex to function.
 //! * `tokio-support` - In addition to adding implementations of the `AsyncConnection` trait to `tokio`'s
 //!                     `TcpStream` and `UnixStream`, this reimplements `NameConnection` in terms of these
-//!                     types, allowing this create to be utilized more effectively in a `tokio` runtime.
+//!                     types, allowing this create to be utilized more effectively in a `tokio` runtime. On
+//!                     Unix, it also adds `TokioDisplayConnection`, which is registered directly with `tokio`'s
+//!                     reactor and can be sent across tasks.
 //!
-//! (Note: By default, `breadx`'s async ecosystem is implemented in terms of the primitives underlying `smol` and
-//! `async-std`, so features for those are unnecessary).
+//! (Note: `async-io-support` uses the primitives underlying `smol` and `async-std`, so features for those are
+//! unnecessary).
 //!
 //! In addition, `breadx` has a feature for each officially supported X11 extension. Use the extension's name as
 //! a feature, or use the `all-extensions` feature to enable every extension.
@@ -144,6 +151,7 @@ extern crate core;
 mod auth_info;
 pub mod auto;
 pub mod client_message_data;
+pub mod cursor;
 pub mod display;
 pub mod error;
 pub mod event;
@@ -21,9 +21,9 @@ use crate::{
 use alloc::{borrow::Cow, boxed::Box};
 use core::num::NonZeroU32;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::display::{prelude::*, AsyncDisplay, PollOr};
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use core::task::{Context, Poll};
 
 /// A wrapper around the `Display` that contains XRender-specific data.
@@ -82,62 +82,8 @@ impl<Dpy: ?Sized> RenderDisplay<Dpy> {
     /// Get a `Pictformat` based on a standard format.
     #[inline]
     pub fn find_standard_format(&self, standard: StandardFormat) -> Option<Pictformat> {
-        const STANDARDS: &[fn(&Pictforminfo) -> bool] = &[
-            |p| {
-                p.ty == PictType::Direct
-                    && p.depth == 32
-                    && p.direct.red_shift == 16
-                    && p.direct.red_mask == 0xFF
-                    && p.direct.green_shift == 8
-                    && p.direct.green_mask == 0xFF
-                    && p.direct.blue_shift == 0
-                    && p.direct.blue_mask == 0xFF
-                    && p.direct.alpha_shift == 24
-                    && p.direct.alpha_mask == 0xFF
-            },
-            |p| {
-                p.ty == PictType::Direct
-                    && p.depth == 24
-                    && p.direct.red_shift == 16
-                    && p.direct.red_mask == 0xFF
-                    && p.direct.green_shift == 8
-                    && p.direct.green_mask == 0xFF
-                    && p.direct.blue_shift == 0
-                    && p.direct.blue_mask == 0xFF
-                    && p.direct.alpha_mask == 0
-            },
-            |p| {
-                p.ty == PictType::Direct
-                    && p.depth == 8
-                    && p.direct.red_mask == 0x00
-                    && p.direct.green_mask == 0x00
-                    && p.direct.blue_mask == 0x00
-                    && p.direct.alpha_shift == 0
-                    && p.direct.alpha_mask == 0xFF
-            },
-            |p| {
-                p.ty == PictType::Direct
-                    && p.depth == 4
-                    && p.direct.red_mask == 0x00
-                    && p.direct.green_mask == 0x00
-                    && p.direct.blue_mask == 0x00
-                    && p.direct.alpha_shift == 0
-                    && p.direct.alpha_mask == 0x0F
-            },
-            |p| {
-                p.ty == PictType::Direct
-                    && p.depth == 1
-                    && p.direct.red_mask == 0x00
-                    && p.direct.green_mask == 0x00
-                    && p.direct.blue_mask == 0x00
-                    && p.direct.alpha_shift == 0
-                    && p.direct.alpha_mask == 0x01
-            },
-        ];
-        let standard_identifier = STANDARDS[standard as usize];
-
         self.formats.iter().find_map(|p| {
-            if standard_identifier(p) {
+            if standard.matches(p) {
                 Some(p.id)
             } else {
                 None
@@ -239,6 +185,66 @@ pub enum StandardFormat {
     A1 = 4,
 }
 
+impl StandardFormat {
+    /// Whether or not a `Pictforminfo` describes this standard format.
+    #[inline]
+    pub(crate) fn matches(self, format: &Pictforminfo) -> bool {
+        const STANDARDS: &[fn(&Pictforminfo) -> bool] = &[
+            |p| {
+                p.ty == PictType::Direct
+                    && p.depth == 32
+                    && p.direct.red_shift == 16
+                    && p.direct.red_mask == 0xFF
+                    && p.direct.green_shift == 8
+                    && p.direct.green_mask == 0xFF
+                    && p.direct.blue_shift == 0
+                    && p.direct.blue_mask == 0xFF
+                    && p.direct.alpha_shift == 24
+                    && p.direct.alpha_mask == 0xFF
+            },
+            |p| {
+                p.ty == PictType::Direct
+                    && p.depth == 24
+                    && p.direct.red_shift == 16
+                    && p.direct.red_mask == 0xFF
+                    && p.direct.green_shift == 8
+                    && p.direct.green_mask == 0xFF
+                    && p.direct.blue_shift == 0
+                    && p.direct.blue_mask == 0xFF
+                    && p.direct.alpha_mask == 0
+            },
+            |p| {
+                p.ty == PictType::Direct
+                    && p.depth == 8
+                    && p.direct.red_mask == 0x00
+                    && p.direct.green_mask == 0x00
+                    && p.direct.blue_mask == 0x00
+                    && p.direct.alpha_shift == 0
+                    && p.direct.alpha_mask == 0xFF
+            },
+            |p| {
+                p.ty == PictType::Direct
+                    && p.depth == 4
+                    && p.direct.red_mask == 0x00
+                    && p.direct.green_mask == 0x00
+                    && p.direct.blue_mask == 0x00
+                    && p.direct.alpha_shift == 0
+                    && p.direct.alpha_mask == 0x0F
+            },
+            |p| {
+                p.ty == PictType::Direct
+                    && p.depth == 1
+                    && p.direct.red_mask == 0x00
+                    && p.direct.green_mask == 0x00
+                    && p.direct.blue_mask == 0x00
+                    && p.direct.alpha_shift == 0
+                    && p.direct.alpha_mask == 0x01
+            },
+        ];
+        STANDARDS[self as usize](format)
+    }
+}
+
 struct XrenderInfo {
     formats: Box<[Pictforminfo]>,
     screens: Box<[Pictscreen<'static, 'static>]>,
@@ -278,6 +284,11 @@ impl<Dpy: DisplayBase + ?Sized> DisplayBase for RenderDisplay<Dpy> {
         self.inner.pop_event()
     }
 
+    #[inline]
+    fn pop_event_if(&mut self, predicate: &mut dyn FnMut(&Event) -> bool) -> Option<Event> {
+        self.inner.pop_event_if(predicate)
+    }
+
     #[inline]
     fn generate_xid(&mut self) -> Option<XID> {
         self.inner.generate_xid()
@@ -392,6 +403,11 @@ where
         self.inner().pop_event()
     }
 
+    #[inline]
+    fn pop_event_if(&mut self, predicate: &mut dyn FnMut(&Event) -> bool) -> Option<Event> {
+        self.inner().pop_event_if(predicate)
+    }
+
     #[inline]
     fn generate_xid(&mut self) -> Option<XID> {
         self.inner().generate_xid()
@@ -478,6 +494,11 @@ impl<Dpy: Display + ?Sized> Display for RenderDisplay<Dpy> {
         self.inner.wait()
     }
 
+    #[inline]
+    fn try_wait(&mut self) -> crate::Result<bool> {
+        self.inner.try_wait()
+    }
+
     #[inline]
     fn send_request_raw(&mut self, request: RequestInfo) -> crate::Result<u16> {
         self.inner.send_request_raw(request)
@@ -493,13 +514,18 @@ where
         self.inner().wait()
     }
 
+    #[inline]
+    fn try_wait(&mut self) -> crate::Result<bool> {
+        self.inner().try_wait()
+    }
+
     #[inline]
     fn send_request_raw(&mut self, request: RequestInfo) -> crate::Result<u16> {
         self.inner().send_request_raw(request)
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 impl<Dpy: AsyncDisplay + ?Sized> AsyncDisplay for RenderDisplay<Dpy> {
     #[inline]
     fn poll_wait(&mut self, cx: &mut Context<'_>) -> Poll<crate::Result> {
@@ -519,9 +545,14 @@ impl<Dpy: AsyncDisplay + ?Sized> AsyncDisplay for RenderDisplay<Dpy> {
     fn poll_send_request_raw(&mut self, cx: &mut Context<'_>) -> Poll<crate::Result<u16>> {
         self.inner.poll_send_request_raw(cx)
     }
+
+    #[inline]
+    fn abandon_send_request_raw(&mut self) {
+        self.inner.abandon_send_request_raw();
+    }
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 impl<'a, Dpy: DisplayBase + ?Sized> AsyncDisplay for &'a RenderDisplay<Dpy>
 where
     &'a Dpy: AsyncDisplay,
@@ -544,6 +575,11 @@ where
     fn poll_send_request_raw(&mut self, cx: &mut Context<'_>) -> Poll<crate::Result<u16>> {
         self.inner().poll_send_request_raw(cx)
     }
+
+    #[inline]
+    fn abandon_send_request_raw(&mut self) {
+        self.inner().abandon_send_request_raw();
+    }
 }
 
 impl<Dpy: Display> RenderDisplay<Dpy> {
@@ -699,7 +735,7 @@ impl<Dpy: Display + ?Sized> RenderDisplay<Dpy> {
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 impl<Dpy: AsyncDisplay> RenderDisplay<Dpy> {
     /// Initialize a RenderDisplay with the appropriate information, async redox.
     #[inline]
@@ -768,7 +804,7 @@ impl<Dpy: AsyncDisplay> RenderDisplay<Dpy> {
     }
 }
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 impl<Dpy: AsyncDisplay + ?Sized> RenderDisplay<Dpy> {
     /// Create a new Picture, async redox.
     #[inline]
@@ -14,7 +14,7 @@ use crate::{
 };
 use alloc::borrow::Cow;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use crate::display::{AsyncDisplay, AsyncDisplayExt};
 
 crate::create_paramaterizer! {
@@ -82,7 +82,7 @@ impl Picture {
     }
 
     /// Change an attribute of this picture, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn change_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -129,7 +129,7 @@ impl Picture {
     }
 
     /// Composite this picture with another, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn composite_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -185,7 +185,7 @@ impl Picture {
     }
 
     /// Fill a series of solid color rectangles on this surface, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn fill_rectangles_async<
         'a,
@@ -219,7 +219,7 @@ impl Picture {
     }
 
     /// Free this picture, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn free_async<Dpy: AsyncDisplay + ?Sized>(self, display: &mut Dpy) -> crate::Result {
         display
@@ -279,7 +279,7 @@ impl Picture {
     }
 
     /// Draw a set of trapezoids, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn trapezoids_async<
         'a,
@@ -310,7 +310,7 @@ impl Picture {
     }
 
     /// Draw a set of triangles, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn triangles_async<
         'a,
@@ -355,7 +355,7 @@ impl Picture {
     }
 
     /// Set this picture's transform, async redox.
-    #[cfg(feature = "async")]
+    #[cfg(feature = "async-core")]
     #[inline]
     pub async fn set_transform_async<Dpy: AsyncDisplay + ?Sized>(
         self,
@@ -5,7 +5,7 @@
 use core::iter;
 use tinyvec::{Array, TinyVec};
 
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 use alloc::boxed::Box;
 
 #[cfg(all(unix, feature = "std"))]
@@ -36,7 +36,7 @@ pub(crate) fn expand_or_truncate_to_length<A: Array<Item = u8>>(tv: &mut TinyVec
 }
 
 /// Type alias for a boxed, sendable `FnOnce` that takes an `A` and returns a `B`.
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 pub(crate) type BoxedFnOnce<A, B> = Box<dyn FnOnce(A) -> B + Send>;
 
 /// Byte reversal table, copied from Xlib.
@@ -93,7 +93,7 @@ pub(crate) const fn roundup(dividend: usize, divisor: usize) -> usize {
 }
 
 /// Take a value, run some kind of operation on it, and then restore that value.
-#[cfg(feature = "async")]
+#[cfg(feature = "async-core")]
 #[inline]
 pub(crate) fn take_mut<T: Default, F: FnOnce(T) -> T>(r: &mut T, f: F) {
     // TODO: consider the take_mut crate